@@ -0,0 +1,560 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+//! Layered configuration shared between the tools.
+//!
+//! Values are merged from each [`Layer`], lowest priority first:
+//! the defaults of the type, the system file, the user file, environment variables and the command line.
+//! Every value remembers the layer it came from, so that errors and `config show` can point at it.
+
+use cfg_if::cfg_if;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, Command, FromArgMatches};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+use thiserror::Error;
+
+/// The directory shared by all tools under the system and user configuration directories.
+const DIRECTORY: &str = "amt";
+
+/// Separates nested keys in environment variables, e.g. `BACKUP_STORE__LEVEL`.
+const ENV_SEPARATOR: &str = "__";
+
+/// A source of configuration values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layer {
+    Default,
+    System(PathBuf),
+    User(PathBuf),
+    /// The name of the environment variable the value was read from.
+    Environment(String),
+    Cli,
+}
+
+impl Display for Layer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Layer::Default => write!(f, "defaults"),
+            Layer::System(path) => write!(f, "system file {}", path.display()),
+            Layer::User(path) => write!(f, "user file {}", path.display()),
+            Layer::Environment(variable) => write!(f, "environment variable {variable}"),
+            Layer::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Unable to read {layer}: {source}")]
+    Read {
+        layer: Layer,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Unable to parse {layer}: {source}")]
+    Parse {
+        layer: Layer,
+        #[source]
+        source: Box<toml::de::Error>,
+    },
+
+    #[error("Unable to serialise {layer}: {source}")]
+    Serialise {
+        layer: Layer,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Invalid configuration from {layer}: {source}")]
+    Invalid {
+        layer: Layer,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Invalid value for `{key}` from {layer}: {reason}")]
+    Validation { key: String, layer: Layer, reason: String },
+}
+
+/// A value which failed validation, see [`Layered::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invalid {
+    /// The dotted path of the value, e.g. `store.level`.
+    pub key: String,
+    pub reason: String,
+}
+
+impl Invalid {
+    pub fn new(key: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// A configuration type which can be loaded from every [`Layer`].
+///
+/// Fields should use `#[serde(default)]` so that each file only needs to contain the values it changes.
+pub trait Layered: Serialize + DeserializeOwned + Default {
+    /// The name of the tool, used for the file names and the environment prefix.
+    const NAME: &'static str;
+
+    /// The prefix of environment variables, without the trailing underscore.
+    fn env_prefix() -> String {
+        Self::NAME.to_uppercase().replace('-', "_")
+    }
+
+    /// Checks the merged configuration, the key of an [`Invalid`] value is used to find the layer it came from.
+    fn validate(&self) -> Result<(), Invalid> {
+        Ok(())
+    }
+}
+
+/// Arguments parsed by clap, along with the ids of those which were given on the command line,
+/// see [`Loader::with_cli_changes`].
+///
+/// The id of each argument must match its key once serialised,
+/// which is the case for derived arguments unless either is renamed.
+#[derive(Debug, Clone)]
+pub struct CliChanges<T> {
+    value: T,
+    given: BTreeSet<String>,
+}
+
+impl<T> CliChanges<T> {
+    pub fn new(value: T, matches: &ArgMatches) -> Self {
+        Self {
+            value,
+            given: given_ids(matches),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for CliChanges<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T: FromArgMatches> FromArgMatches for CliChanges<T> {
+    fn from_arg_matches(matches: &ArgMatches) -> Result<Self, clap::Error> {
+        T::from_arg_matches(matches).map(|value| Self::new(value, matches))
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        self.value.update_from_arg_matches(matches)?;
+        self.given.extend(given_ids(matches));
+        Ok(())
+    }
+}
+
+impl<T: Args> Args for CliChanges<T> {
+    fn group_id() -> Option<clap::Id> {
+        T::group_id()
+    }
+
+    fn augment_args(command: Command) -> Command {
+        T::augment_args(command)
+    }
+
+    fn augment_args_for_update(command: Command) -> Command {
+        T::augment_args_for_update(command)
+    }
+}
+
+/// Collects each layer and merges them into a [`Loaded`] configuration.
+#[derive(Debug, Clone)]
+pub struct Loader<T: Layered> {
+    system: Option<PathBuf>,
+    user: Option<PathBuf>,
+    environment: Vec<(String, String)>,
+    cli: Value,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Layered> Default for Loader<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Layered> Loader<T> {
+    /// Creates a loader using the default file locations and the current environment.
+    pub fn new() -> Self {
+        Self {
+            system: system_directory().map(|dir| dir.join(DIRECTORY).join(format!("{}.toml", T::NAME))),
+            user: user_directory().map(|dir| dir.join(DIRECTORY).join(format!("{}.toml", T::NAME))),
+            environment: env::vars().collect(),
+            cli: Value::Object(Map::new()),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn with_system_file(mut self, path: Option<PathBuf>) -> Self {
+        self.system = path;
+        self
+    }
+
+    pub fn with_user_file(mut self, path: Option<PathBuf>) -> Self {
+        self.user = path;
+        self
+    }
+
+    /// Replaces the environment variables the loader reads from.
+    pub fn with_environment(mut self, environment: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.environment = environment.into_iter().collect();
+        self
+    }
+
+    /// Sets the values given on the command line,
+    /// any flag which wasn't given should be skipped when serialising so that it doesn't override other layers.
+    pub fn with_cli(mut self, cli: &impl Serialize) -> Result<Self, Error> {
+        self.cli = serde_json::to_value(cli).map_err(|source| Error::Serialise {
+            layer: Layer::Cli,
+            source,
+        })?;
+        Ok(self)
+    }
+
+    /// Sets the values given on the command line from a `T` parsed by clap,
+    /// where every flag which wasn't given holds its default.
+    ///
+    /// Only the values of arguments which were given on the command line are kept,
+    /// so a flag given its default value still overrides the other layers.
+    pub fn with_cli_changes(self, cli: &CliChanges<T>) -> Result<Self, Error> {
+        let mut changes = serde_json::to_value(&cli.value).map_err(|source| Error::Serialise {
+            layer: Layer::Cli,
+            source,
+        })?;
+
+        retain_given(&mut changes, &cli.given);
+        self.with_cli(&changes)
+    }
+
+    /// The layers which are read from files, in order of priority.
+    pub fn files(&self) -> Vec<Layer> {
+        let system = self.system.clone().map(Layer::System);
+        let user = self.user.clone().map(Layer::User);
+
+        system.into_iter().chain(user).collect()
+    }
+
+    /// Reads and merges every layer, then deserialises and validates the result.
+    pub fn load(&self) -> Result<Loaded<T>, Error> {
+        let defaults = serde_json::to_value(T::default()).map_err(|source| Error::Serialise {
+            layer: Layer::Default,
+            source,
+        })?;
+
+        let mut merged = Value::Object(Map::new());
+        let mut origins = BTreeMap::new();
+        let mut layers = vec![(Layer::Default, defaults)];
+
+        for layer in self.files() {
+            if let Some(value) = read_file(&layer)? {
+                layers.push((layer, value));
+            }
+        }
+
+        layers.extend(self.environment_layers(&layers[0].1));
+        layers.push((Layer::Cli, self.cli.clone()));
+
+        // Deserialise after each layer, so that the first layer to introduce a bad value is the one blamed.
+        let mut value = None;
+        for (layer, layer_value) in layers {
+            merge(&mut merged, &layer_value, &layer, &mut origins, &mut vec![]);
+            value = Some(T::deserialize(&merged).map_err(|source| Error::Invalid { layer, source })?);
+        }
+
+        let value = value.expect("The defaults layer is always present");
+        let loaded = Loaded { value, merged, origins };
+
+        if let Err(invalid) = loaded.value.validate() {
+            return Err(Error::Validation {
+                layer: loaded.origin(&invalid.key).cloned().unwrap_or(Layer::Default),
+                key: invalid.key,
+                reason: invalid.reason,
+            });
+        }
+
+        Ok(loaded)
+    }
+
+    /// Converts each prefixed environment variable into its own layer,
+    /// values are parsed as JSON unless the default at the same key is a string.
+    fn environment_layers(&self, defaults: &Value) -> Vec<(Layer, Value)> {
+        let prefix = format!("{}_", T::env_prefix());
+        let mut layers = self
+            .environment
+            .iter()
+            .filter_map(|(variable, raw)| {
+                let key = variable.strip_prefix(&prefix)?;
+                let path = key.split(ENV_SEPARATOR).map(str::to_lowercase).collect::<Vec<_>>();
+                if path.iter().any(String::is_empty) {
+                    return None;
+                }
+
+                let value = match lookup(defaults, &path) {
+                    Some(Value::String(_)) => Value::String(raw.clone()),
+                    _ => serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.clone())),
+                };
+
+                let value = path
+                    .into_iter()
+                    .rev()
+                    .fold(value, |value, key| Value::Object(Map::from_iter([(key, value)])));
+
+                Some((Layer::Environment(variable.clone()), value))
+            })
+            .collect::<Vec<_>>();
+
+        // Keep the order stable, as the environment has no defined order.
+        layers.sort_by_key(|(layer, _)| layer.to_string());
+        layers
+    }
+}
+
+/// A merged configuration, which remembers the layer each value came from.
+#[derive(Debug, Clone)]
+pub struct Loaded<T> {
+    value: T,
+    merged: Value,
+    origins: BTreeMap<String, Layer>,
+}
+
+impl<T> Loaded<T> {
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// The layer the value at the dotted key came from,
+    /// if the key is a table then the layer of its most recent value is used.
+    pub fn origin(&self, key: &str) -> Option<&Layer> {
+        let nested = format!("{key}.");
+
+        self.origins.get(key).or_else(|| {
+            self.origins
+                .iter()
+                .filter(|(origin, _)| origin.starts_with(&nested))
+                .map(|(_, layer)| layer)
+                .max_by_key(|layer| priority(layer))
+        })
+    }
+
+    /// Renders every value with the layer it came from.
+    pub fn show(&self) -> String {
+        let mut output = String::new();
+        let mut stack = vec![];
+        self.render(&self.merged, &mut stack, &mut output);
+        output
+    }
+
+    fn render(&self, value: &Value, path: &mut Vec<String>, output: &mut String) {
+        match value {
+            Value::Object(map) if !map.is_empty() || path.is_empty() => {
+                for (key, value) in map {
+                    path.push(key.clone());
+                    self.render(value, path, output);
+                    path.pop();
+                }
+            }
+            value => {
+                let key = path.join(".");
+                let layer = self.origins.get(&key).unwrap_or(&Layer::Default);
+                output.push_str(&format!("{key} = {value} # {layer}\n"));
+            }
+        }
+    }
+}
+
+impl<T> Deref for Loaded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+/// The directory system wide configuration is read from.
+pub fn system_directory() -> Option<PathBuf> {
+    cfg_if! {
+        if #[cfg(windows)] {
+            env::var_os("ProgramData").map(PathBuf::from)
+        } else if #[cfg(unix)] {
+            Some(PathBuf::from("/etc"))
+        } else {
+            None
+        }
+    }
+}
+
+/// The directory user configuration is read from, following the XDG base directory spec on unix.
+pub fn user_directory() -> Option<PathBuf> {
+    cfg_if! {
+        if #[cfg(windows)] {
+            env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        }
+    }
+}
+
+/// The ids of the arguments which were given on the command line, rather than from a default or the environment.
+fn given_ids(matches: &ArgMatches) -> BTreeSet<String> {
+    matches
+        .ids()
+        .map(|id| id.as_str())
+        .filter(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+        .map(str::to_owned)
+        .collect()
+}
+
+fn read_file(layer: &Layer) -> Result<Option<Value>, Error> {
+    let path: &Path = match layer {
+        Layer::System(path) | Layer::User(path) => path,
+        _ => return Ok(None),
+    };
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            return Err(Error::Read {
+                layer: layer.clone(),
+                source,
+            })
+        }
+    };
+
+    toml::from_str(&content).map(Some).map_err(|source| Error::Parse {
+        layer: layer.clone(),
+        source: Box::new(source),
+    })
+}
+
+/// Keeps only the values whose key is a given argument, and any table still containing one.
+fn retain_given(value: &mut Value, given: &BTreeSet<String>) {
+    let Value::Object(map) = value else {
+        return;
+    };
+
+    map.retain(|key, value| {
+        if given.contains(key) {
+            return true;
+        }
+
+        retain_given(value, given);
+        value.as_object().is_some_and(|map| !map.is_empty())
+    });
+}
+
+fn lookup<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| value.get(key))
+}
+
+fn priority(layer: &Layer) -> u8 {
+    match layer {
+        Layer::Default => 0,
+        Layer::System(_) => 1,
+        Layer::User(_) => 2,
+        Layer::Environment(_) => 3,
+        Layer::Cli => 4,
+    }
+}
+
+/// Merges the layer into the target, tables are merged recursively while any other value replaces the previous one.
+fn merge(
+    target: &mut Value,
+    value: &Value,
+    layer: &Layer,
+    origins: &mut BTreeMap<String, Layer>,
+    path: &mut Vec<String>,
+) {
+    match (target, value) {
+        (Value::Object(target), Value::Object(map)) => {
+            for (key, value) in map {
+                path.push(key.clone());
+                let entry = target.entry(key.clone()).or_insert(Value::Null);
+                if entry.is_object() != value.is_object() {
+                    // The shape changed, so forget where the replaced values came from.
+                    let key = path.join(".");
+                    let nested = format!("{key}.");
+                    origins.retain(|origin, _| origin != &key && !origin.starts_with(&nested));
+                    *entry = match value.is_object() {
+                        true => Value::Object(Map::new()),
+                        false => Value::Null,
+                    };
+                }
+                merge(entry, value, layer, origins, path);
+                path.pop();
+            }
+        }
+        (target, value) => {
+            *target = value.clone();
+            origins.insert(path.join("."), layer.clone());
+        }
+    }
+}
+
+/// Subcommands for inspecting the configuration of a tool.
+#[cfg(feature = "ui-cli")]
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum ConfigAction {
+    /// Show the merged configuration and where each value came from
+    Show,
+
+    /// Show the files the configuration is read from
+    Path,
+}
+
+#[cfg(feature = "ui-cli")]
+impl ConfigAction {
+    /// Runs the action against the loader, returning the text to display.
+    pub fn run<T: Layered>(&self, loader: &Loader<T>) -> Result<String, Error> {
+        match self {
+            ConfigAction::Show => Ok(loader.load()?.show()),
+            ConfigAction::Path => Ok(loader
+                .files()
+                .into_iter()
+                .map(|layer| {
+                    let state = match &layer {
+                        Layer::System(path) | Layer::User(path) if path.exists() => "found",
+                        _ => "missing",
+                    };
+                    format!("{layer} ({state})\n")
+                })
+                .collect()),
+        }
+    }
+}
@@ -14,6 +14,10 @@
  * along with this program. If not, see https://www.gnu.org/licenses/.
  */
 
+mod sanitise;
+
+pub use sanitise::{PathSanitiser, Platform};
+
 use anyhow::{Context, Result};
 use cfg_if::cfg_if;
 use std::fs;
@@ -23,7 +27,6 @@ use std::sync::LazyLock;
 cfg_if! {
     if #[cfg(windows)] {
         const PATH_SEPARATOR: char = '\\';
-        const OTHER_PATH_SEPARATOR: char = '/';
         pub static SYSTEM_DRIVE: LazyLock<PathBuf> = LazyLock::new(|| {
             let letter = std::env::var("SystemDrive")
                 .with_context(|| "Getting system drive from environment variable")
@@ -44,12 +47,25 @@ cfg_if! {
             drives
         });
     } else if #[cfg(unix)] {
-        pub const PATH_SEPARATOR: char = '/';
-        pub const OTHER_PATH_SEPARATOR: char = '\\';
+        const PATH_SEPARATOR: char = '/';
         pub static SYSTEM_DRIVE: LazyLock<PathBuf> = LazyLock::new(|| PathBuf::from(PATH_SEPARATOR.to_string()));
     }
 }
 
+/// The directory for data owned by the user, following the XDG base directory spec on unix.
+pub fn data_directory() -> Option<PathBuf> {
+    cfg_if! {
+        if #[cfg(windows)] {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            std::env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+        }
+    }
+}
+
 pub fn create_parents(path: &Path) -> Result<()> {
     path.parent()
         .with_context(|| format!("Get parent directory for {}", &path.display()))
@@ -58,43 +74,9 @@ pub fn create_parents(path: &Path) -> Result<()> {
         })
 }
 
+/// Make every name in the path valid on the current platform, see [`PathSanitiser`].
+///
+/// Relative paths are left relative.
 pub fn normalise_path(path: PathBuf) -> PathBuf {
-    let path = path.to_str().unwrap();
-
-    // - all whitespace has been trimmed.
-    // - all leading `/` has been trimmed.
-    let path = path.trim().trim_start_matches(PATH_SEPARATOR);
-
-    // Fast line for empty path.
-    if path.is_empty() {
-        return SYSTEM_DRIVE.clone();
-    }
-
-    let path = path.replace(OTHER_PATH_SEPARATOR, PATH_SEPARATOR.to_string().as_str());
-
-    let has_trailing = path.ends_with(PATH_SEPARATOR);
-
-    let mut p = path.split(PATH_SEPARATOR).filter(|v| !v.is_empty());
-
-    // Fuck you windows and your shitty filename limitations
-    // TODO -> What else do we need to replace?
-    let mut p = if cfg!(windows) {
-        let drive = p.next().expect("Get drive root");
-        let p = p
-            .map(|v| v.replace(':', "_"))
-            .collect::<Vec<String>>()
-            .join(PATH_SEPARATOR.to_string().as_str());
-
-        format!("{drive}{PATH_SEPARATOR}{p}",)
-    } else {
-        let p = p.collect::<Vec<&str>>().join(PATH_SEPARATOR.to_string().as_str());
-        format!("/{p}")
-    };
-
-    // Append trailing back if input path is ends-with `/`.
-    if has_trailing {
-        p.push(PATH_SEPARATOR);
-    }
-
-    PathBuf::from(p)
+    PathSanitiser::default().sanitise(&path)
 }
@@ -0,0 +1,388 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+/// Characters Windows doesn't allow anywhere in a file name, on top of control characters.
+const WINDOWS_FORBIDDEN: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Names Windows reserves for devices, with or without an extension, alongside `COM0`-`COM9` and `LPT0`-`LPT9`.
+const WINDOWS_RESERVED: [&str; 6] = ["CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$"];
+
+/// Paths longer than this need the `\\?\` prefix on Windows.
+const WINDOWS_MAX_PATH: usize = 260;
+
+/// The platform whose file naming rules a path should follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Windows,
+    Unix,
+}
+
+impl Platform {
+    pub const CURRENT: Self = if cfg!(windows) { Self::Windows } else { Self::Unix };
+}
+
+/// Rewrites paths so every component is a valid file name on the target [`Platform`].
+///
+/// Structure is kept as is: relative paths stay relative, `.` and `..` are left alone,
+/// and a trailing separator is kept; only the names themselves are changed.
+#[derive(Debug, Clone, Copy)]
+pub struct PathSanitiser {
+    platform: Platform,
+    replacement: char,
+    max_length: usize,
+}
+
+impl Default for PathSanitiser {
+    fn default() -> Self {
+        Self::new(Platform::CURRENT)
+    }
+}
+
+impl PathSanitiser {
+    pub const fn new(platform: Platform) -> Self {
+        Self {
+            platform,
+            replacement: '_',
+            max_length: 255,
+        }
+    }
+
+    /// The character used in place of anything which isn't allowed, `_` by default.
+    pub const fn with_replacement(mut self, replacement: char) -> Self {
+        self.replacement = replacement;
+        self
+    }
+
+    /// The longest a single name may be, in bytes on unix and UTF-16 units on Windows.
+    pub const fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    pub fn sanitise(&self, path: &Path) -> PathBuf {
+        match self.platform {
+            Platform::Windows => PathBuf::from(self.windows_path(path.as_os_str())),
+            Platform::Unix => PathBuf::from(from_bytes(self.unix_path(path.as_os_str().as_encoded_bytes()))),
+        }
+    }
+
+    /// Sanitise a single file name, separators included.
+    pub fn sanitise_name(&self, name: &OsStr) -> OsString {
+        match self.platform {
+            Platform::Windows => self.windows_name(name),
+            Platform::Unix => from_bytes(self.unix_name(name.as_encoded_bytes())),
+        }
+    }
+
+    fn windows_path(&self, path: &OsStr) -> OsString {
+        let verbatim = path.as_encoded_bytes().starts_with(br"\\?\");
+        let path = match verbatim {
+            true => path.slice_encoded_bytes(4..),
+            false => path,
+        };
+
+        let bytes = path.as_encoded_bytes();
+        let drive = match bytes {
+            [letter, b':', ..] if letter.is_ascii_alphabetic() => 2,
+            _ => 0,
+        };
+
+        let separators = bytes[drive..].iter().take_while(|byte| is_windows_separator(**byte)).count();
+        let root = match separators {
+            0 => "",
+            1 => "\\",
+            _ if drive == 0 && !verbatim => r"\\",
+            _ => "\\",
+        };
+
+        let names = windows_names(path.slice_encoded_bytes(drive + separators..));
+        let relative = names.iter().any(|name| matches!(name.as_encoded_bytes(), b"." | b".."));
+
+        let mut sanitised = path.slice_encoded_bytes(..drive).to_owned();
+        sanitised.push(root);
+        for (index, name) in names.iter().enumerate() {
+            if index > 0 {
+                sanitised.push("\\");
+            }
+
+            match name.as_encoded_bytes() {
+                b"." | b".." => sanitised.push(name),
+                _ => sanitised.push(self.windows_name(name)),
+            }
+        }
+
+        if !names.is_empty() && bytes.last().is_some_and(|byte| is_windows_separator(*byte)) {
+            sanitised.push("\\");
+        }
+
+        let long = drive > 0 && !root.is_empty() && !relative && sanitised.len() >= WINDOWS_MAX_PATH;
+        match verbatim || long {
+            true => {
+                let mut prefixed = OsString::from(r"\\?\");
+                prefixed.push(sanitised);
+                prefixed
+            }
+            false => sanitised,
+        }
+    }
+
+    /// Anything which isn't valid UTF-8 can't be one of the forbidden characters,
+    /// so it's kept exactly as it was given.
+    fn windows_name(&self, name: &OsStr) -> OsString {
+        let pieces = pieces(name)
+            .into_iter()
+            .map(|piece| match piece {
+                Piece::Char(char) if char.is_control() || WINDOWS_FORBIDDEN.contains(&char) => {
+                    Piece::Char(self.replacement)
+                }
+                piece => piece,
+            })
+            .collect::<Vec<_>>();
+
+        let mut pieces = truncate(pieces, self.max_length);
+
+        // Windows silently drops these, so `name.` and `name` would be the same file.
+        while matches!(pieces.last(), Some(Piece::Char('.' | ' '))) {
+            pieces.pop();
+        }
+
+        if pieces.is_empty() {
+            pieces.push(Piece::Char(self.replacement));
+        }
+
+        let text = pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Char(char) => Some(*char),
+                Piece::Raw(_) => None,
+            })
+            .collect::<Option<String>>();
+
+        if let Some(text) = text
+            && is_reserved(&text)
+        {
+            let stem = pieces
+                .iter()
+                .position(|piece| matches!(piece, Piece::Char('.')))
+                .unwrap_or(pieces.len());
+            pieces.insert(stem, Piece::Char(self.replacement));
+        }
+
+        let mut sanitised = OsString::with_capacity(name.len());
+        for piece in pieces {
+            match piece {
+                Piece::Char(char) => sanitised.push(char.encode_utf8(&mut [0; 4])),
+                Piece::Raw(raw) => sanitised.push(raw),
+            }
+        }
+
+        sanitised
+    }
+
+    fn unix_path(&self, path: &[u8]) -> Vec<u8> {
+        let mut sanitised = Vec::with_capacity(path.len());
+        if path.starts_with(b"/") {
+            sanitised.push(b'/');
+        }
+
+        let names = path
+            .split(|byte| *byte == b'/')
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>();
+        for (index, name) in names.iter().enumerate() {
+            if index > 0 {
+                sanitised.push(b'/');
+            }
+
+            match *name {
+                b"." | b".." => sanitised.extend_from_slice(name),
+                name => sanitised.extend(self.unix_name(name)),
+            }
+        }
+
+        if !names.is_empty() && path.ends_with(b"/") {
+            sanitised.push(b'/');
+        }
+
+        sanitised
+    }
+
+    fn unix_name(&self, name: &[u8]) -> Vec<u8> {
+        let mut replacement = [0; 4];
+        let replacement = self.replacement.encode_utf8(&mut replacement).as_bytes();
+
+        let mut sanitised = Vec::with_capacity(name.len());
+        for byte in name {
+            match byte {
+                b'\0' | b'/' => sanitised.extend_from_slice(replacement),
+                byte => sanitised.push(*byte),
+            }
+        }
+
+        if sanitised.is_empty() {
+            sanitised.extend_from_slice(replacement);
+        }
+
+        truncate_bytes(sanitised, self.max_length)
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    let stem = name
+        .split('.')
+        .next()
+        .unwrap_or(name)
+        .trim_end_matches(' ')
+        .to_ascii_uppercase();
+
+    WINDOWS_RESERVED.contains(&stem.as_str())
+        || matches!(
+            stem.as_bytes(),
+            [b'C', b'O', b'M', b'0'..=b'9'] | [b'L', b'P', b'T', b'0'..=b'9']
+        )
+}
+
+/// Part of a name, either a single character or a run of bytes which aren't valid UTF-8.
+#[derive(Debug, Clone, Copy)]
+enum Piece<'a> {
+    Char(char),
+    Raw(&'a OsStr),
+}
+
+impl Piece<'_> {
+    /// The length in UTF-16 units, raw bytes are each counted as a whole unit so they are never undercounted.
+    fn width(&self) -> usize {
+        match self {
+            Piece::Char(char) => char.len_utf16(),
+            Piece::Raw(raw) => raw.len(),
+        }
+    }
+}
+
+fn pieces(name: &OsStr) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::with_capacity(name.len());
+    let mut raw = None;
+    let mut offset = 0;
+    for chunk in name.as_encoded_bytes().utf8_chunks() {
+        if !chunk.valid().is_empty() {
+            if let Some(start) = raw.take() {
+                pieces.push(Piece::Raw(name.slice_encoded_bytes(start..offset)));
+            }
+
+            pieces.extend(chunk.valid().chars().map(Piece::Char));
+            offset += chunk.valid().len();
+        }
+
+        if !chunk.invalid().is_empty() {
+            raw.get_or_insert(offset);
+            offset += chunk.invalid().len();
+        }
+    }
+
+    if let Some(start) = raw {
+        pieces.push(Piece::Raw(name.slice_encoded_bytes(start..offset)));
+    }
+
+    pieces
+}
+
+/// Split a Windows path on either separator, skipping empty names.
+fn windows_names(path: &OsStr) -> Vec<&OsStr> {
+    let mut names = Vec::new();
+    let mut start = 0;
+    for (index, byte) in path.as_encoded_bytes().iter().enumerate() {
+        if is_windows_separator(*byte) {
+            if index > start {
+                names.push(path.slice_encoded_bytes(start..index));
+            }
+
+            start = index + 1;
+        }
+    }
+
+    if start < path.len() {
+        names.push(path.slice_encoded_bytes(start..));
+    }
+
+    names
+}
+
+const fn is_windows_separator(byte: u8) -> bool {
+    matches!(byte, b'/' | b'\\')
+}
+
+/// Shorten `name` to `max` UTF-16 units, keeping the extension where it fits.
+fn truncate(name: Vec<Piece<'_>>, max: usize) -> Vec<Piece<'_>> {
+    let width = |pieces: &[Piece]| pieces.iter().map(Piece::width).sum::<usize>();
+    if width(&name) <= max {
+        return name;
+    }
+
+    let extension = match name.iter().rposition(|piece| matches!(piece, Piece::Char('.'))) {
+        Some(index) if index > 0 && width(&name[index..]) < max / 2 => index,
+        _ => name.len(),
+    };
+
+    let (stem, extension) = name.split_at(extension);
+    let mut budget = max - width(extension);
+    let mut truncated = Vec::with_capacity(max);
+    for piece in stem {
+        if piece.width() > budget {
+            break;
+        }
+
+        budget -= piece.width();
+        truncated.push(*piece);
+    }
+
+    truncated.extend_from_slice(extension);
+    truncated
+}
+
+/// Shorten `name` to `max` bytes without splitting a UTF-8 sequence, keeping the extension where it fits.
+fn truncate_bytes(name: Vec<u8>, max: usize) -> Vec<u8> {
+    if name.len() <= max {
+        return name;
+    }
+
+    let extension = match name.iter().rposition(|byte| *byte == b'.') {
+        Some(index) if index > 0 && name.len() - index < max / 2 => index,
+        _ => name.len(),
+    };
+
+    let mut end = max - (name.len() - extension);
+    while end > 0 && name[end] & 0b1100_0000 == 0b1000_0000 {
+        end -= 1;
+    }
+
+    let mut truncated = name[..end].to_vec();
+    truncated.extend_from_slice(&name[extension..]);
+    truncated
+}
+
+#[cfg(unix)]
+fn from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+fn from_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
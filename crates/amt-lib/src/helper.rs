@@ -14,25 +14,18 @@
  * along with this program. If not, see https://www.gnu.org/licenses/.
  */
 
-use cfg_if::cfg_if;
+use crate::privilege::privileges;
 use sysexits::ExitCode;
 use tracing::error;
 
 const ERROR_MESSAGE: &str = "Failed to elevate privileges";
 
+/// If the effective user is root, or the process has an elevated token on Windows.
 pub fn elevated_privileges() -> bool {
-    cfg_if! {
-        if #[cfg(windows)] {
-            is_elevated::is_elevated()
-        } else if #[cfg(unix)] {
-            rustix::process::getegid().is_root()
-        } else {
-            warn!("Unsupported platform, assuming not elevated");
-            false
-        }
-    }
+    privileges().elevated()
 }
 
+#[deprecated(note = "describe the operations needed with `amt_lib::privilege::escalate` instead")]
 pub fn require_elevated_privileges() -> Option<ExitCode> {
     let code = elevated_privileges();
 
@@ -25,6 +25,7 @@
 #![feature(impl_trait_in_assoc_type)]
 #![feature(let_chains)]
 #![feature(negative_impls)]
+#![feature(os_str_slice)]
 #![feature(result_flattening)]
 #![feature(stmt_expr_attributes)]
 #![feature(trait_alias)]
@@ -37,12 +38,18 @@
 #![allow(incomplete_features)]
 #![allow(internal_features)]
 
+#[cfg(feature = "config")]
+pub mod config;
 pub mod fs;
 pub mod helper;
 pub mod log;
 pub mod macros;
 pub mod named;
 pub mod pathed;
+pub mod privilege;
+pub mod progress;
+#[cfg(feature = "secret")]
+pub mod secret;
 #[cfg(feature = "ui-cli")]
 pub mod ui;
 #[cfg(feature = "updater")]
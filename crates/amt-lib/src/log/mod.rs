@@ -0,0 +1,306 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+pub mod rolling;
+#[cfg(unix)]
+pub mod syslog;
+
+use crate::ui::cli::flags::CommonFlags;
+use clap::{Args, ValueEnum};
+use std::env;
+use std::path::PathBuf;
+use tracing::{subscriber, Level, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::RollingFileAppender;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{Layer, Registry};
+
+/// How often the log file is rotated.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Rotation {
+    #[default]
+    Daily,
+    Hourly,
+    /// Rotate once the file reaches `--log-max-size`.
+    Size,
+    Never,
+}
+
+/// The format each line of the log file is written in.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Plain,
+    /// A JSON object per line.
+    Json,
+}
+
+/// Flags controlling where and how log files are written,
+/// each can also be set through its environment variable.
+#[derive(Debug, Clone, Args)]
+pub struct LogFlags<const HIDE: bool = false> {
+    /// The directory log files are written to, defaults to `logs` in the temp directory
+    #[arg(long, hide = HIDE, global = true, env = "AMT_LOG_DIR")]
+    pub log_dir: Option<PathBuf>,
+
+    /// How often the log file is rotated
+    #[arg(long, hide = HIDE, global = true, env = "AMT_LOG_ROTATION", value_enum, default_value_t)]
+    pub log_rotation: Rotation,
+
+    /// The size in bytes a log file may grow to when rotating by size
+    #[arg(long, hide = HIDE, global = true, env = "AMT_LOG_MAX_SIZE", default_value_t = LogFlags::<HIDE>::MAX_SIZE)]
+    pub log_max_size: u64,
+
+    /// How many old log files are kept alongside the current one
+    #[arg(long, hide = HIDE, global = true, env = "AMT_LOG_RETENTION", default_value_t = LogFlags::<HIDE>::RETENTION)]
+    pub log_retention: usize,
+
+    /// The format of the log files
+    #[arg(long, hide = HIDE, global = true, env = "AMT_LOG_FORMAT", value_enum, default_value_t)]
+    pub log_format: LogFormat,
+
+    /// Also send logs to the system logger, which journald reads as well
+    #[arg(long, hide = HIDE, global = true, env = "AMT_LOG_SYSLOG", action = clap::ArgAction::SetTrue)]
+    pub syslog: bool,
+}
+
+impl<const HIDE: bool> LogFlags<HIDE> {
+    pub const MAX_SIZE: u64 = 10 * 1024 * 1024;
+    pub const RETENTION: usize = 7;
+
+    /// The directory log files are written to.
+    pub fn directory(&self) -> PathBuf {
+        self.log_dir.clone().unwrap_or_else(|| env::temp_dir().join("logs"))
+    }
+}
+
+impl<const HIDE: bool> Default for LogFlags<HIDE> {
+    fn default() -> Self {
+        Self {
+            log_dir: None,
+            log_rotation: Rotation::default(),
+            log_max_size: Self::MAX_SIZE,
+            log_retention: Self::RETENTION,
+            log_format: LogFormat::default(),
+            syslog: false,
+        }
+    }
+}
+
+#[allow(dead_code)]
+fn level_and_span(flags: &CommonFlags) -> (Level, FmtSpan) {
+    match flags.verbose {
+        0 => (Level::INFO, FmtSpan::NONE),
+        1 => (Level::DEBUG, FmtSpan::NONE),
+        2 => (Level::TRACE, FmtSpan::NONE),
+        3 => (Level::TRACE, FmtSpan::ACTIVE),
+        _ => (Level::TRACE, FmtSpan::FULL),
+    }
+}
+
+#[inline]
+fn add_file_writer<S>(
+    registry: S,
+    named: &str,
+    flags: &LogFlags,
+) -> (
+    impl Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static,
+    WorkerGuard,
+)
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static,
+{
+    use tracing_appender::rolling::Rotation as Rolling;
+
+    let directory = flags.directory();
+    let rolling = |rotation: Rolling| -> anyhow::Result<_> {
+        // The appender prunes old files before creating the directory itself.
+        std::fs::create_dir_all(&directory)?;
+
+        let appender = RollingFileAppender::builder()
+            .rotation(rotation)
+            .filename_prefix(named)
+            .filename_suffix("log")
+            .max_log_files(flags.log_retention + 1)
+            .build(&directory)?;
+
+        Ok(tracing_appender::non_blocking(appender))
+    };
+
+    let writer = match flags.log_rotation {
+        Rotation::Daily => rolling(Rolling::DAILY),
+        Rotation::Hourly => rolling(Rolling::HOURLY),
+        Rotation::Never => rolling(Rolling::NEVER),
+        Rotation::Size => rolling::SizeRollingWriter::new(&directory, named, flags.log_max_size, flags.log_retention)
+            .map(tracing_appender::non_blocking)
+            .map_err(anyhow::Error::from),
+    };
+
+    // Logging is never worth failing the program for, so fall back to not writing a file.
+    let (non_blocking, _guard) = writer.unwrap_or_else(|err| {
+        eprintln!("Unable to write logs to {}: {err}", directory.display());
+        tracing_appender::non_blocking(std::io::sink())
+    });
+
+    let layer = tracing_subscriber::fmt::layer().with_writer(non_blocking).with_ansi(false);
+    let layer = match flags.log_format {
+        LogFormat::Plain => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    };
+
+    (registry.with(layer), _guard)
+}
+
+#[cfg(unix)]
+fn add_syslog_writer<S>(
+    registry: S,
+    named: &str,
+    flags: &CommonFlags,
+) -> impl Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static,
+{
+    use tracing_subscriber::filter::LevelFilter;
+
+    let (level, _) = level_and_span(flags);
+    let layer = flags
+        .log
+        .syslog
+        .then(|| match syslog::Syslog::connect(syslog::Syslog::SOCKET, named) {
+            Ok(writer) => Some(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(writer)
+                    .with_ansi(false)
+                    .without_time()
+                    .with_level(false)
+                    .with_target(false)
+                    .with_filter(LevelFilter::from_level(level)),
+            ),
+            Err(err) => {
+                eprintln!("Unable to connect to the system logger: {err}");
+                None
+            }
+        });
+
+    registry.with(layer.flatten())
+}
+
+#[cfg(not(unix))]
+fn add_syslog_writer<S>(
+    registry: S,
+    _named: &str,
+    flags: &CommonFlags,
+) -> impl Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static,
+{
+    if flags.log.syslog {
+        eprintln!("Logging to the system logger is only supported on unix.");
+    }
+
+    registry
+}
+
+#[cfg(feature = "ui-cli")]
+fn add_ui_layer<S>(
+    registry: S,
+    flags: &CommonFlags,
+) -> impl Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static,
+{
+    use indicatif::ProgressStyle;
+    use tracing_indicatif::{filter::IndicatifFilter, IndicatifLayer};
+    use tracing_subscriber::{fmt::writer::MakeWriterExt, Layer};
+
+    let (level, span) = level_and_span(flags);
+    let layer = IndicatifLayer::new().with_progress_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} {span_child_prefix}{span_name:.cyan/blue}{{{span_fields:.purple}}}",
+        )
+        .unwrap(),
+    );
+
+    let verbosity = if flags.quiet { 0 } else { flags.verbose };
+    let quiet = flags.quiet;
+
+    // When the output is machine-readable stdout is reserved for the result,
+    // so every log line has to be written to stderr instead.
+    #[cfg(feature = "ui-cli-formatting")]
+    let to_stdout = matches!(flags.format, crate::ui::cli::flags::OutputFormat::Human);
+    #[cfg(not(feature = "ui-cli-formatting"))]
+    let to_stdout = true;
+
+    registry
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(
+                    layer
+                        .get_stderr_writer()
+                        .with_filter(move |meta| match quiet {
+                            true => meta.level() != &Level::WARN,
+                            false => true,
+                        } && meta.level() <= &level)
+                        .with_max_level(Level::WARN)
+                        .or_else(
+                            layer
+                                .get_stdout_writer()
+                                .with_filter(move |_| to_stdout)
+                                .with_max_level(level)
+                                .or_else(layer.get_stderr_writer().with_max_level(level)),
+                        ),
+                )
+                .without_time()
+                .with_ansi(!cfg!(windows))
+                .with_span_events(span)
+                .with_target(verbosity > 0)
+                .with_line_number(verbosity > 1)
+                .with_thread_names(verbosity > 2)
+                .with_thread_ids(verbosity > 2),
+        )
+        .with(layer.with_filter(IndicatifFilter::new(!flags.quiet)))
+}
+
+#[cfg(not(feature = "ui-cli"))]
+fn add_ui_layer<S>(
+    registry: S,
+    _flags: &CommonFlags,
+) -> impl Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static,
+{
+    registry
+}
+
+/// Initialises the global subscriber,
+/// `named` is used for the log file names and as the system logger identifier.
+#[inline]
+pub fn init(named: &str, flags: &CommonFlags) -> WorkerGuard {
+    let named = match named.trim() {
+        "" => "amt",
+        named => named,
+    };
+
+    let registry = Registry::default();
+    let registry = add_ui_layer(registry, flags);
+    let registry = add_syslog_writer(registry, named, flags);
+    let (registry, _guard) = add_file_writer(registry, named, &flags.log);
+
+    subscriber::set_global_default(registry).expect("Failed to set global default subscriber");
+    _guard
+}
@@ -0,0 +1,102 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A log writer which rotates its file once it reaches a maximum size.
+///
+/// The current file is always `{name}.log`, rotated files are renamed to `{name}.1.log`, `{name}.2.log`, etc.
+/// with the highest number being the oldest, anything beyond the retention count is removed.
+#[derive(Debug)]
+pub struct SizeRollingWriter {
+    directory: PathBuf,
+    name: String,
+    max_size: u64,
+    retention: usize,
+    file: File,
+    size: u64,
+}
+
+impl SizeRollingWriter {
+    pub fn new(directory: &Path, name: &str, max_size: u64, retention: usize) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+
+        let path = directory.join(format!("{name}.log"));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            name: name.to_owned(),
+            max_size,
+            retention,
+            file,
+            size,
+        })
+    }
+
+    fn path(&self, index: usize) -> PathBuf {
+        match index {
+            0 => self.directory.join(format!("{}.log", self.name)),
+            index => self.directory.join(format!("{}.{index}.log", self.name)),
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.retention == 0 {
+            self.file = File::create(self.path(0))?;
+            self.size = 0;
+            return Ok(());
+        }
+
+        let oldest = self.path(self.retention);
+        if oldest.exists() {
+            fs::remove_file(oldest)?;
+        }
+
+        for index in (0..self.retention).rev() {
+            let from = self.path(index);
+            if from.exists() {
+                fs::rename(&from, self.path(index + 1))?;
+            }
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(self.path(0))?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRollingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Rotate before the write so that a single line is never split across files.
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
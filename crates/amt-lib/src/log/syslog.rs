@@ -0,0 +1,118 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+//! A writer for the local system logger, which is also read by journald.
+//!
+//! Each event is sent as a single RFC 3164 style datagram to the `/dev/log` socket,
+//! with the priority derived from the level of the event.
+
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use tracing::{Level, Metadata};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// The `user` facility, used for all messages.
+const FACILITY: u8 = 1;
+
+#[derive(Debug)]
+pub struct Syslog {
+    socket: UnixDatagram,
+    identifier: String,
+}
+
+impl Syslog {
+    pub const SOCKET: &'static str = "/dev/log";
+
+    /// Connects to the system logger at the socket path.
+    pub fn connect(path: impl AsRef<Path>, identifier: &str) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+
+        Ok(Self {
+            socket,
+            identifier: identifier.to_owned(),
+        })
+    }
+
+    /// Formats a message with its priority header.
+    pub fn format(&self, level: &Level, message: &str) -> String {
+        let severity = match *level {
+            Level::ERROR => 3,
+            Level::WARN => 4,
+            Level::INFO => 6,
+            Level::DEBUG | Level::TRACE => 7,
+        };
+
+        format!(
+            "<{}>{}[{}]: {}",
+            FACILITY * 8 + severity,
+            self.identifier,
+            std::process::id(),
+            message.trim_end()
+        )
+    }
+}
+
+/// Buffers a single event and sends it when dropped.
+pub struct SyslogMessage<'a> {
+    syslog: &'a Syslog,
+    level: Level,
+    buffer: Vec<u8>,
+}
+
+impl Write for SyslogMessage<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SyslogMessage<'_> {
+    fn drop(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let message = self.syslog.format(&self.level, &String::from_utf8_lossy(&self.buffer));
+        // There is nowhere left to report a failure to log to.
+        let _ = self.syslog.socket.send(message.as_bytes());
+    }
+}
+
+impl<'a> MakeWriter<'a> for Syslog {
+    type Writer = SyslogMessage<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        SyslogMessage {
+            syslog: self,
+            level: Level::INFO,
+            buffer: vec![],
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        SyslogMessage {
+            syslog: self,
+            level: *meta.level(),
+            buffer: vec![],
+        }
+    }
+}
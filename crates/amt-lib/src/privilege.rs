@@ -0,0 +1,302 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+//! Detection of the privileges the process is running with.
+//!
+//! Rather than refusing to run without root, tools describe the [`Operation`]s they
+//! want to perform and ask [`escalate`] to either confirm they are allowed, re-run
+//! the process through `sudo` or `pkexec` when a user is there to authenticate,
+//! or report what is missing so the tool can continue with reduced functionality.
+
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::sync::OnceLock;
+use std::{env, io};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+/// Set on the re-executed child so it never tries to elevate itself a second time.
+pub const ELEVATED_ENV: &str = "AMT_ELEVATED";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Unable to locate the current executable")]
+    Executable(#[source] io::Error),
+
+    #[error("Unable to re-run through {0}")]
+    Spawn(Elevator, #[source] io::Error),
+}
+
+/// The Linux capabilities the tools care about, numbered as in `linux/capability.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Capability {
+    Chown = 0,
+    DacOverride = 1,
+    DacReadSearch = 2,
+    Fowner = 3,
+    Kill = 5,
+    NetAdmin = 12,
+    SysAdmin = 21,
+    SysBoot = 22,
+}
+
+impl Capability {
+    pub const fn mask(self) -> u64 {
+        1 << self as u8
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Capability::Chown => "CAP_CHOWN",
+            Capability::DacOverride => "CAP_DAC_OVERRIDE",
+            Capability::DacReadSearch => "CAP_DAC_READ_SEARCH",
+            Capability::Fowner => "CAP_FOWNER",
+            Capability::Kill => "CAP_KILL",
+            Capability::NetAdmin => "CAP_NET_ADMIN",
+            Capability::SysAdmin => "CAP_SYS_ADMIN",
+            Capability::SysBoot => "CAP_SYS_BOOT",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// Something a tool may want to do which isn't available to every user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// Reading and removing files which belong to other users.
+    OtherUsersFiles,
+
+    /// Modifying system wide locations such as `/var` or `C:\Windows`.
+    SystemFiles,
+
+    /// Rebooting the machine or scheduling it to reboot.
+    Reboot,
+}
+
+impl Operation {
+    /// The capabilities which, held together, allow the operation without being root.
+    pub const fn capabilities(self) -> &'static [Capability] {
+        match self {
+            Operation::OtherUsersFiles => &[Capability::DacOverride, Capability::Fowner],
+            Operation::SystemFiles => &[Capability::DacOverride],
+            Operation::Reboot => &[Capability::SysBoot],
+        }
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            Operation::OtherUsersFiles => "access files belonging to other users",
+            Operation::SystemFiles => "modify system locations",
+            Operation::Reboot => "reboot the machine",
+        };
+
+        write!(f, "{description}")
+    }
+}
+
+/// The privileges held by a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Privileges {
+    elevated: bool,
+    capabilities: Option<u64>,
+}
+
+impl Privileges {
+    /// Create from known values, `capabilities` being the effective set where the platform has one.
+    pub const fn new(elevated: bool, capabilities: Option<u64>) -> Self {
+        Self { elevated, capabilities }
+    }
+
+    /// Inspect the current process.
+    ///
+    /// On unix this is the effective user, not the group, being root;
+    /// on Linux the effective capability set is also read from `/proc/self/status`.
+    pub fn detect() -> Self {
+        cfg_if::cfg_if! {
+            if #[cfg(windows)] {
+                Self::new(is_elevated::is_elevated(), None)
+            } else if #[cfg(unix)] {
+                let elevated = rustix::process::geteuid().is_root();
+                let capabilities = std::fs::read_to_string("/proc/self/status")
+                    .ok()
+                    .and_then(|status| parse_capabilities(&status));
+
+                Self::new(elevated, capabilities)
+            } else {
+                warn!("Unsupported platform, assuming not elevated");
+                Self::new(false, None)
+            }
+        }
+    }
+
+    /// If the process is running as root or with an elevated token.
+    pub const fn elevated(&self) -> bool {
+        self.elevated
+    }
+
+    /// If the process holds the capability.
+    ///
+    /// Where capabilities aren't known this falls back to being elevated,
+    /// where they are a root user without the capability (e.g. in a container) doesn't have it.
+    pub const fn has(&self, capability: Capability) -> bool {
+        match self.capabilities {
+            Some(mask) => mask & capability.mask() != 0,
+            None => self.elevated,
+        }
+    }
+
+    pub fn allows(&self, operation: Operation) -> bool {
+        operation.capabilities().iter().all(|capability| self.has(*capability))
+    }
+
+    /// The operations out of those given which the process can't perform.
+    pub fn missing(&self, operations: &[Operation]) -> Vec<Operation> {
+        operations
+            .iter()
+            .copied()
+            .filter(|operation| !self.allows(*operation))
+            .collect()
+    }
+}
+
+/// The privileges of the current process, detected once.
+pub fn privileges() -> &'static Privileges {
+    static PRIVILEGES: OnceLock<Privileges> = OnceLock::new();
+    PRIVILEGES.get_or_init(Privileges::detect)
+}
+
+/// Read the effective capability set from the contents of `/proc/<pid>/status`.
+pub fn parse_capabilities(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|mask| u64::from_str_radix(mask.trim(), 16).ok())
+}
+
+/// A program able to re-run the process with elevated privileges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Elevator {
+    /// Prompts for a password on the terminal.
+    Sudo,
+
+    /// Prompts through the desktop's polkit agent.
+    Pkexec,
+}
+
+impl Elevator {
+    /// Find an elevator usable by whoever is running the process.
+    ///
+    /// There is none when not interactive, when already re-executed, or on Windows
+    /// where elevation has to be requested through UAC when the process is started.
+    pub fn find() -> Option<Self> {
+        if cfg!(not(unix)) || env::var_os(ELEVATED_ENV).is_some() {
+            return None;
+        }
+
+        if io::stdin().is_terminal() && find_program("sudo").is_some() {
+            return Some(Elevator::Sudo);
+        }
+
+        let graphical = env::var_os("DISPLAY").is_some() || env::var_os("WAYLAND_DISPLAY").is_some();
+        if graphical && find_program("pkexec").is_some() {
+            return Some(Elevator::Pkexec);
+        }
+
+        None
+    }
+
+    pub const fn program(&self) -> &'static str {
+        match self {
+            Elevator::Sudo => "sudo",
+            Elevator::Pkexec => "pkexec",
+        }
+    }
+
+    /// Build the command re-running `executable` with `args`.
+    ///
+    /// The program is run through `env` as neither elevator keeps the caller's environment.
+    pub fn command(&self, executable: &Path, args: impl IntoIterator<Item = OsString>) -> Command {
+        let mut command = Command::new(self.program());
+        command.arg("env").arg(format!("{ELEVATED_ENV}=1")).arg(executable).args(args);
+        command
+    }
+
+    /// Re-run the current process with the same arguments and wait for it to finish.
+    pub fn reexec(&self) -> Result<ExitStatus, Error> {
+        let executable = env::current_exe().map_err(Error::Executable)?;
+        let mut command = self.command(&executable, env::args_os().skip(1));
+
+        debug!("Re-running through {self}: {command:?}");
+        command.status().map_err(|err| Error::Spawn(*self, err))
+    }
+}
+
+impl Display for Elevator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.program())
+    }
+}
+
+fn find_program(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// The outcome of [`escalate`].
+#[derive(Debug)]
+pub enum Escalation {
+    /// The process can already perform every operation.
+    Granted,
+
+    /// The process was re-run with elevated privileges and has finished,
+    /// the caller should exit with the same status.
+    Reexecuted(ExitStatus),
+
+    /// The operations which can't be performed, the caller should continue without them.
+    Degraded(Vec<Operation>),
+}
+
+/// Make sure the process can perform `operations`.
+///
+/// When some are missing and `reexec` is allowed, the process is re-run through an
+/// [`Elevator`] if one is usable; otherwise the missing operations are logged and returned.
+pub fn escalate(operations: &[Operation], reexec: bool) -> Result<Escalation, Error> {
+    let missing = privileges().missing(operations);
+    if missing.is_empty() {
+        return Ok(Escalation::Granted);
+    }
+
+    if reexec && let Some(elevator) = Elevator::find() {
+        info!("Elevated privileges are required, re-running through {elevator}");
+        return elevator.reexec().map(Escalation::Reexecuted);
+    }
+
+    for operation in &missing {
+        warn!("Without elevated privileges this won't be able to {operation}");
+    }
+
+    Ok(Escalation::Degraded(missing))
+}
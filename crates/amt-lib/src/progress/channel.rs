@@ -0,0 +1,124 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use crate::progress::{Kind, Progress};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{mpsc, Arc};
+
+/// A change to one of the tasks reported through a [`ChannelProgress`].
+///
+/// Every task has an id unique within the channel, the first being `0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Started {
+        id: usize,
+        parent: Option<usize>,
+        kind: Kind,
+    },
+    Message {
+        id: usize,
+        message: String,
+    },
+    Length {
+        id: usize,
+        length: u64,
+    },
+    Position {
+        id: usize,
+        position: u64,
+    },
+    Finished {
+        id: usize,
+    },
+}
+
+/// Sends every change as an [`Event`], for front-ends which draw progress themselves.
+///
+/// Events for a task whose receiver has been dropped are discarded.
+#[derive(Debug)]
+pub struct ChannelProgress {
+    id: usize,
+    ids: Arc<AtomicUsize>,
+    position: AtomicU64,
+    sender: Sender<Event>,
+}
+
+/// Create a reporter for a task of `kind` and the receiver of its events.
+pub fn channel(kind: Kind) -> (ChannelProgress, Receiver<Event>) {
+    let (sender, receiver) = mpsc::channel();
+    let progress = ChannelProgress::start(None, kind, Arc::new(AtomicUsize::new(0)), sender);
+
+    (progress, receiver)
+}
+
+impl ChannelProgress {
+    fn start(parent: Option<usize>, kind: Kind, ids: Arc<AtomicUsize>, sender: Sender<Event>) -> Self {
+        let id = ids.fetch_add(1, Ordering::Relaxed);
+        let _ = sender.send(Event::Started { id, parent, kind });
+
+        Self {
+            id,
+            ids,
+            position: AtomicU64::new(0),
+            sender,
+        }
+    }
+
+    pub const fn id(&self) -> usize {
+        self.id
+    }
+
+    fn send(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+}
+
+impl Progress for ChannelProgress {
+    fn set_message(&self, message: &str) {
+        self.send(Event::Message {
+            id: self.id,
+            message: message.to_owned(),
+        });
+    }
+
+    fn set_length(&self, length: u64) {
+        self.send(Event::Length { id: self.id, length });
+    }
+
+    fn set_position(&self, position: u64) {
+        self.position.store(position, Ordering::Relaxed);
+        self.send(Event::Position { id: self.id, position });
+    }
+
+    fn inc(&self, delta: u64) {
+        let position = self.position.fetch_add(delta, Ordering::Relaxed) + delta;
+        self.send(Event::Position { id: self.id, position });
+    }
+
+    fn finish(&self) {
+        self.send(Event::Finished { id: self.id });
+    }
+
+    fn child(&self, kind: Kind) -> Box<dyn Progress> {
+        Box::new(ChannelProgress::start(
+            Some(self.id),
+            kind,
+            self.ids.clone(),
+            self.sender.clone(),
+        ))
+    }
+}
@@ -0,0 +1,122 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+//! Reporting the progress of long-running tasks without tying them to a terminal.
+//!
+//! Library code takes a `&dyn Progress` and the front-end decides how it is shown:
+//! as indicatif bars on a terminal, as log lines for unattended runs with [`Logged`],
+//! not at all with [`Hidden`], or as [`Event`]s sent to a GUI through [`channel`].
+
+mod channel;
+
+pub use channel::{channel, ChannelProgress, Event};
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{debug, info};
+
+/// How the progress of a task is measured, which decides how it is displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Only a message, the task has no known length.
+    Spinner,
+
+    /// A message alongside a count of the items done so far.
+    Counter,
+
+    /// Items done out of a known length.
+    Bar,
+
+    /// Bytes transferred out of a known length.
+    Download,
+}
+
+pub trait Progress: Debug + Send + Sync {
+    fn set_message(&self, message: &str);
+
+    fn set_length(&self, length: u64);
+
+    fn set_position(&self, position: u64);
+
+    fn inc(&self, delta: u64);
+
+    /// Mark the task as done, removing it from display.
+    fn finish(&self);
+
+    /// Start reporting a sub-task, displayed beneath this one.
+    fn child(&self, kind: Kind) -> Box<dyn Progress>;
+}
+
+/// Reports nothing.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Hidden;
+
+impl Progress for Hidden {
+    fn set_message(&self, _message: &str) {}
+
+    fn set_length(&self, _length: u64) {}
+
+    fn set_position(&self, _position: u64) {}
+
+    fn inc(&self, _delta: u64) {}
+
+    fn finish(&self) {}
+
+    fn child(&self, _kind: Kind) -> Box<dyn Progress> {
+        Box::new(Hidden)
+    }
+}
+
+/// Reports messages through the logger, for runs without anyone watching such as from cron.
+///
+/// Counts aren't logged as they change but are included with each message.
+#[derive(Debug, Default)]
+pub struct Logged {
+    length: AtomicU64,
+    position: AtomicU64,
+}
+
+impl Progress for Logged {
+    fn set_message(&self, message: &str) {
+        match (
+            self.position.load(Ordering::Relaxed),
+            self.length.load(Ordering::Relaxed),
+        ) {
+            (_, 0) => info!("{message}"),
+            (position, length) => info!("{message} ({position}/{length})"),
+        }
+    }
+
+    fn set_length(&self, length: u64) {
+        self.length.store(length, Ordering::Relaxed);
+    }
+
+    fn set_position(&self, position: u64) {
+        self.position.store(position, Ordering::Relaxed);
+    }
+
+    fn inc(&self, delta: u64) {
+        self.position.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    fn finish(&self) {
+        debug!("Finished after {} items", self.position.load(Ordering::Relaxed));
+    }
+
+    fn child(&self, _kind: Kind) -> Box<dyn Progress> {
+        Box::new(Logged::default())
+    }
+}
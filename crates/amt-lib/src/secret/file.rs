@@ -0,0 +1,212 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use crate::secret::{Error, Secret, SecretStore};
+use argon2::Argon2;
+use async_trait::async_trait;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, OsRng, Payload};
+use chacha20poly1305::{AeadCore, ChaCha20Poly1305, Key, KeyInit, Nonce};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroize;
+
+const SALT_LENGTH: usize = 16;
+
+/// Encrypted to check the passphrase when the store is opened.
+const VERIFIER: &str = "amt-secret-store";
+
+#[derive(Serialize, Deserialize)]
+struct Contents {
+    salt: String,
+    verifier: Entry,
+    entries: BTreeMap<String, Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    nonce: String,
+    value: String,
+}
+
+/// A JSON file of secrets, each encrypted with ChaCha20-Poly1305.
+///
+/// The key is derived from a passphrase with Argon2id and a salt kept in the file,
+/// and each secret's name is authenticated alongside it so values can't be swapped between names.
+pub struct EncryptedFile {
+    path: PathBuf,
+    cipher: ChaCha20Poly1305,
+    contents: Contents,
+}
+
+impl EncryptedFile {
+    /// Open the store at `path`, creating it when it doesn't exist yet.
+    pub fn open(path: impl Into<PathBuf>, passphrase: &Secret<String>) -> Result<Self, Error> {
+        let path = path.into();
+        let existing = match fs::read(&path) {
+            Ok(bytes) => {
+                Some(serde_json::from_slice::<Contents>(&bytes).map_err(|err| Error::Corrupt(path.clone(), err))?)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(Error::Read(path, err)),
+        };
+
+        let salt = match &existing {
+            Some(contents) => hex::decode(&contents.salt).map_err(|_| Error::Decrypt("the salt".into()))?,
+            None => {
+                let mut salt = vec![0; SALT_LENGTH];
+                OsRng.fill_bytes(&mut salt);
+                salt
+            }
+        };
+
+        let cipher = cipher(passphrase, &salt)?;
+        let contents = match existing {
+            Some(contents) => {
+                decrypt(&cipher, VERIFIER, &contents.verifier)?;
+                contents
+            }
+            None => Contents {
+                salt: hex::encode(&salt),
+                verifier: encrypt(&cipher, VERIFIER, VERIFIER.as_bytes())?,
+                entries: BTreeMap::new(),
+            },
+        };
+
+        Ok(Self { path, cipher, contents })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The names of the stored secrets.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.contents.entries.keys().map(String::as_str)
+    }
+
+    /// Writes the store to a temporary file beside it which is then renamed over it,
+    /// so a failed write never leaves a truncated store behind.
+    fn save(&self) -> Result<(), Error> {
+        let mut temp = self.path.as_os_str().to_os_string();
+        temp.push(format!(".{}.tmp", std::process::id()));
+        let temp = PathBuf::from(temp);
+
+        let write = |path: &Path| -> io::Result<()> {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+            let mut file = options.open(path)?;
+            // The mode only applies when the file is created, a leftover file may be more permissive.
+            #[cfg(unix)]
+            file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+
+            file.write_all(&serde_json::to_vec_pretty(&self.contents)?)?;
+            file.sync_all()
+        };
+
+        write(&temp).and_then(|_| fs::rename(&temp, &self.path)).map_err(|err| {
+            let _ = fs::remove_file(&temp);
+            Error::Write(self.path.clone(), err)
+        })
+    }
+}
+
+#[async_trait]
+impl SecretStore for EncryptedFile {
+    async fn get(&self, key: &str) -> Result<Option<Secret<String>>, Error> {
+        let Some(entry) = self.contents.entries.get(key) else {
+            return Ok(None);
+        };
+
+        let plain = decrypt(&self.cipher, key, entry)?;
+        String::from_utf8(plain).map(|value| Some(Secret::new(value))).map_err(|err| {
+            err.into_bytes().zeroize();
+            Error::Encoding(key.to_owned())
+        })
+    }
+
+    async fn set(&mut self, key: &str, value: &Secret<String>) -> Result<(), Error> {
+        let entry = encrypt(&self.cipher, key, value.expose().as_bytes())?;
+        self.contents.entries.insert(key.to_owned(), entry);
+        self.save()
+    }
+
+    async fn remove(&mut self, key: &str) -> Result<bool, Error> {
+        if self.contents.entries.remove(key).is_none() {
+            return Ok(false);
+        }
+
+        self.save().map(|_| true)
+    }
+}
+
+fn cipher(passphrase: &Secret<String>, salt: &[u8]) -> Result<ChaCha20Poly1305, Error> {
+    let mut key = [0u8; 32];
+    let cipher = Argon2::default()
+        .hash_password_into(passphrase.expose().as_bytes(), salt, &mut key)
+        .map(|_| ChaCha20Poly1305::new(Key::from_slice(&key)))
+        .map_err(|err| Error::KeyDerivation(err.to_string()));
+
+    key.zeroize();
+    cipher
+}
+
+fn encrypt(cipher: &ChaCha20Poly1305, key: &str, value: &[u8]) -> Result<Entry, Error> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let value = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: value,
+                aad: key.as_bytes(),
+            },
+        )
+        .map_err(|_| Error::Encrypt(key.to_owned()))?;
+
+    Ok(Entry {
+        nonce: hex::encode(nonce),
+        value: hex::encode(value),
+    })
+}
+
+fn decrypt(cipher: &ChaCha20Poly1305, key: &str, entry: &Entry) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::Decrypt(key.to_owned());
+    let nonce = hex::decode(&entry.nonce).map_err(|_| invalid())?;
+    let value = hex::decode(&entry.value).map_err(|_| invalid())?;
+    if nonce.len() != 12 {
+        return Err(invalid());
+    }
+
+    cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &value,
+                aad: key.as_bytes(),
+            },
+        )
+        .map_err(|_| invalid())
+}
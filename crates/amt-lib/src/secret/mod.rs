@@ -0,0 +1,164 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+//! Handling of secrets such as passwords and API keys.
+//!
+//! Values are wrapped in a [`Secret`] so they are wiped from memory once dropped
+//! and never end up in logs, and can be persisted with a [`SecretStore`].
+
+pub mod file;
+#[cfg(all(target_os = "linux", feature = "secret-service"))]
+pub mod service;
+
+use async_trait::async_trait;
+use std::convert::Infallible;
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+use tracing::debug;
+use zeroize::Zeroize;
+
+pub use file::EncryptedFile;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// A value which is zeroed when dropped and redacted when formatted.
+///
+/// The value is only reachable through [`Secret::expose`],
+/// which makes every place it leaves the wrapper easy to find.
+#[derive(Clone, Default)]
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    pub const fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub const fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> Debug for Secret<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Secret").field(&format_args!("{REDACTED}")).finish()
+    }
+}
+
+impl<T: Zeroize> Display for Secret<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl From<String> for Secret<String> {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl FromStr for Secret<String> {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_owned()))
+    }
+}
+
+impl<'de, T: Zeroize + serde::Deserialize<'de>> serde::Deserialize<'de> for Secret<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Unable to read the secret store {0}")]
+    Read(PathBuf, #[source] io::Error),
+
+    #[error("Unable to write the secret store {0}")]
+    Write(PathBuf, #[source] io::Error),
+
+    #[error("The secret store {0} is corrupt")]
+    Corrupt(PathBuf, #[source] serde_json::Error),
+
+    #[error("Unable to encrypt {0}")]
+    Encrypt(String),
+
+    #[error("Unable to decrypt {0}, the passphrase may be wrong")]
+    Decrypt(String),
+
+    #[error("Unable to derive a key from the passphrase: {0}")]
+    KeyDerivation(String),
+
+    #[error("The secret {0} isn't valid UTF-8")]
+    Encoding(String),
+
+    #[error("Unable to get the passphrase for the secret store")]
+    Passphrase(#[source] anyhow::Error),
+
+    #[error("No user data directory to keep the secret store in")]
+    NoDirectory,
+
+    #[cfg(all(target_os = "linux", feature = "secret-service"))]
+    #[error("Secret Service request failed: {0}")]
+    Service(#[from] secret_service::Error),
+}
+
+/// Somewhere secrets can be kept between runs.
+#[async_trait]
+pub trait SecretStore: Send + Sync {
+    /// Get the secret stored under `key`, if there is one.
+    async fn get(&self, key: &str) -> Result<Option<Secret<String>>, Error>;
+
+    /// Store `value` under `key`, replacing any existing secret.
+    async fn set(&mut self, key: &str, value: &Secret<String>) -> Result<(), Error>;
+
+    /// Remove the secret under `key`, returning if there was one.
+    async fn remove(&mut self, key: &str) -> Result<bool, Error>;
+}
+
+/// Open the store for the tool `name`.
+///
+/// The Secret Service is used when it's running, otherwise an [`EncryptedFile`] in the user
+/// data directory, unlocked with the passphrase returned by `passphrase`.
+pub async fn open_store<F>(name: &str, passphrase: F) -> Result<Box<dyn SecretStore>, Error>
+where
+    F: FnOnce() -> anyhow::Result<Secret<String>> + Send,
+{
+    #[cfg(all(target_os = "linux", feature = "secret-service"))]
+    match service::SecretService::connect(name).await {
+        Ok(store) => return Ok(Box::new(store)),
+        Err(err) => debug!("Secret Service isn't available, falling back to a file: {err}"),
+    }
+
+    let path = crate::fs::data_directory()
+        .ok_or(Error::NoDirectory)?
+        .join("amt")
+        .join(format!("{name}.secrets"));
+
+    debug!("Using secret store at {}", path.display());
+    let passphrase = passphrase().map_err(Error::Passphrase)?;
+    EncryptedFile::open(path, &passphrase).map(|store| Box::new(store) as Box<dyn SecretStore>)
+}
@@ -0,0 +1,91 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use crate::secret::{Error, Secret, SecretStore};
+use async_trait::async_trait;
+use secret_service::EncryptionType;
+use std::collections::HashMap;
+use zeroize::Zeroize;
+
+const CONTENT_TYPE: &str = "text/plain";
+
+/// Secrets kept in the desktop keyring through the Secret Service D-Bus API,
+/// such as GNOME Keyring or KWallet.
+pub struct SecretService {
+    service: secret_service::SecretService<'static>,
+    name: String,
+}
+
+impl SecretService {
+    /// Connect to the session's Secret Service, failing when none is running.
+    pub async fn connect(name: &str) -> Result<Self, Error> {
+        let service = secret_service::SecretService::connect(EncryptionType::Dh).await?;
+
+        Ok(Self {
+            service,
+            name: name.to_owned(),
+        })
+    }
+
+    fn attributes<'a>(&'a self, key: &'a str) -> HashMap<&'a str, &'a str> {
+        HashMap::from([("application", "amt"), ("tool", self.name.as_str()), ("key", key)])
+    }
+}
+
+#[async_trait]
+impl SecretStore for SecretService {
+    async fn get(&self, key: &str) -> Result<Option<Secret<String>>, Error> {
+        let collection = self.service.get_default_collection().await?;
+        let items = collection.search_items(self.attributes(key)).await?;
+        let Some(item) = items.first() else {
+            return Ok(None);
+        };
+
+        item.ensure_unlocked().await?;
+        String::from_utf8(item.get_secret().await?)
+            .map(|value| Some(Secret::new(value)))
+            .map_err(|err| {
+                err.into_bytes().zeroize();
+                Error::Encoding(key.to_owned())
+            })
+    }
+
+    async fn set(&mut self, key: &str, value: &Secret<String>) -> Result<(), Error> {
+        let collection = self.service.get_default_collection().await?;
+        collection.ensure_unlocked().await?;
+        collection
+            .create_item(
+                &format!("{} {key}", self.name),
+                self.attributes(key),
+                value.expose().as_bytes(),
+                true,
+                CONTENT_TYPE,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove(&mut self, key: &str) -> Result<bool, Error> {
+        let collection = self.service.get_default_collection().await?;
+        let items = collection.search_items(self.attributes(key)).await?;
+        for item in &items {
+            item.delete().await?;
+        }
+
+        Ok(!items.is_empty())
+    }
+}
@@ -0,0 +1,88 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+//! Shell completions and man pages for every tool.
+//!
+//! Completions are dynamic, the script printed by `completions <shell>` calls back into the tool
+//! with [`COMPLETE_ENV`] set, so arguments can offer values like country codes using
+//! [`clap_complete::ArgValueCandidates`].
+
+use clap::builder::PossibleValuesParser;
+use clap::{Arg, ArgMatches, Command};
+use clap_complete::env::Shells;
+use clap_complete::CompleteEnv;
+use std::io::{self, Write};
+
+/// The environment variable the shell sets when asking the tool for completions.
+pub const COMPLETE_ENV: &str = "COMPLETE";
+
+const COMPLETIONS: &str = "completions";
+const MANPAGE: &str = "manpage";
+
+/// Adds the hidden `completions <shell>` and `manpage` commands,
+/// and names the command after the running binary rather than the crate it was derived in.
+pub fn augment(command: Command) -> Command {
+    let command = match bin_name() {
+        Some(name) => command.name(name.clone()).bin_name(name),
+        None => command,
+    };
+
+    command
+        .subcommand(
+            Command::new(COMPLETIONS)
+                .about("Print the script which registers completions for the shell")
+                .hide(true)
+                .arg(
+                    Arg::new("shell")
+                        .required(true)
+                        .value_parser(PossibleValuesParser::new(Shells::builtins().names())),
+                ),
+        )
+        .subcommand(Command::new(MANPAGE).about("Print the man page").hide(true))
+}
+
+/// Answers the completion request and exits, if the shell started this process for one.
+pub fn complete(factory: impl Fn() -> Command) {
+    CompleteEnv::with_factory(factory).var(COMPLETE_ENV).complete();
+}
+
+/// Runs the hidden command if one was matched, returning whether it was.
+pub fn generate(matches: &ArgMatches, command: &Command, out: &mut dyn Write) -> io::Result<bool> {
+    match matches.subcommand() {
+        Some((COMPLETIONS, matches)) => {
+            let shell = matches.get_one::<String>("shell").expect("Shell is a required argument");
+            let shells = Shells::builtins();
+            let completer = shells.completer(shell).expect("Shell is validated by the value parser");
+
+            let name = command.get_name();
+            let bin = command.get_bin_name().unwrap_or(name);
+            let executable = std::env::current_exe()?;
+            completer.write_registration(COMPLETE_ENV, name, bin, &executable.to_string_lossy(), out)?;
+            Ok(true)
+        }
+        Some((MANPAGE, _)) => {
+            clap_mangen::Man::new(command.clone()).render(out)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn bin_name() -> Option<String> {
+    std::env::current_exe()
+        .ok()
+        .and_then(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+}
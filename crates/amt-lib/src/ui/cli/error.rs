@@ -14,8 +14,19 @@
  * along with this program. If not, see https://www.gnu.org/licenses/.
  */
 
+//! Errors from the CLI layer, and reporting whatever error a tool finishes with.
+//!
+//! Every error is given a [`Category`] which decides the [`sysexits`] code the tool exits with,
+//! either explicitly with [`Categorise::categorise`] or by looking at the errors in its cause chain.
+//! [`finish`] then renders the error, with each cause, in the output format chosen on the command line.
+
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter};
+use std::io;
 use thiserror::Error;
 
+pub use sysexits::ExitCode;
+
 #[derive(Debug, Error)]
 pub enum CliError {
     #[error("Failed to receive line buffer: {0}")]
@@ -36,6 +47,247 @@ pub enum CliError {
     #[error("{0}")]
     Custom(String),
 
-    #[error("Failed with error")]
+    #[error(transparent)]
     Source(#[from] anyhow::Error),
 }
+
+/// The kind of failure an error represents.
+///
+/// Each maps to a fixed exit code so scripts and schedulers can tell failures apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "ui-cli-formatting",
+    derive(serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Category {
+    /// The command line was invalid.
+    Usage,
+
+    /// Input data was malformed.
+    Data,
+
+    /// An input file doesn't exist.
+    NoInput,
+
+    /// The configuration is missing or invalid.
+    Config,
+
+    /// A remote service couldn't be reached or refused the request.
+    Network,
+
+    /// The user isn't allowed to do what was asked.
+    Permission,
+
+    /// Some of the work failed while the rest succeeded, running again may finish it.
+    Partial,
+
+    /// Reading or writing a local file failed.
+    Io,
+
+    /// Anything else, usually a bug.
+    Internal,
+}
+
+impl Category {
+    pub const fn exit_code(self) -> ExitCode {
+        match self {
+            Self::Usage => ExitCode::Usage,
+            Self::Data => ExitCode::DataErr,
+            Self::NoInput => ExitCode::NoInput,
+            Self::Config => ExitCode::Config,
+            Self::Network => ExitCode::Unavailable,
+            Self::Permission => ExitCode::NoPerm,
+            Self::Partial => ExitCode::TempFail,
+            Self::Io => ExitCode::IoErr,
+            Self::Internal => ExitCode::Software,
+        }
+    }
+
+    /// The category of the first error in the chain which has one, or [`Category::Internal`].
+    pub fn of(err: &anyhow::Error) -> Self {
+        err.chain().find_map(Self::from_error).unwrap_or(Self::Internal)
+    }
+
+    fn from_error(err: &(dyn StdError + 'static)) -> Option<Self> {
+        if let Some(err) = err.downcast_ref::<Categorised>() {
+            return Some(err.category);
+        }
+
+        if let Some(err) = err.downcast_ref::<CliError>() {
+            return match err {
+                CliError::ParseError(_) | CliError::InvalidCommand(_) => Some(Self::Usage),
+                CliError::BufError(_) | CliError::WriteError(_) | CliError::ReadError(_) => Some(Self::Io),
+                CliError::Custom(_) => None,
+                CliError::Source(inner) => inner.chain().find_map(Self::from_error),
+            };
+        }
+
+        if err.is::<clap::Error>() {
+            return Some(Self::Usage);
+        }
+
+        #[cfg(feature = "config")]
+        if err.is::<crate::config::Error>() {
+            return Some(Self::Config);
+        }
+
+        #[cfg(feature = "updater")]
+        if err.is::<reqwest::Error>() {
+            return Some(Self::Network);
+        }
+
+        err.downcast_ref::<io::Error>().map(|err| match err.kind() {
+            io::ErrorKind::PermissionDenied => Self::Permission,
+            io::ErrorKind::NotFound => Self::NoInput,
+            io::ErrorKind::InvalidData => Self::Data,
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::TimedOut => Self::Network,
+            _ => Self::Io,
+        })
+    }
+}
+
+impl Display for Category {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Usage => "usage",
+            Self::Data => "data",
+            Self::NoInput => "noinput",
+            Self::Config => "config",
+            Self::Network => "network",
+            Self::Permission => "permission",
+            Self::Partial => "partial",
+            Self::Io => "io",
+            Self::Internal => "internal",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// An error tagged with its [`Category`], displayed exactly as the error it wraps.
+#[derive(Debug)]
+pub struct Categorised {
+    category: Category,
+    source: anyhow::Error,
+}
+
+impl Categorised {
+    pub const fn category(&self) -> Category {
+        self.category
+    }
+}
+
+impl Display for Categorised {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.source, f)
+    }
+}
+
+impl StdError for Categorised {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source.source()
+    }
+}
+
+/// Tag the error of a result with a [`Category`], in the same way as [`anyhow::Context`].
+pub trait Categorise<T> {
+    fn categorise(self, category: Category) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> Categorise<T> for Result<T, E> {
+    fn categorise(self, category: Category) -> anyhow::Result<T> {
+        self.map_err(|err| {
+            anyhow::Error::new(Categorised {
+                category,
+                source: err.into(),
+            })
+        })
+    }
+}
+
+/// An error as it's shown to the user: its message, every cause that led to it and the code to exit with.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "ui-cli-formatting", derive(serde::Serialize))]
+pub struct ErrorReport {
+    pub category: Category,
+    pub code: u8,
+    pub message: String,
+    pub causes: Vec<String>,
+}
+
+impl ErrorReport {
+    pub fn new(err: &anyhow::Error) -> Self {
+        let category = Category::of(err);
+        let mut chain = err.chain().map(ToString::to_string);
+
+        Self {
+            category,
+            code: category.exit_code() as u8,
+            message: chain.next().unwrap_or_default(),
+            causes: chain.collect(),
+        }
+    }
+
+    pub const fn exit_code(&self) -> ExitCode {
+        self.category.exit_code()
+    }
+}
+
+impl Display for ErrorReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Error: {}", self.message)?;
+        if !self.causes.is_empty() {
+            write!(f, "\n\nCaused by:")?;
+            for (index, cause) in self.causes.iter().enumerate() {
+                write!(f, "\n    {index}: {cause}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ui-cli-formatting")]
+static FORMAT: std::sync::OnceLock<crate::ui::cli::flags::OutputFormat> = std::sync::OnceLock::new();
+
+/// Remember the output format chosen on the command line, so [`report`] renders errors to match.
+///
+/// Only the first call has any effect.
+#[cfg(feature = "ui-cli-formatting")]
+pub fn set_format(format: crate::ui::cli::flags::OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+/// Write `err` to stderr and return the code the tool should exit with.
+///
+/// With `--format json` this is a single JSON object, otherwise the message followed by each cause.
+pub fn report(err: impl Into<anyhow::Error>) -> ExitCode {
+    let report = ErrorReport::new(&err.into());
+
+    #[cfg(feature = "ui-cli-formatting")]
+    if let Some(crate::ui::cli::flags::OutputFormat::Json) = FORMAT.get() {
+        match serde_json::to_string(&serde_json::json!({ "error": &report })) {
+            Ok(json) => eprintln!("{json}"),
+            Err(_) => eprintln!("{report}"),
+        }
+
+        return report.exit_code();
+    }
+
+    eprintln!("{report}");
+    report.exit_code()
+}
+
+/// The exit code for the outcome of a tool's `main`, reporting the error if there was one.
+pub fn finish<E: Into<anyhow::Error>>(result: Result<(), E>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::Ok,
+        Err(err) => report(err),
+    }
+}
@@ -14,13 +14,14 @@
  * along with this program. If not, see https://www.gnu.org/licenses/.
  */
 
+use crate::log::LogFlags;
 use clap::Parser;
 
 /// A struct that contains common flags for the CLI
 ///
 /// These flags are always available to the CLI,
 /// though the implementation is up to the final application.
-#[derive(Default, Debug, Clone, Copy, Parser)]
+#[derive(Default, Debug, Clone, Parser)]
 pub struct CommonFlags<const HIDE: bool = false> {
     /// The verbosity of the terminal logger
     #[arg(short, long, hide = HIDE, global = true, action = clap::ArgAction::Count)]
@@ -30,6 +31,10 @@ pub struct CommonFlags<const HIDE: bool = false> {
     #[arg(short, long, hide = HIDE, global = true, action = clap::ArgAction::SetTrue)]
     pub dry_run: bool,
 
+    /// Don't re-run with elevated privileges, continue with only what the current user can do
+    #[arg(long, hide = HIDE, global = true, env = "AMT_NO_ELEVATE", action = clap::ArgAction::SetTrue)]
+    pub no_elevate: bool,
+
     /// If the program should be run in a quiet mode
     #[arg(short, long, hide = HIDE, global = true, action = clap::ArgAction::SetTrue, default_value_if("format", "OutputFormat::Json", "true"))]
     pub quiet: bool,
@@ -39,9 +44,23 @@ pub struct CommonFlags<const HIDE: bool = false> {
     #[arg(short, long, hide = HIDE, global = true, value_enum, default_value_t = OutputFormat::Human)]
     pub format: OutputFormat,
 
+    /// Update to the newest release once the command has finished
     #[cfg(feature = "updater")]
     #[arg(short, long, hide = HIDE, global = true, action = clap::ArgAction::SetTrue)]
     pub update: bool,
+
+    /// Roll back to the version replaced by the last update once the command has finished
+    #[cfg(feature = "updater")]
+    #[arg(long, hide = HIDE, global = true, action = clap::ArgAction::SetTrue, conflicts_with = "update")]
+    pub rollback: bool,
+
+    /// The release channel to update from
+    #[cfg(feature = "updater")]
+    #[arg(long, hide = HIDE, global = true, env = "AMT_UPDATE_CHANNEL", value_enum, default_value_t)]
+    pub channel: crate::updater::Channel,
+
+    #[command(flatten)]
+    pub log: LogFlags<HIDE>,
 }
 
 /// The output format for the CLI
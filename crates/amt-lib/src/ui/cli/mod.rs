@@ -23,6 +23,7 @@ use std::fmt::Debug;
 use tracing::{trace, warn};
 use ui_inquire::STYLE;
 
+pub mod complete;
 pub mod error;
 pub mod flags;
 pub mod oneshot;
@@ -53,28 +54,39 @@ crate::feature_trait! {
         where
             Self: Sized,
         {
-            use clap::Parser;
+            use clap::{CommandFactory, FromArgMatches};
+
             #[cfg(feature = "ui-repl")]
-            use clap::CommandFactory;
+            let parser = || {
+                complete::augment(repl::ReplParser::<<Self as OneshotHandler>::OneshotAction>::command())
+            };
+            #[cfg(not(feature = "ui-repl"))]
+            let parser = || {
+                complete::augment(oneshot::OneshotParser::<<Self as OneshotHandler>::OneshotAction>::command())
+            };
+
+            complete::complete(parser);
+            let matches = parser().get_matches();
+            if complete::generate(&matches, &parser(), &mut std::io::stdout()).map_err(CliError::WriteError)? {
+                return Ok(());
+            }
 
             #[cfg(feature = "ui-repl")]
             let (command, mut factory) = (
-                repl::ReplParser::<<Self as OneshotHandler>::OneshotAction>::parse(),
+                repl::ReplParser::<<Self as OneshotHandler>::OneshotAction>::from_arg_matches(&matches)
+                    .unwrap_or_else(|err| err.exit()),
                 <Self as repl::ReplHandler>::ReplAction::command()
             );
             #[cfg(not(feature = "ui-repl"))]
-            let command = oneshot::OneshotParser::<<Self as OneshotHandler>::OneshotAction>::parse();
+            let command = oneshot::OneshotParser::<<Self as OneshotHandler>::OneshotAction>::from_arg_matches(&matches)
+                .unwrap_or_else(|err| err.exit());
 
-            #[cfg(feature = "updater")]
-            let updater = if command.flags.update {
-                Some(crate::updater::update())
-            } else {
-                None
-            };
+            #[cfg(feature = "ui-cli-formatting")]
+            error::set_format(command.flags.format);
 
             #[cfg(feature = "ui-repl")]
             if command.repl {
-                <Self as repl::ReplHandler>::repl(self).await?;
+                <Self as repl::ReplHandler>::repl(self, command.flags.clone()).await?;
             } else if let Some(action) = command.action {
                 <Self as OneshotHandler>::handle(self, action, &command.flags).await?;
             } else {
@@ -83,18 +95,29 @@ crate::feature_trait! {
             #[cfg(not(feature = "ui-repl"))]
             <Self as OneshotHandler>::handle(self, command.action, &command.flags).await?;
 
+            // Only replace the binary once the command is done with it.
             #[cfg(feature = "updater")]
-            // Wait for the update to finish before returning.
-            if let Some(updater) = updater {
-                tracing::info!("Waiting for update to finish...");
-                match updater.await {
-                    Ok(_) => tracing::info!("Update successful!"),
-                    Err(err) => tracing::error!("Update failed: {err}"),
+            if command.flags.update || command.flags.rollback {
+                match self.updater() {
+                    None => tracing::warn!("Updating isn't supported by this tool."),
+                    Some(updater) if command.flags.rollback => updater.rollback().map_err(anyhow::Error::from)?,
+                    Some(updater) => match updater.with_channel(command.flags.channel).update().await {
+                        Ok(Some(version)) => tracing::info!("Updated to {version}, it will be used from the next run."),
+                        Ok(None) => tracing::info!("Already up to date."),
+                        Err(err) => tracing::error!("Update failed: {err}"),
+                    },
                 }
             }
 
             Ok(())
         }
+
+        /// The updater used for `--update` and `--rollback`,
+        /// tools which publish releases should return one created with their own name and version.
+        #[cfg(feature = "updater")]
+        fn updater(&self) -> Option<crate::updater::Updater> {
+            None
+        }
     }
 }
 
@@ -145,6 +168,52 @@ where
     }
 }
 
+/// Get a secret from the environment variable `key`, or prompt for it with masked input.
+#[cfg(feature = "secret")]
+pub fn env_or_prompt_secret<V>(key: &str, validator: V) -> Result<crate::secret::Secret<String>>
+where
+    V: StringValidator + 'static,
+{
+    use crate::secret::Secret;
+
+    match std::env::var(key) {
+        Ok(str) => {
+            let secret = Secret::new(str);
+            match validator.validate(secret.expose()) {
+                Err(err) => Err(anyhow!("{} is set but invalid: {}", key, err)),
+                Ok(_) => {
+                    trace!("Validated {} from env", key);
+                    Ok(secret)
+                }
+            }
+        }
+        _ => prompt_secret(key, validator),
+    }
+}
+
+/// Prompt for a secret, masking the input as it's typed.
+#[cfg(feature = "secret")]
+pub fn prompt_secret<V>(title: &str, validator: V) -> Result<crate::secret::Secret<String>>
+where
+    V: StringValidator + 'static,
+{
+    use inquire::{Password, PasswordDisplayMode};
+
+    match Password::new(title)
+        .with_render_config(*STYLE)
+        .with_validator(validator)
+        .with_display_mode(PasswordDisplayMode::Masked)
+        .without_confirmation()
+        .prompt()
+    {
+        Err(err) => Err(anyhow!("Failed to get {} from user: {}", title, err)),
+        Ok(str) => {
+            trace!("Validated {} from user", title);
+            Ok(str.into())
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! populate {
     ($self:ident, $flags:ident) => {
@@ -14,7 +14,9 @@
  * along with this program. If not, see https://www.gnu.org/licenses/.
  */
 
-use indicatif::{ProgressBar, ProgressStyle};
+use crate::progress::{Kind, Logged, Progress};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
 use std::time::Duration;
 
 pub const PROGRESS_CHARS: &str = "█▓▒░  ";
@@ -75,3 +77,70 @@ pub fn download_style() -> ProgressStyle {
         .template(DOWNLOAD_TEMPLATE)
         .unwrap()
 }
+
+/// Reports progress as indicatif bars, with sub-tasks drawn below the task which started them.
+#[derive(Debug, Clone)]
+pub struct Terminal {
+    multi: MultiProgress,
+    bar: ProgressBar,
+}
+
+impl Terminal {
+    /// Start reporting a task of `kind` as a new set of bars.
+    pub fn new(kind: Kind) -> Self {
+        let multi = MultiProgress::new();
+        let bar = multi.add(for_kind(kind));
+
+        Self { multi, bar }
+    }
+
+    pub fn bar(&self) -> &ProgressBar {
+        &self.bar
+    }
+}
+
+/// Bars when there is a terminal to draw them on, otherwise log lines so unattended runs still leave a trail.
+pub fn reporter(kind: Kind) -> Box<dyn Progress> {
+    match std::io::stderr().is_terminal() {
+        true => Box::new(Terminal::new(kind)),
+        false => Box::new(Logged::default()),
+    }
+}
+
+impl Progress for Terminal {
+    fn set_message(&self, message: &str) {
+        self.bar.set_message(message.to_owned());
+    }
+
+    fn set_length(&self, length: u64) {
+        self.bar.set_length(length);
+    }
+
+    fn set_position(&self, position: u64) {
+        self.bar.set_position(position);
+    }
+
+    fn inc(&self, delta: u64) {
+        self.bar.inc(delta);
+    }
+
+    fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+
+    fn child(&self, kind: Kind) -> Box<dyn Progress> {
+        Box::new(Self {
+            multi: self.multi.clone(),
+            bar: self.multi.insert_after(&self.bar, for_kind(kind)),
+        })
+    }
+}
+
+fn for_kind(kind: Kind) -> ProgressBar {
+    match kind {
+        Kind::Spinner => spinner(),
+        Kind::Counter => spinner_with_count(),
+        Kind::Bar => bar(0),
+        Kind::Download => download(),
+    }
+}
@@ -15,9 +15,18 @@
  */
 
 use crate::ui::cli::error::CliError;
+use crate::ui::cli::flags::CommonFlags;
 use crate::ui::cli::CliResult;
-use clap::{CommandFactory, FromArgMatches};
-use tracing::{debug, error, instrument};
+use clap::{Command, CommandFactory, FromArgMatches};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Config, Context, Editor, Helper};
+use std::path::PathBuf;
+use tracing::{debug, error, instrument, warn};
 
 crate::handler!(pub Repl<Option<O>> [
     #[arg(long, short = 'r', action = clap::ArgAction::SetTrue)]
@@ -26,36 +35,48 @@ crate::handler!(pub Repl<Option<O>> [
     /// Run in REPL mode (Read-Eval-Print-Loop)
     ///
     /// This is a mode where the user can enter commands and have them executed
-    /// in a loop until they exit.
+    /// in a loop until they exit, the flags are those the session was started with.
     #[doc(hidden)]
-    async fn repl(&mut self) -> CliResult<()>
+    async fn repl(&mut self, mut flags: _CommonFlags) -> CliResult<()>
     where
         Self: Sized,
     {
+        let command = Self::ReplAction::command().no_binary_name(true);
+        let mut editor = LineEditor::new(command.clone())?;
+
         loop {
-            let line = readline()?;
+            let line = match editor.readline()? {
+                Some(line) => line,
+                None => break,
+            };
             let line = line.trim();
 
             debug!("Read line: {line}");
 
-            match line {
-                l if l.is_empty() | l.starts_with('#') => continue,
-                l => match self.respond(l).await {
-                    Ok(true) => break,
-                    Ok(false) => continue,
-                    Err(err) => {
-                        error!(
-                            r#"
-                            There was an error while processing your command:
-                            ----
-                            {err}
-                            ----
-                            Failed to respond to line {l}
-                            "#
-                        );
-                        continue;
-                    }
-                },
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let result = match Builtin::parse(line) {
+                Some(builtin) => builtin.run(&command, &mut flags),
+                None => self.respond(line, &flags).await,
+            };
+
+            match result {
+                Ok(true) => break,
+                Ok(false) => continue,
+                Err(err) => {
+                    error!(
+                        r#"
+                        There was an error while processing your command:
+                        ----
+                        {err}
+                        ----
+                        Failed to respond to line {line}
+                        "#
+                    );
+                    continue;
+                }
             }
         }
 
@@ -63,28 +84,216 @@ crate::handler!(pub Repl<Option<O>> [
     }
 
     #[doc(hidden)]
-    #[instrument(level = "TRACE", err, ret, skip(self))]
-    async fn respond(&mut self, line: &str) -> CliResult<bool> {
+    #[instrument(level = "TRACE", err, ret, skip(self, flags))]
+    async fn respond(&mut self, line: &str, flags: &_CommonFlags) -> CliResult<bool> {
         let args = shlex::split(line).ok_or_else(|| CliError::ParseError(line.into()))?;
-        let mut matches = Self::ReplAction::command().try_get_matches_from(&args).map_err(CliError::InvalidCommand)?;
+        let mut matches = Self::ReplAction::command()
+            .no_binary_name(true)
+            .try_get_matches_from(&args)
+            .map_err(CliError::InvalidCommand)?;
         let parsed = Self::ReplAction::from_arg_matches_mut(&mut matches).map_err(CliError::InvalidCommand)?;
 
-        <Self as ReplHandler>::handle(self, parsed, &Default::default()).await?;
+        <Self as ReplHandler>::handle(self, parsed, flags).await?;
         Ok(false)
     }
 });
 
-#[instrument(level = "TRACE", err, ret)]
-fn readline() -> CliResult<String> {
-    use std::io::{stdin, stdout, Write};
+/// Commands handled by the REPL itself rather than the tool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Builtin {
+    Exit,
+    /// Print the help of the tool, or of a single command.
+    Help(Option<String>),
+    /// Print the current session settings.
+    Show,
+    SetVerbose(u8),
+    /// A malformed built-in, with the reason it couldn't be used.
+    Invalid(String),
+}
+
+impl Builtin {
+    pub const NAMES: [&'static str; 4] = ["exit", "quit", "help", "set"];
+
+    /// Parses a line as a built-in, returning `None` if it should be passed to the tool instead.
+    pub fn parse(line: &str) -> Option<Self> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+
+        Some(match words.as_slice() {
+            ["exit" | "quit"] => Builtin::Exit,
+            ["help"] => Builtin::Help(None),
+            ["help", command] => Builtin::Help(Some((*command).to_owned())),
+            ["set"] => Builtin::Show,
+            ["set", "verbose", level] => match level.parse() {
+                Ok(level) => Builtin::SetVerbose(level),
+                Err(_) => Builtin::Invalid(format!("{level} is not a valid verbosity level")),
+            },
+            ["set", ..] => Builtin::Invalid("Usage: set verbose <level>".to_owned()),
+            _ => return None,
+        })
+    }
+
+    /// Runs the built-in, returning true if the REPL should exit.
+    pub fn run(self, command: &Command, flags: &mut CommonFlags) -> CliResult<bool> {
+        let mut command = command.clone();
+
+        match self {
+            Builtin::Exit => return Ok(true),
+            Builtin::Help(None) => {
+                command.print_help().map_err(CliError::WriteError)?;
+                println!("\nBuilt-in commands:\n  exit, quit\n  help [command]\n  set [verbose <level>]");
+            }
+            Builtin::Help(Some(name)) => match command.find_subcommand_mut(&name) {
+                Some(subcommand) => subcommand.print_help().map_err(CliError::WriteError)?,
+                None => return Err(CliError::Custom(format!("Unknown command {name}"))),
+            },
+            Builtin::Show => println!("verbose = {}", flags.verbose),
+            Builtin::SetVerbose(level) => {
+                // The terminal logger is only set up once, so this only changes what commands see.
+                flags.verbose = level;
+                println!("verbose = {level}");
+            }
+            Builtin::Invalid(reason) => return Err(CliError::Custom(reason)),
+        }
+
+        Ok(false)
+    }
+}
+
+/// Wraps the line editor, keeping a history file for each tool.
+struct LineEditor {
+    editor: Editor<ReplHelper, FileHistory>,
+    history: Option<PathBuf>,
+}
+
+impl LineEditor {
+    const HISTORY_SIZE: usize = 1000;
+
+    fn new(command: Command) -> CliResult<Self> {
+        let config = Config::builder()
+            .auto_add_history(true)
+            .history_ignore_dups(true)
+            .and_then(|builder| builder.max_history_size(Self::HISTORY_SIZE))
+            .map_err(readline_error)?
+            .build();
 
-    stdout().write(b"$ ").map_err(CliError::WriteError)?;
-    stdout().flush().map_err(CliError::WriteError)?;
+        let history = history_path(command.get_name());
+        let mut editor = Editor::with_config(config).map_err(readline_error)?;
+        editor.set_helper(Some(ReplHelper { command }));
+
+        if let Some(path) = &history
+            && path.exists()
+            && let Err(err) = editor.load_history(path)
+        {
+            warn!("Unable to load history from {}: {err}", path.display());
+        }
+
+        Ok(Self { editor, history })
+    }
+
+    /// Reads the next line, returning `None` once the input has ended.
+    #[instrument(level = "TRACE", err, ret, skip(self))]
+    fn readline(&mut self) -> CliResult<Option<String>> {
+        match self.editor.readline("$ ") {
+            Ok(line) => {
+                self.save();
+                Ok(Some(line))
+            }
+            Err(ReadlineError::Interrupted) => Ok(Some(String::new())),
+            Err(ReadlineError::Eof) => Ok(None),
+            Err(err) => Err(readline_error(err)),
+        }
+    }
+
+    fn save(&mut self) {
+        let Some(path) = &self.history else { return };
+
+        if let Some(parent) = path.parent()
+            && let Err(err) = std::fs::create_dir_all(parent)
+        {
+            warn!("Unable to create history directory {}: {err}", parent.display());
+            return;
+        }
+
+        if let Err(err) = self.editor.save_history(path) {
+            warn!("Unable to save history to {}: {err}", path.display());
+        }
+    }
+}
+
+fn readline_error(err: ReadlineError) -> CliError {
+    match err {
+        ReadlineError::Io(err) => CliError::ReadError(err),
+        err => CliError::Custom(err.to_string()),
+    }
+}
+
+/// The history file for the tool, stored in the user data directory.
+fn history_path(name: &str) -> Option<PathBuf> {
+    crate::fs::data_directory().map(|directory| directory.join("amt").join(format!("{name}.history")))
+}
+
+/// Completes commands and their flags from the clap [`Command`] of the tool.
+struct ReplHelper {
+    command: Command,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(&self.command, line, pos))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// Finds the completions for the word under the cursor,
+/// returning the position the word starts at and the candidates to replace it with.
+pub fn complete(command: &Command, line: &str, pos: usize) -> (usize, Vec<String>) {
+    let line = &line[..pos];
+    let start = line.rfind(char::is_whitespace).map_or(0, |index| index + 1);
+    let (before, word) = line.split_at(start);
+
+    // Walk down to the deepest subcommand already typed.
+    let mut current = command;
+    let mut top_level = true;
+    for token in before.split_whitespace() {
+        if let Some(subcommand) = current.find_subcommand(token) {
+            current = subcommand;
+            top_level = false;
+        }
+    }
 
-    // Read a line from stdin and return it.
-    let mut buffer = String::new();
-    stdin().read_line(&mut buffer).map_err(CliError::ReadError)?;
+    let mut candidates = match word.starts_with('-') {
+        true => current
+            .get_arguments()
+            .filter(|arg| !arg.is_hide_set())
+            .filter_map(|arg| arg.get_long())
+            .map(|long| format!("--{long}"))
+            .collect::<Vec<_>>(),
+        false => current
+            .get_subcommands()
+            .filter(|subcommand| !subcommand.is_hide_set())
+            .map(|subcommand| subcommand.get_name().to_owned())
+            .chain(
+                top_level
+                    .then_some(Builtin::NAMES.iter().map(|name| (*name).to_owned()))
+                    .into_iter()
+                    .flatten(),
+            )
+            .collect(),
+    };
 
-    // Return the buffer
-    Ok(buffer)
+    candidates.retain(|candidate| candidate.starts_with(word));
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
 }
@@ -0,0 +1,279 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+//! Updates the running tool from its published releases.
+//!
+//! Releases are read from a [`ReleaseSource`], GitHub by default or a mirror set through `AMT_UPDATE_MIRROR`.
+//! Every binary must have a detached signature over its version and hash, see [`signature::message`],
+//! which is verified against the public key embedded at build time,
+//! and the replaced binary is kept so that the update can be rolled back.
+
+pub mod signature;
+pub mod source;
+
+use crate::updater::source::{GitHub, Mirror, Release, ReleaseSource};
+use clap::ValueEnum;
+use semver::Version;
+use std::env::consts;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::{debug, info};
+
+/// The public key release signatures are verified against, as hex.
+pub const PUBLIC_KEY: Option<&str> = option_env!("AMT_UPDATE_PUBLIC_KEY");
+
+/// The environment variable used to update from a mirror instead of GitHub.
+pub const MIRROR_ENV: &str = "AMT_UPDATE_MIRROR";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Request to {url} failed: {source}")]
+    Request {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("Request to {url} failed with status {status}")]
+    Status { url: String, status: reqwest::StatusCode },
+
+    #[error("Unable to parse releases from {url}: {source}")]
+    Parse {
+        url: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Invalid release source url {0}")]
+    InvalidUrl(String),
+
+    #[error("Release {version} has no signature for {asset}")]
+    MissingSignature { version: Version, asset: String },
+
+    #[error("The signature of {asset} is invalid")]
+    InvalidSignature { asset: String },
+
+    #[error("No public key was embedded at build time, refusing to install an unverified update")]
+    NoPublicKey,
+
+    #[error("The embedded public key is invalid")]
+    InvalidPublicKey,
+
+    #[error("There is no previous version at {0} to roll back to")]
+    NoBackup(PathBuf),
+
+    #[error("Unable to locate the running executable: {0}")]
+    Executable(#[source] io::Error),
+
+    #[error("Unable to write {0}: {1}")]
+    Io(PathBuf, #[source] io::Error),
+}
+
+/// Which releases are considered when updating.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Channel {
+    /// Only full releases
+    #[default]
+    Stable,
+
+    /// Pre-releases as well as full releases
+    Beta,
+}
+
+impl Channel {
+    pub fn allows(&self, release: &Release) -> bool {
+        match self {
+            Channel::Stable => !release.prerelease && release.version.pre.is_empty(),
+            Channel::Beta => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Updater {
+    name: String,
+    version: Version,
+    channel: Channel,
+    source: Box<dyn ReleaseSource>,
+    public_key: Option<String>,
+    executable: Option<PathBuf>,
+}
+
+impl Updater {
+    /// Creates an updater for the tool, which should be given its own `CARGO_PKG_NAME` and `CARGO_PKG_VERSION`.
+    pub fn new(name: &str, version: &str) -> Self {
+        let source: Box<dyn ReleaseSource> = match std::env::var(MIRROR_ENV) {
+            Ok(url) if !url.is_empty() => match Mirror::new(&url) {
+                Ok(mirror) => Box::new(mirror),
+                Err(err) => {
+                    tracing::warn!("Ignoring {MIRROR_ENV}: {err}");
+                    Box::new(GitHub::default())
+                }
+            },
+            _ => Box::new(GitHub::default()),
+        };
+
+        Self {
+            name: name.to_owned(),
+            version: Version::parse(version).unwrap_or_else(|_| Version::new(0, 0, 0)),
+            channel: Channel::default(),
+            source,
+            public_key: PUBLIC_KEY.map(str::to_owned),
+            executable: None,
+        }
+    }
+
+    pub fn with_channel(mut self, channel: Channel) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    pub fn with_source(mut self, source: impl ReleaseSource + 'static) -> Self {
+        self.source = Box::new(source);
+        self
+    }
+
+    pub fn with_public_key(mut self, public_key: &str) -> Self {
+        self.public_key = Some(public_key.to_owned());
+        self
+    }
+
+    /// Replaces a different executable than the running one.
+    pub fn with_executable(mut self, executable: PathBuf) -> Self {
+        self.executable = Some(executable);
+        self
+    }
+
+    /// The name of the release asset for this platform, matching the names used by the release workflow.
+    pub fn asset_name(&self) -> String {
+        let os = match consts::OS {
+            "macos" => "darwin",
+            os => os,
+        };
+
+        format!("{}-{}-{os}{}", self.name, consts::ARCH, consts::EXE_SUFFIX)
+    }
+
+    /// Finds the newest release on the channel which is newer than the running version.
+    pub async fn check(&self) -> Result<Option<Release>, Error> {
+        let asset = self.asset_name();
+        let release = self
+            .source
+            .releases(&self.name)
+            .await?
+            .into_iter()
+            .filter(|release| self.channel.allows(release))
+            .filter(|release| release.version > self.version)
+            .filter(|release| release.asset(&asset).is_some())
+            .max_by(|a, b| a.version.cmp(&b.version));
+
+        debug!(
+            "Newest release on {:?} channel: {:?}",
+            self.channel,
+            release.as_ref().map(|r| &r.version)
+        );
+        Ok(release)
+    }
+
+    /// Updates to the newest release, returning its version or `None` if already up to date.
+    pub async fn update(&self) -> Result<Option<Version>, Error> {
+        let Some(release) = self.check().await? else {
+            return Ok(None);
+        };
+
+        let name = self.asset_name();
+        let signature_name = format!("{name}.sig");
+        let asset = release.asset(&name).expect("Release was filtered to those with the asset");
+        let signature = release.asset(&signature_name).ok_or_else(|| Error::MissingSignature {
+            version: release.version.clone(),
+            asset: name.clone(),
+        })?;
+
+        let public_key = self.public_key.as_deref().ok_or(Error::NoPublicKey)?;
+
+        info!("Downloading {name} {}", release.version);
+        let binary = self.source.download(&asset.url).await?;
+        let signature = self.source.download(&signature.url).await?;
+        signature::verify(public_key, &release.version, &binary, &signature, &name)?;
+
+        self.install(&binary)?;
+        Ok(Some(release.version))
+    }
+
+    /// Swaps the executable with the one replaced by the last update,
+    /// rolling back a second time returns to the updated version.
+    pub fn rollback(&self) -> Result<(), Error> {
+        let executable = self.executable()?;
+        let backup = sibling(&executable, "old");
+        if !backup.exists() {
+            return Err(Error::NoBackup(backup));
+        }
+
+        let current = sibling(&executable, "rollback");
+        fs::copy(&executable, &current).map_err(|err| Error::Io(current.clone(), err))?;
+        self.replace(&executable, &backup)?;
+        fs::rename(&current, &backup).map_err(|err| Error::Io(backup.clone(), err))?;
+
+        info!("Rolled back {}", executable.display());
+        Ok(())
+    }
+
+    fn install(&self, binary: &[u8]) -> Result<(), Error> {
+        let executable = self.executable()?;
+        let backup = sibling(&executable, "old");
+        let staged = sibling(&executable, "new");
+
+        fs::write(&staged, binary).map_err(|err| Error::Io(staged.clone(), err))?;
+        if let Ok(metadata) = fs::metadata(&executable) {
+            fs::set_permissions(&staged, metadata.permissions()).map_err(|err| Error::Io(staged.clone(), err))?;
+        }
+
+        fs::copy(&executable, &backup).map_err(|err| Error::Io(backup.clone(), err))?;
+        self.replace(&executable, &staged)?;
+        let _ = fs::remove_file(&staged);
+
+        Ok(())
+    }
+
+    /// Replaces the executable with the file, which is left in place.
+    fn replace(&self, executable: &Path, with: &Path) -> Result<(), Error> {
+        // A running executable can't be overwritten on every platform, so it needs to be swapped out.
+        if self.executable.is_none() {
+            return self_replace::self_replace(with).map_err(|err| Error::Io(executable.to_path_buf(), err));
+        }
+
+        fs::copy(with, executable)
+            .map(|_| ())
+            .map_err(|err| Error::Io(executable.to_path_buf(), err))
+    }
+
+    fn executable(&self) -> Result<PathBuf, Error> {
+        match &self.executable {
+            Some(executable) => Ok(executable.clone()),
+            None => std::env::current_exe().map_err(Error::Executable),
+        }
+    }
+}
+
+/// A file next to the path with an extra extension, e.g. `tool.old`.
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
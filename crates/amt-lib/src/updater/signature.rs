@@ -0,0 +1,50 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use crate::updater::Error;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use semver::Version;
+use sha2::{Digest, Sha256};
+
+/// The message a release artefact is signed over, its version followed by the SHA-256 of its bytes.
+///
+/// Including the version stops a validly signed artefact of an older release being served as a newer one.
+pub fn message(version: &Version, data: &[u8]) -> Vec<u8> {
+    let mut message = version.to_string().into_bytes();
+    message.extend_from_slice(&Sha256::digest(data));
+    message
+}
+
+/// Verifies the detached Ed25519 signature of the data as the given version, both the key and signature are hex.
+pub fn verify(public_key: &str, version: &Version, data: &[u8], signature: &[u8], asset: &str) -> Result<(), Error> {
+    let public_key = hex::decode(public_key.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or(Error::InvalidPublicKey)?;
+
+    let invalid = || Error::InvalidSignature {
+        asset: asset.to_owned(),
+    };
+    let signature = std::str::from_utf8(signature).map_err(|_| invalid())?;
+    let signature = hex::decode(signature.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .map(|bytes| Signature::from_bytes(&bytes))
+        .ok_or_else(invalid)?;
+
+    public_key.verify(&message(version, data), &signature).map_err(|_| invalid())
+}
@@ -0,0 +1,228 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use crate::updater::Error;
+use async_trait::async_trait;
+use reqwest::{Client, Url};
+use semver::Version;
+use serde::Deserialize;
+use std::fmt::Debug;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    pub version: Version,
+    pub prerelease: bool,
+    pub assets: Vec<Asset>,
+}
+
+impl Release {
+    pub fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub url: String,
+}
+
+/// Somewhere releases are published to.
+#[async_trait]
+pub trait ReleaseSource: Debug + Send + Sync {
+    /// Lists the releases of the tool.
+    async fn releases(&self, name: &str) -> Result<Vec<Release>, Error>;
+
+    /// Downloads an asset of a release.
+    async fn download(&self, url: &str) -> Result<Vec<u8>, Error>;
+}
+
+/// The GitHub releases of the repository, which are tagged `{name}-v{version}`.
+#[derive(Debug, Clone)]
+pub struct GitHub {
+    owner: String,
+    repo: String,
+    client: Client,
+}
+
+impl Default for GitHub {
+    fn default() -> Self {
+        Self::new("AMTSupport", "tools")
+    }
+}
+
+impl GitHub {
+    pub fn new(owner: &str, repo: &str) -> Self {
+        Self {
+            owner: owner.to_owned(),
+            repo: repo.to_owned(),
+            client: client(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct GitHubRelease {
+    tag_name: String,
+    prerelease: bool,
+    assets: Vec<GitHubAsset>,
+}
+
+#[derive(Deserialize)]
+struct GitHubAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+#[async_trait]
+impl ReleaseSource for GitHub {
+    async fn releases(&self, name: &str) -> Result<Vec<Release>, Error> {
+        let url = format!("https://api.github.com/repos/{}/{}/releases", self.owner, self.repo);
+        let body = get(&self.client, &url).await?;
+        let releases = serde_json::from_slice::<Vec<GitHubRelease>>(&body).map_err(|source| Error::Parse {
+            url: url.clone(),
+            source,
+        })?;
+
+        Ok(releases
+            .into_iter()
+            .filter_map(|release| {
+                Some(Release {
+                    version: parse_tag(name, &release.tag_name)?,
+                    prerelease: release.prerelease,
+                    assets: release
+                        .assets
+                        .into_iter()
+                        .map(|asset| Asset {
+                            name: asset.name,
+                            url: asset.browser_download_url,
+                        })
+                        .collect(),
+                })
+            })
+            .collect())
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>, Error> {
+        get(&self.client, url).await
+    }
+}
+
+/// A plain HTTP mirror, serving `{base}/{name}/releases.json` with asset urls relative to it.
+///
+/// ```json
+/// [{ "version": "1.2.0", "prerelease": false, "assets": [{ "name": "tool-x86_64-linux", "url": "1.2.0/tool-x86_64-linux" }] }]
+/// ```
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    base: Url,
+    client: Client,
+}
+
+impl Mirror {
+    pub fn new(base: &str) -> Result<Self, Error> {
+        // Without the trailing slash joining would replace the last segment.
+        let base = match base.ends_with('/') {
+            true => base.to_owned(),
+            false => format!("{base}/"),
+        };
+
+        Ok(Self {
+            base: Url::parse(&base).map_err(|_| Error::InvalidUrl(base.clone()))?,
+            client: client(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct MirrorRelease {
+    version: Version,
+    #[serde(default)]
+    prerelease: bool,
+    assets: Vec<Asset>,
+}
+
+#[async_trait]
+impl ReleaseSource for Mirror {
+    async fn releases(&self, name: &str) -> Result<Vec<Release>, Error> {
+        let url = self
+            .base
+            .join(&format!("{name}/releases.json"))
+            .map_err(|_| Error::InvalidUrl(format!("{}{name}/releases.json", self.base)))?;
+        let body = get(&self.client, url.as_str()).await?;
+        let releases = serde_json::from_slice::<Vec<MirrorRelease>>(&body).map_err(|source| Error::Parse {
+            url: url.to_string(),
+            source,
+        })?;
+
+        releases
+            .into_iter()
+            .map(|release| {
+                let assets = release
+                    .assets
+                    .into_iter()
+                    .map(|asset| {
+                        let resolved = url.join(&asset.url).map_err(|_| Error::InvalidUrl(asset.url.clone()))?;
+                        Ok(Asset {
+                            name: asset.name,
+                            url: resolved.to_string(),
+                        })
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+                Ok(Release {
+                    version: release.version,
+                    prerelease: release.prerelease,
+                    assets,
+                })
+            })
+            .collect()
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>, Error> {
+        get(&self.client, url).await
+    }
+}
+
+/// Parses the version from a tag, skipping tags which belong to other tools in the repository.
+pub fn parse_tag(name: &str, tag: &str) -> Option<Version> {
+    let version = tag.strip_prefix(name).and_then(|tag| tag.strip_prefix('-')).unwrap_or(tag);
+    Version::parse(version.trim_start_matches('v')).ok()
+}
+
+fn client() -> Client {
+    Client::builder()
+        .user_agent(concat!("amt-lib/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default()
+}
+
+async fn get(client: &Client, url: &str) -> Result<Vec<u8>, Error> {
+    let request = |source| Error::Request {
+        url: url.to_owned(),
+        source,
+    };
+
+    let response = client.get(url).send().await.map_err(request)?;
+    if !response.status().is_success() {
+        return Err(Error::Status {
+            url: url.to_owned(),
+            status: response.status(),
+        });
+    }
+
+    Ok(response.bytes().await.map_err(request)?.to_vec())
+}
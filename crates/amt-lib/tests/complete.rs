@@ -0,0 +1,74 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use amt_lib::ui::cli::complete::{augment, generate, COMPLETE_ENV};
+use clap::{Command, CommandFactory, Parser};
+
+#[derive(Debug, Parser)]
+enum Action {
+    /// Run the thing
+    Run,
+}
+
+fn command() -> Command {
+    augment(Action::command())
+}
+
+fn run(args: &[&str]) -> Option<String> {
+    let command = command();
+    let matches = command.clone().try_get_matches_from(args).unwrap();
+    let mut out = Vec::new();
+
+    generate(&matches, &command, &mut out)
+        .unwrap()
+        .then(|| String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn generates_completion_registration() {
+    let script = run(&["tool", "completions", "bash"]).unwrap();
+
+    assert!(script.contains(COMPLETE_ENV));
+    assert!(script.contains("complete"));
+}
+
+#[test]
+fn generates_manpage() {
+    let page = run(&["tool", "manpage"]).unwrap();
+
+    assert!(page.contains(".TH"));
+    assert!(page.contains("Run the thing"));
+}
+
+#[test]
+fn ignores_other_commands() {
+    assert_eq!(run(&["tool", "run"]), None);
+}
+
+#[test]
+fn commands_are_hidden() {
+    let mut command = command();
+    let help = command.render_help().to_string();
+
+    assert!(help.contains("run"));
+    assert!(!help.contains("completions"));
+    assert!(!help.contains("manpage"));
+}
+
+#[test]
+fn rejects_unknown_shell() {
+    assert!(command().try_get_matches_from(["tool", "completions", "cmd"]).is_err());
+}
@@ -0,0 +1,211 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+#![cfg(feature = "config")]
+
+use amt_lib::config::{CliChanges, Error, Invalid, Layer, Layered, Loader};
+use clap::{Args, CommandFactory, FromArgMatches, Parser};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Parser, Serialize, Deserialize)]
+#[serde(default)]
+struct TestConfig {
+    #[arg(long, default_value = "default")]
+    name: String,
+    #[arg(long, default_value_t = 3)]
+    level: u8,
+    #[command(flatten)]
+    nested: Nested,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Args, Serialize, Deserialize)]
+#[serde(default)]
+struct Nested {
+    #[arg(long)]
+    enabled: bool,
+    #[arg(long)]
+    paths: Vec<String>,
+}
+
+impl Default for TestConfig {
+    fn default() -> Self {
+        Self {
+            name: "default".to_owned(),
+            level: 3,
+            nested: Nested::default(),
+        }
+    }
+}
+
+impl Layered for TestConfig {
+    const NAME: &'static str = "test-tool";
+
+    fn validate(&self) -> Result<(), Invalid> {
+        match self.level {
+            0..=9 => Ok(()),
+            _ => Err(Invalid::new("level", "must be between 0 and 9")),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Cli {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    level: Option<u8>,
+}
+
+fn write(dir: &Path, name: &str, content: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, content).unwrap();
+    path
+}
+
+fn loader(system: Option<PathBuf>, user: Option<PathBuf>, environment: &[(&str, &str)]) -> Loader<TestConfig> {
+    Loader::new()
+        .with_system_file(system)
+        .with_user_file(user)
+        .with_environment(environment.iter().map(|(k, v)| (k.to_string(), v.to_string())))
+}
+
+#[test]
+fn defaults_without_layers() {
+    let loaded = loader(None, None, &[]).load().unwrap();
+
+    assert_eq!(*loaded, TestConfig::default());
+    assert_eq!(loaded.origin("level"), Some(&Layer::Default));
+}
+
+#[test]
+fn layers_override_in_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let system = write(
+        dir.path(),
+        "system.toml",
+        "name = \"system\"\nlevel = 1\n[nested]\nenabled = true\n",
+    );
+    let user = write(dir.path(), "user.toml", "level = 2\n");
+
+    let loaded = loader(
+        Some(system.clone()),
+        Some(user.clone()),
+        &[("TEST_TOOL_NESTED__PATHS", "[\"/tmp\"]"), ("OTHER_LEVEL", "8")],
+    )
+    .with_cli(&Cli { level: Some(5) })
+    .unwrap()
+    .load()
+    .unwrap();
+
+    assert_eq!(loaded.name, "system");
+    assert_eq!(loaded.level, 5);
+    assert!(loaded.nested.enabled);
+    assert_eq!(loaded.nested.paths, vec!["/tmp".to_owned()]);
+
+    assert_eq!(loaded.origin("name"), Some(&Layer::System(system.clone())));
+    assert_eq!(loaded.origin("level"), Some(&Layer::Cli));
+    assert_eq!(loaded.origin("nested.enabled"), Some(&Layer::System(system)));
+    assert_eq!(
+        loaded.origin("nested"),
+        Some(&Layer::Environment("TEST_TOOL_NESTED__PATHS".to_owned()))
+    );
+
+    let shown = loaded.show();
+    assert!(shown.contains("level = 5 # command line"));
+    assert!(shown.contains("name = \"system\" # system file"));
+}
+
+#[test]
+fn environment_keeps_strings() {
+    let loaded = loader(None, None, &[("TEST_TOOL_NAME", "123"), ("TEST_TOOL_LEVEL", "4")])
+        .load()
+        .unwrap();
+
+    assert_eq!(loaded.name, "123");
+    assert_eq!(loaded.level, 4);
+}
+
+#[test]
+fn missing_files_are_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let loaded = loader(Some(dir.path().join("missing.toml")), None, &[]).load().unwrap();
+
+    assert_eq!(*loaded, TestConfig::default());
+}
+
+#[test]
+fn invalid_type_blames_layer() {
+    let dir = tempfile::tempdir().unwrap();
+    let system = write(dir.path(), "system.toml", "level = 1\n");
+    let user = write(dir.path(), "user.toml", "level = \"high\"\n");
+
+    match loader(Some(system), Some(user.clone()), &[]).load() {
+        Err(Error::Invalid { layer, .. }) => assert_eq!(layer, Layer::User(user)),
+        other => panic!("Expected an invalid error, got {other:?}"),
+    }
+}
+
+#[test]
+fn unparsable_file_blames_layer() {
+    let dir = tempfile::tempdir().unwrap();
+    let system = write(dir.path(), "system.toml", "level = \n");
+
+    match loader(Some(system.clone()), None, &[]).load() {
+        Err(Error::Parse { layer, .. }) => assert_eq!(layer, Layer::System(system)),
+        other => panic!("Expected a parse error, got {other:?}"),
+    }
+}
+
+#[test]
+fn validation_blames_layer() {
+    let err = loader(None, None, &[("TEST_TOOL_LEVEL", "12")]).load().unwrap_err();
+
+    match err {
+        Error::Validation { key, layer, .. } => {
+            assert_eq!(key, "level");
+            assert_eq!(layer, Layer::Environment("TEST_TOOL_LEVEL".to_owned()));
+        }
+        other => panic!("Expected a validation error, got {other:?}"),
+    }
+    assert!(loader(None, None, &[("TEST_TOOL_LEVEL", "12")])
+        .load()
+        .unwrap_err()
+        .to_string()
+        .contains("environment variable TEST_TOOL_LEVEL"));
+}
+
+#[test]
+fn cli_changes_only_override_given_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let user = write(dir.path(), "user.toml", "name = \"user\"\nlevel = 7\n");
+
+    // The level is given at its default, which must still override the user file.
+    let matches = TestConfig::command().get_matches_from(["test-tool", "--level", "3", "--enabled"]);
+    let cli = CliChanges::<TestConfig>::from_arg_matches(&matches).unwrap();
+    let loaded = loader(None, Some(user.clone()), &[])
+        .with_cli_changes(&cli)
+        .unwrap()
+        .load()
+        .unwrap();
+
+    assert_eq!(loaded.name, "user");
+    assert_eq!(loaded.level, 3);
+    assert!(loaded.nested.enabled);
+    assert_eq!(loaded.origin("name"), Some(&Layer::User(user)));
+    assert_eq!(loaded.origin("level"), Some(&Layer::Cli));
+    assert_eq!(loaded.origin("nested.enabled"), Some(&Layer::Cli));
+    assert_eq!(loaded.origin("nested.paths"), Some(&Layer::Default));
+}
@@ -0,0 +1,108 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use amt_lib::ui::cli::error::{finish, Categorise, Category, CliError, ErrorReport, ExitCode};
+use anyhow::{anyhow, Context};
+use std::io;
+
+#[test]
+fn io_errors_are_categorised_by_kind() {
+    let category = |kind| Category::of(&anyhow::Error::new(io::Error::from(kind)));
+
+    assert_eq!(category(io::ErrorKind::PermissionDenied), Category::Permission);
+    assert_eq!(category(io::ErrorKind::NotFound), Category::NoInput);
+    assert_eq!(category(io::ErrorKind::ConnectionRefused), Category::Network);
+    assert_eq!(category(io::ErrorKind::UnexpectedEof), Category::Io);
+}
+
+#[test]
+fn explicit_category_survives_context() {
+    let err = Err::<(), _>(io::Error::from(io::ErrorKind::NotFound))
+        .categorise(Category::Config)
+        .context("Loading configuration")
+        .unwrap_err();
+
+    assert_eq!(Category::of(&err), Category::Config);
+    assert_eq!(Category::Config.exit_code(), ExitCode::Config);
+}
+
+#[test]
+fn unknown_errors_are_internal() {
+    assert_eq!(Category::of(&anyhow!("Something went wrong")), Category::Internal);
+    assert_eq!(
+        Category::of(&CliError::Custom("Unknown command".into()).into()),
+        Category::Internal
+    );
+    assert_eq!(Category::of(&CliError::ParseError("\"".into()).into()), Category::Usage);
+}
+
+#[test]
+fn report_includes_every_cause() {
+    let err = Err::<(), _>(io::Error::new(io::ErrorKind::ConnectionReset, "reset by peer"))
+        .context("Downloading records")
+        .categorise(Category::Network)
+        .context("Updating the cache")
+        .unwrap_err();
+
+    let report = ErrorReport::new(&CliError::Source(err).into());
+    assert_eq!(report.category, Category::Network);
+    assert_eq!(report.code, 69);
+    assert_eq!(report.message, "Updating the cache");
+    assert_eq!(report.causes, vec!["Downloading records", "reset by peer"]);
+    assert_eq!(
+        report.to_string(),
+        "Error: Updating the cache\n\nCaused by:\n    0: Downloading records\n    1: reset by peer"
+    );
+}
+
+#[test]
+fn category_survives_the_cli_wrapper() {
+    let err = Err::<(), _>(anyhow!("3 of 4 backends failed"))
+        .categorise(Category::Partial)
+        .unwrap_err();
+
+    let err = CliError::Source(err).into();
+    assert_eq!(Category::of(&err), Category::Partial);
+
+    let report = ErrorReport::new(&err);
+    assert_eq!(report.code, 75);
+    assert_eq!(report.message, "3 of 4 backends failed");
+}
+
+#[cfg(feature = "ui-cli-formatting")]
+#[test]
+fn report_serialises_category_by_name() {
+    let report = ErrorReport::new(
+        &Err::<(), _>(anyhow!("3 of 4 backends failed"))
+            .categorise(Category::Partial)
+            .unwrap_err(),
+    );
+    let json = serde_json::to_value(&report).unwrap();
+
+    assert_eq!(
+        json,
+        serde_json::json!({ "category": "partial", "code": 75, "message": "3 of 4 backends failed", "causes": [] })
+    );
+}
+
+#[test]
+fn finish_maps_the_outcome_to_an_exit_code() {
+    assert_eq!(finish(Ok::<_, anyhow::Error>(())), ExitCode::Ok);
+    assert_eq!(
+        finish(Err(io::Error::from(io::ErrorKind::PermissionDenied))),
+        ExitCode::NoPerm
+    );
+}
@@ -0,0 +1,148 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use amt_lib::fs::{normalise_path, PathSanitiser, Platform};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+const WINDOWS: PathSanitiser = PathSanitiser::new(Platform::Windows);
+const UNIX: PathSanitiser = PathSanitiser::new(Platform::Unix);
+
+fn windows(path: &str) -> PathBuf {
+    WINDOWS.sanitise(Path::new(path))
+}
+
+#[test]
+fn windows_forbidden_characters_are_replaced() {
+    assert_eq!(
+        windows(r"C:\backups\12:30 <daily>?.zip"),
+        Path::new(r"C:\backups\12_30 _daily__.zip")
+    );
+    assert_eq!(WINDOWS.sanitise_name(OsStr::new("a/b\\c|d\u{1}")), "a_b_c_d_");
+}
+
+#[test]
+fn windows_reserved_names_are_renamed() {
+    assert_eq!(WINDOWS.sanitise_name(OsStr::new("CON")), "CON_");
+    assert_eq!(WINDOWS.sanitise_name(OsStr::new("nul.txt")), "nul_.txt");
+    assert_eq!(WINDOWS.sanitise_name(OsStr::new("com1.tar.gz")), "com1_.tar.gz");
+    assert_eq!(WINDOWS.sanitise_name(OsStr::new("COM0")), "COM0_");
+    assert_eq!(WINDOWS.sanitise_name(OsStr::new("lpt0.log")), "lpt0_.log");
+    assert_eq!(WINDOWS.sanitise_name(OsStr::new("CONIN$")), "CONIN$_");
+    assert_eq!(WINDOWS.sanitise_name(OsStr::new("conout$.txt")), "conout$_.txt");
+    assert_eq!(WINDOWS.sanitise_name(OsStr::new("CONSOLE")), "CONSOLE");
+    assert_eq!(WINDOWS.sanitise_name(OsStr::new("LPT10")), "LPT10");
+}
+
+#[test]
+fn windows_trailing_dots_and_spaces_are_removed() {
+    assert_eq!(WINDOWS.sanitise_name(OsStr::new("report. . ")), "report");
+    assert_eq!(WINDOWS.sanitise_name(OsStr::new("...")), "_");
+}
+
+#[test]
+fn windows_structure_is_kept() {
+    assert_eq!(windows("relative/path/"), Path::new(r"relative\path\"));
+    assert_eq!(windows(r"..\up\.\here"), Path::new(r"..\up\.\here"));
+    assert_eq!(windows(r"\\server\share\file"), Path::new(r"\\server\share\file"));
+    assert_eq!(windows(r"\\?\C:\verbatim"), Path::new(r"\\?\C:\verbatim"));
+}
+
+#[test]
+fn windows_long_paths_are_verbatim() {
+    let long = format!(r"C:\{}\{}", "a".repeat(200), "b".repeat(100));
+    let sanitised = windows(&long).to_string_lossy().into_owned();
+
+    assert_eq!(sanitised, format!(r"\\?\{long}"));
+    assert!(!windows(&format!(r"..\{}", "a".repeat(300)))
+        .to_string_lossy()
+        .starts_with(r"\\?\"));
+}
+
+#[test]
+fn names_are_truncated_keeping_the_extension() {
+    let name = format!("{}.tar.gz", "x".repeat(300));
+    let sanitised = UNIX.sanitise_name(OsStr::new(&name));
+
+    assert_eq!(sanitised.len(), 255);
+    assert!(sanitised.to_string_lossy().ends_with(".gz"));
+
+    let name = "é".repeat(200);
+    let sanitised = UNIX.sanitise_name(OsStr::new(&name));
+    assert_eq!(sanitised.len(), 254);
+    assert!(sanitised.to_str().is_some());
+
+    let sanitised = WINDOWS.sanitise_name(OsStr::new(&name));
+    assert_eq!(sanitised.to_string_lossy().chars().count(), 200);
+}
+
+#[test]
+fn unix_only_replaces_nul() {
+    assert_eq!(
+        UNIX.sanitise(Path::new("/home/user/12:30 <daily>?")),
+        Path::new("/home/user/12:30 <daily>?")
+    );
+    assert_eq!(UNIX.sanitise_name(OsStr::new("a/b\0c")), "a_b_c");
+    assert_eq!(UNIX.with_replacement('-').sanitise_name(OsStr::new("a/b")), "a-b");
+}
+
+#[test]
+fn unix_structure_is_kept() {
+    assert_eq!(UNIX.sanitise(Path::new("relative//path/")), Path::new("relative/path/"));
+    assert_eq!(UNIX.sanitise(Path::new("/abs/../path")), Path::new("/abs/../path"));
+    assert_eq!(UNIX.sanitise(Path::new("")), Path::new(""));
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_non_utf8_names_are_kept() {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let name = OsString::from_vec(vec![b'a', 0xff, 0xfe, b'b']);
+    let path = Path::new("/tmp").join(&name);
+
+    assert_eq!(UNIX.sanitise(&path), path);
+    assert_eq!(
+        normalise_path(path.clone()).as_os_str().as_bytes(),
+        path.as_os_str().as_bytes()
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn normalise_keeps_relative_paths() {
+    assert_eq!(
+        normalise_path(PathBuf::from("backups/today")),
+        Path::new("backups/today")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn windows_non_utf8_names_are_kept() {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let name = OsString::from_vec(vec![b'a', 0xff, b':', 0xfe, b'b', b'.']);
+    assert_eq!(WINDOWS.sanitise_name(&name).as_bytes(), b"a\xff_\xfeb");
+
+    let path = OsString::from_vec(b"C:\\backups\\\xff\xfe<daily>".to_vec());
+    assert_eq!(
+        WINDOWS.sanitise(Path::new(&path)).as_os_str().as_bytes(),
+        b"C:\\backups\\\xff\xfe_daily_"
+    );
+}
@@ -0,0 +1,96 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use amt_lib::log::rolling::SizeRollingWriter;
+use amt_lib::log::LogFlags;
+use clap::Parser;
+use std::fs;
+use std::io::Write;
+
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    log: LogFlags,
+}
+
+#[test]
+fn log_flags_default_matches_clap() {
+    let cli = Cli::parse_from(["test"]);
+    let default = LogFlags::<false>::default();
+
+    assert_eq!(cli.log.log_dir, default.log_dir);
+    assert_eq!(cli.log.log_rotation, default.log_rotation);
+    assert_eq!(cli.log.log_max_size, default.log_max_size);
+    assert_eq!(cli.log.log_retention, default.log_retention);
+    assert_eq!(cli.log.log_format, default.log_format);
+    assert_eq!(cli.log.syslog, default.syslog);
+}
+
+#[test]
+fn size_writer_rotates_and_prunes() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut writer = SizeRollingWriter::new(dir.path(), "test", 10, 2).unwrap();
+
+    for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+        writer.write_all(line.as_bytes()).unwrap();
+    }
+    writer.flush().unwrap();
+
+    assert_eq!(fs::read_to_string(dir.path().join("test.log")).unwrap(), "fourth\n");
+    assert_eq!(fs::read_to_string(dir.path().join("test.1.log")).unwrap(), "third\n");
+    assert_eq!(fs::read_to_string(dir.path().join("test.2.log")).unwrap(), "second\n");
+    assert!(!dir.path().join("test.3.log").exists());
+}
+
+#[test]
+fn size_writer_without_retention_truncates() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut writer = SizeRollingWriter::new(dir.path(), "test", 8, 0).unwrap();
+
+    writer.write_all(b"first\n").unwrap();
+    writer.write_all(b"second\n").unwrap();
+    writer.flush().unwrap();
+
+    assert_eq!(fs::read_to_string(dir.path().join("test.log")).unwrap(), "second\n");
+    assert!(!dir.path().join("test.1.log").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn syslog_sends_prioritised_message() {
+    use amt_lib::log::syslog::Syslog;
+    use std::os::unix::net::UnixDatagram;
+    use tracing_subscriber::fmt::MakeWriter;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log.sock");
+    let server = UnixDatagram::bind(&path).unwrap();
+
+    let syslog = Syslog::connect(&path, "tester").unwrap();
+    let mut writer = syslog.make_writer();
+    writer.write_all(b"something happened\n").unwrap();
+    drop(writer);
+
+    let mut buffer = [0; 256];
+    let len = server.recv(&mut buffer).unwrap();
+    let message = String::from_utf8_lossy(&buffer[..len]);
+
+    assert_eq!(
+        message,
+        format!("<14>tester[{}]: something happened", std::process::id())
+    );
+    assert!(syslog.format(&tracing::Level::ERROR, "failed").starts_with("<11>tester["));
+}
@@ -14,4 +14,20 @@
  * along with this program. If not, see https://www.gnu.org/licenses/.
  */
 
+#[cfg(feature = "ui-cli")]
+mod complete;
+#[cfg(feature = "config")]
+mod config;
+#[cfg(feature = "ui-cli")]
+mod error;
+mod fs;
+mod log;
 mod pathed;
+mod privilege;
+mod progress;
+#[cfg(feature = "ui-repl")]
+mod repl;
+#[cfg(feature = "secret")]
+mod secret;
+#[cfg(feature = "updater")]
+mod updater;
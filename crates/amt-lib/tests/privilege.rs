@@ -0,0 +1,75 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use amt_lib::privilege::{parse_capabilities, Capability, Elevator, Operation, Privileges, ELEVATED_ENV};
+use std::ffi::OsString;
+use std::path::Path;
+
+const STATUS: &str = "Name:\tsys-cleaner
+Uid:\t1000\t1000\t1000\t1000
+CapInh:\t0000000000000000
+CapPrm:\t0000000000400002
+CapEff:\t0000000000400002
+CapBnd:\t000001ffffffffff
+";
+
+#[test]
+fn parses_effective_capabilities() {
+    let mask = parse_capabilities(STATUS).unwrap();
+    assert_eq!(mask, Capability::DacOverride.mask() | Capability::SysBoot.mask());
+    assert_eq!(parse_capabilities("Name:\tinit\n"), None);
+}
+
+#[test]
+fn capabilities_take_precedence_over_the_user() {
+    let unprivileged = Privileges::new(false, parse_capabilities(STATUS));
+    assert!(unprivileged.allows(Operation::Reboot));
+    assert!(unprivileged.allows(Operation::SystemFiles));
+    assert!(!unprivileged.allows(Operation::OtherUsersFiles));
+
+    let contained_root = Privileges::new(true, Some(Capability::Chown.mask()));
+    assert!(!contained_root.has(Capability::SysBoot));
+}
+
+#[test]
+fn falls_back_to_elevation_without_capabilities() {
+    assert!(Privileges::new(true, None).allows(Operation::OtherUsersFiles));
+    assert!(!Privileges::new(false, None).has(Capability::Chown));
+}
+
+#[test]
+fn reports_missing_operations() {
+    let privileges = Privileges::new(false, Some(Capability::SysBoot.mask()));
+    let operations = [Operation::OtherUsersFiles, Operation::SystemFiles, Operation::Reboot];
+
+    assert_eq!(
+        privileges.missing(&operations),
+        vec![Operation::OtherUsersFiles, Operation::SystemFiles]
+    );
+    assert!(Privileges::new(true, None).missing(&operations).is_empty());
+}
+
+#[test]
+fn elevator_reruns_through_env() {
+    let command = Elevator::Pkexec.command(Path::new("/usr/bin/sys-cleaner"), [OsString::from("--dry-run")]);
+    let args = command.get_args().collect::<Vec<_>>();
+
+    assert_eq!(command.get_program(), "pkexec");
+    assert_eq!(
+        args,
+        ["env", &*format!("{ELEVATED_ENV}=1"), "/usr/bin/sys-cleaner", "--dry-run"]
+    );
+}
@@ -0,0 +1,101 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use amt_lib::progress::{channel, Event, Hidden, Kind, Logged, Progress};
+
+#[test]
+fn channel_reports_every_change() {
+    let (progress, events) = channel(Kind::Bar);
+    progress.set_length(3);
+    progress.set_message("Exporting");
+    progress.inc(1);
+    progress.inc(2);
+    progress.finish();
+    drop(progress);
+
+    assert_eq!(
+        events.iter().collect::<Vec<_>>(),
+        vec![
+            Event::Started {
+                id: 0,
+                parent: None,
+                kind: Kind::Bar
+            },
+            Event::Length { id: 0, length: 3 },
+            Event::Message {
+                id: 0,
+                message: "Exporting".into()
+            },
+            Event::Position { id: 0, position: 1 },
+            Event::Position { id: 0, position: 3 },
+            Event::Finished { id: 0 },
+        ]
+    );
+}
+
+#[test]
+fn channel_children_have_their_own_ids() {
+    let (progress, events) = channel(Kind::Spinner);
+    let first = progress.child(Kind::Download);
+    let nested = first.child(Kind::Counter);
+    nested.set_position(5);
+    drop((progress, first, nested));
+
+    assert_eq!(
+        events.iter().collect::<Vec<_>>(),
+        vec![
+            Event::Started {
+                id: 0,
+                parent: None,
+                kind: Kind::Spinner
+            },
+            Event::Started {
+                id: 1,
+                parent: Some(0),
+                kind: Kind::Download
+            },
+            Event::Started {
+                id: 2,
+                parent: Some(1),
+                kind: Kind::Counter
+            },
+            Event::Position { id: 2, position: 5 },
+        ]
+    );
+}
+
+#[test]
+fn channel_ignores_a_dropped_receiver() {
+    let (progress, events) = channel(Kind::Spinner);
+    drop(events);
+
+    progress.set_message("Nobody is listening");
+    progress.child(Kind::Bar).inc(1);
+    progress.finish();
+}
+
+#[test]
+fn reporters_work_through_a_trait_object() {
+    let reporters: [Box<dyn Progress>; 2] = [Box::new(Hidden), Box::new(Logged::default())];
+    for progress in reporters {
+        progress.set_length(2);
+        progress.set_message("Working");
+        let child = progress.child(Kind::Download);
+        child.inc(1);
+        child.finish();
+        progress.finish();
+    }
+}
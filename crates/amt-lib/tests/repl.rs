@@ -0,0 +1,86 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+#![cfg(feature = "ui-repl")]
+
+use amt_lib::ui::cli::flags::CommonFlags;
+use amt_lib::ui::cli::repl::{complete, Builtin};
+use clap::{Command, CommandFactory, Parser};
+
+#[derive(Debug, Parser)]
+enum Action {
+    /// Run the thing
+    Run {
+        #[arg(long)]
+        force: bool,
+
+        #[arg(long)]
+        dry: bool,
+    },
+
+    /// Remove the thing
+    Remove,
+
+    #[command(hide = true)]
+    Secret,
+}
+
+fn command() -> Command {
+    Action::command().no_binary_name(true)
+}
+
+#[test]
+fn parses_builtins() {
+    assert_eq!(Builtin::parse("exit"), Some(Builtin::Exit));
+    assert_eq!(Builtin::parse("quit"), Some(Builtin::Exit));
+    assert_eq!(Builtin::parse("help"), Some(Builtin::Help(None)));
+    assert_eq!(Builtin::parse("help run"), Some(Builtin::Help(Some("run".to_owned()))));
+    assert_eq!(Builtin::parse("set verbose 2"), Some(Builtin::SetVerbose(2)));
+    assert!(matches!(Builtin::parse("set verbose loud"), Some(Builtin::Invalid(_))));
+    assert_eq!(Builtin::parse("run --force"), None);
+}
+
+#[test]
+fn set_verbose_updates_session_flags() {
+    let mut flags = CommonFlags::default();
+
+    assert!(!Builtin::SetVerbose(3).run(&command(), &mut flags).unwrap());
+    assert_eq!(flags.verbose, 3);
+    assert!(Builtin::Exit.run(&command(), &mut flags).unwrap());
+}
+
+#[test]
+fn completes_commands_and_builtins() {
+    let (start, candidates) = complete(&command(), "r", 1);
+    assert_eq!(start, 0);
+    assert_eq!(candidates, vec!["remove", "run"]);
+
+    let (_, candidates) = complete(&command(), "", 0);
+    assert!(candidates.contains(&"exit".to_owned()));
+    assert!(!candidates.contains(&"secret".to_owned()));
+}
+
+#[test]
+fn completes_flags_of_subcommand() {
+    let line = "run --f";
+    let (start, candidates) = complete(&command(), line, line.len());
+
+    assert_eq!(start, 4);
+    assert_eq!(candidates, vec!["--force"]);
+
+    let (_, candidates) = complete(&command(), "run ", 4);
+    assert!(!candidates.contains(&"exit".to_owned()));
+}
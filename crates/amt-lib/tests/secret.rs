@@ -0,0 +1,111 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+#![cfg(feature = "secret")]
+
+use amt_lib::secret::{EncryptedFile, Error, Secret, SecretStore};
+use std::fs;
+
+fn passphrase(value: &str) -> Secret<String> {
+    Secret::new(value.to_owned())
+}
+
+#[test]
+fn secrets_are_redacted() {
+    let secret = Secret::new("hunter2".to_string());
+
+    assert_eq!(format!("{secret}"), "[REDACTED]");
+    assert!(!format!("{secret:?}").contains("hunter2"));
+    assert_eq!(secret.expose(), "hunter2");
+}
+
+#[test]
+fn secrets_deserialize_from_their_value() {
+    let secret: Secret<String> = serde_json::from_str(r#""hunter2""#).unwrap();
+    assert_eq!(secret.expose(), "hunter2");
+}
+
+#[tokio::test]
+async fn file_store_round_trips() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("nested").join("tool.secrets");
+
+    let mut store = EncryptedFile::open(&path, &passphrase("correct horse")).unwrap();
+    assert!(store.get("api-key").await.unwrap().is_none());
+    store.set("api-key", &Secret::new("abc123".into())).await.unwrap();
+
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(!contents.contains("abc123"));
+
+    let mut store = EncryptedFile::open(&path, &passphrase("correct horse")).unwrap();
+    assert_eq!(store.get("api-key").await.unwrap().unwrap().expose(), "abc123");
+    assert_eq!(store.keys().collect::<Vec<_>>(), ["api-key"]);
+
+    assert!(store.remove("api-key").await.unwrap());
+    assert!(!store.remove("api-key").await.unwrap());
+    assert!(store.get("api-key").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn file_store_rejects_the_wrong_passphrase() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("tool.secrets");
+
+    let mut store = EncryptedFile::open(&path, &passphrase("correct horse")).unwrap();
+    store.set("api-key", &Secret::new("abc123".into())).await.unwrap();
+
+    let result = EncryptedFile::open(&path, &passphrase("battery staple"));
+    assert!(matches!(result, Err(Error::Decrypt(_))));
+}
+
+#[tokio::test]
+async fn file_store_binds_values_to_their_name() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("tool.secrets");
+
+    let mut store = EncryptedFile::open(&path, &passphrase("correct horse")).unwrap();
+    store.set("first", &Secret::new("one".into())).await.unwrap();
+    store.set("second", &Secret::new("two".into())).await.unwrap();
+
+    let mut contents: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    let entries = contents["entries"].as_object_mut().unwrap();
+    let first = entries["first"].clone();
+    entries.insert("second".into(), first);
+    fs::write(&path, contents.to_string()).unwrap();
+
+    let store = EncryptedFile::open(&path, &passphrase("correct horse")).unwrap();
+    assert!(matches!(store.get("second").await, Err(Error::Decrypt(key)) if key == "second"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn file_store_is_only_readable_by_the_owner() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("tool.secrets");
+
+    let mut store = EncryptedFile::open(&path, &passphrase("correct horse")).unwrap();
+    store.set("api-key", &Secret::new("abc123".into())).await.unwrap();
+
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+    // Every write restricts the store again, not only the one which created it.
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+    store.set("api-key", &Secret::new("def456".into())).await.unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 1);
+}
@@ -0,0 +1,217 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+#![cfg(feature = "updater")]
+
+use amt_lib::updater::signature;
+use amt_lib::updater::source::{parse_tag, Mirror};
+use amt_lib::updater::{Channel, Error, Updater};
+use ed25519_dalek::{Signer, SigningKey};
+use semver::Version;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const NAME: &str = "tool";
+
+/// Serves the files over HTTP on localhost, returning the base url.
+async fn serve(files: HashMap<String, Vec<u8>>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let files = Arc::new(files);
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let files = files.clone();
+
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await.unwrap() {
+                        0 => return,
+                        read => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split_whitespace().nth(1).unwrap_or("/");
+                let (status, body) = match files.get(path) {
+                    Some(body) => ("200 OK", body.clone()),
+                    None => ("404 Not Found", vec![]),
+                };
+
+                let header = format!(
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(header.as_bytes()).await.unwrap();
+                stream.write_all(&body).await.unwrap();
+            });
+        }
+    });
+
+    format!("http://{address}/")
+}
+
+fn key() -> SigningKey {
+    SigningKey::from_bytes(&[7; 32])
+}
+
+fn public_key() -> String {
+    hex::encode(key().verifying_key().as_bytes())
+}
+
+/// Publishes a stable 1.1.0 and a beta 2.0.0-beta.1 release, both signed.
+fn releases(asset: &str) -> HashMap<String, Vec<u8>> {
+    let mut files = HashMap::new();
+    let mut entries = vec![];
+
+    for (version, prerelease) in [("1.1.0", false), ("2.0.0-beta.1", true)] {
+        let binary = format!("binary {version}").into_bytes();
+        let message = signature::message(&Version::parse(version).unwrap(), &binary);
+        let signature = hex::encode(key().sign(&message).to_bytes());
+
+        files.insert(format!("/{NAME}/{version}/{asset}"), binary);
+        files.insert(format!("/{NAME}/{version}/{asset}.sig"), signature.into_bytes());
+        entries.push(serde_json::json!({
+            "version": version,
+            "prerelease": prerelease,
+            "assets": [
+                { "name": asset, "url": format!("{version}/{asset}") },
+                { "name": format!("{asset}.sig"), "url": format!("{version}/{asset}.sig") },
+            ]
+        }));
+    }
+
+    files.insert(format!("/{NAME}/releases.json"), serde_json::to_vec(&entries).unwrap());
+    files
+}
+
+async fn updater(files: HashMap<String, Vec<u8>>, executable: &std::path::Path) -> Updater {
+    let base = serve(files).await;
+
+    Updater::new(NAME, "1.0.0")
+        .with_source(Mirror::new(&base).unwrap())
+        .with_public_key(&public_key())
+        .with_executable(executable.to_path_buf())
+}
+
+#[test]
+fn parses_tags() {
+    assert_eq!(parse_tag(NAME, "tool-v1.2.3"), Some(Version::new(1, 2, 3)));
+    assert_eq!(parse_tag(NAME, "v1.2.3"), Some(Version::new(1, 2, 3)));
+    assert_eq!(parse_tag(NAME, "other-v1.2.3"), None);
+}
+
+#[tokio::test]
+async fn channels_select_release() {
+    let dir = tempfile::tempdir().unwrap();
+    let asset = Updater::new(NAME, "1.0.0").asset_name();
+    let updater = updater(releases(&asset), &dir.path().join(NAME)).await;
+
+    let stable = updater.check().await.unwrap().unwrap();
+    assert_eq!(stable.version, Version::new(1, 1, 0));
+
+    let beta = updater.with_channel(Channel::Beta).check().await.unwrap().unwrap();
+    assert_eq!(beta.version, Version::parse("2.0.0-beta.1").unwrap());
+}
+
+#[tokio::test]
+async fn updates_and_rolls_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let executable = dir.path().join(NAME);
+    fs::write(&executable, "binary 1.0.0").unwrap();
+
+    let asset = Updater::new(NAME, "1.0.0").asset_name();
+    let updater = updater(releases(&asset), &executable).await;
+
+    assert_eq!(updater.update().await.unwrap(), Some(Version::new(1, 1, 0)));
+    assert_eq!(fs::read_to_string(&executable).unwrap(), "binary 1.1.0");
+
+    updater.rollback().unwrap();
+    assert_eq!(fs::read_to_string(&executable).unwrap(), "binary 1.0.0");
+
+    updater.rollback().unwrap();
+    assert_eq!(fs::read_to_string(&executable).unwrap(), "binary 1.1.0");
+}
+
+#[tokio::test]
+async fn rejects_tampered_binary() {
+    let dir = tempfile::tempdir().unwrap();
+    let executable = dir.path().join(NAME);
+    fs::write(&executable, "binary 1.0.0").unwrap();
+
+    let asset = Updater::new(NAME, "1.0.0").asset_name();
+    let mut files = releases(&asset);
+    files.insert(format!("/{NAME}/1.1.0/{asset}"), b"tampered".to_vec());
+    let updater = updater(files, &executable).await;
+
+    assert!(matches!(updater.update().await, Err(Error::InvalidSignature { .. })));
+    assert_eq!(fs::read_to_string(&executable).unwrap(), "binary 1.0.0");
+}
+
+#[tokio::test]
+async fn rejects_signature_of_other_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let executable = dir.path().join(NAME);
+    fs::write(&executable, "binary 1.0.0").unwrap();
+
+    // An older release replayed as a newer one, with its genuine binary and signature.
+    let asset = Updater::new(NAME, "1.0.0").asset_name();
+    let mut files = releases(&asset);
+    let older = b"binary 1.0.0".to_vec();
+    let signature = key().sign(&signature::message(&Version::new(1, 0, 0), &older));
+    files.insert(format!("/{NAME}/1.1.0/{asset}"), older);
+    files.insert(
+        format!("/{NAME}/1.1.0/{asset}.sig"),
+        hex::encode(signature.to_bytes()).into_bytes(),
+    );
+    let updater = updater(files, &executable).await;
+
+    assert!(matches!(updater.update().await, Err(Error::InvalidSignature { .. })));
+}
+
+#[tokio::test]
+async fn rejects_missing_signature() {
+    let dir = tempfile::tempdir().unwrap();
+    let executable = dir.path().join(NAME);
+    fs::write(&executable, "binary 1.0.0").unwrap();
+
+    let asset = Updater::new(NAME, "1.0.0").asset_name();
+    let mut files = releases(&asset);
+    let manifest = serde_json::json!([{
+        "version": "1.1.0",
+        "assets": [{ "name": asset, "url": format!("1.1.0/{asset}") }]
+    }]);
+    files.insert(format!("/{NAME}/releases.json"), serde_json::to_vec(&manifest).unwrap());
+    let updater = updater(files, &executable).await;
+
+    assert!(matches!(updater.update().await, Err(Error::MissingSignature { .. })));
+}
+
+#[test]
+fn rollback_without_backup_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let executable = dir.path().join(NAME);
+    fs::write(&executable, "binary 1.0.0").unwrap();
+
+    let updater = Updater::new(NAME, "1.0.0").with_executable(executable);
+    assert!(matches!(updater.rollback(), Err(Error::NoBackup(_))));
+}
@@ -15,16 +15,23 @@
  */
 
 use crate::config::runtime::Runtime;
+use crate::report::BackendReport;
 use crate::sources::auto_prune::Prune;
 use crate::sources::downloader::Downloader;
 use crate::sources::exporter::Exporter;
-use anyhow::Result;
-use indicatif::{MultiProgress, ProgressBar};
+use crate::store::ChunkStore;
+use amt_lib::pathed::Pathed;
+use amt_lib::progress::Progress;
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
 #[cfg(feature = "sources-bitwarden")]
 use crate::sources::bitwarden::BitWardenCore;
+#[cfg(feature = "sources-command")]
+use crate::sources::command::CommandCore;
+#[cfg(feature = "sources-imap")]
+use crate::sources::imap::ImapCore;
 #[cfg(feature = "sources-1password")]
 use crate::sources::op::core::OnePasswordCore;
 #[cfg(feature = "sources-s3")]
@@ -39,6 +46,10 @@ pub enum Backend {
     BitWarden(BitWardenCore),
     #[cfg(feature = "sources-1password")]
     OnePassword(OnePasswordCore),
+    #[cfg(feature = "sources-imap")]
+    Imap(ImapCore),
+    #[cfg(feature = "sources-command")]
+    Command(CommandCore),
 }
 
 impl Display for Backend {
@@ -50,37 +61,173 @@ impl Display for Backend {
             Backend::BitWarden(bw) => write!(f, "BitWarden ({})", &bw.org_name),
             #[cfg(feature = "sources-1password")]
             Backend::OnePassword(op) => write!(f, "1Password ({})", &op.account),
+            #[cfg(feature = "sources-imap")]
+            Backend::Imap(imap) => write!(f, "IMAP ({}@{})", &imap.username, &imap.host),
+            #[cfg(feature = "sources-command")]
+            Backend::Command(command) => write!(f, "Command ({})", &command.name),
         }
     }
 }
 
 impl Backend {
-    pub async fn run(
-        mut self,
-        config: &Runtime,
-        main_bar: &ProgressBar,
-        progress_bar: &MultiProgress,
-    ) -> Result<Backend> {
+    /// The name used to select this backend on the command line.
+    pub fn name(&self) -> String {
         match self {
             #[cfg(feature = "sources-s3")]
-            Backend::S3(ref mut core) => {
-                core.prune(config, progress_bar)?;
-                core.export(config, main_bar, progress_bar).await?;
+            Backend::S3(core) => core.get_unique_name(),
+            #[cfg(feature = "sources-bitwarden")]
+            Backend::BitWarden(core) => core.get_unique_name(),
+            #[cfg(feature = "sources-1password")]
+            Backend::OnePassword(core) => core.get_unique_name(),
+            #[cfg(feature = "sources-imap")]
+            Backend::Imap(core) => core.get_unique_name(),
+            #[cfg(feature = "sources-command")]
+            Backend::Command(core) => core.get_unique_name(),
+        }
+    }
+
+    /// Prunes and then exports this backend,
+    /// any error is recorded in the returned report rather than being returned.
+    pub async fn run(mut self, config: &Runtime, progress: &dyn Progress) -> BackendReport {
+        let mut report = BackendReport::new(&self);
+        if let Err(err) = self._run(config, progress, &mut report).await {
+            report.error(&err);
+        }
+
+        if let Err(err) = self.store(config, &mut report) {
+            report.error(&err);
+        }
+
+        report
+    }
+
+    /// Moves the exported artefacts into the chunk store if it's enabled,
+    /// and forgets the manifests of any pruned artefacts.
+    fn store(&self, config: &Runtime, report: &mut BackendReport) -> Result<()> {
+        let Some(store) = ChunkStore::open(config) else {
+            return Ok(());
+        };
+
+        for pruned in &report.pruned {
+            store.forget(pruned)?;
+        }
+
+        // The IMAP source syncs incrementally against the files it has already written.
+        #[cfg(feature = "sources-imap")]
+        if matches!(self, Backend::Imap(_)) {
+            return Ok(());
+        }
+
+        for file in &report.files {
+            store.ingest(file).with_context(|| format!("Storing {}", file.display()))?;
+        }
+
+        Ok(())
+    }
+
+    async fn _run(&mut self, config: &Runtime, progress: &dyn Progress, report: &mut BackendReport) -> Result<()> {
+        match self {
+            #[cfg(feature = "sources-s3")]
+            Backend::S3(core) => {
+                report.pruned = core.prune(config, progress)?;
+                report.files = core.export(config, progress).await?;
             }
             #[cfg(feature = "sources-bitwarden")]
-            Backend::BitWarden(ref mut core) => {
-                BitWardenCore::download_cli(config, main_bar, progress_bar).await?;
-                core.prune(config, progress_bar)?;
-                core.export(config, main_bar, progress_bar).await?;
+            Backend::BitWarden(core) => {
+                BitWardenCore::download_cli(config, progress).await?;
+                report.pruned = core.prune(config, progress)?;
+                report.files = core.export(config, progress).await?;
             }
             #[cfg(feature = "sources-1password")]
-            Backend::OnePassword(ref mut core) => {
-                OnePasswordCore::download_cli(config, main_bar, progress_bar).await?;
-                core.prune(config, progress_bar)?;
-                core.export(config, main_bar, progress_bar).await?;
+            Backend::OnePassword(core) => {
+                OnePasswordCore::download_cli(config, progress).await?;
+                report.pruned = core.prune(config, progress)?;
+                report.files = core.export(config, progress).await?;
+            }
+            #[cfg(feature = "sources-imap")]
+            Backend::Imap(core) => {
+                report.pruned = core.prune(config, progress)?;
+                report.files = core.export(config, progress).await?;
+            }
+            #[cfg(feature = "sources-command")]
+            Backend::Command(core) => {
+                report.pruned = core.prune(config, progress)?;
+                report.files = core.export(config, progress).await?;
             }
         }
 
-        Ok(self)
+        Ok(())
+    }
+
+    /// Only runs the pruning step for this backend.
+    pub fn prune(&self, config: &Runtime, progress: &dyn Progress) -> BackendReport {
+        let mut report = BackendReport::new(self);
+        match self.pruner().prune(config, progress) {
+            Ok(pruned) => report.pruned = pruned,
+            Err(err) => report.error(&err),
+        }
+
+        if let Some(store) = ChunkStore::open(config) {
+            for pruned in &report.pruned {
+                if let Err(err) = store.forget(pruned) {
+                    report.error(&err);
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Checks that every existing artefact of this backend is a readable, non-empty file,
+    /// or a stored artefact with all of its chunks present.
+    pub fn verify(&self, config: &Runtime) -> BackendReport {
+        let mut report = BackendReport::new(self);
+        let files = match self.pruner().files(config) {
+            Ok(files) => files,
+            Err(err) => {
+                report.error(&err);
+                return report;
+            }
+        };
+
+        let store = ChunkStore::open(config);
+        for file in files {
+            if let Some(store) = store.as_ref().filter(|_| !file.exists()) {
+                match store.stored(&file) {
+                    Ok(Some(manifest)) if manifest.size > 0 && store.is_complete(&manifest) => {}
+                    Ok(Some(_)) => report.error(&anyhow!("{} is empty or missing chunks", file.display())),
+                    Ok(None) => report.error(&anyhow!("{} is neither on disk nor stored", file.display())),
+                    Err(err) => report.error(&err),
+                }
+
+                report.files.push(file);
+                continue;
+            }
+
+            match file.metadata() {
+                Ok(meta) if meta.is_file() && meta.len() > 0 => {}
+                Ok(_) => report.error(&anyhow!("{} is empty or not a file", file.display())),
+                Err(err) => report.error(&anyhow!(err).context(format!("Reading metadata of {}", file.display()))),
+            }
+
+            report.files.push(file);
+        }
+
+        report
+    }
+
+    fn pruner(&self) -> &dyn Prune {
+        match self {
+            #[cfg(feature = "sources-s3")]
+            Backend::S3(core) => core,
+            #[cfg(feature = "sources-bitwarden")]
+            Backend::BitWarden(core) => core,
+            #[cfg(feature = "sources-1password")]
+            Backend::OnePassword(core) => core,
+            #[cfg(feature = "sources-imap")]
+            Backend::Imap(core) => core,
+            #[cfg(feature = "sources-command")]
+            Backend::Command(core) => core,
+        }
     }
 }
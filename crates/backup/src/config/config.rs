@@ -16,8 +16,8 @@
 
 use crate::config::backend::Backend;
 use crate::config::rules::Rules;
+use crate::store::StoreSettings;
 use anyhow::Result;
-use futures_util::TryFutureExt;
 use std::io;
 use std::path::{Path, PathBuf};
 use tokio::fs;
@@ -63,6 +63,10 @@ pub struct Config {
     pub rules: Rules,
     pub exporters: Vec<Backend>,
 
+    /// The settings for the chunk store, artefacts are only deduplicated when this is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store: Option<StoreSettings>,
+
     #[serde(skip)]
     pub path: Option<PathBuf>,
 }
@@ -76,6 +80,7 @@ impl Config {
             mutated: true,
             rules: Rules::default(),
             exporters: vec![],
+            store: None,
             path: Some(parent_directory.join(Self::FILENAME)),
         }
     }
@@ -173,16 +178,25 @@ impl Config {
         }
 
         trace!("Saving config to {}", path.display());
-        match fs::File::create(path).await.map_err(Error::IoFile) {
-            Err(err) => {
-                error!("Failed to create config file: {err}");
-                Err(err.into())
-            }
-            Ok(mut file) => {
-                let serde = serde_json::to_vec_pretty(&self).map_err(Error::Serde)?;
-                file.write_all(serde.as_slice()).map_err(Error::IoFile).await?;
-                Ok(())
-            }
+        let serde = serde_json::to_vec_pretty(&self).map_err(Error::Serde)?;
+
+        // Write the whole file next to the existing one and swap it in,
+        // so an interrupted save never leaves a truncated configuration behind.
+        let temp = path.with_file_name(format!(".{}.tmp", Self::FILENAME));
+        let result = async {
+            let mut file = fs::File::create(&temp).await?;
+            file.write_all(serde.as_slice()).await?;
+            file.sync_all().await?;
+            fs::rename(&temp, path).await
         }
+        .await;
+
+        if let Err(err) = result {
+            error!("Failed to save config file: {err}");
+            let _ = fs::remove_file(&temp).await;
+            return Err(Error::IoFile(err).into());
+        }
+
+        Ok(())
     }
 }
@@ -0,0 +1,326 @@
+/*
+ * Copyright (c) 2024. James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program.
+ * If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{} is locked by process {} on {} since {}, use --wait to wait for it", .path.display(), .owner.pid, .owner.hostname, .owner.created)]
+    Locked { path: PathBuf, owner: LockInfo },
+
+    #[error("Failed to create lock file {}", .0.display())]
+    Io(PathBuf, #[source] io::Error),
+}
+
+/// Who holds the lock, written into the lock file so that stale locks can be detected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockInfo {
+    pub pid: u32,
+    pub hostname: String,
+    pub created: DateTime<Utc>,
+}
+
+impl LockInfo {
+    fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+            created: Utc::now(),
+        }
+    }
+
+    /// If the owner of the lock is gone.
+    ///
+    /// On the same host a missing process rules the owner out straight away,
+    /// otherwise the lock is only stale once it's old enough, as a live process may
+    /// have reused the owner's id, or there may be no way to check for it at all.
+    pub fn is_stale(&self) -> bool {
+        if self.hostname == Self::current().hostname && !process_exists(self.pid) {
+            return true;
+        }
+
+        Utc::now().signed_duration_since(self.created) > Lock::STALE_AFTER
+    }
+}
+
+/// An advisory lock on a backup directory, released when dropped.
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    pub const FILENAME: &'static str = ".backup.lock";
+
+    /// How old a lock must be before it's considered abandoned, when its owner can't be ruled out.
+    pub const STALE_AFTER: Duration = Duration::hours(24);
+
+    /// How often the lock is retried while waiting.
+    const POLL: std::time::Duration = std::time::Duration::from_secs(1);
+
+    /// Acquires the lock for the directory,
+    /// if `wait` is set this blocks until the current owner releases it.
+    pub async fn acquire(directory: &Path, wait: bool) -> Result<Self> {
+        let path = directory.join(Self::FILENAME);
+        let mut waiting = false;
+
+        loop {
+            match Self::try_acquire(&path)? {
+                Ok(lock) => return Ok(lock),
+                Err(owner) if owner.is_stale() => {
+                    warn!(
+                        "Removing stale lock held by process {} on {} since {}",
+                        owner.pid, owner.hostname, owner.created
+                    );
+                    Self::remove_stale(&path, &owner)?;
+                }
+                Err(owner) if !wait => return Err(Error::Locked { path, owner }.into()),
+                Err(owner) => {
+                    if !waiting {
+                        info!(
+                            "Waiting for process {} on {} to release the lock",
+                            owner.pid, owner.hostname
+                        );
+                        waiting = true;
+                    }
+
+                    tokio::time::sleep(Self::POLL).await;
+                }
+            }
+        }
+    }
+
+    /// Attempts to create the lock file once.
+    ///
+    /// # Returns
+    /// The lock if it was created, otherwise the current owner.
+    fn try_acquire(path: &Path) -> Result<Result<Self, LockInfo>> {
+        let info = LockInfo::current();
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                let lock = Self {
+                    path: path.to_path_buf(),
+                };
+                file.write_all(&serde_json::to_vec_pretty(&info)?)
+                    .map_err(|err| Error::Io(path.to_path_buf(), err))?;
+
+                debug!("Acquired lock {}", path.display());
+                Ok(Ok(lock))
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(Err(Self::owner(path))),
+            Err(err) => Err(Error::Io(path.to_path_buf(), err).into()),
+        }
+    }
+
+    /// Reads the owner from an existing lock file.
+    fn owner(path: &Path) -> LockInfo {
+        let owner = fs::read(path)
+            .ok()
+            .and_then(|slice| serde_json::from_slice::<LockInfo>(&slice).ok());
+
+        owner.unwrap_or_else(|| {
+            // The owner may be between creating and writing the file, treat it as fresh
+            // unless it's old enough that no writer could still be working on it.
+            let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+            let created = modified.map_or_else(Utc::now, DateTime::<Utc>::from);
+            let abandoned = Utc::now().signed_duration_since(created) > Duration::minutes(1);
+
+            LockInfo {
+                pid: 0,
+                hostname: String::from("unknown"),
+                created: if abandoned {
+                    created - Self::STALE_AFTER
+                } else {
+                    created
+                },
+            }
+        })
+    }
+
+    /// Removes a stale lock without racing another process doing the same.
+    ///
+    /// The lock is atomically renamed aside before being removed,
+    /// if what was moved isn't the stale lock then another process has already replaced it,
+    /// so it's linked back into place instead of being deleted.
+    fn remove_stale(path: &Path, stale: &LockInfo) -> Result<()> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let mut aside = path.as_os_str().to_os_string();
+        aside.push(format!(".{}-{nanos}.stale", std::process::id()));
+        let aside = PathBuf::from(aside);
+
+        match fs::rename(path, &aside) {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(Error::Io(path.to_path_buf(), err).into()),
+        }
+
+        let restored = match Self::owner(&aside) == *stale {
+            true => Ok(()),
+            false => {
+                debug!("Lock {} was replaced while removing it, restoring it", path.display());
+                fs::hard_link(&aside, path)
+            }
+        };
+
+        if let Err(err) = fs::remove_file(&aside) {
+            warn!("Failed to remove stale lock {}: {err}", aside.display());
+        }
+
+        match restored {
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => Err(Error::Io(path.to_path_buf(), err).into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        match fs::remove_file(&self.path) {
+            Ok(_) => debug!("Released lock {}", self.path.display()),
+            Err(err) => warn!("Failed to release lock {}: {err}", self.path.display()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn process_exists(pid: u32) -> bool {
+    use rustix::io::Errno;
+    use rustix::process::{test_kill_process, Pid};
+
+    let Some(pid) = i32::try_from(pid).ok().and_then(Pid::from_raw) else {
+        return false;
+    };
+
+    // Permission denied still means there is a process with that id.
+    matches!(test_kill_process(pid), Ok(_) | Err(Errno::PERM))
+}
+
+#[cfg(not(unix))]
+fn process_exists(_pid: u32) -> bool {
+    // Without a way to check, assume the owner is alive and rely on it releasing the lock.
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_owner(directory: &Path, owner: &LockInfo) {
+        fs::write(directory.join(Lock::FILENAME), serde_json::to_vec(owner).unwrap()).unwrap();
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn second_acquire_fails() {
+        let directory = tempfile::tempdir().unwrap();
+        let lock = Lock::acquire(directory.path(), false).await.unwrap();
+
+        let err = Lock::acquire(directory.path(), false).await.unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<Error>(), Some(Error::Locked { owner, .. }) if owner.pid == std::process::id())
+        );
+
+        drop(lock);
+        assert!(!directory.path().join(Lock::FILENAME).exists());
+        Lock::acquire(directory.path(), false).await.unwrap();
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn wait_for_release() {
+        let directory = tempfile::tempdir().unwrap();
+        let lock = Lock::acquire(directory.path(), false).await.unwrap();
+
+        let waiter = tokio::spawn({
+            let directory = directory.path().to_path_buf();
+            async move { Lock::acquire(&directory, true).await }
+        });
+
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(!waiter.is_finished());
+
+        drop(lock);
+        waiter.await.unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test_log::test(tokio::test)]
+    async fn stale_lock_from_dead_process() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut owner = LockInfo::current();
+        owner.pid = i32::MAX as u32;
+        write_owner(directory.path(), &owner);
+
+        Lock::acquire(directory.path(), false).await.unwrap();
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn stale_lock_from_reused_pid() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut owner = LockInfo::current();
+        write_owner(directory.path(), &owner);
+        assert!(Lock::acquire(directory.path(), false).await.is_err());
+
+        owner.created -= Lock::STALE_AFTER + Duration::minutes(1);
+        write_owner(directory.path(), &owner);
+        Lock::acquire(directory.path(), false).await.unwrap();
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn stale_lock_from_other_host() {
+        let directory = tempfile::tempdir().unwrap();
+        let owner = LockInfo {
+            pid: 1,
+            hostname: "some-other-host".into(),
+            created: Utc::now(),
+        };
+        write_owner(directory.path(), &owner);
+        assert!(Lock::acquire(directory.path(), false).await.is_err());
+
+        write_owner(
+            directory.path(),
+            &LockInfo {
+                created: Utc::now() - Lock::STALE_AFTER - Duration::minutes(1),
+                ..owner
+            },
+        );
+        Lock::acquire(directory.path(), false).await.unwrap();
+    }
+
+    #[test]
+    fn replaced_stale_lock_is_restored() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join(Lock::FILENAME);
+        let fresh = LockInfo::current();
+        write_owner(directory.path(), &fresh);
+
+        let stale = LockInfo {
+            created: fresh.created - Lock::STALE_AFTER,
+            ..fresh.clone()
+        };
+        Lock::remove_stale(&path, &stale).unwrap();
+        assert_eq!(Lock::owner(&path), fresh);
+
+        Lock::remove_stale(&path, &fresh).unwrap();
+        assert!(!path.exists());
+        assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 0);
+    }
+}
@@ -17,5 +17,7 @@
 pub mod backend;
 #[allow(clippy::module_inception)] // FIXME
 pub mod config;
+pub mod lock;
 pub mod rules;
 pub mod runtime;
+pub mod secrets;
@@ -17,7 +17,7 @@
 use crate::config::rules::metadata::Metadata;
 use crate::config::rules::rule::Rule;
 use anyhow::{Context, Result};
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Datelike, Duration, Utc};
 use fs_err as fs;
 use macros::{EnumNames, EnumRegex, EnumVariants};
 use obj_builder::builder;
@@ -248,6 +248,49 @@ builder!(#[derive(Copy, PartialEq, Serialize, Deserialize)] AutoPrune {
 });
 
 impl AutoPrune {
+    /// Selects the artefacts which are outside of every retention window and should be removed.
+    ///
+    /// The newest `keep_latest` artefacts are always kept, along with the newest artefact
+    /// from each of the latest `hours` hours, `days` days, `weeks` weeks and `months` months which have one.
+    pub fn expired(&self, mut artefacts: Vec<(PathBuf, DateTime<Utc>)>) -> Vec<PathBuf> {
+        artefacts.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        let windows: [(usize, fn(&DateTime<Utc>) -> i64); 4] = [
+            (self.hours, |time| time.timestamp().div_euclid(3600)),
+            (self.days, |time| time.num_days_from_ce() as i64),
+            (self.weeks, |time| {
+                let week = time.iso_week();
+                week.year() as i64 * 100 + week.week() as i64
+            }),
+            (self.months, |time| time.year() as i64 * 12 + time.month0() as i64),
+        ];
+
+        let mut keep = (0..artefacts.len()).map(|index| index < self.keep_latest).collect::<Vec<_>>();
+        for (count, period) in windows {
+            let mut last = None;
+            let mut seen = 0;
+            for (index, (_, modified)) in artefacts.iter().enumerate() {
+                if seen == count {
+                    break;
+                }
+
+                let current = period(modified);
+                if last != Some(current) {
+                    last = Some(current);
+                    keep[index] = true;
+                    seen += 1;
+                }
+            }
+        }
+
+        artefacts
+            .into_iter()
+            .zip(keep)
+            .filter(|(_, keep)| !keep)
+            .map(|((path, _), _)| path)
+            .collect()
+    }
+
     /// This will iterate over the files, removing the tags from the oldest
     /// files until the maximum number of backups for its tag is reached.
     #[instrument(level = "TRACE")]
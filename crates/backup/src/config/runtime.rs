@@ -15,7 +15,11 @@
  */
 
 use crate::config::config::Config;
+use crate::config::lock::Lock;
+use crate::config::secrets::Secrets;
+use anyhow::Result;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct Runtime {
@@ -25,6 +29,12 @@ pub struct Runtime {
 
     /// The configuration for the backup(s).
     pub config: Config,
+
+    /// The lock on the directory, held for as long as the runtime exists.
+    pub lock: Option<Arc<Lock>>,
+
+    /// The secrets of the backends, shared between clones of the runtime.
+    pub secrets: Secrets,
 }
 
 impl Runtime {
@@ -35,7 +45,18 @@ impl Runtime {
                 ..Default::default()
             },
             directory: backup_directory,
+            lock: None,
+            secrets: Secrets::default(),
+        }
+    }
+
+    /// Saves the configuration, which is also needed once a secret has been moved out of it.
+    pub async fn save(&mut self) -> Result<()> {
+        if self.secrets.migrated() {
+            self.config.mutated = true;
         }
+
+        self.config.save().await
     }
 }
 
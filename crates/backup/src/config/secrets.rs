@@ -0,0 +1,122 @@
+/*
+ * Copyright (C) 2023-2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+//! Secrets of the backends, such as passwords and access keys,
+//! which are kept in a secret store instead of the settings file.
+
+use amt_lib::secret::{open_store, Secret, SecretStore};
+use amt_lib::ui::cli::env_or_prompt_secret;
+use anyhow::{Context, Result};
+use inquire::validator::Validation;
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::{Mutex, OnceCell};
+use tracing::info;
+
+/// The secret store for the backup, opened on first use
+/// so the passphrase is only asked for when a backend needs a secret.
+#[derive(Clone, Default)]
+pub struct Secrets {
+    store: Arc<OnceCell<Mutex<Box<dyn SecretStore>>>>,
+
+    /// Set once a secret has been moved out of the settings file, so that it's saved without it.
+    migrated: Arc<AtomicBool>,
+}
+
+impl Secrets {
+    /// The name the secrets are stored under.
+    pub const NAME: &'static str = "backup";
+
+    /// The variable the passphrase of the encrypted file store is read from before prompting.
+    pub const PASSPHRASE_ENV: &'static str = "BACKUP_SECRETS_PASSPHRASE";
+
+    /// Uses the given store instead of opening one when it's first needed.
+    pub fn new(store: Box<dyn SecretStore>) -> Self {
+        Self {
+            store: Arc::new(OnceCell::from(Mutex::new(store))),
+            migrated: Arc::default(),
+        }
+    }
+
+    async fn store(&self) -> Result<&Mutex<Box<dyn SecretStore>>> {
+        self.store
+            .get_or_try_init(|| async {
+                let passphrase = || {
+                    env_or_prompt_secret(Self::PASSPHRASE_ENV, |str: &str| match str.is_empty() {
+                        true => Ok(Validation::Invalid("The passphrase cannot be empty".into())),
+                        false => Ok(Validation::Valid),
+                    })
+                };
+
+                open_store(Self::NAME, passphrase).await.map(Mutex::new)
+            })
+            .await
+            .context("Opening the secret store")
+    }
+
+    /// Get the secret stored under `key`.
+    pub async fn get(&self, key: &str) -> Result<Secret<String>> {
+        self.store()
+            .await?
+            .lock()
+            .await
+            .get(key)
+            .await?
+            .with_context(|| format!("No secret is stored for {key}, try modifying the backup to enter it again"))
+    }
+
+    /// Store `value` under `key`, replacing any existing secret.
+    pub async fn set(&self, key: &str, value: &Secret<String>) -> Result<()> {
+        self.store()
+            .await?
+            .lock()
+            .await
+            .set(key, value)
+            .await
+            .with_context(|| format!("Storing the secret for {key}"))
+    }
+
+    /// Get the secret under `key`, or move one still in the settings file into the store.
+    ///
+    /// Older settings files kept secrets in plain text,
+    /// once one has been moved the file needs to be saved again without it, see [`Secrets::migrated`].
+    pub async fn resolve(&self, key: &str, plain: &str) -> Result<Secret<String>> {
+        if plain.is_empty() {
+            return self.get(key).await;
+        }
+
+        info!("Moving the secret for {key} out of the settings file");
+        let secret = Secret::new(plain.to_owned());
+        self.set(key, &secret).await?;
+        self.migrated.store(true, Ordering::Relaxed);
+        Ok(secret)
+    }
+
+    /// If any secret has been moved out of the settings file by [`Secrets::resolve`].
+    pub fn migrated(&self) -> bool {
+        self.migrated.load(Ordering::Relaxed)
+    }
+}
+
+impl Debug for Secrets {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Secrets")
+            .field("opened", &self.store.initialized())
+            .field("migrated", &self.migrated())
+            .finish()
+    }
+}
@@ -34,5 +34,7 @@
 #![allow(async_fn_in_trait)]
 
 pub mod config;
+pub mod report;
 pub mod sources;
+pub mod store;
 pub mod ui;
@@ -16,14 +16,26 @@
 
 #[cfg(feature = "ui-cli")]
 #[tokio::main(flavor = "multi_thread")]
-pub async fn main() -> anyhow::Result<()> {
+pub async fn main() -> amt_lib::ui::cli::error::ExitCode {
+    use amt_lib::ui::cli::error::{finish, Category};
     use amt_lib::ui::cli::CliUi;
     use amt_lib::ui::Ui;
     use backup::ui::cli::ui::BackupCli;
 
-    let mut app = BackupCli::new(())?;
-    app.run().await?;
-    app.runtime.unwrap().config.save().await?;
+    let run = async {
+        let mut app = BackupCli::new(())?;
+        let result = app.run().await.map_err(anyhow::Error::from);
 
-    Ok(())
+        // Keep any changes to the configuration even when only some backends failed.
+        let partial = result.as_ref().is_err_and(|err| Category::of(err) == Category::Partial);
+        if result.is_ok() || partial {
+            if let Some(mut runtime) = app.runtime {
+                runtime.save().await?;
+            }
+        }
+
+        result
+    };
+
+    finish(run.await)
 }
@@ -0,0 +1,105 @@
+/*
+ * Copyright (c) 2024. James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program.
+ * If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use serde::Serialize;
+use std::fmt::Display;
+use std::path::PathBuf;
+use tracing::{error, info};
+
+/// The structured outcome of a single action,
+/// this is what is printed to stdout when using `--format json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// The name of the action which was run.
+    pub action: &'static str,
+
+    /// The backup root directory the action was run against.
+    pub directory: PathBuf,
+
+    /// If every backend completed without errors.
+    pub success: bool,
+
+    /// The outcome for each backend the action touched.
+    pub backends: Vec<BackendReport>,
+}
+
+/// The outcome of an action for a single [`Backend`](crate::config::backend::Backend),
+/// or for the chunk store.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendReport {
+    /// The display name of the backend.
+    pub backend: String,
+
+    /// The files which were written, or checked when verifying.
+    pub files: Vec<PathBuf>,
+
+    /// The files which were removed by pruning.
+    pub pruned: Vec<PathBuf>,
+
+    /// The errors encountered, each with its full cause chain.
+    pub errors: Vec<String>,
+}
+
+impl Report {
+    pub fn new(action: &'static str, directory: PathBuf, backends: Vec<BackendReport>) -> Self {
+        Self {
+            action,
+            directory,
+            success: backends.iter().all(BackendReport::success),
+            backends,
+        }
+    }
+
+    /// Logs a human-readable summary of the report.
+    pub fn log(&self) {
+        for backend in &self.backends {
+            for err in &backend.errors {
+                error!("Error while running {} for {}: {err}", self.action, backend.backend);
+            }
+
+            if backend.success() {
+                info!(
+                    "Finished {} for {} ({} files, {} pruned)",
+                    self.action,
+                    backend.backend,
+                    backend.files.len(),
+                    backend.pruned.len()
+                );
+            }
+        }
+    }
+}
+
+impl BackendReport {
+    pub fn new<T: Display + ?Sized>(backend: &T) -> Self {
+        Self {
+            backend: backend.to_string(),
+            files: vec![],
+            pruned: vec![],
+            errors: vec![],
+        }
+    }
+
+    /// Records an error, keeping the whole chain of causes.
+    pub fn error(&mut self, err: &anyhow::Error) {
+        self.errors.push(format!("{err:#}"));
+    }
+
+    #[inline]
+    pub fn success(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
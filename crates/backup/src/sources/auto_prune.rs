@@ -15,87 +15,85 @@
  */
 
 use crate::config::runtime::Runtime;
-use anyhow::Result;
-use indicatif::MultiProgress;
+use crate::store::ChunkStore;
+use amt_lib::progress::Progress;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use fs_err as fs;
 use std::path::PathBuf;
+use tracing::trace;
+
+/// Globs for artefacts on disk and those which have been moved into the chunk store.
+pub fn glob_artefacts(config: &Runtime, pattern: &str) -> Result<Vec<PathBuf>> {
+    let mut files = glob::glob(pattern)
+        .with_context(|| format!("Glob for files in {pattern}"))?
+        .flatten()
+        .collect::<Vec<_>>();
+
+    if let Some(store) = ChunkStore::open(config) {
+        for stored in store.glob(pattern)? {
+            if !files.contains(&stored) {
+                files.push(stored);
+            }
+        }
+    }
+
+    Ok(files)
+}
 
-// TODO :: Implement logic from cleaner crate to handle this!
 pub trait Prune {
-    /// The files which should be possible to prune.
-    /// The files returned by this method will be parsed,
-    /// Against the `AutoPrune` struct to determine if they should be removed.
+    /// The files which should be possible to prune,
+    /// these are checked against the [`AutoPrune`] rules to determine if they should be removed.
+    ///
+    /// Artefacts which have been moved into the chunk store are included,
+    /// see [`glob_artefacts`].
+    ///
+    /// [`AutoPrune`]: crate::config::rules::autoprune::AutoPrune
     fn files(&self, config: &Runtime) -> Result<Vec<PathBuf>>;
 
     /// The main prune function.
     /// This function has a common implementation for all sources,
     /// But can be overridden if needed.
-    /// # Arguments
-    /// * `rules` - The `AutoPrune` struct which contains the rules for pruning.
+    ///
+    /// Nothing is pruned unless the [`AutoPrune`] rules are configured,
+    /// stored artefacts are left for the caller to forget from the chunk store.
     /// # Returns
     /// A `Result` with the `Vec<PathBuf>` of the files which were removed.
-    fn prune(&self, _config: &Runtime, _progress_bar: &MultiProgress) -> Result<Vec<PathBuf>> {
-        // let files = self.files(config)?.sort_by(|a, b| {
-        //     fn chrono(path: &PathBuf) -> Result<DateTime<FixedOffset>> {
-        //         let meta = path.metadata().context("Get meta for comparing times")?;
-        //         let mtime = meta.modified().context("Get modified time for comparing times")?;
-        //
-        //         let now = SystemTime::now();
-        //         let age = now.duration_since(mtime).context("Get duration since modified time")?;
-        //         match Duration::from_std(age) {
-        //             Ok(d) => Ok(d),
-        //             Err(err) => Err(err).context("Convert std to chrono"),
-        //         }
-        //     }
-        //
-        //     let a = a.metadata();
-        //     let b = b.metadata();
-        //
-        //     match (a, b) {
-        //         (Ok(a), Ok(b)) => {
-        //             let a = chrono(a);
-        //             let b = chrono(b);
-        //             match (a, b) {
-        //                 (Ok(a), Ok(b)) => a.cmp(&b),
-        //                 (Err(err), _) => {
-        //                     trace!("Error getting chrono for a: {}", err);
-        //                     std::cmp::Ordering::Equal
-        //                 },
-        //                 (_, Err(err)) => {
-        //                     trace!("Error getting chrono for b: {}", err);
-        //                     std::cmp::Ordering::Equal
-        //                 },
-        //             }
-        //         },
-        //         (Err(err), _) => {
-        //             trace!("Error getting metadata for a: {}", err);
-        //             std::cmp::Ordering::Equal
-        //         },
-        //         (_, Err(err)) => {
-        //             trace!("Error getting metadata for b: {}", err);
-        //             std::cmp::Ordering::Equal
-        //         },
-        //     }
-        // });
-        // let files = self.files(config)?;
-        // let mut files = files.iter();
-        // let mut removed_files = vec![];
-        //
-        // // TODO :: Add dry run option.
-        // while let Some(file) = files.next() {
-        //     if !(config.config.rules.get_auto_prune().unwrap().should_prune(file, files.len())?) {
-        //         trace!("Pruning rules prevented pruning for: {}", file.display());
-        //         continue;
-        //     }
-        //
-        //     trace!("Pruning file: {}", file.display());
-        //     // if !config.cli.flags.dry_run {
-        //     //     std::fs::remove_file(file)?;
-        //     // }
-        //     removed_files.push(file.clone());
-        // }
-        //
-        // Ok(removed_files)
+    ///
+    /// [`AutoPrune`]: crate::config::rules::autoprune::AutoPrune
+    fn prune(&self, config: &Runtime, progress: &dyn Progress) -> Result<Vec<PathBuf>> {
+        let Some(rules) = config.config.rules.get_auto_prune() else {
+            return Ok(vec![]);
+        };
+
+        let store = ChunkStore::open(config);
+        let mut artefacts = vec![];
+        for file in self.files(config)? {
+            let modified = match file.metadata() {
+                Ok(metadata) => metadata.modified().map(DateTime::from).unwrap_or_else(|_| Utc::now()),
+                Err(_) => match store.as_ref().map(|store| store.stored(&file)).transpose()?.flatten() {
+                    Some(manifest) => manifest.modified,
+                    None => {
+                        trace!("Skipping missing file {}", file.display());
+                        continue;
+                    }
+                },
+            };
+
+            artefacts.push((file, modified));
+        }
+
+        let expired = rules.expired(artefacts);
+        progress.set_length(expired.len() as u64);
+        for file in &expired {
+            trace!("Pruning file: {}", file.display());
+            if file.exists() {
+                fs::remove_file(file)?;
+            }
+
+            progress.inc(1);
+        }
 
-        Ok(vec![])
+        Ok(expired)
     }
 }
@@ -25,11 +25,12 @@ use crate::sources::downloader::Downloader;
 use crate::sources::exporter::Exporter;
 use amt_lib::fs::normalise_path;
 use amt_lib::pathed::Pathed;
+use amt_lib::progress::Progress;
 use anyhow::{anyhow, Context, Result};
 use const_format::formatcp;
-use indicatif::{MultiProgress, ProgressBar};
 use std::env;
 use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
 use std::process::Command;
 
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -87,13 +88,8 @@ impl Downloader for BitWardenCore {
 }
 
 impl Exporter for BitWardenCore {
-    async fn export(
-        &mut self,
-        runtime: &Runtime,
-        _main_bar: &ProgressBar,
-        _progress_bar: &MultiProgress,
-    ) -> Result<()> {
-        let export = |format: &str, ext: &str| -> Result<()> {
+    async fn export(&mut self, runtime: &Runtime, _progress: &dyn Progress) -> Result<Vec<PathBuf>> {
+        let export = |format: &str, ext: &str| -> Result<PathBuf> {
             let output_file = normalise_path(self.unique_dir(runtime)?.join(format!(
                 "{org_id}_{date}-{format}.{ext}",
                 org_id = &self.org_id,
@@ -114,12 +110,14 @@ impl Exporter for BitWardenCore {
                 return Err(anyhow!("BitWarden export for {} failed: {string}", &self.org_name));
             }
 
-            Ok(())
+            Ok(output_file)
         };
 
-        export("encrypted_json", "json")?;
-        export("json", "json")?;
-        export("csv", "csv")
+        Ok(vec![
+            export("encrypted_json", "json")?,
+            export("json", "json")?,
+            export("csv", "csv")?,
+        ])
     }
 
     async fn interactive(config: &Runtime) -> Result<Vec<Backend>> {
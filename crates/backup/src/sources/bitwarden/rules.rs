@@ -16,8 +16,8 @@
 
 use super::BitWardenCore;
 use crate::config::runtime::Runtime;
-use crate::sources::auto_prune::Prune;
-use anyhow::{Context, Result};
+use crate::sources::auto_prune::{glob_artefacts, Prune};
+use anyhow::Result;
 use std::path::PathBuf;
 
 impl Prune for BitWardenCore {
@@ -30,8 +30,6 @@ impl Prune for BitWardenCore {
             org = &self.org_name
         );
 
-        glob::glob(&glob)
-            .with_context(|| format!("Glob backup files for {glob}"))
-            .map(|g| g.flatten().collect())
+        glob_artefacts(config, &glob)
     }
 }
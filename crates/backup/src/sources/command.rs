@@ -0,0 +1,444 @@
+/*
+ * Copyright (c) 2024. James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program.
+ * If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::config::backend::Backend;
+use crate::config::rules::metadata::Metadata;
+use crate::config::runtime::Runtime;
+use crate::sources::auto_prune::{glob_artefacts, Prune};
+use crate::sources::exporter::Exporter;
+use crate::store::ChunkStore;
+use amt_lib::fs::normalise_path;
+use amt_lib::pathed::Pathed;
+use amt_lib::progress::Progress;
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use flate2::write::GzEncoder;
+use macros::{EnumNames, EnumVariants};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use tokio::process::Command;
+use tracing::{debug, info};
+
+/// The number of trailing stderr lines included when a command fails.
+const STDERR_TAIL: usize = 20;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("command {command} exited with {status}{}", tail(.stderr))]
+    Failed {
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+
+    #[error("command {command} succeeded but didn't produce {}", .path.display())]
+    MissingOutput { command: String, path: PathBuf },
+}
+
+fn tail(stderr: &str) -> String {
+    match stderr.trim().is_empty() {
+        true => String::new(),
+        false => format!(":\n{}", stderr.trim_end()),
+    }
+}
+
+/// Where the artefact of the command is taken from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CommandOutput {
+    /// Everything the command writes to stdout.
+    Stdout,
+    /// A file the command produces, relative paths are resolved against the working directory.
+    /// The file is moved into the backup directory once the command succeeds.
+    File(PathBuf),
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumVariants, EnumNames)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+}
+
+impl Compression {
+    fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandCore {
+    /// The name of this source, used for the directory and artefact names.
+    pub name: String,
+
+    /// The program followed by its arguments.
+    pub command: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_directory: Option<PathBuf>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+
+    pub output: CommandOutput,
+
+    /// The extension of the artefact before any compression, e.g. `sql`.
+    pub extension: String,
+
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+impl CommandCore {
+    fn display_command(&self) -> String {
+        self.command.join(" ")
+    }
+
+    /// The path of a new artefact, named `{name}-{%Y%m%d-%H%M%S-%3f}.{extension}[.gz]`,
+    /// with a counter after the time if an artefact from the same millisecond already exists.
+    fn artefact(&self, runtime: &Runtime) -> Result<PathBuf> {
+        let directory = self.unique_dir(runtime)?;
+        let store = ChunkStore::open(runtime);
+        let time = Local::now().format("%Y%m%d-%H%M%S-%3f").to_string();
+
+        let mut attempt = 0;
+        loop {
+            let stamp = match attempt {
+                0 => time.clone(),
+                attempt => format!("{time}-{attempt}"),
+            };
+
+            let mut name = format!("{}-{stamp}.{}", self.name, self.extension);
+            if let Some(extension) = self.compression.extension() {
+                name = format!("{name}.{extension}");
+            }
+
+            let path = normalise_path(directory.join(name));
+            let stored = store.as_ref().map(|store| store.stored(&path)).transpose()?.flatten();
+            if !path.exists() && stored.is_none() {
+                return Ok(path);
+            }
+
+            attempt += 1;
+        }
+    }
+
+    /// Runs the command, writing its stdout into `stdout`.
+    async fn execute(&self, stdout: Stdio) -> Result<()> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| anyhow!("No command configured for {}", self.name))?;
+
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .envs(&self.env)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(Stdio::piped());
+        if let Some(directory) = &self.working_directory {
+            command.current_dir(directory);
+        }
+
+        debug!("Running {}", self.display_command());
+        // `output` would replace the configured stdout with a pipe, so spawn and wait instead.
+        let output = command
+            .spawn()
+            .with_context(|| format!("Spawning {}", self.display_command()))?
+            .wait_with_output()
+            .await
+            .with_context(|| format!("Waiting for {}", self.display_command()))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let lines = stderr.lines().collect::<Vec<_>>();
+            return Err(Error::Failed {
+                command: self.display_command(),
+                status: output.status,
+                stderr: lines[lines.len().saturating_sub(STDERR_TAIL)..].join("\n"),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Copies the raw output into the artefact, compressing it if configured.
+    fn store(&self, raw: &Path, artefact: &Path) -> Result<()> {
+        match self.compression {
+            Compression::None => fs::rename(raw, artefact)
+                .or_else(|_| fs::copy(raw, artefact).and_then(|_| fs::remove_file(raw)))
+                .with_context(|| format!("Moving {} to {}", raw.display(), artefact.display())),
+            Compression::Gzip => {
+                let mut reader = BufReader::new(File::open(raw).with_context(|| format!("Opening {}", raw.display()))?);
+                let file = File::create_new(artefact).with_context(|| format!("Creating {}", artefact.display()))?;
+                let mut encoder = GzEncoder::new(BufWriter::new(file), flate2::Compression::default());
+
+                io::copy(&mut reader, &mut encoder).context("Compressing output")?;
+                encoder.finish()?.flush()?;
+                fs::remove_file(raw).with_context(|| format!("Removing {}", raw.display()))
+            }
+        }
+    }
+}
+
+impl Pathed<Runtime> for CommandCore {
+    const NAME: &'static str = "Command";
+
+    fn get_unique_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl Exporter for CommandCore {
+    async fn interactive(_config: &Runtime) -> Result<Vec<Backend>> {
+        use amt_lib::ui::cli::ui_inquire::STYLE;
+        use inquire::validator::Validation;
+        use inquire::{Select, Text};
+
+        let not_empty = |str: &str| match str.trim().is_empty() {
+            true => Ok(Validation::Invalid("Value cannot be empty".into())),
+            false => Ok(Validation::Valid),
+        };
+
+        let name = Text::new("What should this backup be called?")
+            .with_render_config(*STYLE)
+            .with_validator(move |str: &str| match str.contains(['/', '\\']) {
+                true => Ok(Validation::Invalid("Name cannot contain path separators".into())),
+                false => not_empty(str),
+            })
+            .prompt()
+            .context("Prompt for name")?;
+
+        let line = Text::new("What command should be run?")
+            .with_render_config(*STYLE)
+            .with_help_message("This is run through the system shell, e.g. pg_dump mydb")
+            .with_validator(not_empty)
+            .prompt()
+            .context("Prompt for command")?;
+        let command = match cfg!(windows) {
+            true => vec!["cmd".into(), "/C".into(), line],
+            false => vec!["sh".into(), "-c".into(), line],
+        };
+
+        let output = Text::new("Which file does the command produce?")
+            .with_render_config(*STYLE)
+            .with_help_message("Leave empty to capture the output of the command instead")
+            .prompt()
+            .context("Prompt for output file")?;
+        let output = match output.trim() {
+            "" => CommandOutput::Stdout,
+            path => CommandOutput::File(PathBuf::from(path)),
+        };
+
+        let extension = Text::new("What extension should the backup have?")
+            .with_render_config(*STYLE)
+            .with_default("txt")
+            .with_validator(not_empty)
+            .prompt()
+            .context("Prompt for extension")?;
+
+        let compression = Select::new("Should the output be compressed?", Compression::get_variants())
+            .with_render_config(*STYLE)
+            .prompt()
+            .context("Prompt for compression")?;
+
+        Ok(vec![Backend::Command(CommandCore {
+            name,
+            command,
+            working_directory: None,
+            env: HashMap::new(),
+            output,
+            extension: extension.trim_start_matches('.').to_string(),
+            compression,
+        })])
+    }
+
+    async fn export(&mut self, runtime: &Runtime, progress: &dyn Progress) -> Result<Vec<PathBuf>> {
+        progress.set_message(&format!("Running {}...", self.name));
+
+        let existing = self.files(runtime)?;
+        let artefact = self.artefact(runtime)?;
+        let raw = match &self.output {
+            CommandOutput::Stdout => {
+                let raw = artefact.with_extension("partial");
+                let file = File::create(&raw).with_context(|| format!("Creating {}", raw.display()))?;
+                if let Err(err) = self.execute(file.into()).await {
+                    let _ = fs::remove_file(&raw);
+                    return Err(err);
+                }
+
+                raw
+            }
+            CommandOutput::File(path) => {
+                self.execute(Stdio::null()).await?;
+
+                let path = match &self.working_directory {
+                    Some(directory) if path.is_relative() => directory.join(path),
+                    _ => path.clone(),
+                };
+                if !path.exists() {
+                    return Err(Error::MissingOutput {
+                        command: self.display_command(),
+                        path,
+                    }
+                    .into());
+                }
+
+                path
+            }
+        };
+
+        self.store(&raw, &artefact)?;
+
+        let metadata = Metadata::from(artefact.metadata().context("Reading artefact metadata")?);
+        let existing = existing.iter().map(PathBuf::as_path).collect::<Vec<&Path>>();
+        if !runtime.config.rules.would_survive(&existing, &artefact, metadata).await {
+            info!("{} would not survive the rules, removing it.", artefact.display());
+            fs::remove_file(&artefact).with_context(|| format!("Removing {}", artefact.display()))?;
+            return Ok(vec![]);
+        }
+
+        Ok(vec![artefact])
+    }
+}
+
+impl Prune for CommandCore {
+    fn files(&self, config: &Runtime) -> Result<Vec<PathBuf>> {
+        use std::path::MAIN_SEPARATOR;
+
+        let glob = format!(
+            "{}{MAIN_SEPARATOR}{}-*.{}*",
+            self.unique_dir(config)?.display(),
+            self.name,
+            self.extension
+        );
+
+        glob_artefacts(config, &glob).map(|files| {
+            files
+                .into_iter()
+                .filter(|path| path.extension() != Some("partial".as_ref()))
+                .collect()
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use amt_lib::progress::Hidden;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn core(script: &str, output: CommandOutput, compression: Compression) -> CommandCore {
+        CommandCore {
+            name: "test".into(),
+            command: vec!["sh".into(), "-c".into(), script.into()],
+            working_directory: None,
+            env: HashMap::from([("GREETING".into(), "hello".into())]),
+            output,
+            extension: "txt".into(),
+            compression,
+        }
+    }
+
+    async fn export(core: &mut CommandCore, runtime: &Runtime) -> Result<Vec<PathBuf>> {
+        core.export(runtime, &Hidden).await
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn captures_stdout() {
+        let directory = tempfile::tempdir().unwrap();
+        let runtime = Runtime::wrapping(directory.path().to_path_buf());
+        let mut core = core("echo $GREETING", CommandOutput::Stdout, Compression::None);
+
+        let files = export(&mut core, &runtime).await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(fs::read_to_string(&files[0]).unwrap(), "hello\n");
+        assert_eq!(core.files(&runtime).unwrap(), files);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn compresses_produced_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let runtime = Runtime::wrapping(directory.path().to_path_buf());
+        let mut core = core(
+            "echo $GREETING > dump.sql",
+            CommandOutput::File("dump.sql".into()),
+            Compression::Gzip,
+        );
+        core.working_directory = Some(directory.path().to_path_buf());
+
+        let files = export(&mut core, &runtime).await.unwrap();
+        assert!(files[0].to_string_lossy().ends_with(".txt.gz"));
+        assert!(!directory.path().join("dump.sql").exists());
+
+        let mut content = String::new();
+        GzDecoder::new(File::open(&files[0]).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "hello\n");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn failure_includes_status_and_stderr_tail() {
+        let directory = tempfile::tempdir().unwrap();
+        let runtime = Runtime::wrapping(directory.path().to_path_buf());
+        let script = "for i in $(seq 1 30); do echo line$i >&2; done; exit 3";
+        let mut core = core(script, CommandOutput::Stdout, Compression::None);
+
+        let err = format!("{:#}", export(&mut core, &runtime).await.unwrap_err());
+        assert!(err.contains("exit status: 3"), "{err}");
+        assert!(err.contains("line30") && err.contains("line11"), "{err}");
+        assert!(!err.contains("line10"), "{err}");
+        assert!(core.files(&runtime).unwrap().is_empty());
+    }
+
+    #[test]
+    fn artefacts_from_the_same_time_are_unique() {
+        let directory = tempfile::tempdir().unwrap();
+        let runtime = Runtime::wrapping(directory.path().to_path_buf());
+        let core = core("true", CommandOutput::Stdout, Compression::None);
+
+        let first = core.artefact(&runtime).unwrap();
+        File::create(&first).unwrap();
+
+        let second = core.artefact(&runtime).unwrap();
+        assert_ne!(first, second);
+        File::create(&second).unwrap();
+        assert_eq!(core.files(&runtime).unwrap().len(), 2);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn missing_output_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let runtime = Runtime::wrapping(directory.path().to_path_buf());
+        let mut core = core("true", CommandOutput::File("missing".into()), Compression::None);
+        core.working_directory = Some(directory.path().to_path_buf());
+
+        let err = export(&mut core, &runtime).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<Error>(), Some(Error::MissingOutput { .. })));
+    }
+}
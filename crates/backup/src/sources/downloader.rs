@@ -18,10 +18,9 @@ use crate::config::runtime::Runtime;
 use crate::sources::download;
 use crate::sources::exporter::Exporter;
 use amt_lib::fs::create_parents;
-use amt_lib::ui::cli::progress;
+use amt_lib::progress::{Kind, Progress};
 use anyhow::{anyhow, Context, Result};
 use futures_util::StreamExt;
-use indicatif::{MultiProgress, ProgressBar};
 use std::fs::File;
 use std::io::copy;
 use std::path::PathBuf;
@@ -40,7 +39,7 @@ pub trait Downloader: Exporter {
         Ok(Command::new(Self::binary(config)?))
     }
 
-    async fn download_cli(config: &Runtime, main_bar: &ProgressBar, multi_bar: &MultiProgress) -> Result<()> {
+    async fn download_cli(config: &Runtime, progress: &dyn Progress) -> Result<()> {
         let target = Self::binary(config)?;
         create_parents(&target)?;
 
@@ -59,9 +58,9 @@ pub trait Downloader: Exporter {
         let total_size = response.content_length().unwrap();
         let stream = response.bytes_stream().boxed();
 
-        let download_bar = multi_bar.insert_after(main_bar, progress::download());
-        let download = download(total_size, stream, &download_bar).await?;
-        download_bar.finish_and_clear();
+        let download_bar = progress.child(Kind::Download);
+        let download = download(total_size, stream, &*download_bar).await?;
+        download_bar.finish();
 
         let file = File::open(&download).context("Open Download File")?;
         let mut archive = zip::ZipArchive::new(file).context("Open Zip Archive")?;
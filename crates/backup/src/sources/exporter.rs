@@ -18,20 +18,23 @@ use crate::config::backend::Backend;
 use crate::config::runtime::Runtime;
 use crate::sources::downloader::Downloader;
 use amt_lib::pathed::Pathed;
+use amt_lib::progress::Progress;
 use anyhow::Result;
-use indicatif::{MultiProgress, ProgressBar};
 use macros::{EnumNames, EnumVariants};
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::path::PathBuf;
 
 pub trait Exporter: Pathed<Runtime> {
     /// Used to attempt to interactively interactive a new exporter.
     async fn interactive(config: &Runtime) -> Result<Vec<Backend>>;
 
-    // TODO :: Maybe return a reference to file/files which were exported?
     /// This method will export the backup data into memory,
     /// and then write it to the backup directory.
-    async fn export(&mut self, runtime: &Runtime, main_bar: &ProgressBar, progress_bar: &MultiProgress) -> Result<()>;
+    ///
+    /// # Returns
+    /// The paths of the files which were written by this export.
+    async fn export(&mut self, runtime: &Runtime, progress: &dyn Progress) -> Result<Vec<PathBuf>>;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, EnumVariants, EnumNames)]
@@ -42,34 +45,52 @@ pub enum ExporterSource {
     BitWarden,
     #[cfg(feature = "sources-1password")]
     OnePassword,
+    #[cfg(feature = "sources-imap")]
+    Imap,
+    #[cfg(feature = "sources-command")]
+    Command,
 }
 
 impl ExporterSource {
-    pub async fn create(&self, runtime: &Runtime) -> Result<Vec<Backend>> {
+    /// Prepares anything the source needs before it can be configured, such as downloading its CLI.
+    pub async fn setup(&self, runtime: &Runtime, progress: &dyn Progress) -> Result<()> {
         match self {
             #[cfg(feature = "sources-s3")]
-            Self::S3 => super::s3::S3Core::interactive(runtime).await,
+            Self::S3 => Ok(()),
             #[cfg(feature = "sources-bitwarden")]
             Self::BitWarden => {
-                let bars = MultiProgress::new();
-                let main_bar = bars.add(ProgressBar::new_spinner());
-                main_bar.set_message("Setting up BitWarden CLI");
-                super::bitwarden::BitWardenCore::download_cli(runtime, &main_bar, &bars).await?;
-                main_bar.finish_and_clear();
-
-                #[cfg(feature = "ui-cli")]
-                super::bitwarden::BitWardenCore::interactive(runtime).await
+                progress.set_message("Setting up BitWarden CLI");
+                super::bitwarden::BitWardenCore::download_cli(runtime, progress).await
             }
             #[cfg(feature = "sources-1password")]
             Self::OnePassword => {
-                let bars = MultiProgress::new();
-                let main_bar = bars.add(ProgressBar::new_spinner());
-                main_bar.set_message("Setting up 1Password CLI");
-                super::op::core::OnePasswordCore::download_cli(runtime, &main_bar, &bars).await?;
-                main_bar.finish_and_clear();
+                progress.set_message("Setting up 1Password CLI");
+                super::op::core::OnePasswordCore::download_cli(runtime, progress).await
+            }
+            #[cfg(feature = "sources-imap")]
+            Self::Imap => Ok(()),
+            #[cfg(feature = "sources-command")]
+            Self::Command => Ok(()),
+        }
+    }
 
-                super::op::core::OnePasswordCore::interactive(runtime).await
+    /// Interactively configures new backends of this source, [`ExporterSource::setup`] must have been run first.
+    pub async fn create(&self, runtime: &Runtime) -> Result<Vec<Backend>> {
+        match self {
+            #[cfg(feature = "sources-s3")]
+            Self::S3 => super::s3::S3Core::interactive(runtime).await,
+            #[cfg(feature = "sources-bitwarden")]
+            Self::BitWarden =>
+            {
+                #[cfg(feature = "ui-cli")]
+                super::bitwarden::BitWardenCore::interactive(runtime).await
             }
+            #[cfg(feature = "sources-1password")]
+            Self::OnePassword => super::op::core::OnePasswordCore::interactive(runtime).await,
+            #[cfg(feature = "sources-imap")]
+            Self::Imap => super::imap::ImapCore::interactive(runtime).await,
+            #[cfg(feature = "sources-command")]
+            Self::Command => super::command::CommandCore::interactive(runtime).await,
         }
     }
 }
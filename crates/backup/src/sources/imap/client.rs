@@ -0,0 +1,444 @@
+/*
+ * Copyright (c) 2024. James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program.
+ * If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A minimal IMAP4rev1 client which only speaks the handful of commands needed
+//! to mirror mailboxes, `LOGIN`, `LIST`, `EXAMINE`, `UID FETCH` and `LOGOUT`.
+
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use tracing::{instrument, trace};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("server responded to {command} with {status}: {text}")]
+    Status {
+        command: String,
+        status: String,
+        text: String,
+    },
+
+    #[error("unexpected response from server: {0}")]
+    Protocol(String),
+
+    #[error("connection closed by server")]
+    Closed,
+}
+
+pub(crate) trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+/// A single token of a parsed server response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// An atom or number, `NIL` is also represented as an atom.
+    Atom(String),
+    /// A quoted string or a literal.
+    String(Vec<u8>),
+    /// A parenthesised or bracketed list.
+    List(Vec<Token>),
+}
+
+impl Token {
+    pub fn as_str(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Token::Atom(atom) => Some(Cow::Borrowed(atom)),
+            Token::String(bytes) => Some(String::from_utf8_lossy(bytes)),
+            Token::List(_) => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<u32> {
+        match self {
+            Token::Atom(atom) => atom.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn is_atom(&self, value: &str) -> bool {
+        matches!(self, Token::Atom(atom) if atom.eq_ignore_ascii_case(value))
+    }
+}
+
+/// A folder as returned by `LIST`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Folder {
+    pub name: String,
+    pub delimiter: Option<String>,
+    pub selectable: bool,
+}
+
+/// The state of a folder as returned by `EXAMINE`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Mailbox {
+    pub uid_validity: u32,
+    pub exists: u32,
+}
+
+/// A message as returned by `UID FETCH`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub uid: u32,
+    pub flags: Vec<String>,
+    pub body: Vec<u8>,
+}
+
+pub struct Client {
+    stream: BufStream<Box<dyn Io>>,
+    tag: u32,
+}
+
+impl Debug for Client {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client").field("tag", &self.tag).finish()
+    }
+}
+
+impl Client {
+    /// Connects to the server, using implicit TLS when `tls` is set.
+    #[instrument(level = "TRACE")]
+    pub async fn connect(host: &str, port: u16, tls: bool) -> Result<Self> {
+        let tcp = TcpStream::connect((host, port))
+            .await
+            .with_context(|| format!("Connecting to {host}:{port}"))?;
+
+        if !tls {
+            return Self::from_stream(Box::new(tcp)).await;
+        }
+
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+        let name = ServerName::try_from(host.to_owned()).with_context(|| format!("Invalid server name {host}"))?;
+        let stream = TlsConnector::from(Arc::new(config))
+            .connect(name, tcp)
+            .await
+            .with_context(|| format!("Negotiating TLS with {host}"))?;
+
+        Self::from_stream(Box::new(stream)).await
+    }
+
+    /// Wraps an already connected stream, consuming the server greeting.
+    pub(crate) async fn from_stream(stream: Box<dyn Io>) -> Result<Self> {
+        let mut client = Self {
+            stream: BufStream::new(stream),
+            tag: 0,
+        };
+
+        let greeting = tokenise(&client.read_response().await?)?;
+        match greeting.get(1) {
+            Some(status) if status.is_atom("OK") || status.is_atom("PREAUTH") => Ok(client),
+            _ => Err(Error::Protocol(format!("invalid greeting {greeting:?}")).into()),
+        }
+    }
+
+    pub async fn login(&mut self, username: &str, password: &str) -> Result<()> {
+        let command = format!("LOGIN {} {}", quote(username), quote(password));
+        self.command(&command, |_| Ok(()))
+            .await
+            .map_err(|err| match err.downcast::<Error>() {
+                // Don't leak the password into the logs through the error.
+                Ok(Error::Status { status, text, .. }) => Error::Status {
+                    command: "LOGIN".into(),
+                    status,
+                    text,
+                }
+                .into(),
+                Ok(err) => err.into(),
+                Err(err) => err,
+            })
+    }
+
+    /// Lists every folder on the server.
+    pub async fn list(&mut self) -> Result<Vec<Folder>> {
+        let mut folders = vec![];
+        self.command(r#"LIST "" "*""#, |tokens| {
+            if !tokens.get(1).is_some_and(|t| t.is_atom("LIST")) {
+                return Ok(());
+            }
+
+            let (Some(Token::List(attributes)), Some(delimiter), Some(name)) =
+                (tokens.get(2), tokens.get(3), tokens.get(4))
+            else {
+                return Err(Error::Protocol(format!("invalid LIST response {tokens:?}")).into());
+            };
+
+            folders.push(Folder {
+                name: name.as_str().unwrap_or_default().into_owned(),
+                delimiter: match delimiter {
+                    Token::Atom(nil) if nil.eq_ignore_ascii_case("NIL") => None,
+                    other => other.as_str().map(Cow::into_owned),
+                },
+                selectable: !attributes.iter().any(|a| a.is_atom("\\Noselect")),
+            });
+
+            Ok(())
+        })
+        .await?;
+
+        Ok(folders)
+    }
+
+    /// Opens the folder read-only, so that no flags are changed by the backup.
+    pub async fn examine(&mut self, folder: &str) -> Result<Mailbox> {
+        let mut mailbox = Mailbox::default();
+        self.command(&format!("EXAMINE {}", quote(folder)), |tokens| {
+            match tokens.as_slice() {
+                [_, Token::Atom(ok), Token::List(code), ..] if ok.eq_ignore_ascii_case("OK") => {
+                    if let [key, value, ..] = code.as_slice()
+                        && key.is_atom("UIDVALIDITY")
+                    {
+                        mailbox.uid_validity = value.as_number().unwrap_or_default();
+                    }
+                }
+                [_, count, exists, ..] if exists.is_atom("EXISTS") => {
+                    mailbox.exists = count.as_number().unwrap_or_default();
+                }
+                _ => {}
+            }
+
+            Ok(())
+        })
+        .await?;
+
+        Ok(mailbox)
+    }
+
+    /// Fetches every message in the selected folder with a UID greater than `after`,
+    /// each message is passed to `each` as soon as it has been received.
+    pub async fn fetch_after<F>(&mut self, after: u32, mut each: F) -> Result<()>
+    where
+        F: FnMut(Message) -> Result<()>,
+    {
+        let command = format!("UID FETCH {}:* (UID FLAGS BODY.PEEK[])", after.saturating_add(1));
+        self.command(&command, |tokens| {
+            let Some(Token::List(items)) = tokens.get(3).filter(|_| tokens[2].is_atom("FETCH")) else {
+                return Ok(());
+            };
+
+            let mut message = Message {
+                uid: 0,
+                flags: vec![],
+                body: vec![],
+            };
+
+            for pair in items.chunks(2) {
+                match pair {
+                    [key, value] if key.is_atom("UID") => message.uid = value.as_number().unwrap_or_default(),
+                    [key, Token::List(flags)] if key.is_atom("FLAGS") => {
+                        message.flags = flags.iter().filter_map(Token::as_str).map(Cow::into_owned).collect()
+                    }
+                    [key, Token::String(body)] if key.is_atom("BODY[]") => message.body = body.clone(),
+                    _ => {}
+                }
+            }
+
+            // A range of `n:*` always includes the last message, even when its UID is lower than n.
+            if message.uid <= after {
+                trace!("Skipping already fetched message {}", message.uid);
+                return Ok(());
+            }
+
+            each(message)
+        })
+        .await
+    }
+
+    pub async fn logout(mut self) -> Result<()> {
+        self.command("LOGOUT", |_| Ok(())).await
+    }
+
+    /// Sends a command and passes each untagged response to `untagged`,
+    /// until the tagged completion response is received.
+    async fn command<F>(&mut self, command: &str, mut untagged: F) -> Result<()>
+    where
+        F: FnMut(Vec<Token>) -> Result<()>,
+    {
+        self.tag += 1;
+        let tag = format!("A{:04}", self.tag);
+        let verb = command.split(' ').next().unwrap_or_default().to_owned();
+
+        trace!("Sending {tag} {verb}");
+        self.stream.write_all(format!("{tag} {command}\r\n").as_bytes()).await?;
+        self.stream.flush().await?;
+
+        loop {
+            let response = self.read_response().await?;
+            if response.starts_with(b"+") {
+                continue;
+            }
+
+            let tokens = tokenise(&response)?;
+            match tokens.first() {
+                Some(first) if first.is_atom(&tag) => {
+                    let status = tokens.get(1).and_then(Token::as_str).unwrap_or_default().into_owned();
+                    if status.eq_ignore_ascii_case("OK") {
+                        return Ok(());
+                    }
+
+                    let text = String::from_utf8_lossy(&response).trim_end().to_owned();
+                    return Err(Error::Status {
+                        command: verb,
+                        status,
+                        text,
+                    }
+                    .into());
+                }
+                Some(first) if first.is_atom("*") => untagged(tokens)?,
+                _ => return Err(Error::Protocol(String::from_utf8_lossy(&response).into_owned()).into()),
+            }
+        }
+    }
+
+    /// Reads a single complete response, including the contents of any literals.
+    async fn read_response(&mut self) -> Result<Vec<u8>> {
+        let mut buffer = vec![];
+        loop {
+            let start = buffer.len();
+            if self.stream.read_until(b'\n', &mut buffer).await? == 0 {
+                return Err(Error::Closed.into());
+            }
+
+            match literal_length(&buffer[start..]) {
+                None => return Ok(buffer),
+                Some(length) => {
+                    let start = buffer.len();
+                    buffer.resize(start + length, 0);
+                    self.stream.read_exact(&mut buffer[start..]).await?;
+                }
+            }
+        }
+    }
+}
+
+/// Quotes a string for use as a command argument.
+fn quote(value: &str) -> String {
+    format!(r#""{}""#, value.replace('\\', r"\\").replace('"', r#"\""#))
+}
+
+/// If the line ends by announcing a literal, returns the length of that literal.
+fn literal_length(line: &[u8]) -> Option<usize> {
+    let line = line.trim_ascii_end().strip_suffix(b"}")?;
+    let open = line.iter().rposition(|b| *b == b'{')?;
+
+    parse_literal_length(&line[open + 1..]).ok()
+}
+
+/// Parses the length between the braces of a literal, which is followed by `+` for a non-synchronising literal.
+fn parse_literal_length(length: &[u8]) -> Result<usize> {
+    let length = length.strip_suffix(b"+").unwrap_or(length);
+    std::str::from_utf8(length)?.parse::<usize>().context("Parsing literal length")
+}
+
+/// Splits a complete response into its tokens.
+pub fn tokenise(response: &[u8]) -> Result<Vec<Token>> {
+    let mut position = 0;
+    let tokens = tokenise_until(response, &mut position, None)?;
+    Ok(tokens)
+}
+
+fn tokenise_until(input: &[u8], position: &mut usize, close: Option<u8>) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    while *position < input.len() {
+        match input[*position] {
+            b' ' | b'\r' | b'\n' => *position += 1,
+            byte if Some(byte) == close => {
+                *position += 1;
+                return Ok(tokens);
+            }
+            open @ (b'(' | b'[') => {
+                *position += 1;
+                let close = if open == b'(' { b')' } else { b']' };
+                tokens.push(Token::List(tokenise_until(input, position, Some(close))?));
+            }
+            b'"' => {
+                *position += 1;
+                let mut value = vec![];
+                loop {
+                    match input.get(*position) {
+                        None => return Err(Error::Protocol("unterminated quoted string".into()).into()),
+                        Some(b'"') => break,
+                        Some(b'\\') => {
+                            value.extend(input.get(*position + 1));
+                            *position += 2;
+                        }
+                        Some(byte) => {
+                            value.push(*byte);
+                            *position += 1;
+                        }
+                    }
+                }
+
+                *position += 1;
+                tokens.push(Token::String(value));
+            }
+            b'{' => {
+                let end = input[*position..]
+                    .iter()
+                    .position(|b| *b == b'}')
+                    .map(|end| *position + end)
+                    .ok_or_else(|| Error::Protocol("unterminated literal".into()))?;
+                let length = parse_literal_length(&input[*position + 1..end])?;
+
+                // The literal starts on the line after the `}`, which may be followed by whitespace.
+                let start = input[end..]
+                    .windows(2)
+                    .position(|window| window == b"\r\n")
+                    .map(|newline| end + newline + 2)
+                    .ok_or_else(|| Error::Protocol("literal without a line break".into()))?;
+                let value = input
+                    .get(start..start + length)
+                    .ok_or_else(|| Error::Protocol("truncated literal".into()))?;
+                tokens.push(Token::String(value.to_vec()));
+                *position = start + length;
+            }
+            _ => {
+                let start = *position;
+                let mut depth = 0;
+                while let Some(byte) = input.get(*position) {
+                    match byte {
+                        b'[' => depth += 1,
+                        b']' if depth > 0 => depth -= 1,
+                        b' ' | b'(' | b')' | b']' | b'\r' | b'\n' if depth == 0 => break,
+                        _ => {}
+                    }
+
+                    *position += 1;
+                }
+
+                // A stray closing bracket in free-form response text, keep it as its own atom.
+                if start == *position {
+                    *position += 1;
+                }
+
+                tokens.push(Token::Atom(
+                    String::from_utf8_lossy(&input[start..*position]).into_owned(),
+                ));
+            }
+        }
+    }
+
+    match close {
+        None => Ok(tokens),
+        Some(close) => Err(Error::Protocol(format!("missing closing {}", close as char)).into()),
+    }
+}
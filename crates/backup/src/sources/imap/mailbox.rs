@@ -0,0 +1,246 @@
+/*
+ * Copyright (c) 2024. James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program.
+ * If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::sources::imap::client::Message;
+use amt_lib::fs::normalise_path;
+use amt_lib::pathed::ensure_directory_exists;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use macros::{EnumNames, EnumVariants};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Separates a Maildir message's unique name from its info,
+/// `:` isn't valid in Windows file names so `!` is used there as other Maildir implementations do.
+#[cfg(windows)]
+pub const INFO_SEPARATOR: char = '!';
+#[cfg(not(windows))]
+pub const INFO_SEPARATOR: char = ':';
+
+/// The on-disk format mirrored folders are stored in.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumVariants, EnumNames)]
+pub enum MailFormat {
+    /// A directory per folder, with a file per message.
+    #[default]
+    Maildir,
+    /// A single mboxrd file per folder, new messages are appended.
+    Mbox,
+}
+
+/// The last mirrored position of each folder,
+/// used to only fetch messages which are new since the last run.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncState {
+    pub folders: HashMap<String, FolderState>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FolderState {
+    /// The UIDVALIDITY of the folder when it was last mirrored,
+    /// if this changes all previously seen UIDs are meaningless.
+    pub uid_validity: u32,
+
+    /// The highest UID which has been mirrored.
+    pub last_uid: u32,
+}
+
+impl SyncState {
+    pub const FILENAME: &'static str = ".imap-state.json";
+
+    pub fn load(directory: &Path) -> Result<Self> {
+        let path = directory.join(Self::FILENAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let slice = fs::read(&path).with_context(|| format!("Reading {}", path.display()))?;
+        serde_json::from_slice(&slice).with_context(|| format!("Parsing {}", path.display()))
+    }
+
+    pub fn save(&self, directory: &Path) -> Result<()> {
+        let path = directory.join(Self::FILENAME);
+        let temp = path.with_extension("json.tmp");
+
+        fs::write(&temp, serde_json::to_vec_pretty(self)?).with_context(|| format!("Writing {}", temp.display()))?;
+        fs::rename(&temp, &path).with_context(|| format!("Replacing {}", path.display()))
+    }
+}
+
+impl MailFormat {
+    /// The file system safe name for a folder.
+    pub fn folder_name(folder: &str) -> String {
+        folder.replace(['/', '\\'], ".")
+    }
+
+    /// Opens a writer for new messages of the folder.
+    ///
+    /// If the UIDVALIDITY has changed since the last run any existing mbox file is moved aside,
+    /// Maildir messages include the UIDVALIDITY in their name so they don't collide.
+    pub fn open(&self, directory: &Path, folder: &str, previous: u32, uid_validity: u32) -> Result<MailWriter> {
+        let name = Self::folder_name(folder);
+        match self {
+            MailFormat::Maildir => {
+                let root = normalise_path(directory.join(name));
+                for sub in ["cur", "new", "tmp"] {
+                    ensure_directory_exists(&root.join(sub))?;
+                }
+
+                Ok(MailWriter::Maildir {
+                    root,
+                    uid_validity,
+                    written: vec![],
+                })
+            }
+            MailFormat::Mbox => {
+                let path = normalise_path(directory.join(format!("{name}.mbox")));
+                if previous != 0 && previous != uid_validity && path.exists() {
+                    let moved = path.with_file_name(format!("{name}.{previous}.mbox"));
+                    warn!(
+                        "UIDVALIDITY of {folder} changed, moving old mailbox to {}",
+                        moved.display()
+                    );
+                    fs::rename(&path, &moved).with_context(|| format!("Moving {}", path.display()))?;
+                }
+
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .with_context(|| format!("Opening {}", path.display()))?;
+
+                Ok(MailWriter::Mbox {
+                    file: BufWriter::new(file),
+                    path,
+                    written: false,
+                })
+            }
+        }
+    }
+}
+
+pub enum MailWriter {
+    Maildir {
+        root: PathBuf,
+        uid_validity: u32,
+        written: Vec<PathBuf>,
+    },
+    Mbox {
+        file: BufWriter<File>,
+        path: PathBuf,
+        written: bool,
+    },
+}
+
+impl MailWriter {
+    pub fn write(&mut self, message: &Message) -> Result<()> {
+        match self {
+            MailWriter::Maildir {
+                root,
+                uid_validity,
+                written,
+            } => {
+                let name = format!("{uid_validity}.{}.backup", message.uid);
+                let temp = root.join("tmp").join(&name);
+                let path = root
+                    .join("cur")
+                    .join(format!("{name}{INFO_SEPARATOR}2,{}", maildir_flags(&message.flags)));
+
+                fs::write(&temp, &message.body).with_context(|| format!("Writing {}", temp.display()))?;
+                fs::rename(&temp, &path).with_context(|| format!("Moving message into {}", path.display()))?;
+                debug!("Wrote message {} to {}", message.uid, path.display());
+
+                written.push(path);
+            }
+            MailWriter::Mbox { file, written, .. } => {
+                write_mbox(file, &message.body)?;
+                *written = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the writer and returns the files which were written to.
+    pub fn finish(self) -> Result<Vec<PathBuf>> {
+        match self {
+            MailWriter::Maildir { written, .. } => Ok(written),
+            MailWriter::Mbox {
+                mut file,
+                path,
+                written,
+            } => {
+                file.flush().with_context(|| format!("Flushing {}", path.display()))?;
+                Ok(if written { vec![path] } else { vec![] })
+            }
+        }
+    }
+}
+
+/// Converts IMAP system flags into the Maildir info suffix, sorted as the spec requires.
+pub fn maildir_flags(flags: &[String]) -> String {
+    let mut info = flags
+        .iter()
+        .filter_map(|flag| match flag.to_ascii_lowercase().as_str() {
+            "\\draft" => Some('D'),
+            "\\flagged" => Some('F'),
+            "\\answered" => Some('R'),
+            "\\seen" => Some('S'),
+            "\\deleted" => Some('T'),
+            _ => None,
+        })
+        .collect::<Vec<char>>();
+
+    info.sort();
+    info.dedup();
+    info.into_iter().collect()
+}
+
+/// Appends a message in the mboxrd format,
+/// line endings are normalised and any `From ` lines are quoted.
+fn write_mbox<W: Write>(writer: &mut W, body: &[u8]) -> Result<()> {
+    writeln!(
+        writer,
+        "From MAILER-DAEMON {}",
+        Utc::now().format("%a %b %e %H:%M:%S %Y")
+    )?;
+
+    let mut ends_with_newline = true;
+    for line in body.split_inclusive(|b| *b == b'\n') {
+        let content = line.strip_suffix(b"\r\n").or_else(|| line.strip_suffix(b"\n")).unwrap_or(line);
+
+        let unquoted = content.iter().position(|b| *b != b'>').map_or(&[][..], |i| &content[i..]);
+        if unquoted.starts_with(b"From ") {
+            writer.write_all(b">")?;
+        }
+
+        writer.write_all(content)?;
+        ends_with_newline = content.len() != line.len();
+        if ends_with_newline {
+            writer.write_all(b"\n")?;
+        }
+    }
+
+    if !ends_with_newline {
+        writer.write_all(b"\n")?;
+    }
+
+    writer.write_all(b"\n")?;
+    Ok(())
+}
@@ -0,0 +1,546 @@
+/*
+ * Copyright (c) 2024. James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program.
+ * If not, see <https://www.gnu.org/licenses/>.
+ */
+
+pub mod client;
+pub mod mailbox;
+
+use crate::config::backend::Backend;
+use crate::config::runtime::Runtime;
+use crate::sources::auto_prune::Prune;
+use crate::sources::exporter::Exporter;
+use amt_lib::pathed::Pathed;
+use amt_lib::progress::Progress;
+use anyhow::{anyhow, Context, Result};
+use client::Client;
+use mailbox::{FolderState, MailFormat, SyncState};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImapCore {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub username: String,
+
+    /// Only read from older settings files, the password is kept in the runtime's secrets.
+    #[serde(default, skip_serializing)]
+    password: String,
+
+    /// The folders to mirror, if empty every selectable folder is mirrored.
+    #[serde(default)]
+    pub folders: Vec<String>,
+
+    #[serde(default)]
+    pub format: MailFormat,
+}
+
+impl ImapCore {
+    /// The key the password is kept under in the secret store.
+    fn secret_key(&self) -> String {
+        format!("imap/{}", self.get_unique_name())
+    }
+
+    /// Mirrors the configured folders into the directory,
+    /// only fetching messages which weren't seen by a previous run.
+    ///
+    /// # Returns
+    /// The paths of the files which were written or appended to.
+    pub(crate) async fn mirror(
+        &self,
+        client: &mut Client,
+        directory: &Path,
+        progress: &dyn Progress,
+    ) -> Result<Vec<PathBuf>> {
+        let folders = match self.folders.is_empty() {
+            false => self.folders.clone(),
+            true => client
+                .list()
+                .await?
+                .into_iter()
+                .filter(|folder| folder.selectable)
+                .map(|folder| folder.name)
+                .collect(),
+        };
+
+        let mut state = SyncState::load(directory)?;
+        let mut written = vec![];
+        for folder in folders {
+            progress.set_message(&format!("Mirroring {folder}..."));
+            let mailbox = client.examine(&folder).await?;
+            let previous = state.folders.get(&folder).copied().unwrap_or_default();
+
+            let mut current = match previous.uid_validity == mailbox.uid_validity {
+                true => previous,
+                false => {
+                    if previous.uid_validity != 0 {
+                        warn!("UIDVALIDITY of {folder} changed, all messages will be fetched again.");
+                    }
+
+                    FolderState {
+                        uid_validity: mailbox.uid_validity,
+                        last_uid: 0,
+                    }
+                }
+            };
+
+            let mut writer = self
+                .format
+                .open(directory, &folder, previous.uid_validity, mailbox.uid_validity)?;
+            let result = client
+                .fetch_after(current.last_uid, |message| {
+                    writer.write(&message)?;
+                    current.last_uid = current.last_uid.max(message.uid);
+                    progress.inc(1);
+                    Ok(())
+                })
+                .await;
+
+            // Whatever was written before a failure is kept, so the next run can continue from there.
+            let finished = writer.finish();
+            if finished.is_ok() {
+                state.folders.insert(folder.clone(), current);
+                state.save(directory)?;
+            }
+
+            result.with_context(|| format!("Fetching messages from {folder}"))?;
+            written.extend(finished?);
+        }
+
+        Ok(written)
+    }
+}
+
+impl Pathed<Runtime> for ImapCore {
+    const NAME: &'static str = "IMAP";
+
+    fn get_unique_name(&self) -> String {
+        format!("{}@{}", self.username, self.host)
+    }
+}
+
+impl Exporter for ImapCore {
+    async fn interactive(config: &Runtime) -> Result<Vec<Backend>> {
+        use amt_lib::ui::cli::ui_inquire::STYLE;
+        use amt_lib::ui::cli::{env_or_prompt, env_or_prompt_secret};
+        use inquire::list_option::ListOption;
+        use inquire::validator::Validation;
+        use inquire::{Confirm, CustomType, MultiSelect, Select};
+
+        let not_empty = |str: &str| match str.trim().is_empty() {
+            true => Ok(Validation::Invalid("Value cannot be empty".into())),
+            false => Ok(Validation::Valid),
+        };
+
+        let host = env_or_prompt("IMAP_HOST", not_empty)?;
+        let username = env_or_prompt("IMAP_USERNAME", not_empty)?;
+        let tls = Confirm::new("Connect using TLS?")
+            .with_render_config(*STYLE)
+            .with_default(true)
+            .prompt()
+            .context("Prompt for TLS")?;
+        let port = CustomType::<u16>::new("IMAP Port")
+            .with_render_config(*STYLE)
+            .with_default(if tls { 993 } else { 143 })
+            .prompt()
+            .context("Prompt for port")?;
+        let password = env_or_prompt_secret("IMAP_PASSWORD", not_empty)?;
+
+        let mut client = Client::connect(&host, port, tls).await?;
+        client.login(&username, password.expose()).await?;
+        let folders = client
+            .list()
+            .await?
+            .into_iter()
+            .filter(|folder| folder.selectable)
+            .map(|folder| folder.name)
+            .collect::<Vec<_>>();
+        client.logout().await?;
+
+        if folders.is_empty() {
+            return Err(anyhow!("No selectable folders found for {username}@{host}"));
+        }
+
+        let all = folders.len();
+        let folders = MultiSelect::new("Select the folders you want to backup.", folders)
+            .with_render_config(*STYLE)
+            .with_all_selected_by_default()
+            .with_validator(|selections: &[ListOption<&String>]| match selections.len() {
+                0 => Ok(Validation::Invalid("You must select at least one folder.".into())),
+                _ => Ok(Validation::Valid),
+            })
+            .prompt()
+            .context("Get folders from user selection")?;
+
+        let format = Select::new("How should the mail be stored?", MailFormat::get_variants())
+            .with_render_config(*STYLE)
+            .prompt()
+            .context("Prompt for mail format")?;
+
+        info!("Mirroring {} folders from {username}@{host} as {format}", folders.len());
+        let core = ImapCore {
+            host,
+            port,
+            tls,
+            username,
+            password: String::new(),
+            // Selecting everything also picks up folders created in the future.
+            folders: if folders.len() == all { vec![] } else { folders },
+            format,
+        };
+
+        config.secrets.set(&core.secret_key(), &password).await?;
+        Ok(vec![Backend::Imap(core)])
+    }
+
+    async fn export(&mut self, runtime: &Runtime, progress: &dyn Progress) -> Result<Vec<PathBuf>> {
+        let directory = self.unique_dir(runtime)?;
+        let mut client = Client::connect(&self.host, self.port, self.tls).await?;
+        let password = runtime.secrets.resolve(&self.secret_key(), &self.password).await?;
+        client.login(&self.username, password.expose()).await?;
+
+        let written = self.mirror(&mut client, &directory, progress).await?;
+        client.logout().await?;
+
+        Ok(written)
+    }
+}
+
+impl Prune for ImapCore {
+    /// The mirrored messages, these are only verified and never pruned, see [`ImapCore::prune`].
+    fn files(&self, config: &Runtime) -> Result<Vec<PathBuf>> {
+        use std::path::MAIN_SEPARATOR;
+
+        let glob = match self.format {
+            MailFormat::Maildir => format!("*{MAIN_SEPARATOR}cur{MAIN_SEPARATOR}*"),
+            MailFormat::Mbox => "*.mbox".to_string(),
+        };
+        let glob = format!("{}{MAIN_SEPARATOR}{glob}", self.unique_dir(config)?.display());
+
+        glob::glob(&glob)
+            .with_context(|| format!("Glob for files in {}", glob))
+            .map(|glob| glob.flatten().collect())
+    }
+
+    /// Mail is never pruned, messages are only fetched once by their UID
+    /// so any which were removed would be lost instead of fetched again.
+    fn prune(&self, _config: &Runtime, _progress: &dyn Progress) -> Result<Vec<PathBuf>> {
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use amt_lib::progress::Hidden;
+    use mailbox::INFO_SEPARATOR;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    type Folders = Arc<Mutex<BTreeMap<&'static str, (u32, Vec<(u32, &'static str)>)>>>;
+
+    /// A stand-in server which answers only the commands the client sends.
+    async fn serve(folders: Folders) -> u16 {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let folders = folders.clone();
+                tokio::spawn(async move {
+                    let (read, mut write) = stream.into_split();
+                    let mut lines = BufReader::new(read).lines();
+                    let mut selected = "";
+                    write.write_all(b"* OK [CAPABILITY IMAP4rev1] ready\r\n").await.unwrap();
+
+                    while let Some(line) = lines.next_line().await.unwrap() {
+                        let (tag, command) = line.split_once(' ').unwrap();
+                        let mut response = String::new();
+                        match command.split(' ').next().unwrap() {
+                            "LOGIN" if !command.ends_with(r#""secret""#) => {
+                                response += &format!("{tag} NO [AUTHENTICATIONFAILED] Invalid credentials\r\n");
+                            }
+                            "LIST" => {
+                                response += "* LIST (\\Noselect \\HasChildren) \"/\" \"[Gmail]\"\r\n";
+                                for name in folders.lock().unwrap().keys() {
+                                    response += &format!("* LIST (\\HasNoChildren) \"/\" \"{name}\"\r\n");
+                                }
+                            }
+                            "EXAMINE" => {
+                                let name = command.trim_start_matches("EXAMINE ").trim_matches('"');
+                                let folders = folders.lock().unwrap();
+                                let (key, (validity, messages)) = folders.get_key_value(name).unwrap();
+                                selected = *key;
+                                response += "* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft)\r\n";
+                                response += &format!("* {} EXISTS\r\n", messages.len());
+                                response += &format!("* OK [UIDVALIDITY {validity}] UIDs valid\r\n");
+                                response += &format!("{tag} OK [READ-ONLY] EXAMINE completed\r\n");
+                            }
+                            "UID" => {
+                                let from = command.split(' ').nth(2).unwrap().trim_end_matches(":*");
+                                let from = from.parse::<u32>().unwrap();
+                                let folders = folders.lock().unwrap();
+                                let messages = &folders[selected].1;
+                                let mut matching = messages.iter().filter(|(uid, _)| *uid >= from).collect::<Vec<_>>();
+                                if matching.is_empty() {
+                                    matching.extend(messages.last());
+                                }
+
+                                for (sequence, (uid, body)) in matching.into_iter().enumerate() {
+                                    response += &format!(
+                                        "* {} FETCH (UID {uid} FLAGS (\\Seen $Label) BODY[] {{{}}}\r\n{body})\r\n",
+                                        sequence + 1,
+                                        body.len()
+                                    );
+                                }
+                            }
+                            "LOGOUT" => response += "* BYE logging out\r\n",
+                            _ => {}
+                        }
+
+                        if !response.contains(&format!("{tag} ")) {
+                            response += &format!("{tag} OK completed\r\n");
+                        }
+
+                        write.write_all(response.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        port
+    }
+
+    fn core(format: MailFormat, folders: Vec<String>) -> ImapCore {
+        ImapCore {
+            host: "127.0.0.1".into(),
+            port: 0,
+            tls: false,
+            username: "user".into(),
+            password: "secret".into(),
+            folders,
+            format,
+        }
+    }
+
+    fn folders() -> Folders {
+        Arc::new(Mutex::new(BTreeMap::from([
+            (
+                "INBOX",
+                (
+                    7,
+                    vec![(1, "Subject: One\r\n\r\nHello\r\n"), (2, "Subject: Two\r\n\r\nFrom here\r\n")],
+                ),
+            ),
+            ("Sent/2024", (3, vec![(10, "Subject: Sent\r\n\r\nBye\r\n")])),
+        ])))
+    }
+
+    async fn mirror(core: &ImapCore, port: u16, directory: &Path) -> Result<Vec<PathBuf>> {
+        let mut client = Client::connect("127.0.0.1", port, false).await?;
+        client.login(&core.username, &core.password).await?;
+        let written = core.mirror(&mut client, directory, &Hidden).await?;
+        client.logout().await?;
+        Ok(written)
+    }
+
+    #[test]
+    fn tokenise_fetch() {
+        let response = b"* 1 FETCH (UID 4 FLAGS (\\Seen) BODY[] {5}\r\nHi )\n)\r\n";
+        let tokens = client::tokenise(response).unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                client::Token::Atom("*".into()),
+                client::Token::Atom("1".into()),
+                client::Token::Atom("FETCH".into()),
+                client::Token::List(vec![
+                    client::Token::Atom("UID".into()),
+                    client::Token::Atom("4".into()),
+                    client::Token::Atom("FLAGS".into()),
+                    client::Token::List(vec![client::Token::Atom("\\Seen".into())]),
+                    client::Token::Atom("BODY[]".into()),
+                    client::Token::String(b"Hi )\n".to_vec()),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenise_status() {
+        let tokens = client::tokenise(b"* OK [UIDVALIDITY 42] UIDs valid :)\r\n").unwrap();
+        assert_eq!(
+            tokens[2],
+            client::Token::List(vec![
+                client::Token::Atom("UIDVALIDITY".into()),
+                client::Token::Atom("42".into())
+            ])
+        );
+
+        assert!(client::tokenise(b"* 1 FETCH (UID 4\r\n").is_err());
+    }
+
+    #[test]
+    fn tokenise_literals() {
+        let expected = client::Token::List(vec![
+            client::Token::Atom("BODY[]".into()),
+            client::Token::String(b"Hello".to_vec()),
+        ]);
+
+        let tokens = client::tokenise(b"* 1 FETCH (BODY[] {5+}\r\nHello)\r\n").unwrap();
+        assert_eq!(tokens[3], expected);
+
+        let tokens = client::tokenise(b"* 1 FETCH (BODY[] {5} \t\r\nHello)\r\n").unwrap();
+        assert_eq!(tokens[3], expected);
+
+        assert!(client::tokenise(b"* 1 FETCH (BODY[] {5}").is_err());
+    }
+
+    #[test]
+    fn flags_to_maildir() {
+        let flags = ["\\Seen", "\\Flagged", "$Label", "\\Answered", "\\seen"].map(String::from);
+        assert_eq!(mailbox::maildir_flags(&flags), "FRS");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn login_failure() {
+        let port = serve(folders()).await;
+        let mut client = Client::connect("127.0.0.1", port, false).await.unwrap();
+        let err = client.login("user", "wrong").await.unwrap_err();
+
+        assert!(!format!("{err:#}").contains("wrong"));
+        assert!(format!("{err:#}").contains("AUTHENTICATIONFAILED"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn plaintext_password_moved_to_secrets() {
+        use crate::config::config::Config;
+        use crate::config::secrets::Secrets;
+        use amt_lib::secret::{EncryptedFile, Secret};
+
+        let port = serve(folders()).await;
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join(Config::FILENAME);
+
+        // An older settings file, which kept the password in plain text.
+        let mut config = Config::new(directory.path());
+        config.exporters.push(Backend::Imap(ImapCore {
+            port,
+            ..core(MailFormat::Maildir, vec!["INBOX".into()])
+        }));
+        let mut settings = serde_json::to_value(&config).unwrap();
+        settings["exporters"][0]["Imap"]["password"] = "secret".into();
+        std::fs::write(&path, serde_json::to_vec(&settings).unwrap()).unwrap();
+
+        let store = EncryptedFile::open(directory.path().join("test.secrets"), &Secret::new("test".into())).unwrap();
+        let mut runtime = Runtime {
+            directory: directory.path().to_path_buf(),
+            config: Config::load(&path).await.unwrap(),
+            lock: None,
+            secrets: Secrets::new(Box::new(store)),
+        };
+
+        let mut core: ImapCore = serde_json::from_value(settings["exporters"][0]["Imap"].clone()).unwrap();
+        assert_eq!(core.password, "secret");
+        core.export(&runtime, &Hidden).await.unwrap();
+        runtime.save().await.unwrap();
+
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("password"));
+        let stored = runtime.secrets.get(&core.secret_key()).await.unwrap();
+        assert_eq!(stored.expose(), "secret");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn maildir_incremental() {
+        let folders = folders();
+        let port = serve(folders.clone()).await;
+        let directory = tempfile::tempdir().unwrap();
+        let core = core(MailFormat::Maildir, vec![]);
+
+        let written = mirror(&core, port, directory.path()).await.unwrap();
+        assert_eq!(written.len(), 3);
+        let message = directory.path().join(format!("INBOX/cur/7.1.backup{INFO_SEPARATOR}2,S"));
+        assert_eq!(
+            std::fs::read_to_string(message).unwrap(),
+            "Subject: One\r\n\r\nHello\r\n"
+        );
+        assert!(directory
+            .path()
+            .join(format!("Sent.2024/cur/3.10.backup{INFO_SEPARATOR}2,S"))
+            .exists());
+
+        // Nothing new, so nothing should be written.
+        assert!(mirror(&core, port, directory.path()).await.unwrap().is_empty());
+
+        folders
+            .lock()
+            .unwrap()
+            .get_mut("INBOX")
+            .unwrap()
+            .1
+            .push((5, "Subject: Three\r\n\r\n"));
+        let written = mirror(&core, port, directory.path()).await.unwrap();
+        assert_eq!(
+            written,
+            vec![directory.path().join(format!("INBOX/cur/7.5.backup{INFO_SEPARATOR}2,S"))]
+        );
+
+        let state = SyncState::load(directory.path()).unwrap();
+        assert_eq!(
+            state.folders["INBOX"],
+            FolderState {
+                uid_validity: 7,
+                last_uid: 5
+            }
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn mbox_uid_validity_reset() {
+        let folders = folders();
+        let port = serve(folders.clone()).await;
+        let directory = tempfile::tempdir().unwrap();
+        let core = core(MailFormat::Mbox, vec!["INBOX".into()]);
+
+        let written = mirror(&core, port, directory.path()).await.unwrap();
+        let path = directory.path().join("INBOX.mbox");
+        assert_eq!(written, vec![path.clone()]);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.matches("From MAILER-DAEMON ").count(), 2);
+        assert!(content.contains("\n>From here\n"));
+        assert!(!content.contains('\r'));
+
+        folders
+            .lock()
+            .unwrap()
+            .insert("INBOX", (8, vec![(1, "Subject: Rebuilt\r\n\r\n")]));
+        mirror(&core, port, directory.path()).await.unwrap();
+
+        assert!(directory.path().join("INBOX.7.mbox").exists());
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.matches("From MAILER-DAEMON ").count(), 1);
+        assert_eq!(
+            SyncState::load(directory.path()).unwrap().folders["INBOX"].uid_validity,
+            8
+        );
+    }
+}
@@ -15,11 +15,11 @@
  */
 
 use amt_lib::fs::{create_parents, normalise_path};
+use amt_lib::progress::Progress;
 use anyhow::{anyhow, Context};
 use bytes::Bytes;
 use futures_util::stream::BoxStream;
 use futures_util::StreamExt;
-use indicatif::ProgressBar;
 use rand::RngCore;
 use std::cmp::min;
 use std::error::Error;
@@ -35,6 +35,10 @@ mod getter;
 
 #[cfg(feature = "sources-bitwarden")]
 pub mod bitwarden;
+#[cfg(feature = "sources-command")]
+pub mod command;
+#[cfg(feature = "sources-imap")]
+pub mod imap;
 #[cfg(feature = "sources-1password")]
 pub mod op;
 #[cfg(feature = "sources-s3")]
@@ -44,7 +48,7 @@ async fn download_to<E: Error>(
     total_size: u64,
     mut stream: BoxStream<'_, Result<Bytes, E>>,
     path: &PathBuf,
-    progress: &ProgressBar,
+    progress: &dyn Progress,
 ) -> anyhow::Result<()> {
     debug!("Creating parent dir for {}", &path.display());
     create_parents(path)?;
@@ -53,7 +57,7 @@ async fn download_to<E: Error>(
     let mut downloaded = 0u64;
     let mut file = fs::File::create(path).with_context(|| format!("Create file {}", path.display()))?;
 
-    progress.set_message(format!(
+    progress.set_message(&format!(
         "Downloading {}...",
         &path
             .file_name()
@@ -78,7 +82,7 @@ async fn download_to<E: Error>(
 async fn download<E: Error>(
     total_size: u64,
     stream: BoxStream<'_, Result<Bytes, E>>,
-    progress: &ProgressBar,
+    progress: &dyn Progress,
 ) -> anyhow::Result<PathBuf> {
     let path = env::temp_dir().join(format!("download-{}", rand::thread_rng().next_u64()));
     let path = normalise_path(path);
@@ -505,8 +505,8 @@ pub mod item {
     use super::super::one_pux;
     use crate::config::runtime::Runtime;
     use crate::sources::op::account::OnePasswordAccount;
+    use amt_lib::progress::{Kind, Progress};
     use anyhow::{Context, Result};
-    use indicatif::{MultiProgress, ParallelProgressIterator, ProgressBar};
     use macros::CommonFields;
     use rayon::prelude::*;
     use serde::{Deserialize, Serialize};
@@ -916,23 +916,23 @@ pub mod item {
             vault: super::vault::Vault,
             account: &OnePasswordAccount,
             config: &Runtime,
-            bars: (&ProgressBar, &MultiProgress),
+            progress: &dyn Progress,
         ) -> Result<Vec<Item>> {
             trace!("Requesting Items from {vault}");
-            let bar = bars.1.insert_after(bars.0, amt_lib::ui::cli::progress::spinner_with_count());
+            let bar = progress.child(Kind::Counter);
 
-            bar.set_message(format!("Requesting items from `{vault}` vault...",));
+            bar.set_message(&format!("Requesting items from `{vault}` vault...",));
 
             let items = Self::raw(vault.attrs().reference.id(), account.command(config)?)
                 .and_then(|raw| from_slice::<Vec<Item>>(&raw).context("Deserialize items list"))?;
 
             bar.set_length(items.len() as u64);
-            bar.set_message(format!("Requesting items details from `{vault}` vault...",));
+            bar.set_message(&format!("Requesting items details from `{vault}` vault...",));
 
             let items = items
                 .into_par_iter()
-                .progress_with(bar)
                 .map(|item| Self::raw_long(&vault.attrs().reference, item, account.command(config)?))
+                .inspect(|_| bar.inc(1))
                 .map(|r| {
                     if r.is_err() {
                         return Err(r.err().unwrap());
@@ -942,6 +942,7 @@ pub mod item {
                     from_slice::<Item>(vec.as_slice()).context("Deserialize item")
                 })
                 .collect::<Vec<Result<Item>>>();
+            bar.finish();
 
             let mut fin = vec![];
             for item in items {
@@ -17,7 +17,7 @@
 use crate::config::backend::Backend;
 use crate::config::backend::Backend::OnePassword;
 use crate::config::runtime::Runtime;
-use crate::sources::auto_prune::Prune;
+use crate::sources::auto_prune::{glob_artefacts, Prune};
 use crate::sources::downloader::Downloader;
 use crate::sources::exporter::Exporter;
 use crate::sources::getter::CliGetter;
@@ -25,11 +25,11 @@ use crate::sources::op::account::{AccountAttrs, OnePasswordAccount};
 use crate::sources::op::one_pux;
 use amt_lib::fs::normalise_path;
 use amt_lib::pathed::{ensure_directory_exists, ensure_permissions, Pathed};
+use amt_lib::progress::Progress;
 use amt_lib::ui::cli::ui_inquire::STYLE;
 use anyhow::{anyhow, Context, Result};
 use const_format::formatcp;
 use futures_util::TryFutureExt;
-use indicatif::{MultiProgress, ProgressBar};
 use serde::{Deserialize, Serialize};
 use serde_json::to_string_pretty;
 use std::io::Write;
@@ -197,17 +197,17 @@ impl Exporter for OnePasswordCore {
     /// Creates a 1PUX compatible export,
     ///
     /// The name of this file is in format of "1Password-{uuid of the account exporting it}-{%Y%m%d-%H%M%S}.1pux"
-    async fn export(&mut self, runtime: &Runtime, main_bar: &ProgressBar, progress_bar: &MultiProgress) -> Result<()> {
+    async fn export(&mut self, runtime: &Runtime, progress: &dyn Progress) -> Result<Vec<PathBuf>> {
         use chrono::Local;
         use one_pux::{attributes::Attributes, export::Export};
 
         let account = &self.account;
         let file_name = format!("1PasswordExport-{}.1pux", Local::now().format("%Y%m%d-%H%M%S"));
 
-        let file = self.account.unique_dir(runtime)?.join(file_name);
-        let file = normalise_path(file);
+        let path = self.account.unique_dir(runtime)?.join(file_name);
+        let path = normalise_path(path);
 
-        let file = fs::File::create_new(file).context("Create export file")?;
+        let file = fs::File::create_new(&path).context("Create export file")?;
         let mut zip = zip::ZipWriter::new(file);
 
         let options = SimpleFileOptions::default();
@@ -217,7 +217,7 @@ impl Exporter for OnePasswordCore {
             .context("Start writer for attrs.")?;
         zip.write_all(serialised.as_bytes()).context("Write attrs to zip file.")?;
 
-        let (export, errors) = match Export::from(account, runtime, (main_bar, progress_bar)).await {
+        let (export, errors) = match Export::from(account, runtime, progress).await {
             Err(e) => {
                 zip.finish().context("Finish export file")?;
                 return Err(e);
@@ -241,7 +241,7 @@ impl Exporter for OnePasswordCore {
             return Err(anyhow!("Errors occurred during export: {:?}", errors));
         }
 
-        Ok(())
+        Ok(vec![path])
     }
 }
 
@@ -254,8 +254,6 @@ impl Prune for OnePasswordCore {
             self.account.unique_dir(config)?.display()
         );
 
-        glob::glob(&glob)
-            .with_context(|| format!("Glob for files in {}", glob))
-            .map(|glob| glob.flatten().collect())
+        glob_artefacts(config, &glob)
     }
 }
@@ -27,8 +27,8 @@ pub mod export {
     use crate::config::runtime::Runtime;
     use crate::sources::op::account::OnePasswordAccount;
     use crate::sources::op::cli;
+    use amt_lib::progress::Progress;
     use anyhow::{anyhow, Result};
-    use indicatif::{MultiProgress, ProgressBar};
     use serde::{Deserialize, Serialize};
     use tracing::{error, warn};
 
@@ -49,7 +49,7 @@ pub mod export {
         pub async fn from(
             value: &OnePasswordAccount,
             config: &Runtime,
-            bars: (&ProgressBar, &MultiProgress),
+            progress: &dyn Progress,
         ) -> Result<(Self, Vec<anyhow::Error>)> {
             let vaults = cli::vault::Vault::parse(value, config).await?;
             if vaults.is_empty() {
@@ -61,7 +61,7 @@ pub mod export {
             for vault in vaults {
                 let attrs = vault.clone().into();
 
-                let items = match cli::item::Item::parse(vault.clone(), value, config, bars) {
+                let items = match cli::item::Item::parse(vault.clone(), value, config, progress) {
                     Ok(items) => items,
                     Err(e) => {
                         error!("Failed to parse items for vault {vault}: {e}");
@@ -16,17 +16,17 @@
 
 use crate::config::backend::Backend;
 use crate::config::runtime::Runtime;
-use crate::sources::auto_prune::Prune;
+use crate::sources::auto_prune::{glob_artefacts, Prune};
 use crate::sources::download_to;
 use crate::sources::exporter::Exporter;
+use crate::store::ChunkStore;
 use amt_lib::fs::normalise_path;
 use amt_lib::pathed::Pathed;
-use amt_lib::ui::cli::progress::{download, spinner};
+use amt_lib::progress::{Kind, Progress};
 use amt_lib::ui::cli::ui_inquire::STYLE;
 use anyhow::{Context, Result};
 use futures::{Stream, TryStreamExt};
 use futures_util::StreamExt;
-use indicatif::{MultiProgress, ProgressBar};
 use inquire::validator::Validation;
 use obj_builder::{builder, Builder};
 use opendal::layers::LoggingLayer;
@@ -46,6 +46,8 @@ builder!(
         region: String,
         endpoint: String,
         access_key_id: String,
+        /// Only read from older settings files, the key is kept in the runtime's secrets.
+        #[serde(default, skip_serializing)]
         secret_access_key: String
     }
 );
@@ -84,6 +86,11 @@ impl PartialEq for S3Core {
 impl Eq for S3Core {}
 
 impl S3Core {
+    /// The key the secret access key is kept under in the secret store.
+    fn secret_key(&self) -> String {
+        format!("s3/{}/{}", self.base.endpoint, self.base.access_key_id)
+    }
+
     fn op(&mut self) -> &Operator {
         self.op.get_or_insert_with(|| {
             Operator::from_iter::<S3>(<HashMap<String, String>>::from(&self.base))
@@ -104,9 +111,7 @@ impl Prune for S3Core {
             bucket = self.base.root.display()
         );
 
-        glob::glob(&glob)
-            .with_context(|| format!("Failed to glob: {}", glob))
-            .map(|p| p.flatten().collect())
+        glob_artefacts(config, &glob)
     }
 }
 
@@ -119,8 +124,8 @@ impl Pathed<Runtime> for S3Core {
 }
 
 impl Exporter for S3Core {
-    async fn interactive(_config: &Runtime) -> Result<Vec<Backend>> {
-        use amt_lib::ui::cli::{continue_loop, env_or_prompt};
+    async fn interactive(config: &Runtime) -> Result<Vec<Backend>> {
+        use amt_lib::ui::cli::{continue_loop, env_or_prompt, env_or_prompt_secret};
 
         let not_empty_or_ascii =
             |str: &str, msg: &str| match str.chars().any(|c| !c.is_ascii_alphanumeric() && c != '-' && c != '_')
@@ -141,14 +146,14 @@ impl Exporter for S3Core {
         let region = env_or_prompt("S3_REGION", |_: &_| Ok(Validation::Valid))?;
         let endpoint = env_or_prompt("S3_ENDPOINT", |_: &_| Ok(Validation::Valid))?;
         let key_id = env_or_prompt("S3_ACCESS_KEY_ID", |_: &_| Ok(Validation::Valid))?;
-        let secret_key = env_or_prompt("S3_SECRET_ACCESS_KEY", |_: &_| Ok(Validation::Valid))?;
+        let secret_key = env_or_prompt_secret("S3_SECRET_ACCESS_KEY", |_: &_| Ok(Validation::Valid))?;
 
         let base_accessor = HashMap::from([
             ("bucket".to_string(), bucket),
             ("region".to_string(), region),
             ("endpoint".to_string(), endpoint),
             ("access_key_id".to_string(), key_id),
-            ("secret_access_key".to_string(), secret_key), // TODO :: This is not secure at all, maybe use platform specific keychain?
+            ("secret_access_key".to_string(), secret_key.expose().clone()),
         ]);
 
         let base = S3BackendBuilder::default();
@@ -192,17 +197,30 @@ impl Exporter for S3Core {
             }
         }
 
+        // Every object shares the same credentials.
+        if let Some(Backend::S3(core)) = exporters.first() {
+            config.secrets.set(&core.secret_key(), &secret_key).await?;
+        }
+
         Ok(exporters)
     }
 
     // TODO :: Validate files
-    async fn export(&mut self, runtime: &Runtime, main_bar: &ProgressBar, progress_bar: &MultiProgress) -> Result<()> {
-        let progress_state = progress_bar.insert_after(main_bar, spinner());
+    async fn export(&mut self, runtime: &Runtime, progress: &dyn Progress) -> Result<Vec<PathBuf>> {
+        let progress_state = progress.child(Kind::Spinner);
         progress_state.set_message("Initialising S3 exporter...");
 
         let object = self.base.root.clone();
         let output = normalise_path(Self::base_dir(runtime)?.join(&object));
         let existing_files = self.files(runtime)?;
+        let store = ChunkStore::open(runtime);
+        if self.op.is_none() {
+            let secret = runtime
+                .secrets
+                .resolve(&self.secret_key(), &self.base.secret_access_key)
+                .await?;
+            self.base.secret_access_key = secret.expose().clone();
+        }
         let op = self.op();
 
         progress_state.set_message("Requesting objects from S3...");
@@ -221,7 +239,8 @@ impl Exporter for S3Core {
         progress_state.set_message("Processing objects from S3...");
         progress_state.set_length(layer.size_hint().1.unwrap_or(0) as u64);
         progress_state.set_position(0);
-        let download_bar = progress_bar.insert_after(&progress_state, download());
+        let download_bar = progress_state.child(Kind::Download);
+        let mut written = vec![];
 
         while let Some(item) = layer.try_next().await? {
             let meta = item.metadata();
@@ -232,11 +251,21 @@ impl Exporter for S3Core {
 
             let path = normalise_path(output.join(item.name()));
             let filename = path.file_name().unwrap().to_str().unwrap();
-            progress_state.set_message(format!("Processing {:#}", &filename));
+            progress_state.set_message(&format!("Processing {:#}", &filename));
+
+            // Files moved into the chunk store by a previous run no longer exist on disk.
+            if let Some(manifest) = store.as_ref().map(|store| store.stored(&path)).transpose()?.flatten()
+                && manifest.size == meta.content_length()
+                && manifest.modified.timestamp_millis() == meta.last_modified().unwrap().timestamp_millis()
+            {
+                debug!("Skipping export as the stored file is the same");
+                progress_state.inc(1);
+                continue;
+            }
 
             if path.exists() {
                 debug!("Checking if file has changed...");
-                progress_state.set_message(format!("Checking if {:#} has changed...", &filename));
+                progress_state.set_message(&format!("Checking if {:#} has changed...", &filename));
 
                 let host_meta = std::fs::metadata(&path)?;
                 let host_len = host_meta.len();
@@ -266,7 +295,7 @@ impl Exporter for S3Core {
             }
 
             debug!("Checking if file would survive rules...");
-            progress_state.set_message(format!("Checking if {:#} would survive rules...", &filename));
+            progress_state.set_message(&format!("Checking if {:#} would survive rules...", &filename));
 
             let existing = existing_files.iter().map(|p| p.as_path()).collect::<Vec<&Path>>();
             if !runtime.config.rules.would_survive(&existing, &path, meta.clone().into()).await {
@@ -283,28 +312,30 @@ impl Exporter for S3Core {
                 continue;
             }
 
-            progress_state.set_message(format!("Downloading {:#}...", &filename));
+            progress_state.set_message(&format!("Downloading {:#}...", &filename));
             let reader = op.reader_with(item.path()).await?;
             download_to(
                 meta.content_length(),
                 reader.into_bytes_stream(0..).await?.boxed(),
                 &path,
-                &download_bar,
+                &*download_bar,
             )
             .await?;
 
             debug!("Setting access time for {}", &path.to_str().unwrap());
-            progress_state.set_message(format!("Setting access time for {:#}...", &filename));
+            progress_state.set_message(&format!("Setting access time for {:#}...", &filename));
 
             let access_time = meta.last_modified().unwrap();
             filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(access_time.into()))
                 .with_context(|| format!("Failed to set access time for {}", &path.to_str().unwrap()))?;
 
+            written.push(path);
             progress_state.inc(1);
         }
 
-        download_bar.finish_and_clear();
+        download_bar.finish();
+        progress_state.finish();
 
-        Ok(())
+        Ok(written)
     }
 }
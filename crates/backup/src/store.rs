@@ -0,0 +1,331 @@
+/*
+ * Copyright (c) 2024. James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program.
+ * If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A content-addressed chunk store for deduplicating backup artefacts.
+//!
+//! Artefacts are split with content-defined chunking so that an insert or removal
+//! only changes the chunks around it, each unique chunk is compressed with zstd and stored once.
+//! A manifest per artefact records the chunks needed to reassemble it.
+//!
+//! ```text
+//! .chunks/
+//!   objects/ab/ab12…ef.zst
+//!   manifests/Bitwarden/user/export.json.json
+//! ```
+
+use crate::config::runtime::Runtime;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use fastcdc::v2020::StreamCDC;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{debug, info, trace, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StoreSettings {
+    /// The zstd compression level for new chunks.
+    pub level: i32,
+    pub min_chunk: u32,
+    pub avg_chunk: u32,
+    pub max_chunk: u32,
+}
+
+impl Default for StoreSettings {
+    fn default() -> Self {
+        Self {
+            level: 3,
+            min_chunk: 16 * 1024,
+            avg_chunk: 64 * 1024,
+            max_chunk: 256 * 1024,
+        }
+    }
+}
+
+/// The recipe for reassembling a single artefact.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The path of the artefact relative to the backup directory.
+    pub artefact: PathBuf,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    pub chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunk {
+    /// The hex encoded SHA-256 of the uncompressed chunk.
+    pub hash: String,
+    pub length: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChunkStore {
+    /// The backup directory, artefacts are addressed relative to this.
+    directory: PathBuf,
+    root: PathBuf,
+    settings: StoreSettings,
+}
+
+impl ChunkStore {
+    pub const DIRECTORY: &'static str = ".chunks";
+
+    pub fn new(directory: &Path, settings: StoreSettings) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            root: directory.join(Self::DIRECTORY),
+            settings,
+        }
+    }
+
+    /// The store for the runtime, if it has been enabled in the configuration.
+    pub fn open(runtime: &Runtime) -> Option<Self> {
+        runtime.config.store.map(|settings| Self::new(&runtime.directory, settings))
+    }
+
+    fn object(&self, hash: &str) -> PathBuf {
+        self.root.join("objects").join(&hash[..2]).join(format!("{hash}.zst"))
+    }
+
+    fn manifests_dir(&self) -> PathBuf {
+        self.root.join("manifests")
+    }
+
+    /// Resolves an absolute or backup relative artefact path to its relative form.
+    fn relative(&self, artefact: &Path) -> Result<PathBuf> {
+        let relative = match artefact.is_absolute() {
+            true => artefact
+                .strip_prefix(&self.directory)
+                .with_context(|| format!("{} is not inside {}", artefact.display(), self.directory.display()))?,
+            false => artefact,
+        };
+
+        if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Err(anyhow!("Invalid artefact path {}", artefact.display()));
+        }
+
+        Ok(relative.to_path_buf())
+    }
+
+    fn manifest_path(&self, artefact: &Path) -> Result<PathBuf> {
+        let relative = self.relative(artefact)?;
+        let mut name = relative.file_name().context("Artefact has no file name")?.to_os_string();
+        name.push(".json");
+
+        Ok(self.manifests_dir().join(relative.with_file_name(name)))
+    }
+
+    /// Splits the artefact into the store and replaces it with a manifest,
+    /// only chunks which aren't already stored are written.
+    pub fn ingest(&self, artefact: &Path) -> Result<Manifest> {
+        let path = self.directory.join(self.relative(artefact)?);
+        let metadata = path
+            .metadata()
+            .with_context(|| format!("Reading metadata of {}", path.display()))?;
+        let file = File::open(&path).with_context(|| format!("Opening {}", path.display()))?;
+
+        let mut chunks = vec![];
+        let mut new = 0usize;
+        let settings = &self.settings;
+        for chunk in StreamCDC::new(
+            BufReader::new(file),
+            settings.min_chunk,
+            settings.avg_chunk,
+            settings.max_chunk,
+        ) {
+            let chunk = chunk.with_context(|| format!("Chunking {}", path.display()))?;
+            let hash = hex::encode(Sha256::digest(&chunk.data));
+            let object = self.object(&hash);
+
+            if !object.exists() {
+                let compressed =
+                    zstd::encode_all(chunk.data.as_slice(), settings.level).context("Compressing chunk")?;
+                write_atomic(&object, &compressed)?;
+                new += 1;
+            }
+
+            chunks.push(Chunk {
+                hash,
+                length: chunk.length as u32,
+            });
+        }
+
+        let manifest = Manifest {
+            artefact: self.relative(artefact)?,
+            size: metadata.len(),
+            modified: metadata.modified().unwrap_or_else(|_| SystemTime::now()).into(),
+            chunks,
+        };
+
+        let serialised = serde_json::to_vec_pretty(&manifest).context("Serialise manifest")?;
+        write_atomic(&self.manifest_path(artefact)?, &serialised)?;
+        fs::remove_file(&path).with_context(|| format!("Removing stored artefact {}", path.display()))?;
+
+        info!(
+            "Stored {} in {} chunks, {new} of which were new",
+            manifest.artefact.display(),
+            manifest.chunks.len()
+        );
+        Ok(manifest)
+    }
+
+    /// The manifest of the artefact, if it has been stored.
+    pub fn stored(&self, artefact: &Path) -> Result<Option<Manifest>> {
+        let path = self.manifest_path(artefact)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let slice = fs::read(&path).with_context(|| format!("Reading {}", path.display()))?;
+        serde_json::from_slice(&slice)
+            .with_context(|| format!("Parsing {}", path.display()))
+            .map(Some)
+    }
+
+    /// Every manifest in the store.
+    pub fn manifests(&self) -> Result<Vec<Manifest>> {
+        let glob = format!("{}/**/*.json", self.manifests_dir().display());
+        glob::glob(&glob)
+            .with_context(|| format!("Glob for manifests in {}", glob))?
+            .flatten()
+            .map(|path| {
+                let slice = fs::read(&path).with_context(|| format!("Reading {}", path.display()))?;
+                serde_json::from_slice(&slice).with_context(|| format!("Parsing {}", path.display()))
+            })
+            .collect()
+    }
+
+    /// The absolute paths of the stored artefacts matching a glob over the backup directory,
+    /// used alongside the same glob on disk since stored artefacts no longer exist there.
+    pub fn glob(&self, pattern: &str) -> Result<Vec<PathBuf>> {
+        let Ok(relative) = Path::new(pattern).strip_prefix(&self.directory) else {
+            return Ok(vec![]);
+        };
+
+        let manifests = self.manifests_dir();
+        let glob = format!("{}.json", manifests.join(relative).display());
+        let artefacts = glob::glob(&glob)
+            .with_context(|| format!("Glob for manifests in {}", glob))?
+            .flatten()
+            .filter_map(|path| {
+                let relative = path.strip_prefix(&manifests).ok()?;
+                let name = relative.file_name()?.to_str()?.strip_suffix(".json")?;
+                Some(self.directory.join(relative.with_file_name(name)))
+            })
+            .collect();
+
+        Ok(artefacts)
+    }
+
+    /// Reassembles the artefact into the output file, verifying the hash of every chunk.
+    pub fn restore(&self, artefact: &Path, output: &Path) -> Result<Manifest> {
+        let manifest = self
+            .stored(artefact)?
+            .ok_or_else(|| anyhow!("No stored artefact {}", artefact.display()))?;
+
+        let file = File::create_new(output).with_context(|| format!("Creating {}", output.display()))?;
+        let mut writer = BufWriter::new(file);
+        for chunk in &manifest.chunks {
+            let object = self.object(&chunk.hash);
+            let compressed = fs::read(&object).with_context(|| format!("Reading chunk {}", chunk.hash))?;
+            let data =
+                zstd::decode_all(compressed.as_slice()).with_context(|| format!("Decompressing {}", chunk.hash))?;
+
+            if data.len() != chunk.length as usize || hex::encode(Sha256::digest(&data)) != chunk.hash {
+                drop(writer);
+                let _ = fs::remove_file(output);
+                return Err(anyhow!("Chunk {} of {} is corrupt", chunk.hash, artefact.display()));
+            }
+
+            writer.write_all(&data)?;
+        }
+
+        writer.flush()?;
+        filetime::set_file_mtime(output, filetime::FileTime::from_system_time(manifest.modified.into()))
+            .with_context(|| format!("Setting modified time of {}", output.display()))?;
+
+        Ok(manifest)
+    }
+
+    /// Removes the manifest of a pruned artefact, its chunks are left for [`ChunkStore::gc`].
+    pub fn forget(&self, artefact: &Path) -> Result<bool> {
+        let path = self.manifest_path(artefact)?;
+        match path.exists() {
+            false => Ok(false),
+            true => {
+                trace!("Forgetting {}", path.display());
+                fs::remove_file(&path).with_context(|| format!("Removing {}", path.display()))?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Checks every chunk referenced by the manifest is present.
+    pub fn is_complete(&self, manifest: &Manifest) -> bool {
+        manifest.chunks.iter().all(|chunk| self.object(&chunk.hash).is_file())
+    }
+
+    /// Removes every chunk which is no longer referenced by any manifest.
+    ///
+    /// # Returns
+    /// The paths of the removed chunks.
+    pub fn gc(&self) -> Result<Vec<PathBuf>> {
+        let referenced = self
+            .manifests()?
+            .into_iter()
+            .flat_map(|manifest| manifest.chunks)
+            .map(|chunk| chunk.hash)
+            .collect::<HashSet<_>>();
+
+        let glob = format!("{}/objects/*/*.zst", self.root.display());
+        let mut removed = vec![];
+        for object in glob::glob(&glob)
+            .with_context(|| format!("Glob for chunks in {}", glob))?
+            .flatten()
+        {
+            let Some(hash) = object.file_stem().and_then(|stem| stem.to_str()) else {
+                warn!("Skipping unexpected file {}", object.display());
+                continue;
+            };
+
+            if !referenced.contains(hash) {
+                debug!("Removing unreferenced chunk {hash}");
+                fs::remove_file(&object).with_context(|| format!("Removing {}", object.display()))?;
+                removed.push(object);
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Writes to a temporary file next to the path and renames it into place,
+/// so a partially written chunk or manifest is never observed.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let parent = path.parent().context("Path has no parent")?;
+    fs::create_dir_all(parent).with_context(|| format!("Creating {}", parent.display()))?;
+
+    let mut temp = tempfile::NamedTempFile::new_in(parent).context("Creating temporary file")?;
+    temp.write_all(data)?;
+    temp.persist(path)
+        .with_context(|| format!("Persisting {}", path.display()))
+        .map(|_| ())
+}
@@ -16,22 +16,30 @@
 
 use crate::config::backend::Backend;
 use crate::config::config::{Config, Error};
+use crate::config::lock::Lock;
 use crate::config::rules::Rules;
 use crate::config::runtime::Runtime;
+use crate::config::secrets::Secrets;
+use crate::report::{BackendReport, Report};
 use crate::sources::exporter::ExporterSource;
+use crate::store::{ChunkStore, StoreSettings};
 use crate::ui::cli::ui::BackupCli;
 use amt_lib::pathed::ensure_directory_exists;
+use amt_lib::progress::{Hidden, Kind};
 use amt_lib::ui::cli::continue_loop;
+use amt_lib::ui::cli::error::{Categorise, Category};
 use amt_lib::ui::cli::progress;
 use amt_lib::ui::cli::ui_inquire::STYLE;
 use anyhow::{anyhow, Context, Result};
 use clap::Parser;
+use clap_complete::{ArgValueCandidates, CompletionCandidate};
 use inquire::{PathFilter, PathSelect, PathSelectionMode};
 use macros::CommonFields;
 use obj_builder::buildable::Buildable;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::{env, fs};
-use tracing::{error, instrument, trace};
+use tracing::{instrument, trace};
 
 #[derive(Debug, Parser, CommonFields)]
 pub enum Action {
@@ -40,6 +48,10 @@ pub enum Action {
         /// The path to the backup location root directory.
         #[clap(short = 'D', long, help = "The path to the backup location root directory.")]
         destination: Option<PathBuf>,
+
+        /// Wait for any other run using the backup directory to finish, instead of failing.
+        #[clap(long)]
+        wait: bool,
     },
 
     /// Run the backup process with the existing configuration
@@ -47,6 +59,14 @@ pub enum Action {
         /// The path to the backup location root directory.
         #[clap(short = 'D', long, help = "The path to the backup location root directory.")]
         destination: Option<PathBuf>,
+
+        /// Wait for any other run using the backup directory to finish, instead of failing.
+        #[clap(long)]
+        wait: bool,
+
+        /// Only run the backends with these names, instead of every backend.
+        #[clap(long = "backend", value_name = "NAME", add = ArgValueCandidates::new(complete_backend))]
+        backends: Vec<String>,
     },
 
     /// Modify the configuration interactively
@@ -54,6 +74,50 @@ pub enum Action {
         /// The path to the backup location root directory.
         #[clap(short = 'D', long, help = "The path to the backup location root directory.")]
         destination: Option<PathBuf>,
+
+        /// Wait for any other run using the backup directory to finish, instead of failing.
+        #[clap(long)]
+        wait: bool,
+    },
+
+    /// Verify the existing backups are present and readable
+    Verify {
+        /// The path to the backup location root directory.
+        #[clap(short = 'D', long, help = "The path to the backup location root directory.")]
+        destination: Option<PathBuf>,
+
+        /// Wait for any other run using the backup directory to finish, instead of failing.
+        #[clap(long)]
+        wait: bool,
+    },
+
+    /// Prune the existing backups without running any exports
+    Prune {
+        /// The path to the backup location root directory.
+        #[clap(short = 'D', long, help = "The path to the backup location root directory.")]
+        destination: Option<PathBuf>,
+
+        /// Wait for any other run using the backup directory to finish, instead of failing.
+        #[clap(long)]
+        wait: bool,
+    },
+
+    /// Reassemble an artefact from the chunk store
+    RestoreFile {
+        /// The path to the backup location root directory.
+        #[clap(short = 'D', long, help = "The path to the backup location root directory.")]
+        destination: Option<PathBuf>,
+
+        /// Wait for any other run using the backup directory to finish, instead of failing.
+        #[clap(long)]
+        wait: bool,
+
+        /// The artefact to restore, relative to the backup directory.
+        artefact: PathBuf,
+
+        /// Where to write the restored file, defaults to the artefact's original location.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
     },
 }
 
@@ -61,8 +125,11 @@ impl Action {
     #[instrument(level = "TRACE")]
     pub async fn initialise(&self) -> Result<Runtime> {
         match self {
-            Action::Init { destination } => {
-                let config_path = find_backup_config(destination)?;
+            Action::Init { destination, wait } => {
+                let config_path = find_backup_config(destination).categorise(Category::Config)?;
+                // Safety: We know the parent exists because it's a file.
+                let directory = config_path.parent().unwrap().to_path_buf();
+                let lock = Lock::acquire(&directory, *wait).await?;
                 if config_path.exists() {
                     return Err(anyhow!(
                         "A configuration already exists at {}, please run modify instead.",
@@ -70,37 +137,66 @@ impl Action {
                     ));
                 }
 
-                // Safety: We know the parent exists because it's a file.
-                Ok(Runtime::wrapping(config_path.parent().unwrap().to_path_buf()))
+                let mut runtime = Runtime::wrapping(directory);
+                runtime.lock = Some(Arc::new(lock));
+                Ok(runtime)
             }
-            Action::Modify { destination } | Action::Run { destination } => {
-                let config_path = find_backup_config(destination)?;
-                let config = Config::load(&config_path).await?;
+            Action::Modify { destination, wait }
+            | Action::Run { destination, wait, .. }
+            | Action::Verify { destination, wait }
+            | Action::Prune { destination, wait }
+            | Action::RestoreFile { destination, wait, .. } => {
+                let config_path = find_backup_config(destination).categorise(Category::Config)?;
+                // Safety: We know the parent exists because it's a file.
+                let directory = config_path.parent().unwrap().to_path_buf();
+
+                // Only read the configuration once any other run has finished writing it.
+                let lock = Lock::acquire(&directory, *wait).await?;
+                let config = Config::load(&config_path).await.categorise(Category::Config)?;
                 Ok(Runtime {
                     config,
-                    // Safety: We know the parent exists because it's a file.
-                    directory: config_path.parent().unwrap().to_path_buf(),
+                    directory,
+                    lock: Some(Arc::new(lock)),
+                    secrets: Secrets::default(),
                 })
             }
         }
     }
 
-    /// Runs the action.
-    pub async fn run(&self, cli: &mut BackupCli) -> Result<()> {
-        use indicatif::MultiProgress;
-        use std::sync::Arc;
-
+    /// The name of the action, as used on the command line.
+    pub fn name(&self) -> &'static str {
         match self {
+            Action::Init { .. } => "init",
+            Action::Run { .. } => "run",
+            Action::Modify { .. } => "modify",
+            Action::Verify { .. } => "verify",
+            Action::Prune { .. } => "prune",
+            Action::RestoreFile { .. } => "restore-file",
+        }
+    }
+
+    /// Runs the action.
+    ///
+    /// # Returns
+    /// A [`Report`] of the outcome for each backend which was touched.
+    pub async fn run(&self, cli: &mut BackupCli) -> Result<Report> {
+        let directory = cli.runtime.as_ref().unwrap().directory.clone();
+        let backends = match self {
             Action::Init { .. } => {
                 let exporters = new_exporters(cli.runtime.as_ref().unwrap()).await?;
                 let rules = <Rules as Buildable>::from(cli).await?;
+                let store = inquire::Confirm::new("Do you want to deduplicate artefacts into a chunk store?")
+                    .with_render_config(*STYLE)
+                    .with_default(false)
+                    .prompt()?;
                 let config = &mut cli.runtime.as_mut().unwrap().config;
 
                 config.exporters = exporters;
                 config.rules = rules;
+                config.store = store.then(StoreSettings::default);
                 config.mutated = true;
 
-                Ok(())
+                config.exporters.iter().map(BackendReport::new).collect()
             }
             Action::Modify { .. } => {
                 use inquire::Confirm;
@@ -115,6 +211,7 @@ impl Action {
                 }
 
                 // TODO :: Allow removal of existing exporters
+                let mut added = vec![];
                 if Confirm::new("Do you want to modify the exporters?")
                     .with_default(true)
                     .prompt()?
@@ -123,50 +220,145 @@ impl Action {
                     if !exporters.is_empty() {
                         let config = &mut cli.runtime.as_mut().unwrap().config;
 
+                        added = exporters.iter().map(BackendReport::new).collect();
                         config.exporters.extend(exporters);
                         config.mutated = true;
                     }
                 }
 
-                Ok(())
+                added
             }
-            Action::Run { .. } => {
+            Action::Run { backends, .. } => {
                 let config = &cli.runtime.as_ref().unwrap().config;
-                let multi_bar = Arc::new(MultiProgress::new());
-                let total_progress = Arc::new(multi_bar.add(progress::bar(config.exporters.len() as u64)));
+                if let Some(unknown) = backends
+                    .iter()
+                    .find(|name| !config.exporters.iter().any(|e| e.name() == **name))
+                {
+                    return Err(anyhow!("There is no backend named {unknown}"));
+                }
+
+                let exporters = config
+                    .exporters
+                    .iter()
+                    .filter(|exporter| backends.is_empty() || backends.contains(&exporter.name()))
+                    .cloned()
+                    .collect::<Vec<_>>();
 
-                // TODO :: Clean this the fuck up
-                let mut handles = vec![];
-                for exporter in config.exporters.clone() {
-                    let passed_progress = multi_bar.add(progress::spinner());
-                    passed_progress.set_message(format!("Running exporter: {exporter}"));
+                let total_progress = progress::reporter(Kind::Bar);
+                total_progress.set_length(exporters.len() as u64);
+
+                let mut reports = vec![];
+                for exporter in exporters {
+                    let passed_progress = total_progress.child(Kind::Spinner);
+                    passed_progress.set_message(&format!("Running exporter: {exporter}"));
 
-                    let total_progress = total_progress.clone();
-                    let multi_bar = multi_bar.clone();
                     let runtime = cli.runtime.as_ref().unwrap();
 
                     trace!("Running exporter: {}", exporter);
-                    let result = exporter.run(runtime, &passed_progress, &multi_bar).await;
+                    let report = exporter.run(runtime, &*passed_progress).await;
                     total_progress.inc(1);
-                    passed_progress.finish_and_clear();
+                    passed_progress.finish();
 
-                    handles.push(result);
+                    reports.push(report);
                 }
 
-                total_progress.finish_and_clear();
-                // let results = futures::future::join_all(handles).await;
-                for result in handles {
-                    if let Err(e) = result {
-                        error!("Error while running exporter: {:?}", &e);
-                    }
+                total_progress.finish();
+                reports.extend(collect_garbage(cli.runtime.as_ref().unwrap()));
+                reports
+            }
+            Action::Verify { .. } => {
+                let runtime = cli.runtime.as_ref().unwrap();
+                let mut reports = runtime
+                    .config
+                    .exporters
+                    .iter()
+                    .map(|exporter| exporter.verify(runtime))
+                    .collect::<Vec<_>>();
+
+                reports.extend(verify_store(runtime));
+                reports
+            }
+            Action::Prune { .. } => {
+                let runtime = cli.runtime.as_ref().unwrap();
+                let mut reports = runtime
+                    .config
+                    .exporters
+                    .iter()
+                    .map(|exporter| exporter.prune(runtime, &Hidden))
+                    .collect::<Vec<_>>();
+
+                reports.extend(collect_garbage(runtime));
+                reports
+            }
+            Action::RestoreFile { artefact, output, .. } => {
+                let runtime = cli.runtime.as_ref().unwrap();
+                let store = ChunkStore::open(runtime).context("The chunk store isn't enabled for this backup")?;
+                let output = output.clone().unwrap_or_else(|| runtime.directory.join(artefact));
+
+                let mut report = BackendReport::new(STORE);
+                match store.restore(artefact, &output) {
+                    Ok(_) => report.files.push(output),
+                    Err(err) => report.error(&err),
+                }
 
-                    trace!("Finished running exporter successfully");
+                vec![report]
+            }
+        };
+
+        Ok(Report::new(self.name(), directory, backends))
+    }
+}
+
+/// The name the chunk store is reported under.
+const STORE: &str = "Chunk store";
+
+/// Removes the chunks which are no longer referenced after pruning.
+fn collect_garbage(runtime: &Runtime) -> Option<BackendReport> {
+    let store = ChunkStore::open(runtime)?;
+    let mut report = BackendReport::new(STORE);
+    match store.gc() {
+        Ok(removed) => report.pruned = removed,
+        Err(err) => report.error(&err),
+    }
+
+    Some(report)
+}
+
+/// Checks every stored artefact can still be reassembled.
+fn verify_store(runtime: &Runtime) -> Option<BackendReport> {
+    let store = ChunkStore::open(runtime)?;
+    let mut report = BackendReport::new(STORE);
+    match store.manifests() {
+        Err(err) => report.error(&err),
+        Ok(manifests) => {
+            for manifest in manifests {
+                if !store.is_complete(&manifest) {
+                    report.error(&anyhow!("{} is missing chunks", manifest.artefact.display()));
                 }
 
-                Ok(())
+                report.files.push(manifest.artefact);
             }
         }
     }
+
+    Some(report)
+}
+
+/// Completes backend names from the configuration found without prompting.
+fn complete_backend() -> Vec<CompletionCandidate> {
+    let Some(config) = Config::find(&None)
+        .ok()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|slice| serde_json::from_slice::<Config>(&slice).ok())
+    else {
+        return vec![];
+    };
+
+    config
+        .exporters
+        .iter()
+        .map(|exporter| CompletionCandidate::new(exporter.name()).help(Some(exporter.to_string().into())))
+        .collect()
 }
 
 #[instrument(level = "TRACE")]
@@ -214,6 +406,10 @@ pub(crate) async fn new_exporters(runtime: &Runtime) -> Result<Vec<Backend>> {
 
         match source_type {
             Ok(t) => {
+                let progress = progress::reporter(Kind::Spinner);
+                t.setup(runtime, &*progress).await?;
+                progress.finish();
+
                 let vec = t.create(runtime).await?;
                 exporters.extend(vec);
             }
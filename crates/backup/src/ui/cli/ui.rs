@@ -16,11 +16,12 @@
 
 use crate::config::runtime::Runtime;
 use crate::ui::cli::action::Action;
-use amt_lib::ui::cli::flags::CommonFlags;
+use amt_lib::ui::cli::error::{Categorise, Category};
+use amt_lib::ui::cli::flags::{CommonFlags, OutputFormat};
 use amt_lib::ui::cli::oneshot::OneshotHandler;
 use amt_lib::ui::cli::{CliResult, CliUi};
 use amt_lib::ui::Ui;
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use tracing_appender::non_blocking::WorkerGuard;
 
 #[derive(Debug)]
@@ -43,7 +44,20 @@ impl OneshotHandler for BackupCli {
             self.runtime = Some(command.initialise().await?);
         }
 
-        command.run(self).await?;
+        let report = command.run(self).await?;
+        match flags.format {
+            OutputFormat::Human => report.log(),
+            OutputFormat::Json => {
+                let json = serde_json::to_value(&report).context("Serialise report")?;
+                println!("{json:#}");
+            }
+        }
+
+        if !report.success {
+            let failed = report.backends.iter().filter(|backend| !backend.success()).count();
+            Err::<(), _>(anyhow!("{failed} of {} backends failed", report.backends.len()))
+                .categorise(Category::Partial)?;
+        }
 
         Ok(())
     }
@@ -136,3 +136,25 @@
 //
 //     assert!(config.load().is_err());
 // }
+
+mod save {
+    use backup::config::config::Config;
+
+    #[test_log::test(tokio::test)]
+    async fn save_replaces_existing() {
+        let temp = tempfile::tempdir().unwrap();
+        let config = Config::new(temp.path());
+        config.save().await.unwrap();
+
+        let path = config.path.clone().unwrap();
+        let loaded = Config::load(&path).await.unwrap();
+        assert_eq!(loaded.exporters, config.exporters);
+
+        // A new config is always considered mutated, so this writes over the existing file.
+        config.save().await.unwrap();
+        assert!(Config::load(&path).await.is_ok());
+
+        let leftovers = std::fs::read_dir(temp.path()).unwrap().count();
+        assert_eq!(leftovers, 1, "Only settings.json should remain");
+    }
+}
@@ -16,10 +16,11 @@
 
 use assert_fs::prelude::{FileTouch, PathChild};
 use assert_fs::TempDir;
-use backup::config::rules::autoprune::Tag;
+use backup::config::rules::autoprune::{AutoPrune, Tag};
 use backup::config::rules::metadata::Metadata;
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use std::assert_matches::assert_matches;
+use std::path::PathBuf;
 
 // Helper function to create a temporary directory and return its path
 fn create_temp_dir() -> TempDir {
@@ -255,3 +256,35 @@ fn tag_get_tags() {
 //     assert_eq!(result.len(), 1);
 //     assert_eq!(result[0], old.path());
 // }
+
+/// An artefact for every hour of the 40 days before a fixed Monday afternoon,
+/// the index of each is the number of hours before that time.
+fn hourly_artefacts() -> Vec<(PathBuf, DateTime<Utc>)> {
+    let latest = DateTime::parse_from_rfc3339("2024-06-10T12:30:00Z").unwrap().to_utc();
+    (0..24 * 40)
+        .map(|hours| (PathBuf::from(hours.to_string()), latest - Duration::hours(hours)))
+        .collect()
+}
+
+fn kept(rules: &AutoPrune) -> Vec<usize> {
+    let expired = rules.expired(hourly_artefacts());
+    (0..24 * 40)
+        .filter(|index| !expired.contains(&PathBuf::from(index.to_string())))
+        .collect()
+}
+
+#[test]
+fn auto_prune_expired() {
+    let rules = AutoPrune::default();
+    let mut expected = (0..12).collect::<Vec<_>>();
+    expected.extend([13, 37, 61, 85, 109, 133]);
+    assert_eq!(kept(&rules), expected);
+
+    let rules: AutoPrune =
+        serde_json::from_str(r#"{"hours":0,"days":0,"weeks":0,"months":3,"keep_latest":1}"#).unwrap();
+    assert_eq!(kept(&rules), vec![0, 24 * 9 + 13]);
+
+    let rules: AutoPrune =
+        serde_json::from_str(r#"{"hours":0,"days":0,"weeks":0,"months":0,"keep_latest":1000}"#).unwrap();
+    assert!(rules.expired(hourly_artefacts()).is_empty());
+}
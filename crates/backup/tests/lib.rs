@@ -16,3 +16,5 @@
  */
 
 mod config;
+mod report;
+mod store;
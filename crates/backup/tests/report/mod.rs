@@ -0,0 +1,56 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use backup::report::{BackendReport, Report};
+use std::path::PathBuf;
+
+fn backend(name: &str, errors: Vec<String>) -> BackendReport {
+    BackendReport {
+        backend: name.to_string(),
+        files: vec![PathBuf::from("file.json")],
+        pruned: vec![],
+        errors,
+    }
+}
+
+#[test]
+fn report_success() {
+    let report = Report::new(
+        "run",
+        PathBuf::from("/backup"),
+        vec![backend("a", vec![]), backend("b", vec![])],
+    );
+    assert!(report.success);
+
+    let report = Report::new(
+        "run",
+        PathBuf::from("/backup"),
+        vec![backend("a", vec![]), backend("b", vec!["failed".to_string()])],
+    );
+    assert!(!report.success);
+}
+
+#[test]
+fn report_json() {
+    let report = Report::new("verify", PathBuf::from("/backup"), vec![backend("a", vec![])]);
+    let json = serde_json::to_value(&report).unwrap();
+
+    assert_eq!(json["action"], "verify");
+    assert_eq!(json["success"], true);
+    assert_eq!(json["backends"][0]["backend"], "a");
+    assert_eq!(json["backends"][0]["files"][0], "file.json");
+    assert!(json["backends"][0]["errors"].as_array().unwrap().is_empty());
+}
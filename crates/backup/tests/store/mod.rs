@@ -0,0 +1,172 @@
+/*
+ * Copyright (c) 2024. James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with this program.
+ * If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use backup::store::{ChunkStore, StoreSettings};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use std::fs;
+use std::path::Path;
+
+fn random(seed: u64, length: usize) -> Vec<u8> {
+    let mut data = vec![0; length];
+    StdRng::seed_from_u64(seed).fill_bytes(&mut data);
+    data
+}
+
+fn objects(directory: &Path) -> usize {
+    let glob = format!("{}/{}/objects/*/*.zst", directory.display(), ChunkStore::DIRECTORY);
+    glob::glob(&glob).unwrap().count()
+}
+
+fn write(directory: &Path, name: &str, data: &[u8]) {
+    let path = directory.join(name);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, data).unwrap();
+}
+
+#[test]
+fn ingest_and_restore() {
+    let directory = tempfile::tempdir().unwrap();
+    let store = ChunkStore::new(directory.path(), StoreSettings::default());
+    let data = random(1, 1024 * 1024);
+    write(directory.path(), "Source/one/export.json", &data);
+
+    let manifest = store.ingest(&directory.path().join("Source/one/export.json")).unwrap();
+    assert_eq!(manifest.size, data.len() as u64);
+    assert!(manifest.chunks.len() > 1);
+    assert!(!directory.path().join("Source/one/export.json").exists());
+
+    let output = directory.path().join("restored.json");
+    store.restore(Path::new("Source/one/export.json"), &output).unwrap();
+    assert_eq!(fs::read(&output).unwrap(), data);
+
+    let modified = fs::metadata(&output).unwrap().modified().unwrap();
+    assert_eq!(chrono::DateTime::<chrono::Utc>::from(modified), manifest.modified);
+}
+
+#[test]
+fn only_changed_chunks_are_stored() {
+    let directory = tempfile::tempdir().unwrap();
+    let store = ChunkStore::new(directory.path(), StoreSettings::default());
+
+    let mut data = random(2, 2 * 1024 * 1024);
+    write(directory.path(), "first.bin", &data);
+    let first = store.ingest(Path::new("first.bin")).unwrap();
+    let before = objects(directory.path());
+    assert_eq!(before, first.chunks.len());
+
+    // Insert into the middle, shifting everything after it.
+    data.splice(1024 * 1024..1024 * 1024, random(3, 100));
+    write(directory.path(), "second.bin", &data);
+    let second = store.ingest(Path::new("second.bin")).unwrap();
+
+    let new = objects(directory.path()) - before;
+    assert!(new > 0 && new <= 3, "{new} new chunks of {}", second.chunks.len());
+}
+
+#[test]
+fn gc_after_forget() {
+    let directory = tempfile::tempdir().unwrap();
+    let store = ChunkStore::new(directory.path(), StoreSettings::default());
+    let shared = random(4, 512 * 1024);
+
+    write(
+        directory.path(),
+        "a.bin",
+        &[shared.as_slice(), &random(5, 512 * 1024)].concat(),
+    );
+    write(
+        directory.path(),
+        "b.bin",
+        &[shared.as_slice(), &random(6, 512 * 1024)].concat(),
+    );
+    store.ingest(Path::new("a.bin")).unwrap();
+    let b = store.ingest(Path::new("b.bin")).unwrap();
+
+    assert!(store.gc().unwrap().is_empty());
+    assert!(store.forget(Path::new("a.bin")).unwrap());
+    assert!(!store.forget(Path::new("a.bin")).unwrap());
+
+    let removed = store.gc().unwrap();
+    assert!(!removed.is_empty());
+    assert_eq!(objects(directory.path()), b.chunks.len());
+    assert!(store.is_complete(&b));
+
+    store.restore(Path::new("b.bin"), &directory.path().join("b.out")).unwrap();
+    assert!(store.restore(Path::new("a.bin"), &directory.path().join("a.out")).is_err());
+}
+
+#[test]
+fn corrupt_chunk_is_detected() {
+    let directory = tempfile::tempdir().unwrap();
+    let store = ChunkStore::new(directory.path(), StoreSettings::default());
+    write(directory.path(), "file.txt", b"some small artefact");
+
+    let manifest = store.ingest(Path::new("file.txt")).unwrap();
+    let hash = &manifest.chunks[0].hash;
+    let object = directory
+        .path()
+        .join(ChunkStore::DIRECTORY)
+        .join("objects")
+        .join(&hash[..2])
+        .join(format!("{hash}.zst"));
+    fs::write(object, zstd::encode_all(&b"tampered"[..], 3).unwrap()).unwrap();
+
+    let output = directory.path().join("file.out");
+    assert!(store.restore(Path::new("file.txt"), &output).is_err());
+    assert!(!output.exists());
+}
+
+#[test]
+fn rejects_paths_outside_directory() {
+    let directory = tempfile::tempdir().unwrap();
+    let store = ChunkStore::new(directory.path(), StoreSettings::default());
+
+    assert!(store.ingest(Path::new("../escape")).is_err());
+    assert!(store.stored(Path::new("/elsewhere/file")).is_err());
+}
+
+#[test]
+fn pruned_stored_artefact_is_reclaimed() {
+    let directory = tempfile::tempdir().unwrap();
+    let store = ChunkStore::new(directory.path(), StoreSettings::default());
+    write(directory.path(), "Command/test/test-1.txt", &random(7, 256 * 1024));
+    write(directory.path(), "Command/test/test-2.txt", &random(8, 256 * 1024));
+    write(directory.path(), "Command/other.txt", b"not matched");
+    store.ingest(Path::new("Command/test/test-1.txt")).unwrap();
+    let kept = store.ingest(Path::new("Command/test/test-2.txt")).unwrap();
+    store.ingest(Path::new("Command/other.txt")).unwrap();
+
+    let pattern = format!("{}/Command/test/test-*.txt", directory.path().display());
+    let mut stored = store.glob(&pattern).unwrap();
+    stored.sort();
+    assert_eq!(
+        stored,
+        vec![
+            directory.path().join("Command/test/test-1.txt"),
+            directory.path().join("Command/test/test-2.txt")
+        ]
+    );
+    assert!(store.glob("/elsewhere/*.txt").unwrap().is_empty());
+
+    assert!(store.forget(&stored[0]).unwrap());
+    assert!(!store.gc().unwrap().is_empty());
+    assert_eq!(
+        store.glob(&pattern).unwrap(),
+        vec![directory.path().join("Command/test/test-2.txt")]
+    );
+    assert!(store.is_complete(&kept));
+}
@@ -0,0 +1,99 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use country_ip::index::RecordIndex;
+use country_ip::record::Record;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use keshvar::Alpha2;
+use rand::prelude::{Rng, RngCore, SeedableRng, SmallRng};
+use std::hint::black_box;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const COUNTRIES: [Alpha2; 6] = [Alpha2::AU, Alpha2::CN, Alpha2::JP, Alpha2::NZ, Alpha2::US, Alpha2::GB];
+const LOOKUPS: usize = 1_000_000;
+
+/// Roughly the size of the DB-IP country database, a /24 or /48 every few blocks.
+fn records() -> Vec<Record> {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let mut records = Vec::with_capacity(500_000);
+
+    for block in (0..u32::MAX >> 8).step_by(48) {
+        let start = block << 8;
+        records.push(Record::DBRecord {
+            alpha: COUNTRIES[rng.gen_range(0..COUNTRIES.len())],
+            start: IpAddr::V4(Ipv4Addr::from(start)),
+            end: IpAddr::V4(Ipv4Addr::from(start | 0xff)),
+        });
+    }
+
+    for block in (0..u64::from(u32::MAX) >> 8).step_by(4096) {
+        let start = 0x2000_u128 << 112 | u128::from(block) << 80;
+        records.push(Record::DBRecord {
+            alpha: COUNTRIES[rng.gen_range(0..COUNTRIES.len())],
+            start: IpAddr::V6(Ipv6Addr::from(start)),
+            end: IpAddr::V6(Ipv6Addr::from(start | ((1 << 80) - 1))),
+        });
+    }
+
+    records
+}
+
+fn addresses() -> Vec<IpAddr> {
+    let mut rng = SmallRng::seed_from_u64(1);
+    (0..LOOKUPS)
+        .map(|n| match n % 4 {
+            0 => IpAddr::V6(Ipv6Addr::from(0x2000_u128 << 112 | rng.gen_range(0..1_u128 << 104))),
+            _ => IpAddr::V4(Ipv4Addr::from(rng.next_u32())),
+        })
+        .collect()
+}
+
+fn bench_compile(c: &mut Criterion) {
+    let records = records();
+
+    let mut group = c.benchmark_group("compile");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(records.len() as u64));
+    group.bench_function("index", |b| {
+        b.iter_batched(|| records.clone(), RecordIndex::new, BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let index = RecordIndex::new(records());
+    let addresses = addresses();
+
+    let mut group = c.benchmark_group("lookup");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(addresses.len() as u64));
+    group.bench_function("bulk", |b| {
+        b.iter(|| addresses.iter().filter(|addr| index.lookup(addr).is_some()).count())
+    });
+    group.finish();
+
+    c.bench_function("country", |b| {
+        b.iter(|| {
+            COUNTRIES
+                .iter()
+                .map(|alpha| index.country(black_box(alpha)).count())
+                .sum::<usize>()
+        })
+    });
+}
+
+criterion_group!(benches, bench_compile, bench_lookup);
+criterion_main!(benches);
@@ -0,0 +1,341 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Autonomous system numbers, from the `asn` records the registries publish
+//! and an IP-to-ASN table imported into the cache.
+//!
+//! The registries only say which country and organisation an ASN was delegated to,
+//! which ASN announces an address comes from the table, in the tab separated layout published by
+//! [iptoasn](https://iptoasn.com): `range_start  range_end  AS_number  country_code  AS_description`.
+
+use crate::cache::{Cache, Source};
+use crate::db_ip::GZIP_MAGIC;
+use crate::record::Status;
+use crate::registry::Registry;
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use keshvar::Alpha2;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, Read};
+use std::net::IpAddr;
+use std::str::FromStr;
+use thiserror::Error;
+use tokio::sync::OnceCell;
+use tracing::{instrument, trace, warn};
+
+#[derive(Debug, Error)]
+pub enum AsnError {
+    #[error("{0} isn't an ASN, expected a number such as 13335 or AS13335")]
+    Invalid(String),
+}
+
+/// Parses an ASN with or without the `AS` prefix.
+pub fn parse_asn(asn: &str) -> Result<u32, AsnError> {
+    let trimmed = asn.trim();
+    let number = match trimmed.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("as") => &trimmed[2..],
+        _ => trimmed,
+    };
+
+    number.parse().map_err(|_| AsnError::Invalid(asn.to_string()))
+}
+
+/// A block of consecutive ASNs delegated by a registry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsnBlock {
+    pub registry: Registry,
+    pub alpha: Alpha2,
+    pub start: u32,
+    pub count: u32,
+    pub date: String,
+    pub status: Status,
+}
+
+impl AsnBlock {
+    pub fn end(&self) -> u32 {
+        self.start.saturating_add(self.count.saturating_sub(1))
+    }
+
+    pub fn contains(&self, asn: u32) -> bool {
+        (self.start..=self.end()).contains(&asn)
+    }
+}
+
+impl Display for AsnBlock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.count {
+            0 | 1 => write!(f, "AS{}", self.start),
+            _ => write!(f, "AS{}-AS{}", self.start, self.end()),
+        }
+    }
+}
+
+/// The ASN blocks of a registry sorted by their first ASN.
+#[derive(Debug, Default, Clone)]
+pub struct AsnIndex {
+    blocks: Vec<AsnBlock>,
+}
+
+impl AsnIndex {
+    pub fn new(mut blocks: Vec<AsnBlock>) -> Self {
+        blocks.sort_by_key(|block| block.start);
+        Self { blocks }
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// The block holding the ASN.
+    pub fn find(&self, asn: u32) -> Option<&AsnBlock> {
+        let index = self.blocks.partition_point(|block| block.start <= asn);
+        self.blocks[..index].last().filter(|block| block.contains(asn))
+    }
+
+    /// The blocks delegated to the country in order.
+    pub fn country<'a>(&'a self, alpha: &'a Alpha2) -> impl Iterator<Item = &'a AsnBlock> + 'a {
+        self.blocks.iter().filter(move |block| block.alpha == *alpha)
+    }
+}
+
+/// A range of addresses announced by an ASN.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsnRange {
+    pub start: IpAddr,
+    pub end: IpAddr,
+    pub asn: u32,
+
+    /// The country the table puts the ASN in, which may not be where the registry delegated it.
+    pub country: String,
+    pub description: String,
+}
+
+/// An IP-to-ASN table, with the ranges of each family sorted by their first address.
+#[derive(Debug, Default, Clone)]
+pub struct AsnTable {
+    v4: Vec<(u128, u128, AsnRange)>,
+    v6: Vec<(u128, u128, AsnRange)>,
+}
+
+static TABLE: OnceCell<AsnTable> = OnceCell::const_new();
+
+impl AsnTable {
+    /// The imported table, which is never refreshed automatically.
+    pub async fn instance() -> Result<&'static Self> {
+        TABLE.get_or_try_init(|| async { Self::load(&Cache::open()?).await }).await
+    }
+
+    #[instrument(level = "TRACE", skip(cache), err)]
+    pub async fn load(cache: &Cache) -> Result<Self> {
+        let data = cache.load(Source::IpToAsn, true).await?;
+        Self::parse(&data)
+    }
+
+    /// Parse a table, either gzipped or not, skipping the ranges which aren't announced.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let reader: Box<dyn Read> = match data.starts_with(&GZIP_MAGIC) {
+            true => Box::new(GzDecoder::new(data)),
+            false => Box::new(data),
+        };
+
+        let mut table = Self::default();
+        for line in BufReader::new(reader).lines() {
+            let line = line.context("Failed to read the IP-to-ASN table")?;
+            let Some(range) = Self::parse_line(&line) else {
+                trace!("Skipping line {line}");
+                continue;
+            };
+
+            let bits = |addr: IpAddr| match addr {
+                IpAddr::V4(addr) => addr.to_bits() as u128,
+                IpAddr::V6(addr) => addr.to_bits(),
+            };
+            let entry = (bits(range.start), bits(range.end), range);
+            match entry.2.start {
+                IpAddr::V4(_) => table.v4.push(entry),
+                IpAddr::V6(_) => table.v6.push(entry),
+            }
+        }
+
+        table.v4.sort_by_key(|(start, ..)| *start);
+        table.v6.sort_by_key(|(start, ..)| *start);
+        Ok(table)
+    }
+
+    fn parse_line(line: &str) -> Option<AsnRange> {
+        let mut fields = line.split('\t');
+        let start = IpAddr::from_str(fields.next()?.trim()).ok()?;
+        let end = IpAddr::from_str(fields.next()?.trim()).ok()?;
+        let asn = parse_asn(fields.next()?).ok()?;
+        if asn == 0 || start.is_ipv4() != end.is_ipv4() {
+            return None;
+        }
+
+        Some(AsnRange {
+            start,
+            end,
+            asn,
+            country: fields.next().unwrap_or_default().trim().to_string(),
+            description: fields.next().unwrap_or_default().trim().to_string(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.v4.is_empty() && self.v6.is_empty()
+    }
+
+    /// The range holding the address.
+    pub fn lookup(&self, ip: &IpAddr) -> Option<&AsnRange> {
+        let (ranges, bits) = match ip {
+            IpAddr::V4(addr) => (&self.v4, addr.to_bits() as u128),
+            IpAddr::V6(addr) => (&self.v6, addr.to_bits()),
+        };
+
+        let index = ranges.partition_point(|(start, ..)| *start <= bits);
+        ranges[..index]
+            .last()
+            .filter(|(_, end, _)| bits <= *end)
+            .map(|(.., range)| range)
+    }
+}
+
+/// The block holding the ASN, from whichever registry delegated it.
+///
+/// Registries which can't be loaded are skipped, unless none of them can be.
+#[instrument(level = "TRACE", ret, err)]
+pub async fn find(asn: u32, offline: bool) -> Result<Option<AsnBlock>> {
+    let mut loaded = false;
+    let mut last_err = None;
+    for registry in Registry::get_variants() {
+        match registry.records(offline).await {
+            Ok(records) => match records.asns.find(asn) {
+                Some(block) => return Ok(Some(block.clone())),
+                None => loaded = true,
+            },
+            Err(err) => {
+                warn!("Skipping {}: {err:#}", registry.name());
+                last_err = Some(err);
+            }
+        }
+    }
+
+    match (loaded, last_err) {
+        (false, Some(err)) => Err(err),
+        _ => Ok(None),
+    }
+}
+
+/// Every block delegated to the country, by any registry.
+///
+/// A country's ASNs aren't always held by the registry serving its region,
+/// so every registry is searched and those which can't be loaded are skipped, unless none of them can be.
+#[instrument(level = "TRACE", ret, err)]
+pub async fn country(alpha: &Alpha2, offline: bool) -> Result<Vec<AsnBlock>> {
+    let mut blocks = Vec::new();
+    let mut loaded = false;
+    let mut last_err = None;
+    for registry in Registry::get_variants() {
+        match registry.records(offline).await {
+            Ok(records) => {
+                blocks.extend(records.asns.country(alpha).cloned());
+                loaded = true;
+            }
+            Err(err) => {
+                warn!("Skipping {}: {err:#}", registry.name());
+                last_err = Some(err);
+            }
+        }
+    }
+
+    match (loaded, last_err) {
+        (false, Some(err)) => Err(err),
+        _ => {
+            blocks.sort_by_key(|block| block.start);
+            Ok(blocks)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keshvar::Alpha2::{AU, JP};
+
+    const TABLE: &str = "1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET
+1.0.1.0\t1.0.3.255\t0\tNone\tNot routed
+1.0.4.0\t1.0.7.255\t38803\tAU\tGTELECOM-AUSTRALIA Gtelecom-AUSTRALIA
+2001:200::\t2001:200:ffff:ffff:ffff:ffff:ffff:ffff\t2500\tJP\tWIDE-BB WIDE Project
+not\ta\trange
+";
+
+    fn ip(addr: &str) -> IpAddr {
+        IpAddr::from_str(addr).unwrap()
+    }
+
+    #[test]
+    fn test_parse_asn() {
+        assert_eq!(parse_asn("13335").unwrap(), 13335);
+        assert_eq!(parse_asn("AS13335").unwrap(), 13335);
+        assert_eq!(parse_asn("as13335").unwrap(), 13335);
+        assert!(parse_asn("AS").is_err());
+        assert!(parse_asn("cloudflare").is_err());
+    }
+
+    #[test]
+    fn test_index() {
+        let block = |alpha, start, count| AsnBlock {
+            registry: Registry::Apnic,
+            alpha,
+            start,
+            count,
+            date: "20020801".into(),
+            status: Status::Allocated,
+        };
+        let index = AsnIndex::new(vec![block(JP, 2497, 1), block(AU, 173, 1), block(AU, 4608, 1024)]);
+
+        assert_eq!(index.find(173).map(|block| block.alpha), Some(AU));
+        assert_eq!(index.find(5000).map(|block| block.start), Some(4608));
+        assert_eq!(index.find(5632), None);
+        assert_eq!(index.find(1), None);
+        assert_eq!(
+            index.country(&AU).map(ToString::to_string).collect::<Vec<_>>(),
+            vec!["AS173", "AS4608-AS5631"]
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let table = AsnTable::parse(TABLE.as_bytes()).unwrap();
+        assert_eq!(table.len(), 3);
+
+        let range = table.lookup(&ip("1.0.0.1")).unwrap();
+        assert_eq!(range.asn, 13335);
+        assert_eq!(range.description, "CLOUDFLARENET");
+
+        assert_eq!(table.lookup(&ip("1.0.5.5")).map(|range| range.asn), Some(38803));
+        assert_eq!(table.lookup(&ip("1.0.2.1")), None);
+        assert_eq!(table.lookup(&ip("2001:200::1")).map(|range| range.asn), Some(2500));
+        assert_eq!(table.lookup(&ip("::1")), None);
+    }
+}
@@ -0,0 +1,320 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Resolving many addresses at once, read from a file or stdin and written out as CSV or JSON.
+//!
+//! Inputs which can't be parsed or resolved are counted in the [`Summary`] instead of stopping the run,
+//! so one bad line in a large log doesn't throw away everything else.
+
+use crate::cache::Source;
+use crate::record::Record;
+use crate::registry::Registry;
+use crate::RecordDB;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+use tracing::{instrument, warn};
+
+#[derive(Debug, Error)]
+pub enum BulkError {
+    #[error("The input doesn't have a column named {0}")]
+    MissingColumn(String),
+
+    #[error("None of the registries or DB-IP could be loaded")]
+    NoDatabases,
+}
+
+/// How the results of a bulk lookup are written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// One row per input with a header.
+    #[default]
+    Csv,
+
+    /// A single document holding the results and the summary.
+    Json,
+}
+
+/// The outcome of resolving a single input.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Resolution {
+    pub input: String,
+    pub country: Option<String>,
+    pub registry: Option<&'static str>,
+    pub status: Option<String>,
+    pub range_start: Option<IpAddr>,
+    pub range_end: Option<IpAddr>,
+    pub error: Option<String>,
+}
+
+impl Resolution {
+    pub(crate) fn resolved(input: String, record: &Record) -> Self {
+        let (registry, status) = match record {
+            Record::RegistryRecord { registry, status, .. } => (registry.name(), Some(status.name().to_string())),
+            Record::DBRecord { .. } => (Source::DbIp.name(), None),
+        };
+
+        Self {
+            input,
+            country: Some(record.alpha().to_string()),
+            registry: Some(registry),
+            status,
+            range_start: Some(record.start()),
+            range_end: Some(record.end()),
+            error: None,
+        }
+    }
+
+    pub(crate) fn failed(input: String, error: impl ToString) -> Self {
+        Self {
+            input,
+            error: Some(error.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Counts of how every input was handled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    pub total: usize,
+    pub resolved: usize,
+    /// Valid addresses which none of the records contain.
+    pub unresolved: usize,
+    /// Inputs which aren't an address at all.
+    pub invalid: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    summary: Summary,
+    results: Vec<Resolution>,
+}
+
+/// Every record database, searched in order until one contains the address.
+#[derive(Debug)]
+pub struct Resolver {
+    databases: Vec<Box<dyn RecordDB>>,
+}
+
+impl Resolver {
+    pub fn new(databases: Vec<Box<dyn RecordDB>>) -> Self {
+        Self { databases }
+    }
+
+    /// Load every registry followed by DB-IP, skipping any which fail as long as one of them loads.
+    ///
+    /// Only the MaxMind database is used when one has been chosen.
+    #[instrument(level = "TRACE", err)]
+    pub async fn load(offline: bool) -> Result<Self> {
+        if let Some(database) = crate::mmdb::instance().await? {
+            return Ok(Self::new(vec![database]));
+        }
+
+        let mut databases = Vec::new();
+        for registry in Registry::get_variants() {
+            match registry.get(offline).await {
+                Ok(database) => databases.push(database),
+                Err(err) => warn!("Skipping {}: {err:#}", registry.name()),
+            }
+        }
+
+        match crate::db_ip::DB::instance(offline).await {
+            Ok(database) => databases.push(database),
+            Err(err) => warn!("Skipping {}: {err:#}", Source::DbIp.name()),
+        }
+
+        match databases.is_empty() {
+            true => Err(BulkError::NoDatabases.into()),
+            false => Ok(Self::new(databases)),
+        }
+    }
+
+    pub async fn resolve(&self, ip: &IpAddr) -> Option<&Record> {
+        for database in &self.databases {
+            if let Some(record) = database.record(ip).await {
+                return Some(record);
+            }
+        }
+
+        None
+    }
+
+    /// Resolve every address from `input`, writing a [`Resolution`] for each to `output`.
+    ///
+    /// Without a `column` the first field of each line is used,
+    /// otherwise the first line is read as a header and the named column is used.
+    pub async fn resolve_all(
+        &self,
+        input: impl Read,
+        column: Option<&str>,
+        format: Format,
+        mut output: impl Write,
+    ) -> Result<Summary> {
+        let mut summary = Summary::default();
+        let mut results = Vec::new();
+        let mut writer = csv::Writer::from_writer(&mut output);
+
+        for address in addresses(input, column)? {
+            let resolution = match address {
+                Err(err) => {
+                    summary.invalid += 1;
+                    Resolution::failed(String::new(), err)
+                }
+                Ok(address) => match IpAddr::from_str(&address) {
+                    Err(err) => {
+                        summary.invalid += 1;
+                        Resolution::failed(address, err)
+                    }
+                    Ok(ip) => match self.resolve(&ip).await {
+                        Some(record) => {
+                            summary.resolved += 1;
+                            Resolution::resolved(address, record)
+                        }
+                        None => {
+                            summary.unresolved += 1;
+                            Resolution::failed(address, "No record contains this address")
+                        }
+                    },
+                },
+            };
+            summary.total += 1;
+
+            match format {
+                Format::Csv => writer.serialize(&resolution).context("Failed to write result")?,
+                Format::Json => results.push(resolution),
+            }
+        }
+
+        writer.flush().context("Failed to write results")?;
+        drop(writer);
+
+        if format == Format::Json {
+            serde_json::to_writer_pretty(&mut output, &Report { summary, results })
+                .context("Failed to write results")?;
+        }
+        output.flush().context("Failed to write results")?;
+
+        Ok(summary)
+    }
+}
+
+/// Open `path` for reading, with `-` meaning stdin.
+pub fn open(path: &Path) -> Result<Box<dyn Read>> {
+    match path.as_os_str() == "-" {
+        true => Ok(Box::new(std::io::stdin().lock())),
+        false => {
+            let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+            Ok(Box::new(std::io::BufReader::new(file)))
+        }
+    }
+}
+
+/// The addresses from each line of `input`, skipping blank lines and `#` comments.
+fn addresses(
+    input: impl Read,
+    column: Option<&str>,
+) -> Result<impl Iterator<Item = std::result::Result<String, csv::Error>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(column.is_some())
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .comment(Some(b'#'))
+        .from_reader(input);
+
+    let position = match column {
+        None => 0,
+        Some(column) => reader
+            .headers()
+            .context("Failed to read the header")?
+            .iter()
+            .position(|header| header == column)
+            .ok_or_else(|| BulkError::MissingColumn(column.to_owned()))?,
+    };
+
+    Ok(reader
+        .into_records()
+        .map(move |record| record.map(|record| record.get(position).unwrap_or_default().to_owned())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db_ip::DB;
+
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/delegated-apnic-extended-latest");
+
+    fn resolver() -> Resolver {
+        let db = DB::parse(b"10.0.0.0,10.0.0.255,NZ\n").unwrap();
+        Resolver::new(vec![Box::new(Registry::Apnic.parse(FIXTURE)), Box::new(db)])
+    }
+
+    async fn run(input: &str, column: Option<&str>, format: Format) -> Result<(Summary, String)> {
+        let mut output = Vec::new();
+        let summary = resolver().resolve_all(input.as_bytes(), column, format, &mut output).await?;
+        Ok((summary, String::from_utf8(output)?))
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_lines() -> Result<()> {
+        let (summary, output) = run(
+            "# Comment\n133.1.2.3\n\n10.0.0.1\n192.168.1.1\nnot an ip\n",
+            None,
+            Format::Csv,
+        )
+        .await?;
+
+        assert_eq!(
+            summary,
+            Summary {
+                total: 4,
+                resolved: 2,
+                unresolved: 1,
+                invalid: 1,
+            }
+        );
+
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "input,country,registry,status,range_start,range_end,error");
+        assert_eq!(lines[1], "133.1.2.3,JP,apnic,allocated,133.0.0.0,133.255.255.255,");
+        assert_eq!(lines[2], "10.0.0.1,NZ,db-ip,,10.0.0.0,10.0.0.255,");
+        assert!(lines[3].starts_with("192.168.1.1,,,,,,"));
+
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_column() -> Result<()> {
+        let input = "time,client,path\n12:00,101.160.4.20,/\n12:01,2001:200::1,/about\n";
+        let (summary, output) = run(input, Some("client"), Format::Json).await?;
+        assert_eq!(summary.resolved, 2);
+
+        let json = serde_json::from_str::<serde_json::Value>(&output)?;
+        assert_eq!(json["summary"]["total"], 2);
+        assert_eq!(json["results"][0]["country"], "AU");
+        assert_eq!(json["results"][1]["country"], "JP");
+
+        let missing = run(input, Some("address"), Format::Csv).await;
+        assert!(missing.is_err_and(|err| matches!(err.downcast_ref(), Some(BulkError::MissingColumn(_)))));
+
+        Ok(())
+    }
+}
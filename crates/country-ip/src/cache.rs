@@ -0,0 +1,425 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Snapshots of the registry and DB-IP data kept on disk,
+//! so the tool doesn't have to download everything on every run and can work without a network.
+//!
+//! Each [`Source`] has its own directory under the data directory,
+//! holding the raw snapshot as it was downloaded or imported and the [`Metadata`] describing it.
+
+use crate::registry::Registry;
+use amt_lib::fs::data_directory;
+use amt_lib::pathed::Pathed;
+use amt_lib::ui::cli::error::{Categorise, Category};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+use tracing::{debug, info, instrument, warn};
+
+const SNAPSHOT: &str = "snapshot";
+const METADATA: &str = "metadata.json";
+
+#[derive(Debug, Error)]
+pub enum CacheError {
+    #[error("There is no cached data for {0}, run `country-ip update` while online or import a file")]
+    Missing(Source),
+
+    #[error("Failed to read the cached data for {0}")]
+    Read(Source, #[source] std::io::Error),
+
+    #[error("Failed to write the cached data for {0}")]
+    Write(Source, #[source] std::io::Error),
+
+    #[error("The cached metadata for {0} is corrupt")]
+    Metadata(Source, #[source] serde_json::Error),
+
+    #[error("{path} doesn't contain any {name} records", path = .0.display(), name = .1)]
+    Empty(PathBuf, Source),
+}
+
+/// Somewhere records are published.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum Source {
+    Registry(Registry),
+    DbIp,
+
+    /// A table of which ASN announces each address, see [`crate::asn`].
+    IpToAsn,
+}
+
+impl Source {
+    /// Every source of country records,
+    /// the IP-to-ASN table is left out as it's only ever imported or updated by name.
+    pub fn all() -> Vec<Self> {
+        <Self as clap::ValueEnum>::value_variants()
+            .iter()
+            .filter(|source| **source != Self::IpToAsn)
+            .copied()
+            .collect()
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Registry(registry) => registry.name(),
+            Self::DbIp => "db-ip",
+            Self::IpToAsn => "ip-to-asn",
+        }
+    }
+
+    pub fn url(&self) -> String {
+        match self {
+            Self::Registry(registry) => registry.url(),
+            Self::DbIp => crate::db_ip::DB::get_dated_url(),
+            Self::IpToAsn => "https://iptoasn.com/data/ip2asn-combined.tsv.gz".to_string(),
+        }
+    }
+
+    /// How long a snapshot stays fresh for, the registries publish daily while DB-IP publishes monthly.
+    ///
+    /// The IP-to-ASN table is never refreshed on its own, this only decides when to warn it's old.
+    pub const fn max_age(&self) -> TimeDelta {
+        match self {
+            Self::Registry(_) => TimeDelta::days(1),
+            Self::DbIp => TimeDelta::days(31),
+            Self::IpToAsn => TimeDelta::days(7),
+        }
+    }
+
+    /// If `data` holds at least one record from this source.
+    fn validate(&self, data: &[u8]) -> bool {
+        match self {
+            Self::Registry(registry) => !registry.parse(data).index.is_empty(),
+            Self::DbIp => crate::db_ip::DB::parse(data).is_ok_and(|db| !db.is_empty()),
+            Self::IpToAsn => crate::asn::AsnTable::parse(data).is_ok_and(|table| !table.is_empty()),
+        }
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Source {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        <Self as clap::ValueEnum>::value_variants()
+            .iter()
+            .copied()
+            .find(|source| source.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| anyhow!("Unknown source {name}, expected one of {}", names()))
+    }
+}
+
+impl clap::ValueEnum for Source {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Self::Registry(Registry::Afrinic),
+            Self::Registry(Registry::Apnic),
+            Self::Registry(Registry::Arin),
+            Self::Registry(Registry::Lacnic),
+            Self::Registry(Registry::Ripencc),
+            Self::DbIp,
+            Self::IpToAsn,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.name()))
+    }
+}
+
+impl Pathed<PathBuf> for Source {
+    const NAME: &'static str = env!("CARGO_PKG_NAME");
+
+    fn get_unique_name(&self) -> String {
+        self.name().to_owned()
+    }
+}
+
+fn names() -> String {
+    <Source as clap::ValueEnum>::value_variants()
+        .iter()
+        .map(Source::name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Where and when a snapshot came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// When the snapshot was downloaded or imported.
+    pub fetched: DateTime<Utc>,
+
+    /// The URL or file the snapshot was taken from.
+    pub origin: String,
+
+    /// The size of the snapshot in bytes.
+    pub size: u64,
+}
+
+impl Metadata {
+    pub fn age(&self) -> TimeDelta {
+        Utc::now() - self.fetched
+    }
+
+    pub fn is_fresh(&self, source: Source) -> bool {
+        self.age() <= source.max_age()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cache {
+    root: PathBuf,
+}
+
+impl Cache {
+    /// A cache kept under `root`, with each source in its own directory.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The cache in the user's data directory.
+    pub fn open() -> Result<Self> {
+        data_directory()
+            .map(Self::new)
+            .context("Unable to find a data directory for the cache")
+    }
+
+    fn directory(&self, source: Source) -> Result<PathBuf> {
+        source.unique_dir(&self.root)
+    }
+
+    pub fn metadata(&self, source: Source) -> Result<Option<Metadata>> {
+        let path = self.directory(source)?.join(METADATA);
+        match fs::read(&path) {
+            Ok(bytes) => Ok(Some(
+                serde_json::from_slice(&bytes).map_err(|err| CacheError::Metadata(source, err))?,
+            )),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(CacheError::Read(source, err).into()),
+        }
+    }
+
+    pub fn read(&self, source: Source) -> Result<Option<Vec<u8>>> {
+        let path = self.directory(source)?.join(SNAPSHOT);
+        match fs::read(&path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(CacheError::Read(source, err).into()),
+        }
+    }
+
+    /// Replace the snapshot for `source`, the previous one is kept if writing fails part way.
+    ///
+    /// Both files are written in full before either is moved into place,
+    /// and the old metadata is removed first so a crash between the renames leaves a snapshot
+    /// without metadata, which is treated as stale, rather than metadata describing another snapshot.
+    pub fn store(&self, source: Source, data: &[u8], origin: impl Into<String>) -> Result<Metadata> {
+        let directory = self.directory(source)?;
+        let metadata = Metadata {
+            fetched: Utc::now(),
+            origin: origin.into(),
+            size: data.len() as u64,
+        };
+
+        let serialised = serde_json::to_vec_pretty(&metadata)?;
+        let temp = |name: &str| directory.join(format!(".{name}.{}.tmp", std::process::id()));
+        let (snapshot, described) = (temp(SNAPSHOT), temp(METADATA));
+        let result = fs::write(&snapshot, data)
+            .and_then(|_| fs::write(&described, &serialised))
+            .and_then(|_| match fs::remove_file(directory.join(METADATA)) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            })
+            .and_then(|_| fs::rename(&snapshot, directory.join(SNAPSHOT)))
+            .and_then(|_| fs::rename(&described, directory.join(METADATA)));
+
+        if let Err(err) = result {
+            let _ = fs::remove_file(&snapshot);
+            let _ = fs::remove_file(&described);
+            return Err(CacheError::Write(source, err).into());
+        }
+
+        debug!("Cached {} bytes for {source} from {}", metadata.size, metadata.origin);
+        Ok(metadata)
+    }
+
+    /// Download a new snapshot for `source`.
+    #[instrument(level = "TRACE", skip(self), err)]
+    pub async fn update(&self, source: Source) -> Result<Metadata> {
+        let url = source.url();
+        info!("Downloading {source} data from {url}");
+
+        let fetch = async { reqwest::get(&url).await?.error_for_status()?.bytes().await };
+        let data = fetch
+            .await
+            .with_context(|| format!("Failed to download data for {source}"))
+            .categorise(Category::Network)?;
+
+        self.store(source, &data, url)
+    }
+
+    /// Use a local file as the snapshot for `source`, for machines which can't reach the registries.
+    #[instrument(level = "TRACE", skip(self), err)]
+    pub fn import(&self, source: Source, path: &Path) -> Result<Metadata> {
+        let data = fs::read(path)
+            .with_context(|| format!("Failed to read {}", path.display()))
+            .categorise(Category::NoInput)?;
+
+        if !source.validate(&data) {
+            return Err(CacheError::Empty(path.to_path_buf(), source)).categorise(Category::Data);
+        }
+
+        self.store(source, &data, path.display().to_string())
+    }
+
+    /// The snapshot for `source`, downloading a new one first when it's missing or stale.
+    ///
+    /// When `offline` or the download fails any existing snapshot is used no matter its age.
+    #[instrument(level = "TRACE", skip(self), err)]
+    pub async fn load(&self, source: Source, offline: bool) -> Result<Vec<u8>> {
+        let metadata = self.metadata(source)?;
+        let fresh = metadata.as_ref().is_some_and(|metadata| metadata.is_fresh(source));
+        let cached = self.directory(source)?.join(SNAPSHOT).is_file();
+
+        if !fresh && !offline {
+            match self.update(source).await {
+                Ok(_) => {}
+                Err(err) if cached => {
+                    warn!("Using the cached data for {source} as updating failed: {err:#}")
+                }
+                Err(err) => return Err(err),
+            }
+        } else if let Some(metadata) = metadata.as_ref().filter(|_| !fresh) {
+            warn!(
+                "The cached data for {source} is {} days old, run `country-ip update` to refresh it",
+                metadata.age().num_days()
+            );
+        }
+
+        self.read(source)?
+            .ok_or(CacheError::Missing(source))
+            .categorise(Category::NoInput)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/delegated-apnic-extended-latest");
+
+    fn cache() -> (tempfile::TempDir, Cache) {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = Cache::new(directory.path());
+        (directory, cache)
+    }
+
+    #[test]
+    fn test_source_names() {
+        for source in Source::all() {
+            assert_eq!(Source::from_str(source.name()).unwrap(), source);
+        }
+
+        for registry in Registry::get_variants() {
+            assert!(Source::all().contains(&Source::Registry(registry)));
+        }
+
+        assert_eq!(Source::from_str("ip-to-asn").unwrap(), Source::IpToAsn);
+        assert!(!Source::all().contains(&Source::IpToAsn));
+        assert!(Source::from_str("iana").is_err());
+    }
+
+    #[test]
+    fn test_import() -> Result<()> {
+        let (directory, cache) = cache();
+        let source = Source::Registry(Registry::Apnic);
+        let path = directory.path().join("delegated");
+        fs::write(&path, FIXTURE)?;
+
+        assert_eq!(cache.metadata(source)?, None);
+        let metadata = cache.import(source, &path)?;
+        assert_eq!(metadata.size, FIXTURE.len() as u64);
+        assert!(metadata.is_fresh(source));
+
+        assert_eq!(cache.metadata(source)?, Some(metadata));
+        assert_eq!(cache.read(source)?.as_deref(), Some(FIXTURE));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_rejects_other_data() -> Result<()> {
+        let (directory, cache) = cache();
+        let path = directory.path().join("notes.txt");
+        fs::write(&path, "not a registry file")?;
+
+        assert!(cache.import(Source::Registry(Registry::Arin), &path).is_err());
+        assert!(cache.import(Source::DbIp, &path).is_err());
+        assert_eq!(cache.read(Source::Registry(Registry::Arin))?, None);
+
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_offline_load() -> Result<()> {
+        let (_directory, cache) = cache();
+        let source = Source::Registry(Registry::Apnic);
+
+        assert!(cache.load(source, true).await.is_err());
+
+        let mut metadata = cache.store(source, FIXTURE, "fixture")?;
+        metadata.fetched -= TimeDelta::days(7);
+        fs::write(cache.directory(source)?.join(METADATA), serde_json::to_vec(&metadata)?)?;
+
+        assert!(!cache.metadata(source)?.unwrap().is_fresh(source));
+        assert_eq!(cache.load(source, true).await?, FIXTURE);
+
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_store_replaces_both_files() -> Result<()> {
+        let (_directory, cache) = cache();
+        let source = Source::Registry(Registry::Apnic);
+
+        cache.store(source, b"old", "first")?;
+        let metadata = cache.store(source, FIXTURE, "second")?;
+        assert_eq!(cache.metadata(source)?, Some(metadata));
+        assert_eq!(cache.read(source)?.as_deref(), Some(FIXTURE));
+
+        let mut names = fs::read_dir(cache.directory(source)?)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        names.sort();
+        assert_eq!(names, [METADATA, SNAPSHOT]);
+
+        // Interrupted between the renames, the snapshot is still used but is no longer fresh.
+        fs::remove_file(cache.directory(source)?.join(METADATA))?;
+        assert_eq!(cache.metadata(source)?, None);
+        assert_eq!(cache.load(source, true).await?, FIXTURE);
+
+        Ok(())
+    }
+}
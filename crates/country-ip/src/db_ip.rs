@@ -14,58 +14,60 @@
  * along with this program. If not, see https://www.gnu.org/licenses/.
  */
 
+use crate::cache::{Cache, Source};
+use crate::index::RecordIndex;
 use crate::record::Record;
 use crate::record::Record::DBRecord;
 use crate::RecordDB;
-use anyhow::Result;
-use async_compression::tokio::bufread::GzipDecoder;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
-use futures::io::Error as FutureError;
-use futures::TryStreamExt;
+use flate2::read::GzDecoder;
 use keshvar::Alpha2;
+use std::io::{BufRead, BufReader, Read};
 use std::net::IpAddr;
 use std::str::FromStr;
-use std::sync::LazyLock;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio_util::compat::FuturesAsyncReadCompatExt;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
 use tracing::{error, instrument};
 
+/// The first bytes of a gzip stream.
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
 #[derive(Debug, Clone)]
-pub struct DB(Vec<Record>);
+pub struct DB(Arc<RecordIndex>);
 
-pub static DB_INSTANCE: LazyLock<DB> = LazyLock::new(|| {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(async { DB::new().await }).unwrap()
-});
+static DB_INSTANCE: OnceCell<DB> = OnceCell::const_new();
 
 impl DB {
     /// Get the URL to download the current csv file from.
-    fn get_dated_url() -> String {
+    pub(crate) fn get_dated_url() -> String {
         use chrono::Datelike;
 
         let year = chrono::Utc::now().year();
         let month = chrono::Utc::now().month();
         format!(
-            "https://download.db-ip.com/free/dbip-country-lite-{}-{}.csv.gz",
+            "https://download.db-ip.com/free/dbip-country-lite-{}-{:02}.csv.gz",
             year, month
         )
     }
 
-    // TODO - Cache the csv file for reuse.
-    #[instrument(level = "TRACE", ret, err)]
-    async fn new() -> Result<Self> {
-        let response = reqwest::get(Self::get_dated_url()).await?;
-        let stream = response
-            .bytes_stream()
-            .map_err(|e| FutureError::new(futures::io::ErrorKind::Other, e))
-            .into_async_read()
-            .compat();
-        let decoder = GzipDecoder::new(stream);
-        let reader = BufReader::new(decoder);
-        let mut lines = reader.lines();
+    /// Read the database from the cache, refreshing it first when it's stale unless `offline`.
+    #[instrument(level = "TRACE", skip(cache), err)]
+    pub async fn load(cache: &Cache, offline: bool) -> Result<Self> {
+        let data = cache.load(Source::DbIp, offline).await?;
+        Self::parse(&data)
+    }
+
+    /// Parse the CSV published by DB-IP, either as downloaded or already decompressed.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let reader: Box<dyn Read> = match data.starts_with(&GZIP_MAGIC) {
+            true => Box::new(GzDecoder::new(data)),
+            false => Box::new(data),
+        };
 
         let mut records = vec![];
-        while let Some(line) = lines.next_line().await? {
+        for line in BufReader::new(reader).lines() {
+            let line = line.context("Failed to decompress the DB-IP data")?;
             let split = line.split(',').map(|s| s.into()).collect::<Vec<String>>();
             if split.len() != 3 {
                 error!("Invalid line {line}; skipping");
@@ -102,29 +104,31 @@ impl DB {
             records.push(DBRecord { alpha, start, end })
         }
 
-        Ok(Self(records))
+        Ok(Self(Arc::new(RecordIndex::new(records))))
     }
 
-    pub fn instance() -> Box<dyn RecordDB> {
-        Box::new(DB_INSTANCE.clone())
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub async fn instance(offline: bool) -> Result<Box<dyn RecordDB>> {
+        let db = DB_INSTANCE
+            .get_or_try_init(|| async { Self::load(&Cache::open()?, offline).await })
+            .await?;
+        Ok(Box::new(db.clone()))
     }
 }
 
 #[async_trait]
 impl RecordDB for DB {
     #[instrument(level = "TRACE", ret)]
-    async fn lookup(&self, ip: &IpAddr) -> Option<Alpha2> {
-        for record in &self.0 {
-            if &record.start() <= ip && &record.end() >= ip {
-                return Some(*record.alpha());
-            }
-        }
-        None
+    async fn record(&self, ip: &IpAddr) -> Option<&Record> {
+        self.0.lookup(ip)
     }
 
     #[instrument(level = "TRACE", ret)]
     async fn filtered(&self, alpha: &Alpha2) -> Vec<&Record> {
-        self.0.iter().filter(|record| record.alpha() == alpha).collect::<Vec<&Record>>()
+        self.0.country(alpha).collect()
     }
 }
 
@@ -132,11 +136,32 @@ impl RecordDB for DB {
 mod tests {
     use super::*;
     use keshvar::Alpha2::AU;
+    use std::io::Write;
     use std::net::Ipv4Addr;
 
+    const CSV: &str =
+        "1.0.0.0,1.0.0.255,AU\n101.160.0.0,101.191.255.255,AU\n2001:200::,2001:200:ffff:ffff:ffff:ffff:ffff:ffff,JP\n";
+
+    #[test_log::test(tokio::test)]
+    async fn test_parse() -> Result<()> {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(CSV.as_bytes())?;
+        let compressed = encoder.finish()?;
+
+        assert_eq!(DB::parse(CSV.as_bytes())?.0.len(), 3);
+        assert_eq!(DB::parse(&compressed)?.0.len(), 3);
+        assert!(DB::parse(b"not,a\ndatabase")?.is_empty());
+
+        let db = DB::parse(CSV.as_bytes())?;
+        assert_eq!(db.lookup(&IpAddr::V4(Ipv4Addr::new(101, 170, 1, 1))).await, Some(AU));
+
+        Ok(())
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_download() -> Result<()> {
-        let db = DB::new().await;
+        let directory = tempfile::tempdir()?;
+        let db = DB::load(&Cache::new(directory.path()), false).await;
         assert!(db.is_ok());
 
         Ok(())
@@ -144,7 +169,8 @@ mod tests {
 
     #[test_log::test(tokio::test)]
     async fn test_db() -> Result<()> {
-        let db = DB::new().await?;
+        let directory = tempfile::tempdir()?;
+        let db = DB::load(&Cache::new(directory.path()), false).await?;
 
         let record = DBRecord {
             start: IpAddr::V4(Ipv4Addr::new(101, 160, 0, 0)),
@@ -0,0 +1,367 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Every range belonging to a set of countries as the fewest CIDRs that cover them,
+//! written in formats firewalls can load directly for allow and deny lists.
+
+use crate::mmdb;
+use crate::record::Record;
+use anyhow::Result;
+use cidr::IpCidr;
+use keshvar::{Alpha2, Country};
+use std::collections::HashMap;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::RangeInclusive;
+use tracing::instrument;
+
+/// How an exported list is written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// One CIDR per line.
+    #[default]
+    Plain,
+
+    /// Variables holding sets, for `include` in an nftables ruleset.
+    Nftables,
+
+    /// A file for `ipset restore`.
+    Ipset,
+
+    /// `iptables` and `ip6tables` commands appending a rule for each CIDR to a chain.
+    Iptables,
+
+    /// A binary MaxMind DB in the GeoLite2-Country layout.
+    Mmdb,
+}
+
+/// The CIDRs to export, aggregated per address family.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Cidrs {
+    pub v4: Vec<IpCidr>,
+    pub v6: Vec<IpCidr>,
+}
+
+impl Cidrs {
+    /// Aggregate the ranges of `records` into the fewest CIDRs which cover exactly the same addresses.
+    #[instrument(level = "TRACE", skip(records))]
+    pub fn from_records<'a>(records: impl IntoIterator<Item = &'a Record>, ipv4: bool, ipv6: bool) -> Self {
+        let mut v4 = Vec::new();
+        let mut v6 = Vec::new();
+        for record in records {
+            match record.start() {
+                IpAddr::V4(_) if ipv4 => v4.push(record.u128_range()),
+                IpAddr::V6(_) if ipv6 => v6.push(record.u128_range()),
+                _ => {}
+            }
+        }
+
+        Self {
+            v4: aggregate(v4, 32),
+            v6: aggregate(v6, 128),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.v4.is_empty() && self.v6.is_empty()
+    }
+
+    /// Write the CIDRs as `format`.
+    ///
+    /// `name` is used for the nftables variables, ipset sets and iptables chain,
+    /// with the address family appended where a format keeps them separate.
+    /// `target` is the iptables target jumped to for matching addresses.
+    pub fn write(&self, format: Format, name: &str, target: &str, mut out: impl Write) -> IoResult<()> {
+        let families = [("ipv4", "inet", &self.v4), ("ipv6", "inet6", &self.v6)];
+
+        match format {
+            Format::Plain => {
+                for cidr in self.v4.iter().chain(&self.v6) {
+                    writeln!(out, "{cidr}")?;
+                }
+            }
+            Format::Nftables => {
+                for (family, _, cidrs) in families.into_iter().filter(|(_, _, cidrs)| !cidrs.is_empty()) {
+                    writeln!(out, "define {name}_{family} = {{")?;
+                    for cidr in cidrs {
+                        writeln!(out, "    {cidr},")?;
+                    }
+                    writeln!(out, "}}")?;
+                }
+            }
+            Format::Ipset => {
+                for (family, ipset_family, cidrs) in families.into_iter().filter(|(_, _, cidrs)| !cidrs.is_empty()) {
+                    let set = format!("{name}-{family}");
+                    let maxelem = cidrs.len().max(65536);
+                    writeln!(
+                        out,
+                        "create {set} hash:net family {ipset_family} maxelem {maxelem} -exist"
+                    )?;
+                    for cidr in cidrs {
+                        writeln!(out, "add {set} {cidr} -exist")?;
+                    }
+                }
+            }
+            Format::Mmdb => {
+                return Err(IoError::new(
+                    ErrorKind::InvalidInput,
+                    "An MMDB needs the country of each range, use export::write",
+                ))
+            }
+            Format::Iptables => {
+                for (family, _, cidrs) in families {
+                    let command = match family {
+                        "ipv4" => "iptables",
+                        _ => "ip6tables",
+                    };
+
+                    for cidr in cidrs {
+                        writeln!(out, "{command} -A {name} -s {cidr} -j {target}")?;
+                    }
+                }
+            }
+        }
+
+        out.flush()
+    }
+}
+
+/// Collect every range belonging to the countries.
+#[instrument(level = "TRACE", skip(countries), err)]
+pub async fn records(countries: &[Country], offline: bool) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for country in countries {
+        let alpha = country.alpha2();
+        let database = crate::get_record_db(country, offline).await?;
+        records.extend(database.ranges(&alpha).await.into_iter().cloned());
+    }
+
+    Ok(records)
+}
+
+/// Write the records as `format`, returning how many networks were written.
+///
+/// The text formats merge the ranges of every country together, see [`Cidrs::write`],
+/// while an MMDB keeps the ranges of each country apart so addresses can be resolved to them.
+pub fn write(
+    records: &[Record],
+    format: Format,
+    name: &str,
+    target: &str,
+    ipv4: bool,
+    ipv6: bool,
+    out: impl Write,
+) -> IoResult<usize> {
+    if format != Format::Mmdb {
+        let cidrs = Cidrs::from_records(records, ipv4, ipv6);
+        cidrs.write(format, name, target, out)?;
+        return Ok(cidrs.len());
+    }
+
+    let mut countries = HashMap::<Alpha2, Vec<&Record>>::new();
+    for record in records {
+        countries.entry(*record.alpha()).or_default().push(record);
+    }
+
+    // Sorted so the same records always produce the same file.
+    let mut countries = countries.into_iter().collect::<Vec<_>>();
+    countries.sort_unstable_by_key(|(alpha, _)| alpha.to_string());
+
+    let mut writer = mmdb::Writer::default();
+    let mut written = 0;
+    for (alpha, records) in countries {
+        let cidrs = Cidrs::from_records(records, ipv4, ipv6);
+        for cidr in cidrs.v4.iter().chain(&cidrs.v6) {
+            writer
+                .insert(cidr, alpha)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+        }
+        written += cidrs.len();
+    }
+
+    writer.write(out)?;
+    Ok(written)
+}
+
+/// Every country except those excluded when none are given, otherwise the given countries without those excluded.
+pub fn select(include: Vec<Country>, exclude: &[Country]) -> Vec<Country> {
+    let include = match include.is_empty() {
+        true => keshvar::CountryIterator::new().collect(),
+        false => include,
+    };
+
+    include
+        .into_iter()
+        .filter(|country| !exclude.iter().any(|excluded| excluded.alpha2() == country.alpha2()))
+        .collect()
+}
+
+/// Merge the ranges where they overlap or touch, then split them into aligned blocks.
+fn aggregate(mut ranges: Vec<RangeInclusive<u128>>, bits: u32) -> Vec<IpCidr> {
+    ranges.sort_unstable_by_key(|range| *range.start());
+
+    let mut merged: Vec<RangeInclusive<u128>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if *range.start() <= last.end().saturating_add(1) => {
+                if range.end() > last.end() {
+                    *last = *last.start()..=*range.end();
+                }
+            }
+            _ => merged.push(range),
+        }
+    }
+
+    merged.into_iter().flat_map(|range| blocks(range, bits)).collect()
+}
+
+/// The fewest CIDRs covering exactly `range`, each as large as its start address is aligned for.
+fn blocks(range: RangeInclusive<u128>, bits: u32) -> Vec<IpCidr> {
+    let (mut start, end) = range.into_inner();
+    let mut blocks = Vec::new();
+
+    loop {
+        let aligned = match start {
+            0 => bits,
+            start => start.trailing_zeros().min(bits),
+        };
+        let fits = match (end - start).checked_add(1) {
+            Some(size) => 127 - size.leading_zeros(),
+            None => 128,
+        };
+        let size = aligned.min(fits);
+
+        let address = match bits {
+            32 => IpAddr::V4(Ipv4Addr::from(start as u32)),
+            _ => IpAddr::V6(Ipv6Addr::from(start)),
+        };
+        blocks.push(IpCidr::new(address, (bits - size) as u8).expect("Block is aligned to its length"));
+
+        match 1u128.checked_shl(size).and_then(|size| start.checked_add(size)) {
+            Some(next) if next <= end => start = next,
+            _ => break,
+        }
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use keshvar::Alpha2;
+    use std::str::FromStr;
+
+    fn record(start: &str, end: &str) -> Record {
+        Record::DBRecord {
+            alpha: Alpha2::AU,
+            start: IpAddr::from_str(start).unwrap(),
+            end: IpAddr::from_str(end).unwrap(),
+        }
+    }
+
+    fn strings(cidrs: &[IpCidr]) -> Vec<String> {
+        cidrs.iter().map(|cidr| format!("{cidr:#}")).collect()
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let records = [
+            record("1.0.0.0", "1.0.0.255"),
+            record("1.0.1.0", "1.0.1.255"),
+            record("1.0.1.128", "1.0.1.200"),
+            record("10.0.0.1", "10.0.0.6"),
+            record("2001:200::", "2001:200:ffff:ffff:ffff:ffff:ffff:ffff"),
+            record("2001:201::", "2001:201:ffff:ffff:ffff:ffff:ffff:ffff"),
+        ];
+
+        let cidrs = Cidrs::from_records(&records, true, true);
+        assert_eq!(
+            strings(&cidrs.v4),
+            vec!["1.0.0.0/23", "10.0.0.1/32", "10.0.0.2/31", "10.0.0.4/31", "10.0.0.6/32"]
+        );
+        assert_eq!(strings(&cidrs.v6), vec!["2001:200::/31"]);
+
+        let cidrs = Cidrs::from_records(&records, false, true);
+        assert!(cidrs.v4.is_empty());
+        assert_eq!(cidrs.len(), 1);
+    }
+
+    #[test]
+    fn test_whole_space() {
+        let cidrs = Cidrs::from_records(&[record("0.0.0.0", "255.255.255.255"), record("::", "::1")], true, true);
+
+        assert_eq!(strings(&cidrs.v4), vec!["0.0.0.0/0"]);
+        assert_eq!(strings(&cidrs.v6), vec!["::/127"]);
+    }
+
+    #[test]
+    fn test_write() -> IoResult<()> {
+        let cidrs = Cidrs::from_records(
+            &[record("1.0.0.0", "1.0.1.255"), record("2001:200::", "2001:200::1")],
+            true,
+            true,
+        );
+        let write = |format| -> IoResult<String> {
+            let mut out = Vec::new();
+            cidrs.write(format, "au", "DROP", &mut out)?;
+            Ok(String::from_utf8(out).unwrap())
+        };
+
+        assert_eq!(write(Format::Plain)?, "1.0.0.0/23\n2001:200::/127\n");
+        assert_eq!(
+            write(Format::Nftables)?,
+            "define au_ipv4 = {\n    1.0.0.0/23,\n}\ndefine au_ipv6 = {\n    2001:200::/127,\n}\n"
+        );
+        assert_eq!(
+            write(Format::Ipset)?,
+            "create au-ipv4 hash:net family inet maxelem 65536 -exist\nadd au-ipv4 1.0.0.0/23 -exist\n\
+             create au-ipv6 hash:net family inet6 maxelem 65536 -exist\nadd au-ipv6 2001:200::/127 -exist\n"
+        );
+        assert_eq!(
+            write(Format::Iptables)?,
+            "iptables -A au -s 1.0.0.0/23 -j DROP\nip6tables -A au -s 2001:200::/127 -j DROP\n"
+        );
+
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_write_mmdb() -> anyhow::Result<()> {
+        use crate::RecordDB;
+
+        let mut records = vec![record("1.0.0.0", "1.0.1.255"), record("2001:200::", "2001:200::1")];
+        records.push(Record::DBRecord {
+            alpha: Alpha2::NZ,
+            start: IpAddr::from_str("1.0.2.0").unwrap(),
+            end: IpAddr::from_str("1.0.2.255").unwrap(),
+        });
+
+        let mut out = Vec::new();
+        assert_eq!(write(&records, Format::Mmdb, "au", "DROP", true, false, &mut out)?, 2);
+
+        let db = mmdb::Mmdb::parse(out)?;
+        assert_eq!(db.lookup(&IpAddr::from_str("1.0.1.1")?).await, Some(Alpha2::AU));
+        assert_eq!(db.lookup(&IpAddr::from_str("1.0.2.1")?).await, Some(Alpha2::NZ));
+        assert_eq!(db.lookup(&IpAddr::from_str("2001:200::1")?).await, None);
+
+        Ok(())
+    }
+}
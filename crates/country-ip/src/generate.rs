@@ -0,0 +1,392 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Generating many addresses for a country at once.
+//!
+//! Addresses are drawn uniformly from every address the country's ranges cover,
+//! so a `/8` is picked far more often than a `/24` instead of each range being as likely as any other.
+//! Given a seed the same addresses are generated on every run.
+
+use crate::record::{Record, Status};
+use rand::prelude::{Rng, SeedableRng, StdRng};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use thiserror::Error;
+use tracing::instrument;
+
+#[derive(Debug, Error)]
+pub enum GenerateError {
+    #[error("There are no ranges to generate addresses from")]
+    NoRanges,
+
+    #[error("Only {available} unique addresses can be generated, but {requested} were requested")]
+    NotEnough { requested: usize, available: u128 },
+}
+
+/// Special purpose, documentation, private, multicast and reserved IPv4 space.
+const BOGONS_V4: [(Ipv4Addr, u8); 14] = [
+    (Ipv4Addr::new(0, 0, 0, 0), 8),
+    (Ipv4Addr::new(10, 0, 0, 0), 8),
+    (Ipv4Addr::new(100, 64, 0, 0), 10),
+    (Ipv4Addr::new(127, 0, 0, 0), 8),
+    (Ipv4Addr::new(169, 254, 0, 0), 16),
+    (Ipv4Addr::new(172, 16, 0, 0), 12),
+    (Ipv4Addr::new(192, 0, 0, 0), 24),
+    (Ipv4Addr::new(192, 0, 2, 0), 24),
+    (Ipv4Addr::new(192, 168, 0, 0), 16),
+    (Ipv4Addr::new(198, 18, 0, 0), 15),
+    (Ipv4Addr::new(198, 51, 100, 0), 24),
+    (Ipv4Addr::new(203, 0, 113, 0), 24),
+    (Ipv4Addr::new(224, 0, 0, 0), 4),
+    (Ipv4Addr::new(240, 0, 0, 0), 4),
+];
+
+/// Everything outside of global unicast, which includes multicast and link-local,
+/// and the special purpose and documentation space inside of it.
+const BOGONS_V6: [(Ipv6Addr, u8); 8] = [
+    (Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 3),
+    (Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), 32),
+    (Ipv6Addr::new(0x2002, 0, 0, 0, 0, 0, 0, 0), 16),
+    (Ipv6Addr::new(0x3ffe, 0, 0, 0, 0, 0, 0, 0), 16),
+    (Ipv6Addr::new(0x3fff, 0, 0, 0, 0, 0, 0, 0), 20),
+    (Ipv6Addr::new(0x4000, 0, 0, 0, 0, 0, 0, 0), 2),
+    (Ipv6Addr::new(0x8000, 0, 0, 0, 0, 0, 0, 0), 1),
+];
+
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// How many addresses to generate.
+    pub count: usize,
+
+    /// Never generate the same address twice.
+    pub unique: bool,
+
+    pub ipv6: bool,
+
+    /// Leave out reserved, multicast and other bogon space.
+    pub exclude_bogons: bool,
+
+    /// Only use ranges with one of these statuses,
+    /// records without a status such as DB-IP's are always used.
+    pub statuses: Vec<Status>,
+}
+
+impl Options {
+    /// Whether the record is one addresses should be generated from.
+    pub fn accepts(&self, record: &Record) -> bool {
+        if record.start().is_ipv6() != self.ipv6 {
+            return false;
+        }
+
+        match record {
+            Record::RegistryRecord { status, .. } => self.statuses.is_empty() || self.statuses.contains(status),
+            Record::DBRecord { .. } => true,
+        }
+    }
+}
+
+/// The generator to use, seeded when reproducible output is wanted.
+pub fn rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Every address of a single family that can be generated, as sorted and disjoint inclusive spans.
+#[derive(Debug, Clone)]
+pub struct Pool {
+    ipv6: bool,
+    spans: Vec<(u128, u128)>,
+
+    /// The number of addresses in each span and every span before it.
+    totals: Vec<u128>,
+}
+
+impl Pool {
+    #[instrument(level = "TRACE", skip(records))]
+    pub fn new<'a>(records: impl IntoIterator<Item = &'a Record>, ipv6: bool, exclude_bogons: bool) -> Self {
+        let mut spans = records
+            .into_iter()
+            .filter(|record| record.start().is_ipv6() == ipv6)
+            .map(|record| record.u128_range().into_inner())
+            .collect::<Vec<_>>();
+        spans.sort_unstable();
+
+        // Countries can hold overlapping records, which would otherwise be counted twice.
+        let mut merged: Vec<(u128, u128)> = Vec::with_capacity(spans.len());
+        for (start, end) in spans {
+            match merged.last_mut() {
+                Some((_, last)) if start <= last.saturating_add(1) => *last = (*last).max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        if exclude_bogons {
+            merged = subtract(merged, &bogons(ipv6));
+        }
+
+        let mut total = 0u128;
+        let totals = merged
+            .iter()
+            .map(|(start, end)| {
+                total = total.saturating_add(end - start + 1);
+                total
+            })
+            .collect();
+
+        Self {
+            ipv6,
+            spans: merged,
+            totals,
+        }
+    }
+
+    /// How many addresses are in the pool.
+    pub fn len(&self) -> u128 {
+        self.totals.last().copied().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Picks an address with each address in the pool as likely as any other.
+    ///
+    /// # Panics
+    ///
+    /// If the pool is empty.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> IpAddr {
+        let offset = rng.gen_range(0..self.len());
+        let index = self.totals.partition_point(|total| *total <= offset);
+        let before = match index {
+            0 => 0,
+            _ => self.totals[index - 1],
+        };
+
+        let raw = self.spans[index].0 + (offset - before);
+        match self.ipv6 {
+            true => IpAddr::V6(Ipv6Addr::from(raw)),
+            false => IpAddr::V4(Ipv4Addr::from(raw as u32)),
+        }
+    }
+
+    /// Picks `count` addresses, without repeating any if `unique` is set.
+    pub fn generate<R: Rng + ?Sized>(
+        &self,
+        count: usize,
+        unique: bool,
+        rng: &mut R,
+    ) -> Result<Vec<IpAddr>, GenerateError> {
+        if self.is_empty() {
+            return Err(GenerateError::NoRanges);
+        }
+
+        if !unique {
+            return Ok((0..count).map(|_| self.sample(rng)).collect());
+        }
+
+        if count as u128 > self.len() {
+            return Err(GenerateError::NotEnough {
+                requested: count,
+                available: self.len(),
+            });
+        }
+
+        let mut seen = HashSet::with_capacity(count);
+        let mut addresses = Vec::with_capacity(count);
+        while addresses.len() < count {
+            let addr = self.sample(rng);
+            if seen.insert(addr) {
+                addresses.push(addr);
+            }
+        }
+
+        Ok(addresses)
+    }
+}
+
+/// Generates addresses from the records the options accept.
+#[instrument(level = "TRACE", skip(records, rng), err)]
+pub fn generate<'a, R: Rng + ?Sized>(
+    records: impl IntoIterator<Item = &'a Record>,
+    options: &Options,
+    rng: &mut R,
+) -> Result<Vec<IpAddr>, GenerateError> {
+    let records = records.into_iter().filter(|record| options.accepts(record));
+    Pool::new(records, options.ipv6, options.exclude_bogons).generate(options.count, options.unique, rng)
+}
+
+fn bogons(ipv6: bool) -> Vec<(u128, u128)> {
+    let mut bogons = match ipv6 {
+        true => BOGONS_V6
+            .iter()
+            .map(|(addr, prefix)| span(addr.to_bits(), *prefix, 128))
+            .collect::<Vec<_>>(),
+        false => BOGONS_V4
+            .iter()
+            .map(|(addr, prefix)| span(addr.to_bits() as u128, *prefix, 32))
+            .collect(),
+    };
+
+    bogons.sort_unstable();
+    bogons
+}
+
+/// The inclusive span of a network with `bits` sized addresses.
+fn span(start: u128, prefix: u8, bits: u32) -> (u128, u128) {
+    let host = u128::MAX.checked_shr(128 - bits + prefix as u32).unwrap_or(0);
+    (start, start | host)
+}
+
+/// Removes the holes from the spans, both of which have to be sorted.
+fn subtract(spans: Vec<(u128, u128)>, holes: &[(u128, u128)]) -> Vec<(u128, u128)> {
+    let mut remaining = Vec::with_capacity(spans.len());
+    for (mut start, end) in spans {
+        let mut emptied = false;
+        for (hole_start, hole_end) in holes {
+            if *hole_start > end || *hole_end < start {
+                continue;
+            }
+
+            if *hole_start > start {
+                remaining.push((start, hole_start - 1));
+            }
+
+            match hole_end.checked_add(1) {
+                Some(next) if next <= end => start = next,
+                _ => {
+                    emptied = true;
+                    break;
+                }
+            }
+        }
+
+        if !emptied {
+            remaining.push((start, end));
+        }
+    }
+
+    remaining
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::Registry;
+    use keshvar::Alpha2::AU;
+
+    fn db(start: &str, end: &str) -> Record {
+        Record::DBRecord {
+            alpha: AU,
+            start: start.parse().unwrap(),
+            end: end.parse().unwrap(),
+        }
+    }
+
+    fn registry(start: &str, size: u32, status: Status) -> Record {
+        Record::RegistryRecord {
+            registry: Registry::Apnic,
+            alpha: AU,
+            value: start.parse().unwrap(),
+            range: size,
+            date: "20240101".into(),
+            status,
+        }
+    }
+
+    #[test]
+    fn test_seeded() {
+        let records = [db("1.0.0.0", "1.0.255.255"), db("2001:200::", "2001:200::ffff")];
+        let options = Options {
+            count: 20,
+            ..Default::default()
+        };
+
+        let first = generate(&records, &options, &mut rng(Some(7))).unwrap();
+        let second = generate(&records, &options, &mut rng(Some(7))).unwrap();
+        assert_eq!(first, second);
+        assert!(first.iter().all(|addr| records[0].contains(addr)));
+
+        let other = generate(&records, &options, &mut rng(Some(8))).unwrap();
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_weighted() {
+        // The /16 holds 256 times as many addresses as the /24.
+        let records = [db("1.0.0.0", "1.0.0.255"), db("2.0.0.0", "2.0.255.255")];
+        let options = Options {
+            count: 10_000,
+            ..Default::default()
+        };
+
+        let addresses = generate(&records, &options, &mut rng(Some(1))).unwrap();
+        let small = addresses.iter().filter(|addr| records[0].contains(addr)).count();
+        assert!(small < 200, "{small} of 10000 addresses came from the /24");
+    }
+
+    #[test]
+    fn test_unique() {
+        let records = [db("1.0.0.0", "1.0.0.15")];
+        let options = Options {
+            count: 16,
+            unique: true,
+            ..Default::default()
+        };
+
+        let mut addresses = generate(&records, &options, &mut rng(Some(3))).unwrap();
+        addresses.sort();
+        addresses.dedup();
+        assert_eq!(addresses.len(), 16);
+
+        let options = Options { count: 17, ..options };
+        assert!(matches!(
+            generate(&records, &options, &mut rng(Some(3))),
+            Err(GenerateError::NotEnough { available: 16, .. })
+        ));
+    }
+
+    #[test]
+    fn test_bogons() {
+        let pool = Pool::new(&[db("9.255.255.0", "11.0.0.255")], false, true);
+        assert_eq!(pool.spans, vec![(0x09ffff00, 0x09ffffff), (0x0b000000, 0x0b0000ff)]);
+
+        let pool = Pool::new(&[db("224.0.0.0", "255.255.255.255")], false, true);
+        assert!(pool.is_empty());
+
+        let pool = Pool::new(&[db("::", "ffff::")], true, true);
+        assert!(pool.spans.iter().all(|(start, _)| *start >> 125 == 1));
+        assert!(!pool.is_empty());
+    }
+
+    #[test]
+    fn test_statuses() {
+        let records = [
+            registry("1.0.0.0", 256, Status::Allocated),
+            registry("2.0.0.0", 256, Status::Assigned),
+        ];
+        let options = Options {
+            count: 50,
+            statuses: vec![Status::Assigned],
+            ..Default::default()
+        };
+
+        let addresses = generate(&records, &options, &mut rng(None)).unwrap();
+        assert!(addresses.iter().all(|addr| records[1].contains(addr)));
+    }
+}
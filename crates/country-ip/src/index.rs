@@ -0,0 +1,224 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::record::Record;
+use keshvar::Alpha2;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use tracing::instrument;
+
+/// Records compiled into sorted, non-overlapping ranges,
+/// so that an address can be resolved with a binary search instead of a scan.
+#[derive(Debug, Default)]
+pub struct RecordIndex {
+    records: Vec<Record>,
+    v4: Vec<Span<u32>>,
+    v6: Vec<Span<u128>>,
+    countries: HashMap<Alpha2, Vec<usize>>,
+}
+
+/// An inclusive range of addresses and the position of the record it came from.
+#[derive(Debug, Clone, Copy)]
+struct Span<T> {
+    start: T,
+    end: T,
+    record: usize,
+}
+
+impl RecordIndex {
+    /// Compile the records into an index.
+    ///
+    /// Where records overlap the one starting first wins,
+    /// and a wider record wins over a narrower one starting at the same address.
+    #[instrument(level = "TRACE", skip(records), fields(records = records.len()))]
+    pub fn new(records: Vec<Record>) -> Self {
+        let mut v4 = Vec::new();
+        let mut v6 = Vec::new();
+        for (position, record) in records.iter().enumerate() {
+            let range = record.u128_range();
+            let span = Span {
+                start: *range.start(),
+                end: *range.end(),
+                record: position,
+            };
+
+            match record.start() {
+                IpAddr::V4(_) => v4.push(span),
+                IpAddr::V6(_) => v6.push(span),
+            }
+        }
+
+        let v4 = compile(v4)
+            .into_iter()
+            .map(|span| Span {
+                start: span.start as u32,
+                end: span.end as u32,
+                record: span.record,
+            })
+            .collect();
+        let v6 = compile(v6);
+
+        let mut countries = HashMap::<Alpha2, Vec<usize>>::new();
+        for (position, record) in records.iter().enumerate() {
+            countries.entry(*record.alpha()).or_default().push(position);
+        }
+        for positions in countries.values_mut() {
+            positions.sort_unstable_by_key(|position| records[*position].start());
+        }
+
+        Self {
+            records,
+            v4,
+            v6,
+            countries,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Every record in the order they were given to [`RecordIndex::new`].
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Find the record containing the address.
+    pub fn lookup(&self, ip: &IpAddr) -> Option<&Record> {
+        let position = match ip {
+            IpAddr::V4(addr) => find(&self.v4, addr.to_bits()),
+            IpAddr::V6(addr) => find(&self.v6, addr.to_bits()),
+        }?;
+
+        Some(&self.records[position])
+    }
+
+    /// The records for a country, ordered by their first address.
+    pub fn country(&self, alpha: &Alpha2) -> impl Iterator<Item = &Record> {
+        self.countries
+            .get(alpha)
+            .into_iter()
+            .flatten()
+            .map(|position| &self.records[*position])
+    }
+
+    /// The countries which have at least one record.
+    pub fn countries(&self) -> impl Iterator<Item = &Alpha2> {
+        self.countries.keys()
+    }
+}
+
+/// Sort the spans and trim them so none overlap.
+fn compile(mut spans: Vec<Span<u128>>) -> Vec<Span<u128>> {
+    spans.sort_unstable_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+
+    let mut compiled: Vec<Span<u128>> = Vec::with_capacity(spans.len());
+    for mut span in spans {
+        if let Some(last) = compiled.last() {
+            if span.end <= last.end {
+                continue;
+            }
+
+            if span.start <= last.end {
+                span.start = last.end + 1;
+            }
+        }
+
+        compiled.push(span);
+    }
+
+    compiled.shrink_to_fit();
+    compiled
+}
+
+fn find<T: Copy + Ord>(spans: &[Span<T>], key: T) -> Option<usize> {
+    let after = spans.partition_point(|span| span.start <= key);
+    let span = spans.get(after.checked_sub(1)?)?;
+
+    (key <= span.end).then_some(span.record)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::record::Record::DBRecord;
+    use keshvar::Alpha2::{AU, JP, NZ};
+    use std::str::FromStr;
+
+    fn record(alpha: Alpha2, start: &str, end: &str) -> Record {
+        DBRecord {
+            alpha,
+            start: IpAddr::from_str(start).unwrap(),
+            end: IpAddr::from_str(end).unwrap(),
+        }
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        IpAddr::from_str(addr).unwrap()
+    }
+
+    #[test]
+    fn test_lookup() {
+        let index = RecordIndex::new(vec![
+            record(JP, "133.0.0.0", "133.255.255.255"),
+            record(AU, "1.0.0.0", "1.0.0.255"),
+            record(NZ, "2001:200::", "2001:200:ffff:ffff:ffff:ffff:ffff:ffff"),
+            record(AU, "1.0.4.0", "1.0.7.255"),
+        ]);
+
+        assert_eq!(index.lookup(&ip("1.0.0.0")).map(Record::alpha), Some(&AU));
+        assert_eq!(index.lookup(&ip("1.0.0.255")).map(Record::alpha), Some(&AU));
+        assert_eq!(index.lookup(&ip("1.0.1.0")).map(Record::alpha), None);
+        assert_eq!(index.lookup(&ip("1.0.5.5")).map(Record::alpha), Some(&AU));
+        assert_eq!(index.lookup(&ip("133.1.2.3")).map(Record::alpha), Some(&JP));
+        assert_eq!(index.lookup(&ip("0.0.0.1")).map(Record::alpha), None);
+        assert_eq!(index.lookup(&ip("255.255.255.255")).map(Record::alpha), None);
+        assert_eq!(index.lookup(&ip("2001:200::1")).map(Record::alpha), Some(&NZ));
+        assert_eq!(index.lookup(&ip("::1")).map(Record::alpha), None);
+    }
+
+    #[test]
+    fn test_overlapping() {
+        let index = RecordIndex::new(vec![
+            record(JP, "10.0.0.128", "10.0.1.255"),
+            record(AU, "10.0.0.0", "10.0.0.255"),
+            record(NZ, "10.0.0.0", "10.0.0.15"),
+        ]);
+
+        assert_eq!(index.lookup(&ip("10.0.0.1")).map(Record::alpha), Some(&AU));
+        assert_eq!(index.lookup(&ip("10.0.0.200")).map(Record::alpha), Some(&AU));
+        assert_eq!(index.lookup(&ip("10.0.1.0")).map(Record::alpha), Some(&JP));
+        assert_eq!(index.lookup(&ip("10.0.2.0")).map(Record::alpha), None);
+    }
+
+    #[test]
+    fn test_country() {
+        let index = RecordIndex::new(vec![
+            record(AU, "101.160.0.0", "101.191.255.255"),
+            record(JP, "133.0.0.0", "133.255.255.255"),
+            record(AU, "1.0.0.0", "1.0.0.255"),
+        ]);
+
+        let starts = index.country(&AU).map(Record::start).collect::<Vec<_>>();
+        assert_eq!(starts, vec![ip("1.0.0.0"), ip("101.160.0.0")]);
+        assert_eq!(index.country(&NZ).count(), 0);
+        assert_eq!(index.countries().count(), 2);
+    }
+}
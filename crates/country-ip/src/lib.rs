@@ -19,20 +19,29 @@
 
 use crate::record::Record;
 use crate::registry::Registry;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use futures::StreamExt;
 use keshvar::{Alpha2, Alpha3, Country, CountryIterator};
 use rand::prelude::IteratorRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
 use std::fmt::Debug;
 use std::net::IpAddr;
 use thiserror::Error;
 use tracing::{debug, error, instrument, warn};
 
+pub mod asn;
+pub mod bulk;
+pub mod cache;
 pub mod db_ip;
+pub mod export;
+pub mod generate;
+pub mod index;
+pub mod mmdb;
 pub mod record;
 pub mod registry;
+pub mod serve;
+pub mod stats;
 pub mod ui;
 
 #[derive(Debug, Error)]
@@ -42,26 +51,41 @@ pub enum Error {
 }
 
 #[instrument(level = "TRACE", ret, err, fields(country = %country.iso_short_name()))]
-pub async fn get_record_db(country: &Country) -> Result<Box<dyn RecordDB>> {
+pub async fn get_record_db(country: &Country, offline: bool) -> Result<Box<dyn RecordDB>> {
+    if let Some(db) = mmdb::instance().await? {
+        return Ok(db);
+    }
+
     match Registry::get_for(country) {
         Ok(registry) => {
             debug!("Using registry {} for {}", registry.name(), country.iso_short_name());
-            registry.get().await
+            registry.get(offline).await
         }
         Err(err) => {
             error!("{err}");
             debug!("Falling back to DB-IP for {}", country.iso_short_name());
-            Ok(db_ip::DB::instance())
+            db_ip::DB::instance(offline).await
         }
     }
 }
 
 #[async_trait]
 pub trait RecordDB: Send + Sync + Debug + Unpin {
-    async fn lookup(&self, ip: &IpAddr) -> Option<Alpha2>;
+    /// The record containing the address.
+    async fn record(&self, ip: &IpAddr) -> Option<&Record>;
+
+    async fn lookup(&self, ip: &IpAddr) -> Option<Alpha2> {
+        self.record(ip).await.map(|record| *record.alpha())
+    }
 
     async fn filtered(&self, alpha: &Alpha2) -> Vec<&Record>;
 
+    /// Every record in use by the country,
+    /// including those [`RecordDB::filtered`] leaves out as addresses shouldn't be generated from them.
+    async fn ranges(&self, alpha: &Alpha2) -> Vec<&Record> {
+        self.filtered(alpha).await
+    }
+
     async fn random_ipv4(&self, alpha: &Alpha2) -> Option<IpAddr> {
         self.filtered(alpha)
             .await
@@ -81,51 +105,88 @@ pub trait RecordDB: Send + Sync + Debug + Unpin {
     }
 }
 
+#[instrument(level = "TRACE", ret, err, skip(rng))]
+fn get_country<R: Rng + ?Sized>(alpha: &Option<String>, rng: &mut R) -> std::result::Result<Country, Error> {
+    alpha.as_deref().map(parse_country).unwrap_or_else(|| {
+        warn!("No country specified, generating random country");
+        CountryIterator::new().choose(rng).ok_or_else(|| unreachable!())
+    })
+}
+
+/// Parse an ISO 3166-1 alpha-2 or alpha-3 code, ignoring case.
 #[instrument(level = "TRACE", ret, err)]
-fn get_country(alpha: &Option<String>) -> std::result::Result<Country, Error> {
-    alpha
-        .as_ref()
-        .filter(|alpha| (2..=3).contains(&alpha.len()))
-        .map(|alpha| alpha.to_uppercase())
-        .map(|alpha| match alpha.len() {
-            2 => Alpha2::try_from(&*alpha)
-                .map(Country::from)
-                .map_err(|_| Error::InvalidCountryCode(alpha)),
-            3 => Alpha3::try_from(&*alpha)
-                .map(Country::from)
-                .map_err(|_| Error::InvalidCountryCode(alpha)),
-            _ => unreachable!(),
-        })
-        .unwrap_or_else(|| {
-            warn!("No country specified, generating random country");
-            CountryIterator::new().choose(&mut thread_rng()).ok_or_else(|| unreachable!())
-        })
+pub fn parse_country(alpha: &str) -> std::result::Result<Country, Error> {
+    let alpha = alpha.to_uppercase();
+    match alpha.len() {
+        2 => Alpha2::try_from(&*alpha)
+            .map(Country::from)
+            .map_err(|_| Error::InvalidCountryCode(alpha)),
+        3 => Alpha3::try_from(&*alpha)
+            .map(Country::from)
+            .map_err(|_| Error::InvalidCountryCode(alpha)),
+        _ => Err(Error::InvalidCountryCode(alpha)),
+    }
 }
 
-#[instrument(level = "TRACE", ret, err, fields(country = %country.iso_short_name()))]
-async fn get(country: &Country, use_ipv6: &bool) -> Result<IpAddr> {
+/// Generates many addresses for the country, weighted by the size of its ranges.
+///
+/// Without any statuses only the ranges [`RecordDB::filtered`] leaves in are used.
+#[instrument(level = "TRACE", ret, err, skip(rng), fields(country = %country.iso_short_name()))]
+async fn generate<R: Rng + Send + ?Sized>(
+    country: &Country,
+    options: &generate::Options,
+    rng: &mut R,
+    offline: bool,
+) -> Result<Vec<IpAddr>> {
     let alpha = country.alpha2();
-    let record_db = get_record_db(country).await?;
-    match use_ipv6 {
-        true => record_db.random_ipv6(&alpha),
-        false => record_db.random_ipv4(&alpha),
-    }
-    .await
-    .ok_or_else(|| anyhow::anyhow!("No IP addresses found for {}", country.iso_short_name()))
+    let record_db = get_record_db(country, offline).await?;
+    let records = match options.statuses.is_empty() {
+        true => record_db.filtered(&alpha).await,
+        false => record_db.ranges(&alpha).await,
+    };
+
+    generate::generate(records, options, rng)
+        .with_context(|| format!("Unable to generate addresses for {}", country.iso_short_name()))
 }
 
 #[instrument(level = "TRACE", ret, err)]
-async fn lookup(addr: &IpAddr) -> Result<Country> {
+async fn lookup(addr: &IpAddr, offline: bool) -> Result<Country> {
+    if let Some(db) = mmdb::instance().await? {
+        return db
+            .lookup(addr)
+            .await
+            .map(|alpha| alpha.to_country())
+            .ok_or_else(|| anyhow!("No country found for IP address"));
+    }
+
     let variants: Vec<Registry> = Registry::get_variants();
-    let mut stream = futures::stream::iter(variants.iter())
-        .map(|reg| reg.get())
-        .buffer_unordered(variants.len());
+    let concurrency = variants.len();
+    // Owning each registry keeps the stream Send for callers that spawn the lookup.
+    let mut stream = futures::stream::iter(variants)
+        .map(|reg| async move { (reg, reg.get(offline).await) })
+        .buffer_unordered(concurrency);
 
-    while let Some(Ok(db)) = stream.next().await {
+    // A registry which can't be loaded shouldn't stop the others from being searched.
+    let mut failure = None;
+    let mut loaded = false;
+    while let Some((reg, db)) = stream.next().await {
+        let db = match db {
+            Ok(db) => db,
+            Err(err) => {
+                warn!("Unable to load the records for {reg:?}: {err:#}");
+                failure = Some(err);
+                continue;
+            }
+        };
+
+        loaded = true;
         if let Some(alpha) = db.lookup(addr).await {
             return Ok(alpha.to_country());
         }
     }
 
-    Err(anyhow!("No country found for IP address"))
+    match failure {
+        Some(err) if !loaded => Err(err.context("Unable to load the records of any registry")),
+        _ => Err(anyhow!("No country found for IP address")),
+    }
 }
@@ -14,22 +14,29 @@
  * along with this program. If not, see https://www.gnu.org/licenses/.
  */
 
+use amt_lib::ui::cli::error::{finish, ExitCode};
+
+fn main() -> ExitCode {
+    // iced starts its own runtime, so the window has to be opened outside of the cli's.
+    #[cfg(feature = "ui-gui")]
+    if std::env::args_os().len() <= 1 {
+        let _guard = amt_lib::log::init(env!("CARGO_PKG_NAME"), &Default::default());
+        return finish(country_ip::ui::gui::run());
+    }
+
+    cli()
+}
+
 #[tokio::main]
-#[cfg(feature = "ui-cli")]
-async fn main() -> amt_lib::ui::cli::CliResult<()> {
+async fn cli() -> ExitCode {
     use amt_lib::ui::cli::CliUi;
     use amt_lib::ui::Ui;
     use country_ip::ui::cli::ui::CountryIPCli;
 
-    let mut ui = CountryIPCli::new(())?;
-    ui.run().await
-}
+    let mut ui = match CountryIPCli::new(()) {
+        Ok(ui) => ui,
+        Err(err) => return finish(Err(err)),
+    };
 
-// #[tokio::main]
-// #[cfg(feature = "ui-gui")]
-// async fn main() {
-//     use country_ip::ui::gui::application::CountryIPApp;
-//     use iced::{Application, Sandbox, Settings};
-//
-//     <CountryIPApp as Application>::run(Settings::default())
-// }
+    finish(ui.run().await)
+}
@@ -0,0 +1,434 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Reading and writing MaxMind DB files in the GeoLite2-Country layout,
+//! so standard GeoIP databases can be used in place of the registry data and ours can be used by other software.
+
+use crate::index::RecordIndex;
+use crate::record::Record;
+use crate::RecordDB;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use cidr::IpCidr;
+use ipnetwork::{IpNetwork, Ipv6Network};
+use keshvar::{Alpha2, Country};
+use maxminddb::{geoip2, Reader};
+use std::collections::HashMap;
+use std::io::{Result as IoResult, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use thiserror::Error;
+use tokio::sync::OnceCell;
+use tracing::{debug, instrument};
+
+/// Separates the search tree from the data section.
+const DATA_SEPARATOR: [u8; 16] = [0; 16];
+/// Marks the start of the metadata at the end of the file.
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+/// Readers such as libmaxminddb choose how to decode records from the type,
+/// so we claim to be GeoLite2-Country which has the same layout as what we write.
+const DATABASE_TYPE: &str = "GeoLite2-Country";
+/// IPv4-mapped, 6to4 and Teredo, which MaxMind databases point at the IPv4 networks under ::/96.
+const IPV4_ALIASES: [(u128, u8); 3] = [(0xffff << 32, 96), (0x2002 << 112, 16), (0x2001 << 112, 32)];
+
+static PATH: OnceLock<PathBuf> = OnceLock::new();
+static INSTANCE: OnceCell<Mmdb> = OnceCell::const_new();
+
+/// Use the database at `path` instead of the registry and DB-IP data.
+pub fn use_database(path: PathBuf) {
+    let _ = PATH.set(path);
+}
+
+/// The database chosen with [`use_database`], if there is one.
+pub async fn instance() -> Result<Option<Box<dyn RecordDB>>> {
+    let Some(path) = PATH.get() else {
+        return Ok(None);
+    };
+
+    let db = INSTANCE.get_or_try_init(|| async { Mmdb::open(path) }).await?;
+    Ok(Some(Box::new(db.clone())))
+}
+
+/// The networks of a MaxMind DB file with a country.
+#[derive(Debug, Clone)]
+pub struct Mmdb(Arc<RecordIndex>);
+
+impl Mmdb {
+    #[instrument(level = "TRACE", err)]
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(data).with_context(|| format!("Failed to read a MaxMind database from {}", path.display()))
+    }
+
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
+        let reader = Reader::from_source(data)?;
+        // Networks under ::/96 in an IPv6 tree are read back as IPv4, so the root covers both,
+        // but the aliases of the IPv4 networks elsewhere in the tree have to be skipped.
+        let root = match reader.metadata.ip_version {
+            6 => IpNetwork::V6("::/0".parse()?),
+            _ => IpNetwork::V4("0.0.0.0/0".parse()?),
+        };
+
+        let mut records = Vec::new();
+        for item in reader.within::<geoip2::Country>(root)? {
+            let item = item?;
+            if matches!(item.ip_net, IpNetwork::V6(network) if is_alias(&network)) {
+                continue;
+            }
+
+            let Some(alpha) = item
+                .info
+                .country
+                .or(item.info.registered_country)
+                .and_then(|country| country.iso_code)
+                .and_then(|code| Alpha2::try_from(code).ok())
+            else {
+                continue;
+            };
+
+            let (start, end) = bounds(item.ip_net);
+            records.push(Record::DBRecord { alpha, start, end });
+        }
+
+        debug!("Read {} networks", records.len());
+        Ok(Self(Arc::new(RecordIndex::new(records))))
+    }
+}
+
+#[async_trait]
+impl RecordDB for Mmdb {
+    #[instrument(level = "TRACE", ret)]
+    async fn record(&self, ip: &IpAddr) -> Option<&Record> {
+        self.0.lookup(ip)
+    }
+
+    #[instrument(level = "TRACE", ret)]
+    async fn filtered(&self, alpha: &Alpha2) -> Vec<&Record> {
+        self.0.country(alpha).collect()
+    }
+}
+
+fn is_alias(network: &Ipv6Network) -> bool {
+    let bits = network.network().to_bits();
+    IPV4_ALIASES
+        .iter()
+        .any(|(alias, length)| network.prefix() >= *length && bits >> (128 - length) == alias >> (128 - length))
+}
+
+fn bounds(network: IpNetwork) -> (IpAddr, IpAddr) {
+    match network {
+        IpNetwork::V4(network) => {
+            let host = u32::MAX.checked_shr(network.prefix().into()).unwrap_or(0);
+            let start = network.network().to_bits();
+            (
+                IpAddr::V4(Ipv4Addr::from(start)),
+                IpAddr::V4(Ipv4Addr::from(start | host)),
+            )
+        }
+        IpNetwork::V6(network) => {
+            let host = u128::MAX.checked_shr(network.prefix().into()).unwrap_or(0);
+            let start = network.network().to_bits();
+            (
+                IpAddr::V6(Ipv6Addr::from(start)),
+                IpAddr::V6(Ipv6Addr::from(start | host)),
+            )
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum InsertError {
+    #[error("{0} is within ::/96, which holds the IPv4 networks")]
+    Ipv4Space(IpCidr),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Child {
+    Empty,
+    Node(usize),
+    Data(usize),
+}
+
+/// Builds an IPv6 database, with IPv4 networks under ::/96 where readers expect them.
+#[derive(Debug)]
+pub struct Writer {
+    nodes: Vec<[Child; 2]>,
+    data: Vec<u8>,
+    countries: HashMap<Alpha2, usize>,
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        Self {
+            nodes: vec![[Child::Empty; 2]],
+            data: Vec::new(),
+            countries: HashMap::new(),
+        }
+    }
+}
+
+impl Writer {
+    /// Record the network as belonging to the country, replacing anything already inserted within it.
+    ///
+    /// IPv6 networks within ::/96 are rejected as they would overwrite the IPv4 networks.
+    pub fn insert(&mut self, cidr: &IpCidr, alpha: Alpha2) -> Result<(), InsertError> {
+        let (bits, length) = match cidr.first_address() {
+            IpAddr::V4(addr) => (u128::from(addr.to_bits()), 96 + cidr.network_length()),
+            IpAddr::V6(addr) if addr.to_bits() >> 32 == 0 => return Err(InsertError::Ipv4Space(*cidr)),
+            IpAddr::V6(addr) => (addr.to_bits(), cidr.network_length()),
+        };
+
+        let data = self.country(alpha);
+        let mut node = 0;
+        for depth in 0..length {
+            let bit = ((bits >> (127 - depth)) & 1) as usize;
+            if depth + 1 == length {
+                self.nodes[node][bit] = Child::Data(data);
+                return Ok(());
+            }
+
+            node = match self.nodes[node][bit] {
+                Child::Node(next) => next,
+                // Either a new branch, or splitting a larger network so both halves keep its data.
+                child => {
+                    let fill = match child {
+                        Child::Data(_) => child,
+                        _ => Child::Empty,
+                    };
+                    self.nodes.push([fill; 2]);
+                    let next = self.nodes.len() - 1;
+                    self.nodes[node][bit] = Child::Node(next);
+                    next
+                }
+            };
+        }
+
+        if length == 0 {
+            self.nodes[0] = [Child::Data(data); 2];
+        }
+
+        Ok(())
+    }
+
+    pub fn write(&self, mut out: impl Write) -> IoResult<()> {
+        let node_count = self.nodes.len();
+        let value = |child: Child| match child {
+            Child::Empty => node_count,
+            Child::Node(node) => node,
+            Child::Data(offset) => node_count + DATA_SEPARATOR.len() + offset,
+        };
+
+        let largest = node_count + DATA_SEPARATOR.len() + self.data.len();
+        let record_size: u16 = match largest {
+            largest if largest < 1 << 24 => 24,
+            largest if largest < 1 << 28 => 28,
+            _ => 32,
+        };
+
+        let mut tree = Vec::with_capacity(node_count * usize::from(record_size) / 4);
+        for [left, right] in &self.nodes {
+            let (left, right) = (value(*left) as u32, value(*right) as u32);
+            match record_size {
+                24 => {
+                    tree.extend_from_slice(&left.to_be_bytes()[1..]);
+                    tree.extend_from_slice(&right.to_be_bytes()[1..]);
+                }
+                28 => {
+                    tree.extend_from_slice(&left.to_be_bytes()[1..]);
+                    tree.push(((((left >> 24) & 0x0f) << 4) | ((right >> 24) & 0x0f)) as u8);
+                    tree.extend_from_slice(&right.to_be_bytes()[1..]);
+                }
+                _ => {
+                    tree.extend_from_slice(&left.to_be_bytes());
+                    tree.extend_from_slice(&right.to_be_bytes());
+                }
+            }
+        }
+
+        let mut metadata = Vec::new();
+        encode::map(&mut metadata, 9);
+        encode::string(&mut metadata, "binary_format_major_version");
+        encode::uint(&mut metadata, encode::UINT16, 2);
+        encode::string(&mut metadata, "binary_format_minor_version");
+        encode::uint(&mut metadata, encode::UINT16, 0);
+        encode::string(&mut metadata, "build_epoch");
+        encode::uint(&mut metadata, encode::UINT64, chrono::Utc::now().timestamp() as u64);
+        encode::string(&mut metadata, "database_type");
+        encode::string(&mut metadata, DATABASE_TYPE);
+        encode::string(&mut metadata, "description");
+        encode::map(&mut metadata, 1);
+        encode::string(&mut metadata, "en");
+        encode::string(
+            &mut metadata,
+            "Country ranges from the regional internet registries, by country-ip",
+        );
+        encode::string(&mut metadata, "ip_version");
+        encode::uint(&mut metadata, encode::UINT16, 6);
+        encode::string(&mut metadata, "languages");
+        encode::array(&mut metadata, 1);
+        encode::string(&mut metadata, "en");
+        encode::string(&mut metadata, "node_count");
+        encode::uint(&mut metadata, encode::UINT32, node_count as u64);
+        encode::string(&mut metadata, "record_size");
+        encode::uint(&mut metadata, encode::UINT16, record_size.into());
+
+        out.write_all(&tree)?;
+        out.write_all(&DATA_SEPARATOR)?;
+        out.write_all(&self.data)?;
+        out.write_all(METADATA_MARKER)?;
+        out.write_all(&metadata)?;
+        out.flush()
+    }
+
+    /// The offset of the country's data, written the first time it's used.
+    fn country(&mut self, alpha: Alpha2) -> usize {
+        if let Some(offset) = self.countries.get(&alpha) {
+            return *offset;
+        }
+
+        let offset = self.data.len();
+        let data = &mut self.data;
+        encode::map(data, 1);
+        encode::string(data, "country");
+        encode::map(data, 2);
+        encode::string(data, "iso_code");
+        encode::string(data, &alpha.to_string());
+        encode::string(data, "names");
+        encode::map(data, 1);
+        encode::string(data, "en");
+        encode::string(data, Country::from(alpha).iso_short_name());
+
+        self.countries.insert(alpha, offset);
+        offset
+    }
+}
+
+/// The data section encoding, see <https://maxmind.github.io/MaxMind-DB/>.
+mod encode {
+    const STRING: u8 = 2;
+    const MAP: u8 = 7;
+    pub const UINT16: u8 = 5;
+    pub const UINT32: u8 = 6;
+    pub const UINT64: u8 = 9;
+    const ARRAY: u8 = 11;
+
+    fn control(out: &mut Vec<u8>, kind: u8, size: usize) {
+        let (size, extra) = match size {
+            size if size < 29 => (size as u8, vec![]),
+            size if size < 285 => (29, vec![(size - 29) as u8]),
+            size if size < 65_821 => (30, ((size - 285) as u16).to_be_bytes().to_vec()),
+            size => (31, ((size - 65_821) as u32).to_be_bytes()[1..].to_vec()),
+        };
+
+        match kind {
+            kind if kind <= 7 => out.push((kind << 5) | size),
+            kind => out.extend([size, kind - 7]),
+        }
+        out.extend(extra);
+    }
+
+    pub fn string(out: &mut Vec<u8>, value: &str) {
+        control(out, STRING, value.len());
+        out.extend_from_slice(value.as_bytes());
+    }
+
+    pub fn uint(out: &mut Vec<u8>, kind: u8, value: u64) {
+        let bytes = value.to_be_bytes();
+        let bytes = &bytes[(value.leading_zeros() / 8) as usize..];
+        control(out, kind, bytes.len());
+        out.extend_from_slice(bytes);
+    }
+
+    pub fn map(out: &mut Vec<u8>, pairs: usize) {
+        control(out, MAP, pairs);
+    }
+
+    pub fn array(out: &mut Vec<u8>, length: usize) {
+        control(out, ARRAY, length);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn ip(addr: &str) -> IpAddr {
+        IpAddr::from_str(addr).unwrap()
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_round_trip() -> Result<()> {
+        let mut writer = Writer::default();
+        writer.insert(&IpCidr::from_str("1.0.0.0/24")?, Alpha2::AU)?;
+        writer.insert(&IpCidr::from_str("133.0.0.0/8")?, Alpha2::JP)?;
+        writer.insert(&IpCidr::from_str("133.1.0.0/16")?, Alpha2::NZ)?;
+        writer.insert(&IpCidr::from_str("2001:200::/32")?, Alpha2::JP)?;
+
+        let mut data = Vec::new();
+        writer.write(&mut data)?;
+
+        let reader = Reader::from_source(data.as_slice())?;
+        assert_eq!(reader.metadata.database_type, DATABASE_TYPE);
+        let country = reader.lookup::<geoip2::Country>(ip("1.0.0.1"))?;
+        assert_eq!(country.country.and_then(|country| country.iso_code), Some("AU"));
+
+        // Inserting NZ split the JP network into the eight networks around it.
+        let db = Mmdb::parse(data)?;
+        assert_eq!(db.0.len(), 11);
+        assert_eq!(db.lookup(&ip("1.0.0.255")).await, Some(Alpha2::AU));
+        assert_eq!(db.lookup(&ip("1.0.1.0")).await, None);
+        assert_eq!(db.lookup(&ip("133.0.0.1")).await, Some(Alpha2::JP));
+        assert_eq!(db.lookup(&ip("133.1.200.1")).await, Some(Alpha2::NZ));
+        assert_eq!(db.lookup(&ip("133.2.0.0")).await, Some(Alpha2::JP));
+        assert_eq!(db.lookup(&ip("2001:200::1")).await, Some(Alpha2::JP));
+        assert_eq!(db.filtered(&Alpha2::JP).await.len(), 9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipv4_space_is_rejected() -> Result<()> {
+        let mut writer = Writer::default();
+        assert!(writer.insert(&IpCidr::from_str("::1/128")?, Alpha2::AU).is_err());
+        assert!(writer.insert(&IpCidr::from_str("::/96")?, Alpha2::AU).is_err());
+        assert!(writer.insert(&IpCidr::from_str("::ffff:0:0/96")?, Alpha2::AU).is_ok());
+
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_ipv4_aliases_are_skipped() -> Result<()> {
+        let mut writer = Writer::default();
+        writer.insert(&IpCidr::from_str("1.0.0.0/24")?, Alpha2::AU)?;
+        writer.insert(&IpCidr::from_str("::ffff:100:0/120")?, Alpha2::AU)?;
+        writer.insert(&IpCidr::from_str("2002:100::/24")?, Alpha2::AU)?;
+        writer.insert(&IpCidr::from_str("2001:0:100::/40")?, Alpha2::AU)?;
+        writer.insert(&IpCidr::from_str("2001:200::/32")?, Alpha2::JP)?;
+
+        let mut data = Vec::new();
+        writer.write(&mut data)?;
+
+        let db = Mmdb::parse(data)?;
+        assert_eq!(db.0.len(), 2);
+        assert_eq!(db.filtered(&Alpha2::AU).await.len(), 1);
+        assert!(db.filtered(&Alpha2::AU).await.iter().all(|record| record.start().is_ipv4()));
+        assert_eq!(db.lookup(&ip("2001:200::1")).await, Some(Alpha2::JP));
+
+        Ok(())
+    }
+}
@@ -18,8 +18,11 @@ use crate::registry::Registry;
 use cidr::Ipv6Inet;
 use keshvar::Alpha2;
 use macros::CommonFields;
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::ops::RangeInclusive;
+use std::str::FromStr;
 use tracing::instrument;
 
 #[derive(Debug, Clone, CommonFields)]
@@ -39,11 +42,67 @@ pub enum Record {
     },
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Status {
     Assigned,
     Allocated,
     Reserved,
+    Available,
+
+    /// A status that isn't part of the exchange format yet.
+    Other(String),
+}
+
+impl Status {
+    pub fn name(&self) -> &str {
+        match self {
+            Status::Assigned => "assigned",
+            Status::Allocated => "allocated",
+            Status::Reserved => "reserved",
+            Status::Available => "available",
+            Status::Other(status) => status,
+        }
+    }
+
+    /// Whether the range is delegated to someone, rather than being held back or free.
+    pub const fn in_use(&self) -> bool {
+        matches!(self, Status::Assigned | Status::Allocated)
+    }
+}
+
+impl FromStr for Status {
+    type Err = Infallible;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        Ok(match status {
+            "assigned" => Status::Assigned,
+            "allocated" => Status::Allocated,
+            "reserved" => Status::Reserved,
+            "available" => Status::Available,
+            status => Status::Other(status.to_string()),
+        })
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Only the statuses of ranges in use can be chosen on the command line.
+impl clap::ValueEnum for Status {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Status::Assigned, Status::Allocated]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        match self {
+            Status::Assigned => Some(clap::builder::PossibleValue::new("assigned")),
+            Status::Allocated => Some(clap::builder::PossibleValue::new("allocated")),
+            _ => None,
+        }
+    }
 }
 
 impl Record {
@@ -98,9 +157,14 @@ impl Record {
 
     #[instrument(level = "TRACE", ret)]
     pub fn random(&self) -> IpAddr {
-        use rand::prelude::{Rng, SeedableRng, SmallRng};
+        use rand::prelude::{SeedableRng, SmallRng};
+
+        self.random_with(&mut SmallRng::from_entropy())
+    }
 
-        let mut rng = SmallRng::from_entropy();
+    /// A random address within the record, drawn from the given generator so it can be seeded.
+    #[instrument(level = "TRACE", ret, skip(rng))]
+    pub fn random_with<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> IpAddr {
         let raw = rng.gen_range(self.u128_range());
         match self.start() {
             IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(raw as u32)),
@@ -14,9 +14,13 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::asn::{AsnBlock, AsnIndex};
+use crate::cache::{Cache, Source};
+use crate::index::RecordIndex;
 use crate::record::Record::RegistryRecord;
 use crate::record::{Record, Status};
 use crate::registry::RegistryErrors::RegistryFailed;
+use crate::stats::{self, Entry, Kind, Line, LineError, ParseStats};
 use crate::RecordDB;
 use anyhow::{Context, Result};
 use async_trait::async_trait;
@@ -27,16 +31,13 @@ use std::collections::HashMap;
 use std::io::BufRead;
 use std::net::IpAddr;
 use std::str::FromStr;
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 use thiserror::Error;
 use tokio::sync::Mutex;
-use tracing::instrument;
+use tracing::{debug, instrument, trace, warn};
 
 #[derive(Debug, Error)]
 pub enum RegistryErrors {
-    #[error("Failed to get data for {}", .0.name())]
-    DownloadFailed(Registry, #[source] anyhow::Error),
-
     #[error("Failed to parse data for {}", .0.name())]
     ParseFailed(Registry, #[source] anyhow::Error),
 
@@ -75,35 +76,42 @@ const DELEGATES: [Alpha2; 12] = [
 #[derive(Debug, Clone)]
 pub struct RegistryRecords {
     pub registry: Registry,
-    pub inner: Vec<Record>,
+    pub index: Arc<RecordIndex>,
+    pub asns: Arc<AsnIndex>,
+    pub stats: Arc<ParseStats>,
 }
 
 #[async_trait]
 impl RecordDB for RegistryRecords {
     #[instrument(level = "TRACE", ret)]
-    async fn lookup(&self, ip: &IpAddr) -> Option<Alpha2> {
-        self.inner
-            .iter()
-            .par_bridge()
-            .find_first(|record| record.range().contains(ip))
-            .map(|record| *record.alpha())
+    async fn record(&self, ip: &IpAddr) -> Option<&Record> {
+        self.index.lookup(ip)
     }
 
     #[instrument(level = "TRACE", ret)]
     async fn filtered(&self, country: &Alpha2) -> Vec<&Record> {
-        self.inner
-            .iter()
-            .par_bridge()
-            .filter(|record| record.alpha() == country)
+        self.index
+            .country(country)
             .filter(|record| match record {
                 RegistryRecord { status, .. } => *status == Status::Allocated,
                 _ => true,
             })
             .collect()
     }
+
+    #[instrument(level = "TRACE", ret)]
+    async fn ranges(&self, country: &Alpha2) -> Vec<&Record> {
+        self.index
+            .country(country)
+            .filter(|record| match record {
+                RegistryRecord { status, .. } => status.in_use(),
+                _ => true,
+            })
+            .collect()
+    }
 }
 
-static DOWNLOADED: LazyLock<Mutex<HashMap<Registry, RegistryRecords>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static LOADED: LazyLock<Mutex<HashMap<Registry, RegistryRecords>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
 
 impl Registry {
     #[instrument(level = "TRACE", ret, err, fields(country = %country.iso_short_name()))]
@@ -196,89 +204,151 @@ impl Registry {
     }
 
     #[instrument(level = "TRACE", err, ret)]
-    pub async fn get(&self) -> Result<Box<dyn RecordDB>> {
-        let records = match self.downloaded().await {
-            Err(_) => {
-                self.download().await?;
-                self.downloaded().await
-            }
-            value => value,
-        }?;
+    pub async fn get(&self, offline: bool) -> Result<Box<dyn RecordDB>> {
+        Ok(Box::new(self.records(offline).await?))
+    }
 
-        Ok(Box::new(records))
+    /// The records of this registry, loading them the first time they're needed.
+    pub async fn records(&self, offline: bool) -> Result<RegistryRecords> {
+        match self.loaded().await {
+            Some(records) => Ok(records),
+            None => self.load(&Cache::open()?, offline).await,
+        }
     }
 
     #[instrument(level = "TRACE", ret)]
-    async fn downloaded(&self) -> Result<RegistryRecords> {
-        let map = &(DOWNLOADED.lock().await);
-        map.get(self).ok_or(anyhow::anyhow!("Data not downloaded")).cloned()
+    async fn loaded(&self) -> Option<RegistryRecords> {
+        LOADED.lock().await.get(self).cloned()
+    }
+
+    /// Read the records from the cache, refreshing it first when it's stale unless `offline`.
+    #[instrument(level = "TRACE", skip(cache), err)]
+    pub async fn load(&self, cache: &Cache, offline: bool) -> Result<RegistryRecords> {
+        let data = cache.load(Source::Registry(*self), offline).await?;
+        let records = self.parse(&data);
+        debug!(
+            "Read {} ranges from {} records of {}",
+            records.stats.ranges,
+            records.stats.entries.values().sum::<u64>(),
+            self.name()
+        );
+
+        LOADED.lock().await.insert(*self, records.clone());
+        Ok(records)
     }
 
-    #[instrument(level = "TRACE", ret, err)]
-    async fn download(&self) -> Result<()> {
-        let url = &self.url();
-        let data = reqwest::get(url)
-            .await
-            .map_err(|e| RegistryErrors::DownloadFailed(*self, e.into()))?
-            .bytes()
-            .await
-            .map_err(|e| RegistryErrors::DownloadFailed(*self, e.into()))?
-            .to_vec();
-
-        let parsed_lines = data
+    /// Parse the records from a delegation statistics file published by this registry.
+    ///
+    /// Lines which can't be parsed are skipped rather than failing the whole file,
+    /// what was skipped is counted in the [`ParseStats`] of the records.
+    pub fn parse(&self, data: &[u8]) -> RegistryRecords {
+        let lines = data
             .lines()
             .par_bridge()
             .flatten()
-            .filter(|line| !line.starts_with('#')) // Remove comments
-            .map(|line| line.split('|').map(|s| s.into()).collect::<Vec<String>>()) // Split on pipe
-            .filter_map(|split| {
-                let alpha = match &*split[1] {
-                    str if str.len() != 2 => return None,
-                    str => match Alpha2::try_from(str) {
-                        Ok(alpha) => alpha,
-                        Err(_) => return None,
-                    },
-                };
-
-                let value = match IpAddr::from_str(&split[3]) {
-                    Ok(value) => value,
-                    Err(_) => return None,
-                };
-
-                let range = match u32::from_str(&split[4]) {
-                    Ok(range) => range,
-                    Err(_) => return None,
-                };
-
-                let date = split[5].to_string();
-
-                let status = match &*split[6] {
-                    "assigned" => Status::Assigned,
-                    "allocated" => Status::Allocated,
-                    "reserved" => Status::Reserved,
-                    _ => unreachable!("Invalid status"),
-                };
-
-                Some(RegistryRecord {
-                    registry: *self,
-                    alpha,
-                    value,
-                    range,
-                    date,
-                    status,
-                })
-            })
+            .filter_map(|line| stats::parse_line(&line).transpose())
             .collect::<Vec<_>>();
 
-        let record = RegistryRecords {
+        let mut stats = ParseStats::default();
+        let mut records = Vec::with_capacity(lines.len());
+        let mut asns = Vec::new();
+        for line in lines {
+            match line {
+                Ok(Line::Version(version)) => stats.version = Some(version),
+                Ok(Line::Summary(summary)) => stats.summaries.push(summary),
+                Ok(Line::Entry(entry)) if entry.kind == Kind::Asn => {
+                    stats.record(&entry);
+                    if let Some(block) = self.asn(entry) {
+                        stats.asns += 1;
+                        asns.push(block);
+                    }
+                }
+                Ok(Line::Entry(entry)) => {
+                    stats.record(&entry);
+                    match self.record(entry) {
+                        Ok(Some(record)) => {
+                            stats.ranges += 1;
+                            records.push(record);
+                        }
+                        Ok(None) => {}
+                        Err(err) => {
+                            trace!("Skipping line from {}: {err}", self.name());
+                            stats.invalid += 1;
+                        }
+                    }
+                }
+                Err(err) => {
+                    trace!("Skipping line from {}: {err}", self.name());
+                    stats.invalid += 1;
+                }
+            }
+        }
+
+        for (status, count) in &stats.unknown_statuses {
+            warn!("{} has {count} records with the unknown status {status}", self.name());
+        }
+        for (summary, read) in stats.mismatched() {
+            warn!(
+                "{} summarises {} {} records but {read} were read",
+                self.name(),
+                summary.count,
+                summary.kind
+            );
+        }
+        if stats.invalid > 0 {
+            warn!("{} has {} lines which couldn't be parsed", self.name(), stats.invalid);
+        }
+
+        RegistryRecords {
             registry: *self,
-            inner: parsed_lines,
+            index: Arc::new(RecordIndex::new(records)),
+            asns: Arc::new(AsnIndex::new(asns)),
+            stats: Arc::new(stats),
+        }
+    }
+
+    /// The ASN block of an entry delegated to a country.
+    fn asn(&self, entry: Entry) -> Option<AsnBlock> {
+        Some(AsnBlock {
+            registry: *self,
+            alpha: entry.alpha()?,
+            start: u32::from_str(&entry.start).ok()?,
+            count: u32::try_from(entry.value).ok()?,
+            date: entry.date.unwrap_or_default(),
+            status: entry.status,
+        })
+    }
+
+    /// The address range of an entry delegated to a country.
+    ///
+    /// Fails when the block doesn't fit in its address family,
+    /// a count of IPv4 addresses must be at least one and not run past the last address,
+    /// and an IPv6 prefix length can't be more than 128.
+    fn record(&self, entry: Entry) -> Result<Option<Record>, LineError> {
+        let Some(alpha) = entry.alpha() else {
+            return Ok(None);
         };
 
-        let mut map = DOWNLOADED.lock().await;
-        map.insert(*self, record);
+        let value = IpAddr::from_str(&entry.start).map_err(|_| LineError::Address(entry.start.clone()))?;
+        let range = u32::try_from(entry.value).ok().filter(|range| match value {
+            IpAddr::V4(start) => *range > 0 && start.to_bits().checked_add(range - 1).is_some(),
+            IpAddr::V6(_) => *range <= 128,
+        });
+        let Some(range) = range else {
+            return Err(LineError::Size {
+                start: entry.start,
+                value: entry.value,
+            });
+        };
 
-        Ok(())
+        Ok(Some(RegistryRecord {
+            registry: *self,
+            alpha,
+            value,
+            range,
+            date: entry.date.unwrap_or_default(),
+            status: entry.status,
+        }))
     }
 }
 
@@ -286,19 +356,181 @@ impl Registry {
 mod test {
     use super::*;
     use keshvar::CountryIterator;
-    use tracing::trace;
+
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/delegated-apnic-extended-latest");
+
+    #[test]
+    fn test_parse() {
+        let records = Registry::Apnic.parse(FIXTURE);
+        assert_eq!(records.index.len(), 7);
+
+        let starts = records.index.records().iter().map(Record::start).collect::<Vec<_>>();
+        assert!(starts.contains(&IpAddr::from_str("101.160.0.0").unwrap()));
+        assert!(starts.contains(&IpAddr::from_str("2001:200::").unwrap()));
+        assert!(!starts.contains(&IpAddr::from_str("223.255.236.0").unwrap()));
+    }
+
+    #[test]
+    fn test_fixtures() {
+        // The registry, its fixture, how many address ranges and ASN blocks are kept and how many records have no country.
+        let fixtures: [(Registry, &[u8], usize, usize, u64); 5] = [
+            (
+                Registry::Afrinic,
+                include_bytes!("../tests/fixtures/delegated-afrinic-extended-latest"),
+                3,
+                1,
+                2,
+            ),
+            (Registry::Apnic, FIXTURE, 7, 2, 2),
+            (
+                Registry::Arin,
+                include_bytes!("../tests/fixtures/delegated-arin-extended-latest"),
+                4,
+                2,
+                3,
+            ),
+            (
+                Registry::Lacnic,
+                include_bytes!("../tests/fixtures/delegated-lacnic-extended-latest"),
+                4,
+                1,
+                2,
+            ),
+            (
+                Registry::Ripencc,
+                include_bytes!("../tests/fixtures/delegated-ripencc-extended-latest"),
+                3,
+                1,
+                0,
+            ),
+        ];
+
+        for (registry, data, ranges, asns, unallocated) in fixtures {
+            let records = registry.parse(data);
+            let stats = &records.stats;
+
+            assert_eq!(records.index.len(), ranges, "{registry:?}");
+            assert_eq!(records.asns.len(), asns, "{registry:?}");
+            assert_eq!(stats.ranges, ranges as u64, "{registry:?}");
+            assert_eq!(stats.asns, asns as u64, "{registry:?}");
+            assert_eq!(stats.unallocated, unallocated, "{registry:?}");
+            assert_eq!(stats.invalid, 0, "{registry:?}");
+            assert!(stats.unknown_statuses.is_empty(), "{registry:?}");
+            assert!(stats.mismatched().is_empty(), "{registry:?}: {:?}", stats.mismatched());
+
+            let version = stats.version.as_ref().expect("Every fixture has a version line");
+            assert_eq!(version.registry, registry.target_name());
+            assert_eq!(version.records, stats.entries.values().sum::<u64>(), "{registry:?}");
+        }
+    }
+
+    #[test]
+    fn test_ripencc_eu() {
+        let records = Registry::Ripencc.parse(include_bytes!("../tests/fixtures/delegated-ripencc-extended-latest"));
+
+        // EU isn't a country, and neither is the ZZ used for reserved blocks.
+        assert_eq!(records.stats.unknown_countries, 4);
+        assert!(records
+            .index
+            .records()
+            .iter()
+            .all(|record| matches!(record.alpha(), Alpha2::GB | Alpha2::DE)));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_unknown() {
+        let data = b"2|apnic|20241017|3|19830613|20241016|+1000
+apnic|*|ipv4|*|4|summary
+apnic|AU|ipv4|1.0.0.0|256|20110811|allocated|A91872ED
+apnic|AU|ipv4|1.0.4.0|1024|20110412|transferred|A91872ED
+apnic|AU|ipv4|1.0.8.0
+";
+        let records = Registry::Apnic.parse(data);
+
+        assert_eq!(records.stats.unknown_statuses.get("transferred"), Some(&1));
+        assert_eq!(records.stats.invalid, 1);
+        assert_eq!(records.stats.mismatched().len(), 1);
+
+        // Ranges with an unknown status are kept for lookups but never generated from.
+        assert_eq!(records.index.len(), 2);
+        assert_eq!(records.ranges(&Alpha2::AU).await.len(), 1);
+        assert_eq!(
+            records.lookup(&IpAddr::from_str("1.0.5.1").unwrap()).await,
+            Some(Alpha2::AU)
+        );
+    }
+
+    #[test]
+    fn test_invalid_ranges() {
+        let entry = |line| match stats::parse_line(line) {
+            Ok(Some(Line::Entry(entry))) => entry,
+            line => panic!("Expected a record but got {line:?}"),
+        };
+
+        assert!(matches!(
+            Registry::Apnic.record(entry("apnic|JP|ipv6|2001:200::|129|20020801|allocated")),
+            Err(LineError::Size { value: 129, .. })
+        ));
+        assert!(matches!(
+            Registry::Apnic.record(entry("apnic|AU|ipv4|1.0.0.0|0|20110811|allocated")),
+            Err(LineError::Size { value: 0, .. })
+        ));
+        assert!(matches!(
+            Registry::Apnic.record(entry("apnic|AU|ipv4|255.255.255.255|2|20110811|allocated")),
+            Err(LineError::Size { value: 2, .. })
+        ));
+        assert!(matches!(
+            Registry::Apnic.record(entry("apnic|AU|ipv4|255.255.255.255|1|20110811|allocated")),
+            Ok(Some(_))
+        ));
+
+        let records = Registry::Apnic.parse(
+            b"apnic|AU|ipv4|1.0.0.0|0|20110811|allocated
+apnic|AU|ipv4|1.0.4.0|1024|20110412|allocated
+",
+        );
+        assert_eq!(records.stats.invalid, 1);
+        assert_eq!(records.index.len(), 1);
+    }
+
+    #[test]
+    fn test_asns() {
+        let records = Registry::Apnic.parse(FIXTURE);
+
+        let block = records.asns.find(173).expect("AS173 is in the fixture");
+        assert_eq!(block.alpha, Alpha2::AU);
+        assert_eq!(block.status, Status::Allocated);
+        assert_eq!(
+            records.asns.country(&Alpha2::JP).map(|block| block.start).collect::<Vec<_>>(),
+            vec![2497]
+        );
+        assert!(records.asns.find(174).is_none());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_filtered() {
+        let records = Registry::Apnic.parse(FIXTURE);
+
+        // Only allocated ranges are used, so the assigned 1.0.0.0/24 is skipped.
+        assert_eq!(records.filtered(&Alpha2::AU).await.len(), 2);
+        assert_eq!(
+            records.lookup(&IpAddr::from_str("133.1.2.3").unwrap()).await,
+            Some(Alpha2::JP)
+        );
+    }
 
     #[test_log::test(tokio::test)]
     async fn test_downloads() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let cache = Cache::new(directory.path());
         for reg in Registry::get_variants() {
             trace!("Testing {reg:?} | url: {}", reg.url());
 
-            reg.download().await?;
-            let records = reg.downloaded().await;
+            let records = reg.load(&cache, false).await;
             debug_assert!(records.is_ok(), "Failed to download {reg:?}; {records:?}");
 
             let records = records?;
-            debug_assert!(!records.inner.is_empty(), "No records for {reg:?}");
+            debug_assert!(!records.index.is_empty(), "No records for {reg:?}");
         }
 
         Ok(())
@@ -307,9 +539,11 @@ mod test {
     #[test_log::test(tokio::test)]
     async fn test_all_countries() -> Result<()> {
         trace!("Initialising registries");
+        let directory = tempfile::tempdir()?;
+        let cache = Cache::new(directory.path());
         for reg in Registry::get_variants() {
             trace!("Initialising {reg:?}");
-            reg.download().await?;
+            reg.load(&cache, false).await?;
         }
 
         trace!("Testing all countries");
@@ -326,7 +560,7 @@ mod test {
             debug_assert!(registry.is_ok(), "Failed to get registry for {country:?}; {registry:?}");
             let registry = registry?;
 
-            let records = registry.get().await?;
+            let records = registry.get(false).await?;
             let records = records.filtered(&country.alpha2()).await;
             debug_assert!(!records.is_empty(), "No records for {country:?}");
         }
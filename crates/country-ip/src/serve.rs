@@ -0,0 +1,358 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A local HTTP service answering lookups from the cached data,
+//! so other services can query us without shelling out for every address.
+//!
+//! The cache is checked for new snapshots on an interval, such as after `country-ip update` has run,
+//! and the data is swapped out without interrupting requests in flight.
+
+use crate::bulk::Resolution;
+use crate::cache::{Cache, Metadata, Source};
+use crate::db_ip::DB;
+use crate::export::Cidrs;
+use crate::record::Record;
+use crate::registry::{Registry, RegistryRecords};
+use crate::RecordDB;
+use anyhow::Result;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, StatusCode, Uri};
+use hyper_util::rt::TokioIo;
+use keshvar::Country;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::future::Future;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tracing::{debug, info, instrument, warn};
+
+type Response = hyper::Response<Full<Bytes>>;
+
+#[derive(Debug, Error)]
+pub enum ServeError {
+    #[error("There is no cached data to serve, run `country-ip update` while online or import a file")]
+    NoData,
+}
+
+/// Everything loaded from the cache at one point in time.
+#[derive(Debug, Default)]
+struct Data {
+    registries: HashMap<Registry, RegistryRecords>,
+    db_ip: Option<DB>,
+    mmdb: Option<Box<dyn RecordDB>>,
+}
+
+impl Data {
+    async fn load(cache: &Cache, offline: bool) -> Self {
+        let mut data = Self::default();
+        for registry in Registry::get_variants() {
+            if let Some(snapshot) = Self::snapshot(cache, Source::Registry(registry), offline).await {
+                data.registries.insert(registry, registry.parse(&snapshot));
+            }
+        }
+
+        if let Some(snapshot) = Self::snapshot(cache, Source::DbIp, offline).await {
+            match DB::parse(&snapshot) {
+                Ok(db) => data.db_ip = Some(db),
+                Err(err) => warn!("Skipping {}: {err:#}", Source::DbIp),
+            }
+        }
+
+        data
+    }
+
+    /// The snapshot of the source, or none with a warning if it can't be loaded.
+    async fn snapshot(cache: &Cache, source: Source, offline: bool) -> Option<Vec<u8>> {
+        cache
+            .load(source, offline)
+            .await
+            .inspect_err(|err| warn!("Skipping {source}: {err:#}"))
+            .ok()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.registries.is_empty() && self.db_ip.is_none() && self.mmdb.is_none()
+    }
+
+    /// Where the records for the country are, the same as [`crate::get_record_db`].
+    fn database(&self, country: &Country) -> Option<&dyn RecordDB> {
+        if let Some(mmdb) = &self.mmdb {
+            return Some(mmdb.as_ref());
+        }
+
+        let registry = Registry::get_for(country)
+            .ok()
+            .and_then(|registry| self.registries.get(&registry));
+        match registry {
+            Some(records) => Some(records),
+            None => self.db_ip.as_ref().map(|db| db as &dyn RecordDB),
+        }
+    }
+
+    async fn record(&self, ip: &IpAddr) -> Option<&Record> {
+        if let Some(mmdb) = &self.mmdb {
+            return mmdb.record(ip).await;
+        }
+
+        for registry in Registry::get_variants() {
+            let Some(records) = self.registries.get(&registry) else {
+                continue;
+            };
+
+            if let Some(record) = records.record(ip).await {
+                return Some(record);
+            }
+        }
+
+        match &self.db_ip {
+            Some(db) => db.record(ip).await,
+            None => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Service {
+    cache: Cache,
+    data: RwLock<Arc<Data>>,
+    /// The metadata of every snapshot when the data was last loaded.
+    fingerprint: Mutex<Vec<Option<Metadata>>>,
+}
+
+impl Service {
+    /// Load the data from `cache`, updating any stale snapshots first unless `offline`.
+    ///
+    /// When a MaxMind database has been chosen it's served instead, and never reloaded.
+    #[instrument(level = "TRACE", err)]
+    pub async fn load(cache: Cache, offline: bool) -> Result<Self> {
+        let data = match crate::mmdb::instance().await? {
+            Some(mmdb) => Data {
+                mmdb: Some(mmdb),
+                ..Default::default()
+            },
+            None => Data::load(&cache, offline).await,
+        };
+
+        if data.is_empty() {
+            return Err(ServeError::NoData.into());
+        }
+
+        Ok(Self {
+            fingerprint: Mutex::new(fingerprint(&cache)),
+            data: RwLock::new(Arc::new(data)),
+            cache,
+        })
+    }
+
+    /// Read the cache again if any snapshot has changed since it was last read, returning if it had.
+    pub async fn reload(&self) -> Result<bool> {
+        let current = fingerprint(&self.cache);
+        if self.data().mmdb.is_some() || *self.fingerprint.lock().unwrap() == current {
+            return Ok(false);
+        }
+
+        info!("The cache has changed, reloading the data");
+        let data = Data::load(&self.cache, true).await;
+        if data.is_empty() {
+            warn!("Keeping the current data as the cache no longer has anything to serve");
+            return Ok(false);
+        }
+
+        *self.data.write().unwrap() = Arc::new(data);
+        *self.fingerprint.lock().unwrap() = current;
+        Ok(true)
+    }
+
+    /// Answer requests from `listener` until `shutdown` completes,
+    /// checking the cache for changes every `interval`.
+    pub async fn serve(
+        self: Arc<Self>,
+        listener: TcpListener,
+        interval: Duration,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<()> {
+        let reloader = tokio::spawn({
+            let service = self.clone();
+            async move {
+                let mut ticks = tokio::time::interval(interval);
+                ticks.tick().await;
+                loop {
+                    ticks.tick().await;
+                    if let Err(err) = service.reload().await {
+                        warn!("Failed to reload the data: {err:#}");
+                    }
+                }
+            }
+        });
+
+        info!("Listening on http://{}", listener.local_addr()?);
+        tokio::pin!(shutdown);
+        loop {
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        warn!("Failed to accept a connection: {err}");
+                        continue;
+                    }
+                },
+                _ = &mut shutdown => break,
+            };
+
+            let service = self.clone();
+            tokio::spawn(async move {
+                let handler = service_fn(move |request| {
+                    let service = service.clone();
+                    async move { Ok::<_, Infallible>(service.handle(request).await) }
+                });
+
+                if let Err(err) = http1::Builder::new().serve_connection(TokioIo::new(stream), handler).await {
+                    debug!("Connection from {peer} failed: {err}");
+                }
+            });
+        }
+
+        reloader.abort();
+        Ok(())
+    }
+
+    fn data(&self) -> Arc<Data> {
+        self.data.read().unwrap().clone()
+    }
+
+    #[instrument(level = "DEBUG", skip_all, fields(method = %request.method(), uri = %request.uri()))]
+    async fn handle(&self, request: Request<Incoming>) -> Response {
+        if request.method() != Method::GET {
+            return error(StatusCode::METHOD_NOT_ALLOWED, "Only GET requests are supported");
+        }
+
+        let data = self.data();
+        let segments = request.uri().path().trim_matches('/').split('/').collect::<Vec<_>>();
+        match segments.as_slice() {
+            ["lookup", ip] => lookup(&data, ip).await,
+            ["random", country] => random(&data, country, flag(request.uri(), "ipv6")).await,
+            ["ranges", country] => ranges(&data, country).await,
+            _ => error(
+                StatusCode::NOT_FOUND,
+                "Unknown endpoint, expected /lookup/{ip}, /random/{country} or /ranges/{country}",
+            ),
+        }
+    }
+}
+
+async fn lookup(data: &Data, ip: &str) -> Response {
+    let ip = match IpAddr::from_str(ip) {
+        Ok(ip) => ip,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+
+    match data.record(&ip).await {
+        Some(record) => respond(StatusCode::OK, &Resolution::resolved(ip.to_string(), record)),
+        None => respond(
+            StatusCode::NOT_FOUND,
+            &Resolution::failed(ip.to_string(), "No record contains this address"),
+        ),
+    }
+}
+
+async fn random(data: &Data, country: &str, ipv6: bool) -> Response {
+    let country = match crate::parse_country(country) {
+        Ok(country) => country,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+
+    let Some(database) = data.database(&country) else {
+        return error(StatusCode::SERVICE_UNAVAILABLE, "No data is loaded for this country");
+    };
+
+    let alpha = country.alpha2();
+    let ip = match ipv6 {
+        true => database.random_ipv6(&alpha).await,
+        false => database.random_ipv4(&alpha).await,
+    };
+
+    match ip {
+        Some(ip) => respond(StatusCode::OK, &json!({ "country": alpha.to_string(), "ip": ip })),
+        None => error(StatusCode::NOT_FOUND, "No addresses found for this country"),
+    }
+}
+
+async fn ranges(data: &Data, country: &str) -> Response {
+    let country = match crate::parse_country(country) {
+        Ok(country) => country,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err),
+    };
+
+    let Some(database) = data.database(&country) else {
+        return error(StatusCode::SERVICE_UNAVAILABLE, "No data is loaded for this country");
+    };
+
+    let alpha = country.alpha2();
+    let cidrs = Cidrs::from_records(database.ranges(&alpha).await, true, true);
+    let strings = |cidrs: &[cidr::IpCidr]| cidrs.iter().map(|cidr| format!("{cidr:#}")).collect::<Vec<_>>();
+
+    respond(
+        StatusCode::OK,
+        &json!({
+            "country": alpha.to_string(),
+            "ipv4": strings(&cidrs.v4),
+            "ipv6": strings(&cidrs.v6),
+        }),
+    )
+}
+
+fn fingerprint(cache: &Cache) -> Vec<Option<Metadata>> {
+    Source::all()
+        .into_iter()
+        .map(|source| cache.metadata(source).ok().flatten())
+        .collect()
+}
+
+/// If the query has `name` without a value, or with `true` or `1`.
+fn flag(uri: &Uri, name: &str) -> bool {
+    uri.query()
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .filter_map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (key == name).then_some(value),
+            None => (pair == name).then_some("true"),
+        })
+        .any(|value| value.eq_ignore_ascii_case("true") || value == "1")
+}
+
+fn respond(status: StatusCode, body: &impl Serialize) -> Response {
+    let body = serde_json::to_vec(body).unwrap_or_default();
+    hyper::Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body)))
+        .expect("Status and header are valid")
+}
+
+fn error(status: StatusCode, message: impl Display) -> Response {
+    respond(status, &json!({ "error": message.to_string() }))
+}
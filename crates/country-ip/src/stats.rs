@@ -0,0 +1,347 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The RIR statistics exchange format each registry publishes its delegations in.
+//!
+//! A file starts with a version line and a summary line for each type of record,
+//! followed by a record per delegated, reserved or available block of ASNs or addresses:
+//!
+//! ```text
+//! 2|apnic|20241017|12|19830613|20241016|+1000
+//! apnic|*|ipv4|*|7|summary
+//! apnic|AU|ipv4|101.160.0.0|2097152|20110302|allocated|A91A7381
+//! ```
+//!
+//! The extended files add an opaque id shared by every record held by the same organisation,
+//! and registries are free to add further fields after it.
+
+use crate::record::Status;
+use keshvar::Alpha2;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LineError {
+    #[error("Expected at least {expected} fields but found {found}")]
+    Fields { expected: usize, found: usize },
+
+    #[error("Unknown record type {0}")]
+    Kind(String),
+
+    #[error("{0} isn't a valid count")]
+    Count(String),
+
+    #[error("{0} isn't a valid address")]
+    Address(String),
+
+    #[error("{value} isn't a valid size for a block starting at {start}")]
+    Size { start: String, value: u64 },
+}
+
+/// The type of resource a record delegates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Asn,
+    Ipv4,
+    Ipv6,
+}
+
+impl Kind {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Kind::Asn => "asn",
+            Kind::Ipv4 => "ipv4",
+            Kind::Ipv6 => "ipv6",
+        }
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Kind {
+    type Err = LineError;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "asn" => Ok(Kind::Asn),
+            "ipv4" => Ok(Kind::Ipv4),
+            "ipv6" => Ok(Kind::Ipv6),
+            kind => Err(LineError::Kind(kind.to_string())),
+        }
+    }
+}
+
+/// The first line of a file, describing the file itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    /// The version of the format, `2` or `2.3`.
+    pub version: String,
+    pub registry: String,
+    pub serial: String,
+
+    /// How many records the file holds, excluding the header and summaries.
+    pub records: u64,
+    pub start_date: String,
+    pub end_date: String,
+    pub utc_offset: String,
+}
+
+/// How many records of a type the file holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    pub registry: String,
+    pub kind: Kind,
+    pub count: u64,
+}
+
+/// A block of ASNs or addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub registry: String,
+
+    /// The country code, empty for available and some reserved blocks,
+    /// or a code which isn't a country such as `EU` or `ZZ`.
+    pub cc: String,
+    pub kind: Kind,
+
+    /// The first ASN or address of the block.
+    pub start: String,
+
+    /// How many ASNs or IPv4 addresses the block holds, or the prefix length of an IPv6 block.
+    pub value: u64,
+
+    /// When the block was delegated, if it has been.
+    pub date: Option<String>,
+    pub status: Status,
+
+    /// The same for every block held by an organisation, only present in the extended files.
+    pub opaque_id: Option<String>,
+
+    /// Any fields the registry adds after the opaque id.
+    pub extensions: Vec<String>,
+}
+
+impl Entry {
+    /// The country the block is delegated to, if the code is one.
+    pub fn alpha(&self) -> Option<Alpha2> {
+        match self.cc.len() {
+            2 => Alpha2::try_from(&*self.cc).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Version(Version),
+    Summary(Summary),
+    Entry(Entry),
+}
+
+/// Parses a single line, giving nothing for comments and blank lines.
+pub fn parse_line(line: &str) -> Result<Option<Line>, LineError> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.trim().is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let fields = line.split('|').map(str::trim).collect::<Vec<_>>();
+    let count = |value: &str| value.parse::<u64>().map_err(|_| LineError::Count(value.to_string()));
+    let optional = |value: &str| match value {
+        "" => None,
+        value => Some(value.to_string()),
+    };
+
+    // The version is the only line starting with a number rather than the registry.
+    if fields[0].starts_with(|char: char| char.is_ascii_digit()) {
+        require(&fields, 7)?;
+        return Ok(Some(Line::Version(Version {
+            version: fields[0].to_string(),
+            registry: fields[1].to_string(),
+            serial: fields[2].to_string(),
+            records: count(fields[3])?,
+            start_date: fields[4].to_string(),
+            end_date: fields[5].to_string(),
+            utc_offset: fields[6].to_string(),
+        })));
+    }
+
+    if fields.len() == 6 && fields[5] == "summary" {
+        return Ok(Some(Line::Summary(Summary {
+            registry: fields[0].to_string(),
+            kind: fields[2].parse()?,
+            count: count(fields[4])?,
+        })));
+    }
+
+    require(&fields, 7)?;
+    Ok(Some(Line::Entry(Entry {
+        registry: fields[0].to_string(),
+        cc: fields[1].to_string(),
+        kind: fields[2].parse()?,
+        start: fields[3].to_string(),
+        value: count(fields[4])?,
+        date: optional(fields[5]).filter(|date| date != "00000000"),
+        status: Status::from_str(&fields[6].to_lowercase()).unwrap_or_else(|err| match err {}),
+        opaque_id: fields.get(7).copied().and_then(optional),
+        extensions: fields.iter().skip(8).map(|field| field.to_string()).collect(),
+    })))
+}
+
+fn require(fields: &[&str], expected: usize) -> Result<(), LineError> {
+    match fields.len() < expected {
+        true => Err(LineError::Fields {
+            expected,
+            found: fields.len(),
+        }),
+        false => Ok(()),
+    }
+}
+
+/// What was found while parsing a file.
+#[derive(Debug, Default, Clone)]
+pub struct ParseStats {
+    pub version: Option<Version>,
+    pub summaries: Vec<Summary>,
+
+    /// How many records of each type were read.
+    pub entries: HashMap<Kind, u64>,
+
+    /// Records which were kept as address ranges.
+    pub ranges: u64,
+
+    /// Records which were kept as ASN blocks.
+    pub asns: u64,
+
+    /// Records without a country, such as available blocks.
+    pub unallocated: u64,
+
+    /// Records with a code which isn't a country, such as `EU`.
+    pub unknown_countries: u64,
+
+    /// Statuses outside of the exchange format and how many records had them.
+    pub unknown_statuses: HashMap<String, u64>,
+
+    /// Lines which couldn't be parsed.
+    pub invalid: u64,
+}
+
+impl ParseStats {
+    /// Counts the entry against its type, country and status.
+    pub fn record(&mut self, entry: &Entry) {
+        *self.entries.entry(entry.kind).or_default() += 1;
+
+        if entry.cc.is_empty() {
+            self.unallocated += 1;
+        } else if entry.alpha().is_none() {
+            self.unknown_countries += 1;
+        }
+
+        if let Status::Other(status) = &entry.status {
+            *self.unknown_statuses.entry(status.clone()).or_default() += 1;
+        }
+    }
+
+    /// The summaries which don't match how many records of their type were read, with the count that was read.
+    pub fn mismatched(&self) -> Vec<(&Summary, u64)> {
+        self.summaries
+            .iter()
+            .map(|summary| (summary, self.entries.get(&summary.kind).copied().unwrap_or(0)))
+            .filter(|(summary, read)| summary.count != *read)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        assert_eq!(parse_line("# A comment"), Ok(None));
+        assert_eq!(parse_line("  \r"), Ok(None));
+
+        let Ok(Some(Line::Version(version))) = parse_line("2.3|arin|1729137600000|148271|19700101|20241016|-0400")
+        else {
+            panic!("Expected a version line");
+        };
+        assert_eq!(version.version, "2.3");
+        assert_eq!(version.records, 148271);
+        assert_eq!(version.utc_offset, "-0400");
+
+        assert_eq!(
+            parse_line("ripencc|*|ipv6|*|1234|summary"),
+            Ok(Some(Line::Summary(Summary {
+                registry: "ripencc".into(),
+                kind: Kind::Ipv6,
+                count: 1234,
+            })))
+        );
+
+        let Ok(Some(Line::Entry(entry))) = parse_line("lacnic|BR|asn|1916|1|19890101|allocated|160809|e-stats|extra\r")
+        else {
+            panic!("Expected a record");
+        };
+        assert_eq!(entry.kind, Kind::Asn);
+        assert_eq!(entry.alpha(), Some(Alpha2::BR));
+        assert_eq!(entry.opaque_id.as_deref(), Some("160809"));
+        assert_eq!(entry.extensions, vec!["e-stats", "extra"]);
+    }
+
+    #[test]
+    fn test_quirks() {
+        let Ok(Some(Line::Entry(entry))) = parse_line("apnic||ipv4|223.255.236.0|1024||available|") else {
+            panic!("Expected a record");
+        };
+        assert_eq!(entry.status, Status::Available);
+        assert_eq!(entry.date, None);
+        assert_eq!(entry.opaque_id, None);
+
+        let Ok(Some(Line::Entry(entry))) = parse_line("afrinic|ZA|ipv4|41.0.0.0|2097152|00000000|Allocated") else {
+            panic!("Expected a record");
+        };
+        assert_eq!(entry.status, Status::Allocated);
+        assert_eq!(entry.date, None);
+
+        let Ok(Some(Line::Entry(entry))) = parse_line("ripencc|EU|ipv4|2.56.8.0|1024|20190710|transferred|") else {
+            panic!("Expected a record");
+        };
+        assert_eq!(entry.alpha(), None);
+        assert_eq!(entry.status, Status::Other("transferred".into()));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            parse_line("apnic|AU|ipv4|1.0.0.0"),
+            Err(LineError::Fields { expected: 7, found: 4 })
+        );
+        assert_eq!(
+            parse_line("apnic|AU|ipv5|1.0.0.0|256|20110811|assigned"),
+            Err(LineError::Kind("ipv5".into()))
+        );
+        assert_eq!(
+            parse_line("apnic|AU|ipv4|1.0.0.0|lots|20110811|assigned"),
+            Err(LineError::Count("lots".into()))
+        );
+    }
+}
@@ -14,7 +14,19 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use clap_complete::CompletionCandidate;
+use keshvar::CountryIterator;
+
 mod oneshot;
 // #[cfg(feature = "ui-repl")]
 // mod repl;
 pub mod ui;
+
+/// Completes country arguments with the alpha-2 code of every country.
+pub(crate) fn complete_country() -> Vec<CompletionCandidate> {
+    CountryIterator::new()
+        .map(|country| {
+            CompletionCandidate::new(country.alpha2().to_string()).help(Some(country.iso_short_name().into()))
+        })
+        .collect()
+}
@@ -14,8 +14,16 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use clap::Parser;
-use std::net::IpAddr;
+use crate::asn;
+use crate::bulk::Format;
+use crate::cache::Source;
+use crate::export::Format as ExportFormat;
+use crate::mmdb;
+use crate::record::Status;
+use clap::{Args, Parser};
+use clap_complete::ArgValueCandidates;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
 pub enum OneshotAction {
@@ -24,11 +32,35 @@ pub enum OneshotAction {
     /// or a random country if no country is specified
     /// Countries must be specified as ISO 3166-1 alpha-2 or alpha-3 codes (e.g. US, USA, GB, GBR)
     Get {
+        #[arg(add = ArgValueCandidates::new(super::complete_country))]
         country: Option<String>,
 
         /// Use IpV6 addresses instead of IpV4
         #[arg(long, short = '6', action = clap::ArgAction::SetTrue)]
         ipv6: bool,
+
+        /// How many addresses to generate
+        #[arg(long, short = 'n', default_value_t = 1)]
+        count: usize,
+
+        /// Seed the generator so the same addresses are generated on every run
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Never generate the same address twice
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        unique: bool,
+
+        /// Leave out reserved, multicast and other bogon space
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        exclude_bogons: bool,
+
+        /// Only use registry ranges with this status, instead of only allocated ranges
+        #[arg(long, value_enum)]
+        status: Vec<Status>,
+
+        #[command(flatten)]
+        data: DataArgs,
     },
 
     /// Lookup an IP address and get the country it belongs to.
@@ -36,6 +68,146 @@ pub enum OneshotAction {
         /// The IP address to lookup.
         ///
         /// This can be either an IPv4 or IPv6 address.
-        addr: IpAddr,
+        #[arg(required_unless_present = "input", conflicts_with = "input")]
+        addr: Option<IpAddr>,
+
+        /// Lookup every address in a file instead, one per line or as CSV, use `-` to read from stdin.
+        #[arg(long, short)]
+        input: Option<PathBuf>,
+
+        /// Read the input as CSV with a header, using the addresses in this column.
+        #[arg(long, conflicts_with = "addr")]
+        column: Option<String>,
+
+        /// How to write the results of a bulk lookup.
+        #[arg(long, value_enum, default_value_t = Format::Csv, conflicts_with = "addr")]
+        output: Format,
+
+        #[command(flatten)]
+        data: DataArgs,
+    },
+
+    /// Find who an ASN was delegated to, or every ASN delegated to a country.
+    ///
+    /// With an address the ASN announcing it is found in an IP-to-ASN table,
+    /// which has to be imported first with `country-ip import ip-to-asn <FILE>`.
+    Asn {
+        /// The ASN, with or without the AS prefix (e.g. 13335, AS13335)
+        #[arg(
+            required_unless_present_any = ["country", "addr"],
+            conflicts_with_all = ["country", "addr"],
+            value_parser = asn::parse_asn
+        )]
+        number: Option<u32>,
+
+        /// List every ASN delegated to this country
+        #[arg(long, short, conflicts_with = "addr", add = ArgValueCandidates::new(super::complete_country))]
+        country: Option<String>,
+
+        /// Find the ASN announcing this address
+        #[arg(long, short)]
+        addr: Option<IpAddr>,
+
+        #[command(flatten)]
+        data: DataArgs,
     },
+
+    /// Export every range of the countries as CIDRs, for firewall allow and deny lists.
+    ///
+    /// Every country is exported when none are given, less any that are excluded.
+    Export {
+        #[arg(add = ArgValueCandidates::new(super::complete_country))]
+        countries: Vec<String>,
+
+        /// Countries to leave out of the export.
+        #[arg(long, short, add = ArgValueCandidates::new(super::complete_country))]
+        exclude: Vec<String>,
+
+        /// Only export IPv4 ranges.
+        #[arg(long, short = '4', action = clap::ArgAction::SetTrue, conflicts_with = "ipv6")]
+        ipv4: bool,
+
+        /// Only export IPv6 ranges.
+        #[arg(long, short = '6', action = clap::ArgAction::SetTrue)]
+        ipv6: bool,
+
+        /// How to write the list.
+        #[arg(long, value_enum, default_value_t = ExportFormat::Plain)]
+        output: ExportFormat,
+
+        /// The name of the nftables variables, ipset sets or iptables chain.
+        #[arg(long, default_value = "country")]
+        name: String,
+
+        /// The iptables target for matching addresses.
+        #[arg(long, default_value = "DROP")]
+        target: String,
+
+        #[command(flatten)]
+        data: DataArgs,
+    },
+
+    /// Answer lookups over HTTP on a local address, reloading the data when the cache is updated.
+    ///
+    /// Serves `GET /lookup/{ip}`, `GET /random/{country}?ipv6=true` and `GET /ranges/{country}` as JSON.
+    Serve {
+        /// The address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+
+        /// How many seconds between checking the cache for new snapshots.
+        #[arg(long, default_value_t = 60)]
+        reload_interval: u64,
+
+        #[command(flatten)]
+        data: DataArgs,
+    },
+
+    /// Download new snapshots of the registry data into the cache.
+    Update {
+        /// The sources to update, every source when none are given.
+        #[arg(value_enum)]
+        sources: Vec<Source>,
+
+        /// Only show how old each cached snapshot is without downloading anything.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        check: bool,
+    },
+
+    /// Import a snapshot from a local file, for machines without access to the registries.
+    Import {
+        /// The source the file was published by.
+        #[arg(value_enum)]
+        source: Source,
+
+        /// A delegation statistics file for a registry, the CSV (optionally gzipped) for DB-IP,
+        /// or a tab separated IP-to-ASN table (optionally gzipped) such as the one from iptoasn.com.
+        file: PathBuf,
+    },
+}
+
+/// Options for where record data is read from.
+#[derive(Debug, Clone, Default, Args)]
+pub struct DataArgs {
+    /// Only use the cached data, never downloading anything even when it's out of date.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub offline: bool,
+
+    /// Use a MaxMind database, such as GeoLite2-Country, instead of the registry data.
+    #[arg(long, value_name = "FILE")]
+    pub mmdb: Option<PathBuf>,
+}
+
+impl DataArgs {
+    /// Chooses the database to read records from.
+    ///
+    /// # Returns
+    /// If only the cached data should be used.
+    pub fn apply(self) -> bool {
+        if let Some(path) = self.mmdb {
+            mmdb::use_database(path);
+        }
+
+        self.offline
+    }
 }
@@ -15,22 +15,41 @@
  */
 
 use super::oneshot::OneshotAction;
+use crate::asn::{self, AsnBlock, AsnTable};
+use crate::bulk::{self, Resolver};
+use crate::cache::{Cache, Metadata, Source};
+use crate::export;
+use crate::generate::{self, Options};
+use crate::serve::Service;
 use amt_lib::populate;
-use amt_lib::ui::cli::error::CliError;
+use amt_lib::progress::{Kind, Progress};
+use amt_lib::ui::cli::error::{Categorise, Category};
 use amt_lib::ui::cli::flags::CommonFlags;
 use amt_lib::ui::cli::oneshot::OneshotHandler;
+use amt_lib::ui::cli::progress::Terminal;
 use amt_lib::ui::cli::{CliResult, CliUi};
 use amt_lib::ui::Ui;
-use tracing::{error, info, info_span};
+use anyhow::Context;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tracing::{debug, error, info, warn};
 use tracing_appender::non_blocking::WorkerGuard;
-use tracing_indicatif::span_ext::IndicatifSpanExt;
 
 #[derive(Default, Debug)]
 pub struct CountryIPCli {
     _guard: Option<WorkerGuard>,
 }
 
-impl CliUi for CountryIPCli {}
+impl CliUi for CountryIPCli {
+    #[cfg(feature = "updater")]
+    fn updater(&self) -> Option<amt_lib::updater::Updater> {
+        Some(amt_lib::updater::Updater::new(
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+        ))
+    }
+}
 
 impl Ui for CountryIPCli {
     type Args = ();
@@ -49,34 +68,265 @@ impl OneshotHandler for CountryIPCli {
     async fn handle(&mut self, command: Self::OneshotAction, flags: &CommonFlags) -> CliResult<()> {
         populate!(self, flags);
 
-        let span = info_span!("feedback");
-        span.pb_set_style(&amt_lib::ui::cli::progress::style_spinner());
-        span.pb_start();
+        let progress = Terminal::new(Kind::Spinner);
+        let result = run(command, &progress).await;
+        progress.finish();
+        result
+    }
+}
+
+/// Runs the action, reporting what is being loaded through `progress`.
+async fn run(command: OneshotAction, progress: &dyn Progress) -> CliResult<()> {
+    match command {
+        OneshotAction::Get {
+            country,
+            ipv6,
+            count,
+            seed,
+            unique,
+            exclude_bogons,
+            status,
+            data,
+        } => {
+            let offline = data.apply();
+            let mut rng = generate::rng(seed);
+
+            progress.set_message("Fetching country data...");
+            let country = crate::get_country(&country, &mut rng).categorise(Category::Usage)?;
+
+            progress.set_message("Getting IP Records...");
+            let options = Options {
+                count,
+                unique,
+                ipv6,
+                exclude_bogons,
+                statuses: status,
+            };
+            let addresses = crate::generate(&country, &options, &mut rng, offline).await?;
+
+            for addr in addresses {
+                info!("{} => {addr}", country.iso_short_name());
+            }
+        }
+        OneshotAction::Lookup {
+            input: Some(input),
+            column,
+            output,
+            data,
+            ..
+        } => {
+            let offline = data.apply();
+            let reader = bulk::open(&input).categorise(Category::NoInput)?;
+
+            progress.set_message("Loading IP Records...");
+            let resolver = Resolver::load(offline).await?;
+
+            progress.set_message("Looking up IP addresses...");
+            let stdout = std::io::BufWriter::new(std::io::stdout().lock());
+            let summary = resolver
+                .resolve_all(reader, column.as_deref(), output, stdout)
+                .await
+                .categorise(Category::Data)?;
+
+            debug!("Resolved {} of {} addresses", summary.resolved, summary.total);
+            if summary.unresolved > 0 {
+                warn!("{} addresses weren't found in any records", summary.unresolved);
+            }
+            if summary.invalid > 0 {
+                warn!("{} inputs weren't valid addresses", summary.invalid);
+            }
+        }
+        OneshotAction::Lookup {
+            addr: Some(addr), data, ..
+        } => {
+            let offline = data.apply();
+            progress.set_message("Looking up IP address...");
+            let ip = crate::lookup(&addr, offline).await;
 
-        match command {
-            OneshotAction::Get { country, ipv6 } => {
-                span.pb_set_message("Fetching country data...");
-                let country = crate::get_country(&country).map_err(|err| CliError::Source(err.into()))?;
+            match ip {
+                Ok(country) => info!("{addr} => {}", country.iso_short_name()),
+                Err(err) => {
+                    error!("Failed to lookup IP address: {err}");
+                    info!("{addr} => Unknown")
+                }
+            }
+        }
+        OneshotAction::Lookup { .. } => unreachable!("Clap requires either an address or an input"),
+        OneshotAction::Asn {
+            number: Some(number),
+            data,
+            ..
+        } => {
+            let offline = data.apply();
+            progress.set_message("Loading ASN records...");
+            match asn::find(number, offline).await? {
+                Some(block) => info!("AS{number} => {}", describe_block(&block)),
+                None => warn!("AS{number} => Not delegated by any registry"),
+            }
+        }
+        OneshotAction::Asn {
+            country: Some(country),
+            data,
+            ..
+        } => {
+            let offline = data.apply();
+            let country = crate::parse_country(&country).categorise(Category::Usage)?;
 
-                span.pb_set_message("Getting IP Records...");
-                let random = crate::get(&country, &ipv6).await.map_err(CliError::Source)?;
+            progress.set_message("Loading ASN records...");
+            let blocks = asn::country(&country.alpha2(), offline).await?;
+            if blocks.is_empty() {
+                warn!("No ASNs are delegated to {}", country.iso_short_name());
+            }
 
-                info!("{} => {random}", country.iso_short_name());
+            for block in blocks {
+                info!("{} => {block}", country.iso_short_name());
             }
-            OneshotAction::Lookup { addr, .. } => {
-                span.pb_set_message("Looking up IP address...");
-                let ip = crate::lookup(&addr).await;
+        }
+        OneshotAction::Asn {
+            addr: Some(addr), data, ..
+        } => {
+            let offline = data.apply();
+            progress.set_message("Loading the IP-to-ASN table...");
+            let table = AsnTable::instance().await.categorise(Category::NoInput)?;
 
-                match ip {
-                    Ok(country) => info!("{addr} => {}", country.iso_short_name()),
+            match table.lookup(&addr) {
+                Some(range) => {
+                    info!("{addr} => AS{} {}", range.asn, range.description);
+
+                    progress.set_message("Loading ASN records...");
+                    if let Some(block) = asn::find(range.asn, offline).await? {
+                        info!("AS{} => {}", range.asn, describe_block(&block));
+                    }
+                }
+                None => warn!("{addr} => Not announced by any ASN in the table"),
+            }
+        }
+        OneshotAction::Asn { .. } => unreachable!("Clap requires a number, country or address"),
+        OneshotAction::Export {
+            countries,
+            exclude,
+            ipv4,
+            ipv6,
+            output,
+            name,
+            target,
+            data,
+        } => {
+            let offline = data.apply();
+            let parse = |countries: Vec<String>| {
+                countries
+                    .iter()
+                    .map(|country| crate::parse_country(country))
+                    .collect::<Result<Vec<_>, _>>()
+                    .categorise(Category::Usage)
+            };
+            let countries = export::select(parse(countries)?, &parse(exclude)?);
+
+            progress.set_message("Collecting IP Records...");
+            let records = export::records(&countries, offline).await?;
+            if records.is_empty() {
+                warn!("No ranges found to export");
+            }
+
+            let stdout = std::io::BufWriter::new(std::io::stdout().lock());
+            let written =
+                export::write(&records, output, &name, &target, !ipv6, !ipv4, stdout).categorise(Category::Io)?;
+            debug!("Exported {written} CIDRs for {} countries", countries.len());
+        }
+        OneshotAction::Serve {
+            listen,
+            reload_interval,
+            data,
+        } => {
+            let offline = data.apply();
+
+            progress.set_message("Loading IP Records...");
+            let service = Service::load(Cache::open()?, offline).await?;
+            let listener = TcpListener::bind(listen)
+                .await
+                .with_context(|| format!("Failed to listen on {listen}"))
+                .categorise(Category::Io)?;
+            progress.finish();
+
+            let shutdown = async {
+                let _ = tokio::signal::ctrl_c().await;
+                info!("Shutting down");
+            };
+            Arc::new(service)
+                .serve(listener, Duration::from_secs(reload_interval), shutdown)
+                .await?;
+        }
+        OneshotAction::Update { sources, check } => {
+            let cache = Cache::open()?;
+            let sources = match sources.is_empty() {
+                true => Source::all(),
+                false => sources,
+            };
+
+            let total = sources.len();
+            let mut failed = 0;
+            for source in sources {
+                if check {
+                    match cache.metadata(source)? {
+                        Some(metadata) => info!("{source} => {}", describe(source, &metadata)),
+                        None => warn!("{source} => Not cached"),
+                    }
+                    continue;
+                }
+
+                progress.set_message(&format!("Updating {source}..."));
+                match cache.update(source).await {
+                    Ok(metadata) => info!("{source} => Updated, {} bytes", metadata.size),
                     Err(err) => {
-                        error!("Failed to lookup IP address: {err}");
-                        info!("{addr} => Unknown")
+                        error!("{source} => {err:#}");
+                        failed += 1;
                     }
                 }
             }
+
+            if failed > 0 {
+                let category = match failed == total {
+                    true => Category::Network,
+                    false => Category::Partial,
+                };
+
+                Err::<(), _>(anyhow::anyhow!("Failed to update {failed} of {total} sources")).categorise(category)?;
+            }
         }
+        OneshotAction::Import { source, file } => {
+            let metadata = Cache::open()?.import(source, &file)?;
+            info!("{source} => Imported {} bytes from {}", metadata.size, metadata.origin);
+        }
+    }
+
+    Ok(())
+}
+
+fn describe_block(block: &AsnBlock) -> String {
+    let mut description = format!(
+        "{} in {block}, {} by {}",
+        block.alpha.to_country().iso_short_name(),
+        block.status,
+        block.registry.name()
+    );
 
-        Ok(())
+    if !block.date.is_empty() {
+        description.push_str(&format!(" on {}", block.date));
     }
+
+    description
+}
+
+fn describe(source: Source, metadata: &Metadata) -> String {
+    let freshness = match metadata.is_fresh(source) {
+        true => "fresh",
+        false => "stale",
+    };
+
+    format!(
+        "Fetched {} from {} ({freshness}, {} days old)",
+        metadata.fetched.format("%Y-%m-%d %H:%M"),
+        metadata.origin,
+        metadata.age().num_days()
+    )
 }
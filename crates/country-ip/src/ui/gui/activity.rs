@@ -0,0 +1,62 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use amt_lib::progress::{self, ChannelProgress, Event, Kind};
+use iced::time::{self, Duration};
+use iced::Subscription;
+use std::sync::mpsc::Receiver;
+
+/// How often the events of a running task are read.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What a task running in the background is doing, as reported through a [`ChannelProgress`].
+#[derive(Debug)]
+pub struct Activity {
+    events: Receiver<Event>,
+    message: Option<String>,
+}
+
+impl Activity {
+    /// Start tracking a new task, the returned reporter should be moved into it.
+    pub fn start() -> (Self, ChannelProgress) {
+        let (progress, events) = progress::channel(Kind::Spinner);
+        let activity = Self { events, message: None };
+
+        (activity, progress)
+    }
+
+    /// The latest message of any of the task's steps.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Reads every event sent since the last poll.
+    pub fn poll(&mut self) {
+        for event in self.events.try_iter() {
+            if let Event::Message { message, .. } = event {
+                self.message = Some(message);
+            }
+        }
+    }
+
+    /// Ticks while there is an activity to poll.
+    pub fn subscription(activity: Option<&Self>) -> Subscription<()> {
+        match activity {
+            Some(_) => time::every(POLL_INTERVAL).map(|_| ()),
+            None => Subscription::none(),
+        }
+    }
+}
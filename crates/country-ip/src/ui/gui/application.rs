@@ -14,143 +14,229 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::gui::pages::generate::{GenerateMessage, GeneratePage};
-use crate::gui::pages::lookup::{LookupMessage, LookupPage};
-use anyhow::Error;
-use iced::{
-    widget::{button, column, pick_list, row, text, Container},
-    Command, Element, Length, Renderer, Theme,
-};
-use keshvar::{find_by_iso_short_name, Alpha2, Country, CountryIterator};
-use std::borrow::Cow;
+use crate::cache::{Cache, Metadata, Source};
+use crate::ui::gui::page::Page;
+use crate::ui::gui::pages::generate::{GenerateMessage, GeneratePage};
+use crate::ui::gui::pages::lookup::{LookupMessage, LookupPage};
+use iced::widget::{button, column, container, horizontal_rule, row, text};
+use iced::{Element, Length, Subscription, Task, Theme};
+use keshvar::{Alpha2, Alpha3, CountryIterator};
 use std::fmt::{Display, Formatter};
-use std::future::IntoFuture;
-use std::iter::IntoIterator;
-use std::net::IpAddr;
 use std::sync::LazyLock;
 use tracing::error;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct CountryIPApp {
-    selected_country: Option<WrappedCountry>,
-    shown_addr: Option<IpAddr>,
+    tab: Tab,
+    generate: GeneratePage,
+    lookup: LookupPage,
 
-    page: usize,
-    pages: Vec<Page>,
-
-    error: Option<Error>,
+    /// The cached snapshot of each source, if there is one.
+    freshness: Vec<(Source, Option<Metadata>)>,
 }
 
-#[derive(Debug, Clone)]
-pub enum Page {
-    Lookup(LookupPage),
-    Generate(GeneratePage),
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    #[default]
+    Generate,
+    Lookup,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
-    Error(String),
-    ChangeTab(usize),
+    ChangeTab(Tab),
 
-    Lookup(LookupMessage),
     Generate(GenerateMessage),
+    Lookup(LookupMessage),
 }
 
-impl iced::Application for CountryIPApp {
-    type Executor = iced::executor::Default;
-    type Message = Message;
-    type Theme = Theme;
-    type Flags = ();
-
-    fn new(_flags: Self::Flags) -> (Self, Command<Self::Message>) {
-        // TODO - load data?
-        let instance = Self {
-            selected_country: None,
-            shown_addr: None,
-            page: 0,
-            pages: vec![Page::Generate(GeneratePage::default()), Page::Lookup(LookupPage::default())],
-            error: None,
-        };
-        let command = Command::none();
+impl CountryIPApp {
+    pub fn new() -> (Self, Task<Message>) {
+        let mut instance = Self::default();
+        instance.refresh();
 
-        (instance, command)
+        (instance, Task::none())
     }
 
-    fn title(&self) -> String {
-        self.pages[self.page].title()
-    }
+    pub fn title(&self) -> String {
+        let title = match self.tab {
+            Tab::Generate => self.generate.title(),
+            Tab::Lookup => self.lookup.title(),
+        };
 
-    fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
-        use std::future::IntoFuture;
+        format!("{title} - Country IP")
+    }
 
+    pub fn update(&mut self, message: Message) -> Task<Message> {
         match message {
-            Message::ChangeTab(page) => {
-                self.page = page;
-                Command::none()
+            Message::ChangeTab(tab) => {
+                self.tab = tab;
+                Task::none()
             }
-            Message::Error(err) => {
-                error!("Error: {}", err);
-                // TODO - show error
-                Command::none()
+            Message::Generate(message) => {
+                // Generating may have downloaded a fresh snapshot.
+                if let GenerateMessage::Generated(_) = message {
+                    self.refresh();
+                }
+
+                self.generate.update(message).map(Message::Generate)
+            }
+            Message::Lookup(message) => {
+                if let LookupMessage::Result(_) = message {
+                    self.refresh();
+                }
+
+                self.lookup.update(message).map(Message::Lookup)
             }
-            Message::Lookup(_) => {}
-            Message::Generate(_) => {}
         }
     }
 
-    fn view(&self) -> Element<'_, Self::Message, Renderer<Self::Theme>> {
-        let content = match &self.pages[self.page] {
-            Page::Lookup(page) => page.view(),
-            Page::Generate(page) => page.view(),
+    pub fn view(&self) -> Element<'_, Message> {
+        let tab = |label, tab| {
+            button(text(label))
+                .style(match self.tab == tab {
+                    true => button::primary,
+                    false => button::secondary,
+                })
+                .on_press(Message::ChangeTab(tab))
         };
 
-        Container::new(content)
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .center_x()
-            .center_y()
-            .into()
+        let page = match self.tab {
+            Tab::Generate => self.generate.view().map(Message::Generate),
+            Tab::Lookup => self.lookup.view().map(Message::Lookup),
+        };
+
+        column![
+            row![tab("Generate", Tab::Generate), tab("Lookup", Tab::Lookup)].spacing(5),
+            container(page).width(Length::Fill).height(Length::Fill),
+            horizontal_rule(1),
+            self.status_bar(),
+        ]
+        .spacing(10)
+        .padding(10)
+        .into()
+    }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        Subscription::batch([
+            self.generate.subscription().map(Message::Generate),
+            self.lookup.subscription().map(Message::Lookup),
+        ])
     }
 
-    fn theme(&self) -> Self::Theme {
+    pub fn theme(&self) -> Theme {
         Theme::Dark
     }
-}
 
-pub(crate) const WRAPPED_COUNTRIES: LazyLock<Vec<WrappedCountry>> =
-    LazyLock::new(|| CountryIterator::new().into_iter().map(WrappedCountry).collect());
+    /// Shows how old the snapshot of each source is.
+    fn status_bar(&self) -> Element<'_, Message> {
+        let sources = self.freshness.iter().map(|(source, metadata)| {
+            let status = match metadata {
+                None => "not cached".to_string(),
+                Some(metadata) => format!(
+                    "{} days old{}",
+                    metadata.age().num_days(),
+                    match metadata.is_fresh(*source) {
+                        true => "",
+                        false => ", stale",
+                    }
+                ),
+            };
+
+            text(format!("{source}: {status}")).size(12).into()
+        });
+
+        row(sources).spacing(15).wrap().into()
+    }
 
-#[derive(Debug, Clone)]
-pub struct WrappedCountry(pub Country);
+    fn refresh(&mut self) {
+        let cache = match Cache::open() {
+            Ok(cache) => cache,
+            Err(err) => {
+                error!("Unable to open the cache: {err:#}");
+                return;
+            }
+        };
 
-impl PartialEq<Self> for WrappedCountry {
-    fn eq(&self, other: &Self) -> bool {
-        self.0.country_code() == other.0.country_code()
+        self.freshness = Source::all()
+            .into_iter()
+            .map(|source| {
+                let metadata = cache.metadata(source).unwrap_or_else(|err| {
+                    error!("Unable to read the metadata for {source}: {err:#}");
+                    None
+                });
+
+                (source, metadata)
+            })
+            .collect();
     }
 }
 
-impl Eq for WrappedCountry {}
+/// Every country sorted by name, as shown in the picker.
+pub(crate) static COUNTRIES: LazyLock<Vec<CountryEntry>> = LazyLock::new(|| {
+    let mut countries = CountryIterator::new()
+        .map(|country| CountryEntry {
+            alpha2: country.alpha2(),
+            alpha3: country.alpha3(),
+            name: country.iso_short_name().to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    countries.sort_by(|a, b| a.name.cmp(&b.name));
+    countries
+});
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountryEntry {
+    pub alpha2: Alpha2,
+    pub alpha3: Alpha3,
+    pub name: String,
+}
 
-impl Display for WrappedCountry {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0.iso_short_name())
+impl CountryEntry {
+    /// Whether the country's name contains the query or either of its codes are the query, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim();
+        query.is_empty()
+            || self.name.to_lowercase().contains(&query.to_lowercase())
+            || self.alpha2.to_string().eq_ignore_ascii_case(query)
+            || self.alpha3.to_string().eq_ignore_ascii_case(query)
     }
 }
 
-impl Into<WrappedCountry> for String {
-    fn into(self) -> WrappedCountry {
-        WrappedCountry(find_by_iso_short_name(&self).unwrap())
+impl Display for CountryEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.alpha2)
     }
 }
 
-impl From<Alpha2> for WrappedCountry {
-    fn from(value: Alpha2) -> Self {
-        WrappedCountry(value.to_country())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(alpha2: Alpha2) -> CountryEntry {
+        COUNTRIES.iter().find(|entry| entry.alpha2 == alpha2).unwrap().clone()
+    }
+
+    #[test]
+    fn test_matches() {
+        let nz = entry(Alpha2::NZ);
+
+        assert!(nz.matches(""));
+        assert!(nz.matches("  "));
+        assert!(nz.matches("zealand"));
+        assert!(nz.matches("New Zea"));
+        assert!(nz.matches("nz"));
+        assert!(nz.matches(" NZL "));
+
+        assert!(!nz.matches("australia"));
+        assert!(!nz.matches("au"));
+        assert!(!nz.matches("nzla"));
     }
-}
 
-impl Into<Cow<'_, WrappedCountry>> for WrappedCountry {
-    fn into(self) -> Cow<'static, WrappedCountry> {
-        Cow::Owned(self)
+    #[test]
+    fn test_countries_are_sorted() {
+        assert!(COUNTRIES.windows(2).all(|pair| pair[0].name <= pair[1].name));
+        assert!(COUNTRIES.iter().all(|entry| entry.matches(&entry.alpha3.to_string())));
     }
 }
@@ -14,6 +14,18 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+pub mod activity;
 pub mod application;
 pub mod page;
 pub mod pages;
+
+use application::CountryIPApp;
+
+/// Opens the window and blocks until it's closed.
+pub fn run() -> iced::Result {
+    iced::application(CountryIPApp::title, CountryIPApp::update, CountryIPApp::view)
+        .theme(CountryIPApp::theme)
+        .subscription(CountryIPApp::subscription)
+        .window_size((640.0, 560.0))
+        .run_with(CountryIPApp::new)
+}
@@ -14,16 +14,18 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use iced::application::StyleSheet;
-use iced::widget::Container;
-use iced::{Application, Command, Renderer as R};
+use iced::{Element, Subscription, Task};
 
-pub trait Page<'a, Message, Renderer = R> {
+pub trait Page {
     type Message;
 
     fn title(&self) -> String;
 
-    fn view<C: <M>, M>(&mut self) -> C;
+    fn view(&self) -> Element<'_, Self::Message>;
 
-    fn update(&mut self, message: Self::Message) -> Command<Self::Message>;
+    fn update(&mut self, message: Self::Message) -> Task<Self::Message>;
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        Subscription::none()
+    }
 }
@@ -14,30 +14,36 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::gui::application::{Message, WrappedCountry, WRAPPED_COUNTRIES};
-use crate::gui::page::Page;
-use iced::application::StyleSheet;
-use iced::widget::{button, column, pick_list, row, text, Container};
-use iced::{Command, Renderer};
-use std::future::IntoFuture;
+use crate::generate::{self, Options};
+use crate::ui::gui::activity::Activity;
+use crate::ui::gui::application::COUNTRIES;
+use crate::ui::gui::page::Page;
+use amt_lib::progress::Progress;
+use iced::widget::{button, column, row, scrollable, text, text_input, toggler};
+use iced::{Element, Length, Subscription, Task};
+use keshvar::Alpha2;
 use std::net::IpAddr;
-use tracing::error;
 
 #[derive(Debug, Clone)]
 pub enum GenerateMessage {
-    UpdateCountry(WrappedCountry),
-    UpdateAddr(IpAddr),
+    Search(String),
+    Select(Alpha2),
+    UseIpv6(bool),
 
-    GenerateIpV4,
-    GenerateIpV6,
-
-    None,
+    Generate,
+    Progress,
+    Generated(Result<IpAddr, String>),
+    Copy,
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug)]
 pub struct GeneratePage {
-    pub addr: Option<IpAddr>,
-    pub country: Option<WrappedCountry>,
+    search: String,
+    country: Option<Alpha2>,
+    ipv6: bool,
+
+    generating: Option<Activity>,
+    result: Option<Result<IpAddr, String>>,
 }
 
 impl Page for GeneratePage {
@@ -47,104 +53,116 @@ impl Page for GeneratePage {
         "Generate IP".to_string()
     }
 
-    fn view<C: Container<M>, M>(&mut self) -> C {
-        let countries = WRAPPED_COUNTRIES.clone();
-
-        let content = row![
-            column![
-                text("Country").size(12),
-                pick_list(countries, self.country.clone(), GenerateMessage::UpdateCountry),
-            ],
-            "",
-            "",
-            column![
-                text("Generated Address").size(12),
-                text(&self.addr.map(|addr| addr.to_string()).unwrap_or_else(|| "None".to_string())).size(12),
-            ],
-            column![
-                button(text("Generate IPv4"))
-                    .on_press(GenerateMessage::GenerateIpV4)
-                    .padding(10),
-                button(text("Generate IPv6"))
-                    .on_press(GenerateMessage::GenerateIpV6)
-                    .padding(10),
-            ],
-        ];
-
-        Container::new(content)
+    fn view(&self) -> Element<'_, Self::Message> {
+        let countries = COUNTRIES.iter().filter(|country| country.matches(&self.search)).map(|country| {
+            button(text(country.to_string()))
+                .width(Length::Fill)
+                .style(match self.country == Some(country.alpha2) {
+                    true => button::primary,
+                    false => button::text,
+                })
+                .on_press(GenerateMessage::Select(country.alpha2))
+                .into()
+        });
+
+        let result = match &self.result {
+            None => text("None"),
+            Some(Ok(addr)) => text(addr.to_string()),
+            Some(Err(err)) => text(err).style(text::danger),
+        };
+        let copy = match &self.result {
+            Some(Ok(_)) => Some(GenerateMessage::Copy),
+            _ => None,
+        };
+        let generate = match self.country.is_some() && self.generating.is_none() {
+            true => Some(GenerateMessage::Generate),
+            false => None,
+        };
+
+        column![
+            text("Country").size(12),
+            text_input("Search by name or code", &self.search).on_input(GenerateMessage::Search),
+            scrollable(column(countries)).height(Length::Fill),
+            toggler(self.ipv6).label("IPv6").on_toggle(GenerateMessage::UseIpv6),
+            row![
+                button(text(match &self.generating {
+                    Some(activity) => activity.message().unwrap_or("Generating..."),
+                    None => "Generate",
+                }))
+                .on_press_maybe(generate),
+                button(text("Copy")).on_press_maybe(copy),
+            ]
+            .spacing(5),
+            text("Generated Address").size(12),
+            result,
+        ]
+        .spacing(5)
+        .into()
     }
 
-    fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
+    fn update(&mut self, message: Self::Message) -> Task<Self::Message> {
         match message {
-            GenerateMessage::None => Command::none(),
-            GenerateMessage::UpdateCountry(country) => {
+            GenerateMessage::Search(search) => {
+                self.search = search;
+                Task::none()
+            }
+            GenerateMessage::Select(country) => {
                 self.country = Some(country);
-                Command::none()
+                Task::none()
             }
-            GenerateMessage::UpdateAddr(addr) => {
-                self.addr = Some(addr);
-                Command::none()
+            GenerateMessage::UseIpv6(ipv6) => {
+                self.ipv6 = ipv6;
+                Task::none()
             }
-            GenerateMessage::GenerateIpV4 => {
-                let country = match &self.country {
-                    Some(country) => country.clone(),
-                    None => {
-                        error!("No country selected");
-                        return Command::none();
-                    }
+            GenerateMessage::Generate => {
+                let Some(country) = self.country else {
+                    return Task::none();
                 };
-                let alpha = country.0.alpha2();
 
-                Command::perform(
+                let ipv6 = self.ipv6;
+                let (activity, progress) = Activity::start();
+                self.generating = Some(activity);
+                Task::perform(
                     async move {
-                        match crate::get_record_db(&country.0).await {
-                            Ok(db) => db
-                                .random_ipv4(&alpha)
-                                .await
-                                .ok_or_else(|| anyhow::Error::msg("No IPv4 addresses found for country")),
-                            Err(err) => Err(err),
-                        }
-                    }
-                    .into_future(),
-                    |result| match result {
-                        Err(err) => {
-                            error!("{}", err.to_string());
-                            GenerateMessage::None
+                        let options = Options {
+                            count: 1,
+                            ipv6,
+                            ..Default::default()
+                        };
+
+                        progress.set_message("Getting IP Records...");
+                        let result =
+                            crate::generate(&country.to_country(), &options, &mut generate::rng(None), false).await;
+                        progress.finish();
+
+                        match result {
+                            Ok(addresses) => addresses.first().copied().ok_or_else(|| "No address generated".into()),
+                            Err(err) => Err(format!("{err:#}")),
                         }
-                        Ok(addr) => GenerateMessage::UpdateAddr(addr),
                     },
+                    GenerateMessage::Generated,
                 )
             }
-            GenerateMessage::GenerateIpV6 => {
-                let country = match &self.country {
-                    Some(country) => country.clone(),
-                    None => {
-                        error!("No country selected");
-                        return Command::none();
-                    }
-                };
-                let alpha = country.0.alpha2();
+            GenerateMessage::Progress => {
+                if let Some(activity) = &mut self.generating {
+                    activity.poll();
+                }
 
-                Command::perform(
-                    async move {
-                        match crate::get_record_db(&country.0).await {
-                            Ok(db) => db
-                                .random_ipv6(&alpha)
-                                .await
-                                .ok_or_else(|| anyhow::Error::msg("No IPv6 addresses found for country")),
-                            Err(err) => Err(err),
-                        }
-                    }
-                    .into_future(),
-                    |result| match result {
-                        Ok(addr) => GenerateMessage::UpdateAddr(addr),
-                        Err(err) => {
-                            error!("Error generating IPv6 address: {}", err);
-                            GenerateMessage::None
-                        }
-                    },
-                )
+                Task::none()
+            }
+            GenerateMessage::Generated(result) => {
+                self.generating = None;
+                self.result = Some(result);
+                Task::none()
             }
+            GenerateMessage::Copy => match &self.result {
+                Some(Ok(addr)) => iced::clipboard::write(addr.to_string()),
+                _ => Task::none(),
+            },
         }
     }
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        Activity::subscription(self.generating.as_ref()).map(|()| GenerateMessage::Progress)
+    }
 }
@@ -14,31 +14,34 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::db_ip;
-use crate::gui::application::WrappedCountry;
-use crate::gui::page::Page;
-use iced::application::StyleSheet;
-use iced::widget::{button, column, text, text_input, Container};
-use iced::{Command, Renderer};
+use crate::ui::gui::activity::Activity;
+use crate::ui::gui::page::Page;
+use amt_lib::progress::Progress;
+use iced::widget::{button, column, row, text, text_input};
+use iced::{Element, Subscription, Task};
 use keshvar::Alpha2;
-use std::future::IntoFuture;
 use std::net::IpAddr;
 
 #[derive(Debug, Clone)]
 pub enum LookupMessage {
-    Result(Option<WrappedCountry>),
     InputChanged(String),
     Lookup,
+    Progress,
+    Result(Result<Alpha2, String>),
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug)]
 pub struct LookupPage {
-    pub addr: Option<IpAddr>,
+    input: String,
 
-    pub input: String,
-    pub valid: bool,
+    looking_up: Option<Activity>,
+    result: Option<Result<Alpha2, String>>,
+}
 
-    pub result: Option<WrappedCountry>,
+impl LookupPage {
+    fn addr(&self) -> Option<IpAddr> {
+        self.input.trim().parse().ok()
+    }
 }
 
 impl Page for LookupPage {
@@ -48,45 +51,80 @@ impl Page for LookupPage {
         "IP Lookup".to_string()
     }
 
-    fn view<T>(&mut self) -> Container<'_, Self::Message, T> {
-        let content = column![
+    fn view(&self) -> Element<'_, Self::Message> {
+        let lookup = match self.addr().is_some() && self.looking_up.is_none() {
+            true => Some(LookupMessage::Lookup),
+            false => None,
+        };
+
+        let result = match &self.result {
+            None => text("None"),
+            Some(Ok(alpha)) => text(format!("{} ({alpha})", alpha.to_country().iso_short_name())),
+            Some(Err(err)) => text(err).style(text::danger),
+        };
+
+        column![
             text("IP Address").size(12),
-            text_input(&mut self.input, "IP Address", LookupMessage::InputChanged),
-            button("Lookup").on_press(LookupMessage::Lookup),
+            row![
+                text_input("IP Address", &self.input)
+                    .on_input(LookupMessage::InputChanged)
+                    .on_submit(LookupMessage::Lookup),
+                button(text(match &self.looking_up {
+                    Some(activity) => activity.message().unwrap_or("Looking up..."),
+                    None => "Lookup",
+                }))
+                .on_press_maybe(lookup),
+            ]
+            .spacing(5),
             text("Result").size(12),
-            text(&self.result.map(|country| country.0.iso_short_name()).unwrap_or_else(|| "None")).size(12),
-        ];
-
-        Container::new(content)
+            result,
+        ]
+        .spacing(5)
+        .into()
     }
 
-    fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
+    fn update(&mut self, message: Self::Message) -> Task<Self::Message> {
         match message {
-            LookupMessage::Result(result) => {
-                self.result = result;
-                Command::none()
+            LookupMessage::InputChanged(input) => {
+                self.input = input;
+                Task::none()
             }
+            LookupMessage::Lookup if self.looking_up.is_some() => Task::none(),
             LookupMessage::Lookup => {
-                let db = db_ip::DB::instance();
-                let addr = match &self.addr {
-                    Some(addr) => addr.clone(),
-                    None => return Command::none(),
+                let Some(addr) = self.addr() else {
+                    self.result = Some(Err(format!("{} isn't a valid IP address", self.input.trim())));
+                    return Task::none();
                 };
 
-                Command::perform(
-                    async move { db.lookup(&*addr).await }.into_future(),
-                    |result: Option<Alpha2>| LookupMessage::Result(result.map(WrappedCountry::from)),
+                let (activity, progress) = Activity::start();
+                self.looking_up = Some(activity);
+                Task::perform(
+                    async move {
+                        progress.set_message("Looking up IP address...");
+                        let result = crate::lookup(&addr, false).await;
+                        progress.finish();
+
+                        result.map(|country| country.alpha2()).map_err(|err| format!("{err:#}"))
+                    },
+                    LookupMessage::Result,
                 )
             }
-            LookupMessage::InputChanged(input) => {
-                self.input = input;
-                match input.parse::<IpAddr>() {
-                    Ok(_) => self.valid = true,
-                    Err(_) => self.valid = false,
-                };
+            LookupMessage::Progress => {
+                if let Some(activity) = &mut self.looking_up {
+                    activity.poll();
+                }
 
-                Command::none()
+                Task::none()
+            }
+            LookupMessage::Result(result) => {
+                self.looking_up = None;
+                self.result = Some(result);
+                Task::none()
             }
         }
     }
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        Activity::subscription(self.looking_up.as_ref()).map(|()| LookupMessage::Progress)
+    }
 }
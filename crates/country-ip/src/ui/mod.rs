@@ -16,5 +16,5 @@
 
 #[cfg(feature = "ui-cli")]
 pub mod cli;
-// #[cfg(feature = "ui-gui")]
-// pub mod gui;
+#[cfg(feature = "ui-gui")]
+pub mod gui;
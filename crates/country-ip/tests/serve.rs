@@ -0,0 +1,148 @@
+/*
+ * Copyright (C) 2023 James Draycott <me@racci.dev>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use anyhow::Result;
+use country_ip::cache::{Cache, Source};
+use country_ip::registry::Registry;
+use country_ip::serve::Service;
+use serde_json::Value;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+const FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/delegated-apnic-extended-latest"
+);
+const APNIC: Source = Source::Registry(Registry::Apnic);
+
+struct Server {
+    address: SocketAddr,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl Server {
+    async fn start(cache: Cache) -> Result<Self> {
+        let service = Arc::new(Service::load(cache, true).await?);
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        tokio::spawn(service.serve(listener, Duration::from_millis(50), async {
+            let _ = stopped.await;
+        }));
+
+        Ok(Self {
+            address,
+            _shutdown: shutdown,
+        })
+    }
+
+    async fn get(&self, path: &str) -> Result<(u16, Value)> {
+        let response = reqwest::get(format!("http://{}{path}", self.address)).await?;
+        let status = response.status().as_u16();
+        Ok((status, serde_json::from_str(&response.text().await?)?))
+    }
+}
+
+fn cache(directory: &Path) -> Result<Cache> {
+    let cache = Cache::new(directory);
+    cache.import(APNIC, Path::new(FIXTURE))?;
+    Ok(cache)
+}
+
+#[test_log::test(tokio::test)]
+async fn test_lookup() -> Result<()> {
+    let directory = tempfile::tempdir()?;
+    let server = Server::start(cache(directory.path())?).await?;
+
+    let (status, body) = server.get("/lookup/133.1.2.3").await?;
+    assert_eq!(status, 200);
+    assert_eq!(body["country"], "JP");
+    assert_eq!(body["registry"], "apnic");
+    assert_eq!(body["status"], "allocated");
+    assert_eq!(body["range_start"], "133.0.0.0");
+
+    let (status, body) = server.get("/lookup/2001:200::1").await?;
+    assert_eq!((status, &body["country"]), (200, &Value::from("JP")));
+
+    assert_eq!(server.get("/lookup/10.0.0.1").await?.0, 404);
+    assert_eq!(server.get("/lookup/not-an-ip").await?.0, 400);
+    assert_eq!(server.get("/nothing/here").await?.0, 404);
+
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn test_random() -> Result<()> {
+    let directory = tempfile::tempdir()?;
+    let server = Server::start(cache(directory.path())?).await?;
+
+    let (status, body) = server.get("/random/au").await?;
+    assert_eq!(status, 200);
+    assert_eq!(body["country"], "AU");
+    let ip = body["ip"].as_str().unwrap().parse::<IpAddr>()?;
+    assert!(ip.is_ipv4());
+
+    let (status, body) = server.get("/random/AUS?ipv6=true").await?;
+    assert_eq!(status, 200);
+    assert!(body["ip"].as_str().unwrap().starts_with("2001:360:"));
+
+    assert_eq!(server.get("/random/xx").await?.0, 400);
+
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn test_ranges() -> Result<()> {
+    let directory = tempfile::tempdir()?;
+    let server = Server::start(cache(directory.path())?).await?;
+
+    let (status, body) = server.get("/ranges/jp").await?;
+    assert_eq!(status, 200);
+    assert_eq!(body["ipv4"], serde_json::json!(["133.0.0.0/8"]));
+    assert_eq!(body["ipv6"], serde_json::json!(["2001:200::/35"]));
+
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn test_reload() -> Result<()> {
+    let directory = tempfile::tempdir()?;
+    let cache = cache(directory.path())?;
+    let server = Server::start(cache.clone()).await?;
+    assert_eq!(server.get("/lookup/202.36.1.1").await?.1["country"], "NZ");
+
+    let updated = directory.path().join("updated");
+    let fixture = std::fs::read_to_string(FIXTURE)?;
+    std::fs::write(
+        &updated,
+        fixture.replace("apnic|NZ|ipv4|202.36.0.0", "apnic|AU|ipv4|202.36.0.0"),
+    )?;
+    cache.import(APNIC, &updated)?;
+
+    for _ in 0..100 {
+        if server.get("/lookup/202.36.1.1").await?.1["country"] == "AU" {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    panic!("The updated cache was never reloaded");
+}
@@ -19,6 +19,7 @@ use crate::endpoints::nable::endpoints::{HostInfo, NSightEndpoint};
 use crate::endpoints::nable::structs::site::Site;
 use crate::endpoints::nable::structs::template::Template;
 use crate::endpoints::nable::NSightApiKey;
+use amt_lib::secret::Secret;
 use anyhow::Result;
 use reqwest::{Client, ClientBuilder, RequestBuilder};
 use std::cell::LazyCell;
@@ -34,8 +35,7 @@ pub struct Driver {
 
     endpoint: NSightEndpoint,
 
-    // TODO :: Securely store these.
-    password: String,
+    password: Secret<String>,
 }
 
 #[derive(Debug, Error)]
@@ -56,7 +56,9 @@ impl Driver {
     }
 
     pub async fn new<S: AsRef<str>>(endpoint: S) -> Result<Self> {
-        use inquire::{Password, Text};
+        use amt_lib::ui::cli::prompt_secret;
+        use inquire::validator::Validation;
+        use inquire::Text;
 
         // TODO :: OS and installed browser detection.
         let driver_capabilities = DesiredCapabilities::firefox();
@@ -79,8 +81,14 @@ impl Driver {
         let input_email = Text::new("Please enter your email: ").prompt()?;
         input_next(&driver, "email", &input_email, By::ClassName("login-next-button")).await?;
 
-        let input_password = Password::new("Please enter your password: ").without_confirmation().prompt()?;
-        input_next(&driver, "password", &input_password, By::ClassName("login-next-button")).await?;
+        let input_password = prompt_secret("Please enter your password: ", |_: &_| Ok(Validation::Valid))?;
+        input_next(
+            &driver,
+            "password",
+            input_password.expose(),
+            By::ClassName("login-next-button"),
+        )
+        .await?;
 
         let input_2fa = Text::new("Please enter your 2FA code: ").prompt()?;
         input_next(&driver, "code", &input_2fa, By::Id("verify-submit")).await?;
@@ -126,7 +134,7 @@ impl Driver {
             ("action", "createAction"),
             ("dashaction", "24"),
             ("data", "33455"),
-            ("password", &self.password),
+            ("password", self.password.expose()),
             ("confirmed", "false"),
             ("applyMonitoringTemplateFromEntityTree", "true"),
             ("siteid", "193840"),
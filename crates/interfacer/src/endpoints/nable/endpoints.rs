@@ -81,7 +81,19 @@ pub enum Response {
 #[derive(Debug, Parser)]
 pub struct HostInfo {
     pub endpoint: String,
-    pub api_key: NSightApiKey,
+
+    /// The API key, once given it's kept in the secret store and can be left out.
+    #[arg(long, env = "NSIGHT_API_KEY", hide_env_values = true)]
+    pub api_key: Option<NSightApiKey>,
+}
+
+impl HostInfo {
+    /// The API key, which must have been loaded with [`NSightApiKey::load`] if it wasn't given.
+    pub fn key(&self) -> anyhow::Result<&NSightApiKey> {
+        self.api_key
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No API key for {}", self.endpoint))
+    }
 }
 
 impl Endpoint for NSightEndpoint {
@@ -191,7 +203,7 @@ mod macros {
                 let url = format!(
                     "https://{endpoint}/api/?apikey={api_key}&service={service}{params}",
                     endpoint = host_info.endpoint,
-                    api_key = host_info.api_key.0,
+                    api_key = host_info.key()?.0.expose(),
                     service = stringify!($service),
                     params = {
                         let mut params = String::new();
@@ -18,11 +18,15 @@ pub mod endpoints;
 pub mod structs;
 pub mod template;
 
+use amt_lib::secret::{open_store, Secret};
+use amt_lib::ui::cli::{env_or_prompt_secret, prompt_secret};
 use anyhow::Result;
+use inquire::validator::Validation;
 use std::str::FromStr;
 
+/// The key used to access the N-sight API, kept out of logs and wiped when dropped.
 #[derive(Debug, Clone)]
-pub struct NSightApiKey(pub String);
+pub struct NSightApiKey(pub Secret<String>);
 
 impl NSightApiKey {
     pub fn new<S: AsRef<str>>(key: S) -> Result<Self> {
@@ -30,12 +34,44 @@ impl NSightApiKey {
             anyhow::bail!("Invalid API key provided.");
         }
 
-        Ok(Self(key.as_ref().to_string()))
+        Ok(Self(Secret::new(key.as_ref().to_string())))
     }
 
     pub fn verify<S: AsRef<str>>(key: S) -> bool {
         key.as_ref().len() == 32
     }
+
+    /// The key for the endpoint, kept in the secret store between runs.
+    ///
+    /// A `given` key replaces the stored one,
+    /// when neither exists the key is prompted for and stored.
+    pub async fn load(endpoint: &str, given: Option<Self>) -> Result<Self> {
+        let passphrase = || {
+            env_or_prompt_secret("INTERFACER_SECRETS_PASSPHRASE", |str: &str| match str.is_empty() {
+                true => Ok(Validation::Invalid("The passphrase cannot be empty".into())),
+                false => Ok(Validation::Valid),
+            })
+        };
+
+        let mut store = open_store(env!("CARGO_PKG_NAME"), passphrase).await?;
+        let name = format!("nsight/{endpoint}");
+        if let Some(key) = given {
+            store.set(&name, &key.0).await?;
+            return Ok(key);
+        }
+
+        if let Some(secret) = store.get(&name).await? {
+            return Self::new(secret.expose());
+        }
+
+        let secret = prompt_secret("N-sight API key", |key: &str| match Self::verify(key) {
+            true => Ok(Validation::Valid),
+            false => Ok(Validation::Invalid("The API key must be 32 characters".into())),
+        })?;
+        store.set(&name, &secret).await?;
+
+        Ok(Self(secret))
+    }
 }
 
 impl FromStr for NSightApiKey {
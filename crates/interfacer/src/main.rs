@@ -18,12 +18,14 @@
 #![feature(lazy_cell)]
 
 use amt_lib::log;
+use amt_lib::ui::cli::error::{finish, Categorise, Category, ExitCode};
 use amt_lib::ui::cli::flags::CommonFlags;
 use anyhow::Result;
 use clap::{Parser, Subcommand, ValueEnum};
 use csv::{Writer, WriterBuilder};
 use interfacer::endpoints::endpoint::Endpoint;
 use interfacer::endpoints::nable::endpoints::{NSightEndpoint, Response};
+use interfacer::endpoints::nable::NSightApiKey;
 use macros::{EnumNames, EnumVariants};
 use serde::{Serialize, Serializer};
 use std::fmt::Debug;
@@ -64,19 +66,29 @@ enum Output {
 }
 
 #[tokio::main(flavor = "multi_thread")]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
+    finish(run().await)
+}
+
+async fn run() -> Result<()> {
     let cli = Cli::parse();
-    let _ = log::init("", cli.flags);
+    let _ = log::init(env!("CARGO_PKG_NAME"), &cli.flags);
 
     match cli.endpoint {
-        Endpoints::Nable { args, request, output } => {
+        Endpoints::Nable {
+            mut args,
+            request,
+            output,
+        } => {
             // use endpoints::nable::driver::Driver;
 
             // let driver = Driver::new().await?;
             // driver.deploy_templates("8319").await?;
 
+            let api_key = NSightApiKey::load(&args.endpoint, args.api_key.take());
+            args.api_key = Some(api_key.await.categorise(Category::Config)?);
             let instance = NSightEndpoint::new(args);
-            let response = instance.handle(request).await?;
+            let response = instance.handle(request).await.categorise(Category::Network)?;
 
             match output {
                 Output::Stdout => {
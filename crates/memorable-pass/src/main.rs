@@ -14,13 +14,18 @@
  * along with this program. If not, see https://www.gnu.org/licenses/.
  */
 
+use amt_lib::ui::cli::error::{finish, ExitCode};
 use amt_lib::ui::cli::CliUi;
 use amt_lib::ui::Ui;
 use anyhow::Result;
 use memorable_pass::ui::cli::ui::MemorablePassCli;
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 8)]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
+    finish(run().await)
+}
+
+async fn run() -> Result<()> {
     let mut cli = MemorablePassCli::new(())?;
     cli.run().await?;
 
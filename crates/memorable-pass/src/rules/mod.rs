@@ -24,6 +24,7 @@ pub mod transformation;
 use crate::rules::addition::digits::DigitAddition;
 use crate::rules::addition::separator::SeparatorAddition;
 use crate::rules::transformation::case::CaseTransformation;
+use amt_lib::config::{Invalid, Layered};
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
@@ -76,3 +77,31 @@ impl Default for Rules {
         }
     }
 }
+
+impl Layered for Rules {
+    const NAME: &'static str = env!("CARGO_PKG_NAME");
+
+    fn validate(&self) -> Result<(), Invalid> {
+        if !(1..10).contains(&self.word_count) {
+            return Err(Invalid::new("word_count", "must be between 1 and 9"));
+        }
+
+        for (key, length) in [
+            ("word_length_min", self.word_length_min),
+            ("word_length_max", self.word_length_max),
+        ] {
+            if !(3..=9).contains(&length) {
+                return Err(Invalid::new(key, "must be between 3 and 9"));
+            }
+        }
+
+        if self.word_length_min > self.word_length_max {
+            return Err(Invalid::new(
+                "word_length_min",
+                "must not be greater than word_length_max",
+            ));
+        }
+
+        Ok(())
+    }
+}
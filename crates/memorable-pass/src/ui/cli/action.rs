@@ -15,12 +15,19 @@
  */
 
 use crate::rules::Rules;
+use amt_lib::config::{CliChanges, ConfigAction};
 use clap::Parser;
 
 #[derive(Debug, Parser)]
 pub enum Action {
     Generate {
         #[command(flatten)]
-        rules: Rules,
+        rules: CliChanges<Rules>,
+    },
+
+    /// Inspect the configuration files the default rules are read from.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
     },
 }
@@ -17,6 +17,8 @@
 use crate::config;
 use crate::rules::Rules;
 use crate::ui::cli::action::Action;
+use amt_lib::config::Loader;
+use amt_lib::ui::cli::error::{Categorise, Category};
 use amt_lib::ui::cli::flags::{CommonFlags, OutputFormat};
 use amt_lib::ui::cli::oneshot::OneshotHandler;
 use amt_lib::ui::cli::{CliResult, CliUi};
@@ -32,7 +34,15 @@ pub struct MemorablePassCli {
     rules: Option<Rules>,
 }
 
-impl CliUi for MemorablePassCli {}
+impl CliUi for MemorablePassCli {
+    #[cfg(feature = "updater")]
+    fn updater(&self) -> Option<amt_lib::updater::Updater> {
+        Some(amt_lib::updater::Updater::new(
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+        ))
+    }
+}
 
 impl OneshotHandler for MemorablePassCli {
     type OneshotAction = Action;
@@ -45,6 +55,13 @@ impl OneshotHandler for MemorablePassCli {
 
         match command {
             Action::Generate { rules } => {
+                // Only the flags which were given override the configuration files.
+                let rules = Loader::<Rules>::new()
+                    .with_cli_changes(&rules)
+                    .and_then(|loader| loader.load())
+                    .categorise(Category::Config)?
+                    .into_inner();
+
                 let passwords = crate::generate(&rules).await;
                 self.rules.replace(rules);
 
@@ -64,6 +81,9 @@ impl OneshotHandler for MemorablePassCli {
                     }
                 }
             }
+            Action::Config { action } => {
+                print!("{}", action.run(&Loader::<Rules>::new()).categorise(Category::Config)?);
+            }
         }
 
         Ok(())
@@ -14,19 +14,25 @@
  * along with this program. If not, see https://www.gnu.org/licenses/.
  */
 
+use amt_lib::ui::cli::error::finish;
 use amt_lib::ui::{cli::CliUi, Ui};
 use anyhow::Result;
 use rebooter::ui::cli::RebooterCli;
-use sysexits::ExitCode;
+use std::process::{ExitCode, ExitStatus};
 
 #[tokio::main]
-async fn main() -> Result<ExitCode> {
-    if let Some(code) = amt_lib::helper::require_elevated_privileges() {
-        return Ok(code);
+async fn main() -> ExitCode {
+    match run().await {
+        // The elevated copy has already reported how it went, so its status is passed on as is.
+        Ok(Some(status)) => ExitCode::from(status.code().and_then(|code| u8::try_from(code).ok()).unwrap_or(1)),
+        result => finish(result.map(|_| ())).into(),
     }
+}
 
+/// Runs the application, dropping it before returning so the log is flushed.
+async fn run() -> Result<Option<ExitStatus>> {
     let mut application = RebooterCli::new(())?;
     application.run().await?;
 
-    Ok(ExitCode::Ok)
+    Ok(application.exit_status())
 }
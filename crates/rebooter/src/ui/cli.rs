@@ -17,18 +17,29 @@
 use crate::schedule::ScheduledReboot;
 use crate::ui::actions::Action;
 use amt_lib::populate;
+use amt_lib::privilege::{escalate, Escalation, Operation};
+use amt_lib::ui::cli::error::{Categorise, Category};
 use amt_lib::ui::cli::flags::CommonFlags;
 use amt_lib::ui::cli::oneshot::OneshotHandler;
 use amt_lib::ui::cli::{CliResult, CliUi};
 use amt_lib::ui::Ui;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{Local, NaiveDateTime, NaiveTime};
-use tracing::{error, info};
+use std::process::ExitStatus;
+use tracing::{error, info, warn};
 use tracing_appender::non_blocking::WorkerGuard;
 
 #[derive(Debug)]
 pub struct RebooterCli {
     _guard: Option<WorkerGuard>,
+    exit_status: Option<ExitStatus>,
+}
+
+impl RebooterCli {
+    /// The status of the elevated copy the action was handed off to, if it was.
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        self.exit_status
+    }
 }
 
 impl Ui for RebooterCli {
@@ -36,7 +47,10 @@ impl Ui for RebooterCli {
     where
         Self: Sized,
     {
-        Ok(Self { _guard: None })
+        Ok(Self {
+            _guard: None,
+            exit_status: None,
+        })
     }
 }
 
@@ -61,11 +75,30 @@ impl OneshotHandler for RebooterCli {
                 }
             }
             Action::QueryAndSchedule { mut reasons } => {
+                let can_schedule = match escalate(&[Operation::Reboot], !flags.no_elevate)
+                    .context("Unable to re-run with elevated privileges")
+                    .categorise(Category::Permission)?
+                {
+                    Escalation::Reexecuted(status) => {
+                        self.exit_status = Some(status);
+                        return Ok(());
+                    }
+                    Escalation::Degraded(_) => false,
+                    Escalation::Granted => true,
+                };
+
                 while let Some(reason) = reasons.pop() {
                     if !reason.valid() {
                         continue;
                     }
 
+                    if !can_schedule {
+                        warn!(
+                            "{reason} would require a restart, but it can't be scheduled without elevated privileges."
+                        );
+                        continue;
+                    }
+
                     let when = get_schedule_time();
                     info!("Scheduling {reason} for {when}.");
 
@@ -93,7 +93,7 @@ pub async fn run_cleaners(runtime: &'static Runtime) -> Vec<CleanupResult> {
     let time = Instant::now();
     let mut results = vec![];
 
-    let mut cleaner_stream = tokio_stream::iter(&runtime.cli.cleaners).map(|cleaner_ref| {
+    let mut cleaner_stream = tokio_stream::iter(&runtime.settings.cleaners).map(|cleaner_ref| {
         let cleaner_ref = *cleaner_ref;
 
         tokio::runtime::Handle::current().clone().spawn(async move {
@@ -21,6 +21,7 @@ use async_trait::async_trait;
 use clap::ValueEnum;
 use macros::{CommonFields, Delegation, EnumNames, EnumVariants};
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use std::io;
 use std::path::PathBuf;
@@ -28,7 +29,10 @@ use thiserror::Error;
 use tokio_stream::StreamExt;
 use tracing::{instrument, trace, warn};
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Delegation, EnumVariants, ValueEnum, EnumNames)]
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Hash, Delegation, EnumVariants, ValueEnum, EnumNames, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 #[delegate(trait = CleanerInternal)]
 pub enum Cleaner {
     #[delegate(path = crate::cleaners::impls::browser::BrowserCleaner)]
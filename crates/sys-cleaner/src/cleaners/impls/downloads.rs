@@ -42,13 +42,21 @@ impl CleanerInternal for DownloadsCleaner {
 
         vec![
             // Windows Update / Prefetch files
-            Location::Globbing(WINDIR.join("Downloaded Program Files/*").to_string_lossy().into()),
-            Location::Globbing(WINDIR.join("SoftwareDistribution/Download/*").to_string_lossy().into()),
-            Location::Globbing(WINDIR.join("Prefetch/*").to_string_lossy().into()),
+            Location::system(Location::Globbing(
+                WINDIR.join("Downloaded Program Files/*").to_string_lossy().into(),
+            )),
+            Location::system(Location::Globbing(
+                WINDIR.join("SoftwareDistribution/Download/*").to_string_lossy().into(),
+            )),
+            Location::system(Location::Globbing(WINDIR.join("Prefetch/*").to_string_lossy().into())),
             // Graphic Drivers
-            Location::Globbing(PROGRAM_DATA.join("NVIDIA Corporation/Downloader/*").to_string_lossy().into()),
+            Location::system(Location::Globbing(
+                PROGRAM_DATA.join("NVIDIA Corporation/Downloader/*").to_string_lossy().into(),
+            )),
             // Package Managers
-            Location::Globbing(SYSTEM_DRIVE.join("NinitePro/NiniteDownloads/Files/*").to_string_lossy().into()),
+            Location::system(Location::Globbing(
+                SYSTEM_DRIVE.join("NinitePro/NiniteDownloads/Files/*").to_string_lossy().into(),
+            )),
         ]
     }
 
@@ -47,10 +47,12 @@ impl CleanerInternal for LogCleaner {
         }
 
         vec![
-            Location::Globbing(str(PROGRAM_DATA.join("NVIDIA/*"))),
-            Location::Globbing(str(PROGRAM_DATA.join("Microsoft/Windows/WER/ReportArchive/*"))),
-            Location::Globbing(str(WINDIR.join("Panther/*"))),
-            Location::Globbing(str(WINDIR.join("Minidump/*"))),
+            Location::system(Location::Globbing(str(PROGRAM_DATA.join("NVIDIA/*")))),
+            Location::system(Location::Globbing(str(
+                PROGRAM_DATA.join("Microsoft/Windows/WER/ReportArchive/*")
+            ))),
+            Location::system(Location::Globbing(str(WINDIR.join("Panther/*")))),
+            Location::system(Location::Globbing(str(WINDIR.join("Minidump/*")))),
             Location::Sub(&USERS, "AppData/Local/CrashDumps/*".into()),
         ]
     }
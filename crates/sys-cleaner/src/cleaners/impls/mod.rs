@@ -15,6 +15,7 @@
  */
 
 use crate::cleaners::location::Location;
+use amt_lib::privilege::{privileges, Operation};
 use cfg_if::cfg_if;
 use std::sync::LazyLock;
 
@@ -28,7 +29,10 @@ pub mod trash;
 
 cfg_if! {
     if #[cfg(unix)] {
-        pub static USERS: LazyLock<Location> = LazyLock::new(|| Location::Globbing("/home/*/".into()));
+        pub static USERS: LazyLock<Location> = LazyLock::new(|| match privileges().allows(Operation::OtherUsersFiles) {
+            true => Location::Globbing("/home/*/".into()),
+            false => Location::Environment("HOME".into()),
+        });
     } else if #[cfg(windows)] {
         use crate::cleaners::env_dir;
         use std::path::PathBuf;
@@ -36,6 +40,10 @@ cfg_if! {
         pub static PROGRAM_DATA: LazyLock<PathBuf> = LazyLock::new(|| env_dir("ProgramData".into()).expect("This is always set on Windows"));
         pub static WINDIR: LazyLock<PathBuf> = LazyLock::new(|| env_dir("windir".into()).expect("This is always set on Windows"));
         pub static USERS: LazyLock<Location> = LazyLock::new(|| {
+            if !privileges().allows(Operation::OtherUsersFiles) {
+                return Location::Environment("USERPROFILE".into());
+            }
+
             let mut path = env_dir("SystemDrive".to_owned()).unwrap();
             path.push("\\Users");
             path.push("*");
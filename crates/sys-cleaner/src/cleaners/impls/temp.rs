@@ -41,7 +41,7 @@ impl CleanerInternal for TempCleaner {
 
         vec![
             Location::Sub(&USERS, format!("{prefix}Temp/*")),
-            Location::Globbing(WINDIR.join("Temp/*").to_string_lossy().into()),
+            Location::system(Location::Globbing(WINDIR.join("Temp/*").to_string_lossy().into())),
         ]
     }
 
@@ -14,6 +14,7 @@
  * along with this program. If not, see https://www.gnu.org/licenses/.
  */
 
+use amt_lib::privilege::{privileges, Operation};
 use glob::Paths;
 use std::collections::HashMap;
 use std::fs;
@@ -35,6 +36,11 @@ pub enum Location {
     ///
     /// This can be used to specify a location which is determined from the contents of another location.
     Sub(&'static Location, String),
+
+    /// A location shared by the whole system.
+    ///
+    /// This is skipped unless the process is able to modify system locations.
+    System(Box<Location>),
 }
 
 impl Location {
@@ -43,11 +49,16 @@ impl Location {
             Location::Environment(var) => environment(var),
             Location::Globbing(pattern) => globbing(pattern).flatten().collect(),
             Location::Sub(location, sub_location) => sub(location, sub_location).into_iter().collect(),
+            Location::System(location) => system(location),
         }
         .into_iter()
         .collect()
     }
 
+    pub fn system(location: Location) -> Self {
+        Location::System(Box::new(location))
+    }
+
     pub fn get_recursed(&self) -> Vec<PathBuf> {
         let init_top = self.get_path();
         recurse(init_top)
@@ -147,6 +158,16 @@ fn environment(var: &str) -> Vec<PathBuf> {
     }
 }
 
+#[instrument(level = "TRACE")]
+fn system(location: &Location) -> Vec<PathBuf> {
+    if privileges().allows(Operation::SystemFiles) {
+        location.get_path()
+    } else {
+        debug!("Skipping {location:?} as it requires elevated privileges");
+        vec![]
+    }
+}
+
 #[instrument(level = "TRACE")]
 fn globbing(pattern: &str) -> Paths {
     debug!("Globbing pattern {}", pattern);
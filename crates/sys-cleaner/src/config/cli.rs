@@ -15,8 +15,9 @@
  */
 
 use crate::cleaners::cleaner::Cleaner;
+use amt_lib::config::ConfigAction;
 use amt_lib::ui::cli::flags::CommonFlags;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 #[derive(Default, Debug, Parser)]
 #[command(name = env!["CARGO_PKG_NAME"], version, author, about)]
@@ -24,12 +25,22 @@ pub struct Cli {
     #[command(flatten)]
     pub flags: CommonFlags,
 
-    // #[command(subcommand)]
-    // pub complete: Option<Shell>,
     #[arg(
     ignore_case = true,
     default_values_t = Cleaner::get_variants(),
     last = true
     )]
     pub cleaners: Vec<Cleaner>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Inspect the configuration files the cleaners are read from.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
 }
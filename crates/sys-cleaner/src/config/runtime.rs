@@ -15,14 +15,19 @@
  */
 
 use super::cli::Cli;
+use super::settings::Settings;
+use amt_lib::config::{CliChanges, Loader};
+use amt_lib::ui::cli::error::{Categorise, Category};
 use anyhow::Error;
 use anyhow::Result;
+use clap::ArgMatches;
 use std::sync::RwLock;
 use tracing::error;
 
 #[derive(Debug)]
 pub struct Runtime {
     pub cli: Cli,
+    pub settings: Settings,
     pub errors: RwLock<Vec<Error>>,
 }
 
@@ -30,13 +35,42 @@ impl Runtime
 where
     Self: Send + Sync + 'static,
 {
-    pub fn new() -> Result<Self> {
-        use clap::Parser;
+    /// Parses the command line, exiting early if completions were generated.
+    ///
+    /// The settings aren't loaded yet, so that commands which inspect them work even if they're broken.
+    pub fn parse() -> Result<(Cli, ArgMatches)> {
+        use amt_lib::ui::cli::complete;
+        use clap::{CommandFactory, FromArgMatches};
 
-        let cli = Cli::parse();
+        let command = || complete::augment(Cli::command());
+        complete::complete(command);
+
+        let matches = command().get_matches();
+        if complete::generate(&matches, &command(), &mut std::io::stdout())? {
+            std::process::exit(0);
+        }
+
+        let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+        Ok((cli, matches))
+    }
+
+    pub fn new(cli: Cli, matches: &ArgMatches) -> Result<Self> {
         let errors = RwLock::new(Vec::new());
 
-        Ok(Self { cli, errors })
+        // Only the cleaners given on the command line override the configuration files.
+        let changes = CliChanges::new(
+            Settings {
+                cleaners: cli.cleaners.clone(),
+            },
+            matches,
+        );
+        let settings = Loader::new()
+            .with_cli_changes(&changes)
+            .and_then(|loader| loader.load())
+            .categorise(Category::Config)?
+            .into_inner();
+
+        Ok(Self { cli, settings, errors })
     }
 
     pub fn submit_error(&mut self, error: Error) {
@@ -0,0 +1,47 @@
+/*
+ * Copyright (C) 2024. James Draycott me@racci.dev
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+ * See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see https://www.gnu.org/licenses/.
+ */
+
+use crate::cleaners::cleaner::Cleaner;
+use amt_lib::config::{Invalid, Layered};
+use serde::{Deserialize, Serialize};
+
+/// The settings which can be kept in the configuration files,
+/// the command line overrides any value it was given.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The cleaners which are run.
+    pub cleaners: Vec<Cleaner>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            cleaners: Cleaner::get_variants(),
+        }
+    }
+}
+
+impl Layered for Settings {
+    const NAME: &'static str = env!("CARGO_PKG_NAME");
+
+    fn validate(&self) -> Result<(), Invalid> {
+        match self.cleaners.is_empty() {
+            true => Err(Invalid::new("cleaners", "at least one cleaner must be enabled")),
+            false => Ok(()),
+        }
+    }
+}
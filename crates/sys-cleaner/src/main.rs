@@ -20,19 +20,51 @@
 #![allow(incomplete_features)]
 #![feature(inherent_associated_types)]
 
-use amt_lib::helper::require_elevated_privileges;
+use amt_lib::config::Loader;
+use amt_lib::privilege::{escalate, Escalation, Operation};
+use amt_lib::ui::cli::error::{finish, Categorise, Category, ExitCode};
 use anyhow::Result;
-use std::sync::LazyLock;
 use sys_cleaner::application::application;
+use sys_cleaner::config::cli::Command;
 use sys_cleaner::config::runtime::Runtime;
+use sys_cleaner::config::settings::Settings;
 
-static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| Runtime::new().unwrap());
+/// Without these only the current user's files are cleaned.
+const OPERATIONS: &[Operation] = if cfg!(windows) {
+    &[Operation::OtherUsersFiles, Operation::SystemFiles]
+} else {
+    &[Operation::OtherUsersFiles]
+};
 
 #[tokio::main(flavor = "multi_thread")]
-async fn main() -> Result<()> {
-    let _guard = amt_lib::log::init(env!("CARGO_PKG_NAME"), &RUNTIME.cli.flags);
-    let _ = require_elevated_privileges().is_some_and(|code| code.exit());
-    application(&RUNTIME).await?;
+async fn main() -> ExitCode {
+    finish(run().await)
+}
+
+async fn run() -> Result<()> {
+    let (cli, matches) = Runtime::parse()?;
+    let _guard = amt_lib::log::init(env!("CARGO_PKG_NAME"), &cli.flags);
+
+    if let Some(Command::Config { action }) = &cli.command {
+        print!(
+            "{}",
+            action.run(&Loader::<Settings>::new()).categorise(Category::Config)?
+        );
+        return Ok(());
+    }
+
+    let runtime: &'static Runtime = Box::leak(Box::new(Runtime::new(cli, &matches)?));
+
+    match escalate(OPERATIONS, !runtime.cli.flags.no_elevate).categorise(Category::Permission)? {
+        Escalation::Reexecuted(status) => {
+            drop(_guard);
+            std::process::exit(status.code().unwrap_or(1))
+        }
+        Escalation::Degraded(_) => tracing::info!("Only the current user's files will be cleaned."),
+        Escalation::Granted => {}
+    }
+
+    application(runtime).await?;
 
     Ok(())
 }